@@ -5,15 +5,27 @@ use crate::qbbn::model::unified::UnifiedExponentialModel;
 use crate::qbbn::graphdb::adapter::GraphDBAdapter;
 use crate::qbbn::graphdb::schema::{NodeLabel, EdgeLabel};
 // use crate::qbbn::common::model::FactorModel;
-use crate::qbbn::model::objects::{Predicate, Proposition, Argument, ConstantArgument, LabeledArgument};
+use crate::qbbn::model::objects::{Predicate, Proposition, Argument, ConstantArgument, Entity, LabeledArgument};
+use crate::qbbn::common::model::InferenceModel;
+use crate::qbbn::common::proposition_db::RedisBeliefTable;
+use crate::qbbn::inference::engine::Inferencer;
+use crate::qbbn::inference::explanation::{ExplanationConfig, ExplanationTree};
+use crate::qbbn::inference::graph::PropositionGraph;
+use crate::qbbn::inference::value_of_information::{QuestionConfig, QuestionFocus, RankedQuestion};
 use crate::qbbn::model::creators::{proposition, relation};
-// use crate::qbbn::common::redis::MockConnection as Connection;
+use crate::qbbn::common::redis::MockConnection as Connection;
 use log::{info, debug};
 use std::collections::HashMap;
 use std::error::Error;
 use std::sync::Arc;
 use chrono::Utc;
 
+/// Forward/backward passes run before explaining a belief.
+const EXPLANATION_PASSES: usize = 10;
+
+/// The domain of the entities that `add_proposition_with_prior` puts in its roles.
+pub const ENTITY_DOMAIN: &str = "Entity";
+
 /// High-level interface for using the belief network as agent memory for LLMs
 /// 
 /// This provides a user-friendly API that:
@@ -108,7 +120,7 @@ impl BeliefMemory {
             let _entity_id = self.ensure_entity(&entity_name)?;
             
             let arg = Argument::Constant(ConstantArgument {
-                domain: "Entity".to_string(),
+                domain: ENTITY_DOMAIN.to_string(),
                 entity_id: entity_name.clone(),
             });
             
@@ -342,18 +354,87 @@ impl BeliefMemory {
         Ok(Vec::new())
     }
     
-    /// Generate natural language explanation for a belief
+    /// Generate a plain-text explanation for a belief
+    ///
+    /// `proposition_id` is the proposition's hash string. See `explain_belief`.
     pub fn explain_belief_in_natural_language(&self, proposition_id: &str) -> Result<String, Box<dyn Error>> {
-        // TODO: Use LLM to generate explanation based on belief network structure
-        Ok(format!("Belief {} is based on the current evidence in the network.", proposition_id))
+        Ok(self.explain_belief(proposition_id, &ExplanationConfig::default())?.render_text())
+    }
+
+    /// Explain the belief in a proposition of the namespace's target graph
+    ///
+    /// Belief propagation is run over the graph with the namespace's stored proposition
+    /// probabilities as evidence, and the proposition's marginal is broken down into the
+    /// implications and evidence behind it.
+    pub fn explain_belief(
+        &self,
+        proposition_id: &str,
+        config: &ExplanationConfig,
+    ) -> Result<ExplanationTree, Box<dyn Error>> {
+        let mut conn = self.adapter.get_connection().into_inner();
+        let mut inferencer = self.target_inferencer(&mut conn, None)?;
+        let proposition = inferencer
+            .bfs_order
+            .iter()
+            .filter(|node| node.is_single())
+            .map(|node| node.extract_single())
+            .find(|proposition| proposition.hash_string() == proposition_id)
+            .ok_or_else(|| format!("{} is not in the target's graph.", proposition_id))?;
+        inferencer.initialize_chart(&mut conn)?;
+        for _ in 0..EXPLANATION_PASSES {
+            inferencer.do_full_forward_and_backward(&mut conn)?;
+        }
+        inferencer.explain(&mut conn, &proposition, config)
     }
     
-    /// Suggest questions to reduce uncertainty about an entity
-    pub fn suggest_questions_for_uncertainty(&self, entity_name: &str) -> Result<Vec<String>, Box<dyn Error>> {
-        // TODO: Analyze belief network to find high-uncertainty propositions
-        Ok(vec![
-            format!("What is the relationship between {} and other entities?", entity_name),
-            format!("Can you provide more information about {}?", entity_name),
-        ])
+    /// Suggest the questions that would most reduce uncertainty about the entity
+    /// `entity_name` of `domain`, best first. See `rank_questions`. Entities added through
+    /// `add_proposition_with_prior` are in `ENTITY_DOMAIN`.
+    pub fn suggest_questions_for_uncertainty(&self, domain: &str, entity_name: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let entity = Entity {
+            domain: domain.to_string(),
+            name: entity_name.to_string(),
+        };
+        let config = QuestionConfig {
+            render_text: true,
+            ..QuestionConfig::default()
+        };
+        let questions = self.rank_questions(&QuestionFocus::Entity(entity), &config)?;
+        Ok(questions.into_iter().filter_map(|question| question.text).collect())
+    }
+
+    /// Rank the unobserved propositions by value of information about `focus`
+    ///
+    /// An entity is looked for in the grounded graph of the namespace's target, and the
+    /// namespace's stored proposition probabilities are the evidence.
+    pub fn rank_questions(
+        &self,
+        focus: &QuestionFocus,
+        config: &QuestionConfig,
+    ) -> Result<Vec<RankedQuestion>, Box<dyn Error>> {
+        let mut conn = self.adapter.get_connection().into_inner();
+        let target = match focus {
+            QuestionFocus::Target(target) => Some(target.clone()),
+            QuestionFocus::Entity(_) => None,
+        };
+        let inferencer = self.target_inferencer(&mut conn, target)?;
+        inferencer.value_of_information(&mut conn, focus, config)
+    }
+
+    /// An inferencer over the grounded graph of `target`, or of the namespace's stored
+    /// target, reading the namespace's stored proposition probabilities as evidence.
+    fn target_inferencer(
+        &self,
+        conn: &mut Connection,
+        target: Option<Proposition>,
+    ) -> Result<Box<Inferencer>, Box<dyn Error>> {
+        let model = InferenceModel::new_shared(self.namespace.clone())?;
+        let target = match target {
+            Some(target) => target,
+            None => model.graph.get_target(conn)?,
+        };
+        let proposition_graph = PropositionGraph::new_shared(conn, &model.graph, target)?;
+        let fact_memory = RedisBeliefTable::new_shared(self.namespace.clone())?;
+        Inferencer::new_mutable(model, proposition_graph, fact_memory)
     }
 }
\ No newline at end of file
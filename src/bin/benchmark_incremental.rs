@@ -0,0 +1,95 @@
+use bayeslog::qbbn::{
+    common::{
+        interface::{BeliefTable, ScenarioMaker},
+        model::InferenceModel,
+        proposition_db::HashMapBeliefTable,
+        resources::ResourceContext,
+        train::do_training,
+    },
+    inference::{engine::Inferencer, graph::PropositionGraph},
+    scenarios::dating_simple::SimpleDating,
+};
+use clap::Parser;
+use std::time::{Duration, Instant};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Latency of incremental belief updates as evidence arrives one fact at a time", long_about = None)]
+struct Args {
+    /// Entities per domain of the dating scenario
+    #[arg(long, default_value_t = 20)]
+    entities_per_domain: i32,
+
+    /// Number of facts observed, one update each
+    #[arg(long, default_value_t = 50)]
+    observations: usize,
+
+    /// Seeds the scenario and the weights
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+fn percentile(sorted: &[Duration], fraction: f64) -> Duration {
+    let index = ((sorted.len() as f64 - 1.0) * fraction).round() as usize;
+    sorted[index]
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let args = Args::parse();
+
+    let namespace = "dating_simple";
+    let mut resources = ResourceContext::new_in_memory(namespace)?;
+    resources.config.entities_per_domain = args.entities_per_domain;
+    resources.config.seed = Some(args.seed);
+    SimpleDating {}.setup_scenario(&resources)?;
+    do_training(&resources, namespace.to_string())?;
+
+    let mut connection = resources.connection.lock().unwrap();
+    let model = InferenceModel::new_shared(namespace.to_string())?;
+    let target = model.graph.get_target(&mut connection)?;
+    let proposition_graph = PropositionGraph::new_shared(&mut connection, &model.graph, target.clone())?;
+    let fact_memory = HashMapBeliefTable::new();
+    let mut inferencer = Inferencer::new_mutable(model, proposition_graph, fact_memory.clone())?;
+    inferencer.initialize_chart(&mut connection)?;
+
+    let start = Instant::now();
+    inferencer.do_full_forward_and_backward(&mut connection)?;
+    let full_pass = start.elapsed();
+    println!("{} nodes, full forward and backward pass {:?}", inferencer.bfs_order.len(), full_pass);
+
+    // Observe the graph's propositions one at a time, as an agent would.
+    let nodes: Vec<_> = inferencer
+        .bfs_order
+        .iter()
+        .filter(|node| node.is_single() && node.extract_single() != target)
+        .take(args.observations)
+        .cloned()
+        .collect();
+    let mut latencies = vec![];
+    let mut visits = 0;
+    for node in &nodes {
+        fact_memory.store_proposition_probability(&mut connection, &node.extract_single(), 1.0)?;
+        let start = Instant::now();
+        let stats = inferencer.do_incremental_update(&mut connection, std::slice::from_ref(node))?;
+        latencies.push(start.elapsed());
+        visits += stats.nodes_visited;
+    }
+    if latencies.is_empty() {
+        println!("No observable propositions in the graph.");
+        return Ok(());
+    }
+
+    latencies.sort();
+    let total: Duration = latencies.iter().sum();
+    let under_millisecond = latencies.iter().filter(|latency| **latency < Duration::from_millis(1)).count();
+    println!("{} incremental updates, {:.1} nodes visited on average", latencies.len(), visits as f64 / latencies.len() as f64);
+    println!(
+        "mean {:?}  median {:?}  p95 {:?}  max {:?}",
+        total / latencies.len() as u32,
+        percentile(&latencies, 0.5),
+        percentile(&latencies, 0.95),
+        latencies[latencies.len() - 1]
+    );
+    println!("{} of {} updates under 1ms", under_millisecond, latencies.len());
+    Ok(())
+}
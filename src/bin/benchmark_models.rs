@@ -44,6 +44,7 @@ fn benchmark_entity_count(entity_count: usize, run_standard: bool, run_torch: bo
         marginal_output_file: None,
         storage_type: StorageType::InMemory,
         db_path: None,
+        seed: None,
     };
     
     let mut standard_time = None;
@@ -8,7 +8,7 @@ use bayeslog::{
             resources::ResourceContext,
             setup::{parse_configuration_options, CommandLineOptions},
         },
-        explorer::routes::{animation_route::internal_animation, experiment_route::internal_experiment, factors_route::internal_factors, index_route::internal_index, marginals_route::internal_marginals, network_route::internal_network, weights_route::internal_weights},
+        explorer::routes::{animation_route::internal_animation, experiment_route::internal_experiment, factors_route::internal_factors, index_route::internal_index, marginals_route::internal_marginals, network_route::internal_network, sensitivity_route::internal_sensitivity, weights_route::internal_weights},
     }
 };
 use rocket::response::content::RawHtml as Html;
@@ -61,12 +61,17 @@ fn animation(experiment_name: String, test_scenario: String, context: &State<Web
     internal_animation(&experiment_name, &test_scenario, &context.namespace)
 }
 
+#[get("/sensitivity/<experiment_name>/<test_scenario>")]
+fn sensitivity(experiment_name: String, test_scenario: String, context: &State<WebContext>) -> Html<String> {
+    internal_sensitivity(&experiment_name, &test_scenario, &context.namespace)
+}
+
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
     let config = parse_configuration_options();
     rocket::build()
         .manage(WebContext::new(config))
-        .mount("/", routes![home, experiment, network, weights, marginals, factors, animation])
+        .mount("/", routes![home, experiment, network, weights, marginals, factors, animation, sensitivity])
         .mount("/static", FileServer::from("static"))
         .launch()
         .await?;
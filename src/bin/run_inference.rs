@@ -44,6 +44,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         marginal_output_file: None,
         storage_type,
         db_path: Some(db_path),
+        seed: None,
     };
     
     // Create resource context using the persistent database
@@ -0,0 +1,48 @@
+use bayeslog::qbbn::common::sweep::{best_trial, run_sweep, SweepSpec};
+use clap::Parser;
+use std::fs::File;
+use std::io::Write;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Grid or random search over training and inference parameters", long_about = None)]
+struct Args {
+    /// Sweep spec, TOML if the file ends in .toml and JSON otherwise
+    #[arg(long)]
+    spec: String,
+
+    /// File the trial results are written to, one JSON object per line
+    #[arg(long, default_value = "sweep_results.jsonl")]
+    results: String,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let args = Args::parse();
+    let spec = SweepSpec::from_file(&args.spec)?;
+    println!("Sweeping {} over {:?}", spec.scenario, spec.parameters.keys().collect::<Vec<_>>());
+
+    let mut results_file = File::create(&args.results)?;
+    let results = run_sweep(&spec, |result| {
+        writeln!(results_file, "{}", serde_json::to_string(result)?)?;
+        results_file.flush()?;
+        match (&result.error, result.score) {
+            (Some(error), _) => println!("trial {} failed: {}", result.trial, error),
+            (None, Some(score)) => println!("trial {} score {:.6} {:?}", result.trial, score, result.parameters),
+            (None, None) => println!("trial {} unscored {:?}", result.trial, result.parameters),
+        }
+        Ok(())
+    })?;
+
+    println!("\n{} trials written to {}", results.len(), args.results);
+    match best_trial(&results) {
+        Some(best) => println!(
+            "Best: trial {} score {:.6} {}",
+            best.trial,
+            best.score.unwrap(),
+            serde_json::to_string(&best.parameters)?
+        ),
+        None => println!("No trial was scored."),
+    }
+    Ok(())
+}
@@ -52,6 +52,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         marginal_output_file: None,
         storage_type,
         db_path,
+        seed: None,
     };
     println!("Scenario: {}", config.scenario_name);
     
@@ -5,12 +5,13 @@ use bayeslog::qbbn::{
     model::{
         torch_exponential::TorchExponentialModel,
         exponential::ExponentialModel,
-        objects::{ImplicationFactor, Proposition, Argument},
+        objects::{CpdType, ImplicationFactor, Proposition, Argument},
     },
     inference::graph::PropositionFactor,
 };
 use log::info;
 use std::time::Instant;
+use std::collections::HashMap;
 
 fn create_test_factor() -> (ImplicationFactor, FactorContext) {
     use bayeslog::qbbn::model::objects::{
@@ -51,12 +52,14 @@ fn create_test_factor() -> (ImplicationFactor, FactorContext) {
     // Create implication factor
     let implication = ImplicationFactor {
         premise: PredicateGroup {
-            terms: vec![likes_pred]
+            terms: vec![likes_pred],
+            negations: vec![],
         },
         conclusion: dates_pred,
         role_maps: GroupRoleMap {
             role_maps: vec![] // No role mapping needed for this simple example
-        }
+        },
+        cpd: CpdType::Learned,
     };
     
     // Create factor context with some probabilities
@@ -70,6 +73,7 @@ fn create_test_factor() -> (ImplicationFactor, FactorContext) {
     let factor_context = FactorContext {
         factor: vec![prop_factor],
         probabilities: vec![0.8], // likes(alice, bob) has 0.8 probability
+        numeric_values: HashMap::new(),
     };
     
     (implication, factor_context)
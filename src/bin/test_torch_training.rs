@@ -25,6 +25,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         marginal_output_file: None,
         storage_type: StorageType::InMemory,
         db_path: None,
+        seed: None,
     };
     
     // Setup resources
@@ -3,6 +3,7 @@ use std::env;
 
 use bayeslog::qbbn::common::setup::parse_configuration_options;
 use bayeslog::qbbn::common::{resources::ResourceContext, train::setup_and_train};
+use bayeslog::qbbn::model::tying::WeightTying;
 use bayeslog::qbbn::scenarios::factory::ScenarioMakerFactory;
 
 fn main() {
@@ -19,10 +20,19 @@ fn main() {
     
     let config = parse_configuration_options();
     println!("Scenario: {}", &config.scenario_name);
+    let namespace = config.scenario_name.clone();
     
     let resources = ResourceContext::new(&config).expect("Couldn't create resources.");
     println!("Created resources");
     
+    // Weight tying groups, stored with the weights before any are initialized
+    if let Ok(path) = env::var("BAYESLOG_WEIGHT_TYING") {
+        let tying = WeightTying::from_file(&path).expect("Couldn't read the weight tying groups.");
+        println!("Tying weights in {} groups from {}", tying.groups.len(), path);
+        let mut connection = resources.connection.lock().unwrap();
+        WeightTying::store(&mut connection, &namespace, Some(&tying)).expect("Couldn't store the weight tying groups.");
+    }
+    
     let scenario_maker = ScenarioMakerFactory::new_shared(&config.scenario_name).unwrap();
     println!("Created scenario maker for {}", &config.scenario_name);
     
@@ -36,10 +46,10 @@ fn main() {
         scenario_maker.setup_scenario(&resources).expect("Error setting up scenario");
         
         // Then do batch training
-        do_batch_training(&resources, config.scenario_name.clone(), true).expect("Error in torch training");
+        do_batch_training(&resources, namespace.clone(), true).expect("Error in torch training");
     } else {
         // Use standard training
-        setup_and_train(&resources, scenario_maker.borrow(), &config.scenario_name).expect("Error in training.");
+        setup_and_train(&resources, scenario_maker.borrow(), &namespace).expect("Error in training.");
     }
     
     println!("Training completed successfully!");
@@ -4,23 +4,41 @@ use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::params;
 use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How long a connection to an in-memory database waits for another one's write.
+const IN_MEMORY_BUSY_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// GraphDatabase handles storage and retrieval of nodes and edges using SQLite.
 pub struct GraphDatabase {
     /// Connection pool for SQLite
     pool: Pool<SqliteConnectionManager>,
+    /// Write counters by name, kept in memory for as long as this handle lives
+    counters: Mutex<HashMap<String, u64>>,
 }
 
 impl GraphDatabase {
     /// Create a new graph database with an in-memory SQLite database
+    ///
+    /// The database lives in SQLite's `memdb` VFS under a fresh UUID, so every connection
+    /// in the pool sees the same data, as the worker threads of batch training need, while
+    /// separate in-memory databases stay separate. It lasts only as long as one of its
+    /// connections is open, so the pool keeps its idle connections.
+    ///
+    /// Locking is SQLite's usual locking of the whole database. A reader waits, for up to
+    /// `IN_MEMORY_BUSY_TIMEOUT`, for a write in flight to commit or roll back.
     pub fn new_in_memory() -> Result<Self> {
-        let manager = SqliteConnectionManager::memory();
+        let manager = SqliteConnectionManager::file(format!("file:/bayeslog-{}?vfs=memdb", uuid::Uuid::new_v4()))
+            .with_init(|conn| conn.busy_timeout(IN_MEMORY_BUSY_TIMEOUT));
         let pool = Pool::builder()
             .max_size(10) // Maximum connections in the pool
+            .idle_timeout(None)
+            .max_lifetime(None)
             .build(manager)
             .context("Failed to create connection pool")?;
         
-        let db = Self { pool };
+        let db = Self { pool, counters: Mutex::default() };
         db.initialize_schema()?;
         Ok(db)
     }
@@ -33,7 +51,7 @@ impl GraphDatabase {
             .build(manager)
             .context("Failed to create connection pool")?;
         
-        let db = Self { pool };
+        let db = Self { pool, counters: Mutex::default() };
         db.initialize_schema()?;
         Ok(db)
     }
@@ -152,6 +170,19 @@ impl GraphDatabase {
         }
     }
 
+    /// The counter `name`, 0 until first bumped
+    ///
+    /// Counters are not stored in the database. They let the users of one handle, and of
+    /// the connections made from it, tell when something they computed is stale.
+    pub fn counter(&self, name: &str) -> u64 {
+        self.counters.lock().unwrap().get(name).copied().unwrap_or(0)
+    }
+
+    /// Increment the counter `name`
+    pub fn bump_counter(&self, name: &str) {
+        *self.counters.lock().unwrap().entry(name.to_string()).or_insert(0) += 1;
+    }
+
     /// Add a node to the graph database
     pub fn add_node(&self, label: &str, properties: HashMap<String, Value>) -> Result<String> {
         let node = Node::new(label, properties);
@@ -634,6 +665,45 @@ impl GraphDatabase {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_in_memory_readers_wait_for_writes() {
+        let db = GraphDatabase::new_in_memory().unwrap();
+        let db = &db;
+        let (started, write_started) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(move || {
+                db.with_transaction(|tx| {
+                    tx.execute(
+                        "INSERT INTO nodes (id, label, properties) VALUES ('pending', 'TestLabel', '{}')",
+                        [],
+                    )?;
+                    started.send(()).unwrap();
+                    std::thread::sleep(Duration::from_millis(200));
+                    Ok(())
+                })
+            });
+            // Each reader takes its own connection from the pool, and sees the row once
+            // the write has committed.
+            write_started.recv().unwrap();
+            let readers: Vec<_> = (0..4).map(|_| scope.spawn(move || db.get_node("pending"))).collect();
+            for reader in readers {
+                assert!(reader.join().unwrap().unwrap().is_some());
+            }
+            writer.join().unwrap().unwrap();
+        });
+
+        // A rolled back write is never seen.
+        let result: Result<()> = db.with_transaction(|tx| {
+            tx.execute(
+                "INSERT INTO nodes (id, label, properties) VALUES ('discarded', 'TestLabel', '{}')",
+                [],
+            )?;
+            Err(anyhow::anyhow!("roll back"))
+        });
+        assert!(result.is_err());
+        assert!(db.get_node("discarded").unwrap().is_none());
+    }
+
     #[test]
     fn test_add_and_get_node() {
         let db = GraphDatabase::new_in_memory().unwrap();
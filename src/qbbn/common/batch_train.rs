@@ -1,19 +1,29 @@
+use crate::graph::database::GraphDatabase;
 use crate::qbbn::{
     common::{
         interface::BeliefTable,
-        model::FactorContext,
         graph::InferenceGraph,
+        model::FactorModel,
         proposition_db::RedisBeliefTable,
     },
+    graphdb::GraphDBAdapter,
     model::{
-        choose::extract_backimplications_from_proposition,
-        objects::{Proposition, PropositionGroup},
+        config::ExponentialTrainingConfig,
+        device::TorchConfig,
+        objects::{Proposition, PropositionChoice},
     },
 };
 use crate::qbbn::common::redis::MockConnection as Connection;
 use log::info;
+use rand::seq::SliceRandom;
+use rayon::prelude::*;
+use std::collections::HashSet;
 use std::error::Error;
-use super::train::TrainingPlan;
+use std::sync::Arc;
+use super::train::{
+    extract_question_example, extract_test_examples, train_epochs_with, TrainerConfig, TrainingExample,
+    TrainingHistory, TrainingPlan,
+};
 use super::resources::ResourceContext;
 
 /// Batch training support for GPU acceleration
@@ -23,172 +33,203 @@ pub struct BatchTrainer {
 
 impl BatchTrainer {
     pub fn new(batch_size: usize) -> Self {
-        BatchTrainer { batch_size }
+        BatchTrainer { batch_size: batch_size.max(1) }
     }
 
-    /// Extract training data in batches
-    pub fn prepare_training_batches(
+    /// Stream the examples behind `propositions` in batches. Each batch is extracted
+    /// when it is asked for, with its factors extracted in parallel.
+    pub fn batches<'a>(
         &self,
-        connection: &mut Connection,
-        proposition_db: &dyn BeliefTable,
-        graph: &InferenceGraph,
-        propositions: &[Proposition],
-    ) -> Result<Vec<(Vec<FactorContext>, Vec<f64>)>, Box<dyn Error>> {
-        let mut batches = Vec::new();
-        let mut current_factors = Vec::new();
-        let mut current_probabilities = Vec::new();
-
-        for proposition in propositions {
-            // Extract factor context
-            let factor = extract_factor_for_training(
-                connection,
-                proposition_db,
-                graph,
-                proposition.clone(),
-            )?;
-            
-            // Get gold probability
-            let probability = proposition_db
-                .get_proposition_probability(connection, proposition)?
-                .expect("Probability should exist");
+        connection: &Connection,
+        proposition_db: &'a dyn BeliefTable,
+        graph: &'a InferenceGraph,
+        propositions: &'a [Proposition],
+    ) -> TrainingBatches<'a> {
+        TrainingBatches {
+            graph_db: Arc::clone(&connection.adapter.graph_db),
+            namespace: connection.adapter.namespace.clone(),
+            proposition_db,
+            graph,
+            propositions,
+            batch_size: self.batch_size,
+            position: 0,
+            seen_choices: HashSet::new(),
+        }
+    }
+}
 
-            current_factors.push(factor);
-            current_probabilities.push(probability);
+/// Iterator over training batches. The outcomes of a categorical variable make one
+/// example, in the batch of its first outcome.
+pub struct TrainingBatches<'a> {
+    graph_db: Arc<GraphDatabase>,
+    namespace: String,
+    proposition_db: &'a dyn BeliefTable,
+    graph: &'a InferenceGraph,
+    propositions: &'a [Proposition],
+    batch_size: usize,
+    position: usize,
+    seen_choices: HashSet<PropositionChoice>,
+}
 
-            // Create batch when size is reached
-            if current_factors.len() >= self.batch_size {
-                batches.push((
-                    std::mem::take(&mut current_factors),
-                    std::mem::take(&mut current_probabilities),
-                ));
+impl TrainingBatches<'_> {
+    fn extract(&mut self, chunk: &[Proposition]) -> Result<Vec<TrainingExample>, Box<dyn Error>> {
+        let graph_db = &self.graph_db;
+        let namespace = &self.namespace;
+        let proposition_db = self.proposition_db;
+        let graph = self.graph;
+        // Each worker reads through its own connection to the shared database.
+        let extracted: Vec<Result<_, String>> = chunk
+            .par_iter()
+            .map_init(
+                || Connection::new(GraphDBAdapter::new(Arc::clone(graph_db), namespace)),
+                |connection, proposition| {
+                    extract_question_example(connection, proposition_db, graph, proposition)
+                        .map_err(|e| e.to_string())
+                },
+            )
+            .collect();
+        let mut examples = vec![];
+        for result in extracted {
+            let (choice, example) = result?;
+            if let Some(choice) = choice
+                && !self.seen_choices.insert(choice)
+            {
+                continue;
             }
+            examples.extend(example);
         }
-
-        // Add remaining items as final batch
-        if !current_factors.is_empty() {
-            batches.push((current_factors, current_probabilities));
-        }
-
-        Ok(batches)
+        Ok(examples)
     }
 }
 
-/// Extract factor context for a single proposition
-fn extract_factor_for_training(
-    connection: &mut Connection,
-    proposition_db: &dyn BeliefTable,
-    graph: &InferenceGraph,
-    conclusion: Proposition,
-) -> Result<FactorContext, Box<dyn Error>> {
-    let factors = extract_backimplications_from_proposition(connection, graph, &conclusion)?;
-    let mut probabilities = vec![];
-    
-    for factor in &factors {
-        let probability = extract_group_probability_for_training(
-            connection,
-            proposition_db,
-            &factor.premise,
-        )?;
-        probabilities.push(probability);
+impl Iterator for TrainingBatches<'_> {
+    type Item = Result<Vec<TrainingExample>, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.propositions.len() {
+            return None;
+        }
+        let end = (self.position + self.batch_size).min(self.propositions.len());
+        let chunk = &self.propositions[self.position..end];
+        self.position = end;
+        Some(self.extract(chunk))
     }
-    
-    Ok(FactorContext {
-        factor: factors,
-        probabilities,
-    })
 }
 
-/// Extract probability for a proposition group
-fn extract_group_probability_for_training(
+/// Train on one batch: a single update for its boolean conclusions, and its categorical
+/// variables one at a time. Returns the mean loss.
+pub fn train_on_batch(
     connection: &mut Connection,
-    proposition_db: &dyn BeliefTable,
-    premise: &PropositionGroup,
+    factor_model: &mut dyn FactorModel,
+    batch: &[TrainingExample],
 ) -> Result<f64, Box<dyn Error>> {
-    let mut product = 1.0;
-    for term in &premise.terms {
-        let part = proposition_db
-            .get_proposition_probability(connection, term)?
-            .unwrap_or(0.0);
-        product *= part;
+    let total = batch.len();
+    let mut factors = vec![];
+    let mut probabilities = vec![];
+    let mut loss = 0f64;
+    for example in batch {
+        match example {
+            TrainingExample::Binary(factor, probability) => {
+                factors.push(factor.clone());
+                probabilities.push(*probability);
+            }
+            TrainingExample::Choice(factor, gold) => {
+                loss += factor_model.train_distribution(connection, factor, gold)?.loss;
+            }
+        }
+    }
+    if !factors.is_empty() {
+        loss += factor_model.train_batch(connection, &factors, &probabilities)?.loss * factors.len() as f64;
     }
-    Ok(product)
+    Ok(loss / total.max(1) as f64)
 }
 
-/// Batch training function that can utilize GPU acceleration
+/// Batch training function that can utilize GPU acceleration, with the batch size
+/// from `TorchConfig::from_env`
 pub fn do_batch_training(
     resources: &ResourceContext,
     namespace: String,
     use_torch: bool,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<TrainingHistory, Box<dyn Error>> {
+    let batch_size = TorchConfig::from_env().batch_size;
+    do_batch_training_with_batch_size(resources, namespace, use_torch, batch_size)
+}
+
+/// Multi-epoch training in batches of `batch_size`, with held-out evaluation on the
+/// labeled test questions. The epochs and early stopping come from `TorchConfig::from_env`.
+pub fn do_batch_training_with_batch_size(
+    resources: &ResourceContext,
+    namespace: String,
+    use_torch: bool,
+    batch_size: usize,
+) -> Result<TrainingHistory, Box<dyn Error>> {
     let mut connection = resources.connection.lock().unwrap();
     let graph = InferenceGraph::new_mutable(namespace.clone())?;
     let proposition_db = RedisBeliefTable::new_mutable(namespace.clone())?;
     let plan = TrainingPlan::new(namespace.clone())?;
-    
+
+    let mut torch_config = TorchConfig::from_env();
+    torch_config.seed = torch_config.seed.or(resources.config.seed);
+    let config = TrainerConfig {
+        batch_size,
+        ..TrainerConfig::from(&torch_config)
+    };
+
     // Choose model based on configuration
-    let mut factor_model: Box<dyn crate::qbbn::common::model::FactorModel> = if use_torch {
+    let mut factor_model: Box<dyn FactorModel> = if use_torch {
         info!("Using GPU-accelerated TorchExponentialModel");
-        crate::qbbn::model::torch_exponential::TorchExponentialModel::new_mutable(namespace.clone())?
+        crate::qbbn::model::torch_exponential::TorchExponentialModel::new_with_config(namespace.clone(), torch_config)?
     } else {
         info!("Using standard ExponentialModel");
-        crate::qbbn::model::exponential::ExponentialModel::new_mutable(namespace.clone())?
+        let options = crate::qbbn::model::exponential::ExponentialModelOptions {
+            training: ExponentialTrainingConfig {
+                seed: config.model.seed,
+                ..ExponentialTrainingConfig::default()
+            },
+            calibrator: crate::qbbn::model::calibrator::CalibratorUpdate::Clear,
+            ..Default::default()
+        };
+        crate::qbbn::model::exponential::ExponentialModel::new_with_options(namespace.clone(), options)?
     };
-    
+
     // Initialize weights for all implications
     let implications = graph.get_all_implications(&mut connection)?;
     for implication in implications {
         factor_model.initialize_connection(&mut connection, &implication)?;
     }
-    
-    // Get training data
-    let training_questions = plan.get_training_questions(&mut connection)?;
-    info!("Processing {} training examples", training_questions.len());
-    
-    if use_torch {
-        // Use batch training for GPU
-        let batch_trainer = BatchTrainer::new(32); // TODO: Make configurable
-        let batches = batch_trainer.prepare_training_batches(
-            &mut connection,
-            proposition_db.as_ref(),
-            &graph,
-            &training_questions,
-        )?;
-        
-        info!("Created {} batches for training", batches.len());
-        
-        for (batch_idx, (factors, probabilities)) in batches.iter().enumerate() {
-            // For now, train examples individually even in torch mode
-            // TODO: Implement true batch training in TorchExponentialModel
-            for (factor, &probability) in factors.iter().zip(probabilities.iter()) {
-                let _stats = factor_model.train(&mut connection, factor, probability)?;
-            }
-            
-            if batch_idx % 10 == 0 {
-                info!("Processed batch {}/{}", batch_idx + 1, batches.len());
+
+    // The training examples are extracted in parallel a batch at a time, anew every
+    // epoch, so only one batch is held in memory.
+    let mut training_questions = plan.get_training_questions(&mut connection)?;
+    let test_questions = plan.get_test_questions(&mut connection)?;
+    let test = extract_test_examples(&mut connection, proposition_db.as_ref(), &graph, &test_questions)?;
+    info!(
+        "Training on {} questions in batches of {}, for up to {} epochs",
+        training_questions.len(),
+        batch_size,
+        config.max_epochs
+    );
+
+    let batch_trainer = BatchTrainer::new(batch_size);
+    let history = train_epochs_with(
+        &mut connection,
+        factor_model.as_mut(),
+        &test,
+        &config,
+        |connection, factor_model, rng| {
+            if config.shuffle {
+                training_questions.shuffle(rng);
             }
-        }
-    } else {
-        // Standard training loop
-        for (idx, proposition) in training_questions.iter().enumerate() {
-            let factor = extract_factor_for_training(
-                &mut connection,
-                proposition_db.as_ref(),
-                &graph,
-                proposition.clone(),
-            )?;
-            
-            let probability = proposition_db
-                .get_proposition_probability(&mut connection, proposition)?
-                .expect("Probability should exist");
-            
-            let _stats = factor_model.train(&mut connection, &factor, probability)?;
-            
-            if idx % 100 == 0 {
-                info!("Processed {}/{} examples", idx + 1, training_questions.len());
+            let mut total_loss = 0f64;
+            let mut examples = 0;
+            for batch in batch_trainer.batches(connection, proposition_db.as_ref(), &graph, &training_questions) {
+                let batch = batch?;
+                total_loss += train_on_batch(connection, factor_model, &batch)? * batch.len() as f64;
+                examples += batch.len();
             }
-        }
-    }
-    
-    info!("Training complete");
-    Ok(())
-}
\ No newline at end of file
+            Ok(total_loss / examples.max(1) as f64)
+        },
+    )?;
+    info!("Training complete: kept epoch {} of {}", history.best_epoch + 1, history.epochs.len());
+    Ok(history)
+}
@@ -0,0 +1,308 @@
+use crate::qbbn::{
+    common::{
+        interface::BeliefTable,
+        model::{FactorModel, InferenceModel},
+        proposition_db::{MaskedBeliefTable, RedisBeliefTable},
+    },
+    inference::graph::PropositionGraph,
+    model::{
+        calibrator::Calibrator,
+        config::ExponentialTrainingConfig,
+        exponential::ExponentialModelOptions,
+        objects::Proposition,
+    },
+};
+use crate::qbbn::common::redis::MockConnection as Connection;
+use log::info;
+use std::error::Error;
+use std::sync::Arc;
+
+use super::em_train::run_inference;
+use super::resources::ResourceContext;
+use super::train::{extract_test_examples, TrainingExample, TrainingPlan};
+
+/// Predicted probabilities are kept this far from 0 and 1 when computing log-loss.
+const CALIBRATION_EPSILON: f64 = 1e-9;
+
+/// Predictions falling into `[lower, upper)`, or `[lower, 1]` for the last bin.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReliabilityBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+    /// Mean predicted probability, 0 if the bin is empty.
+    pub mean_predicted: f64,
+    /// Mean gold value, 0 if the bin is empty.
+    pub observed: f64,
+}
+
+/// A reliability diagram over equal-width bins, with summary scores of the same
+/// `(predicted, gold)` pairs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CalibrationMetrics {
+    pub bins: Vec<ReliabilityBin>,
+    /// Expected calibration error: the count-weighted mean gap between predicted and
+    /// observed over the bins.
+    pub ece: f64,
+    /// Maximum calibration error: the largest gap in a non-empty bin.
+    pub mce: f64,
+    pub brier: f64,
+    pub log_loss: f64,
+    pub examples: usize,
+}
+
+impl CalibrationMetrics {
+    pub fn from_predictions(predictions: &[(f64, f64)], bins: usize) -> CalibrationMetrics {
+        let bins = bins.max(1);
+        let width = 1f64 / bins as f64;
+        let mut metrics = CalibrationMetrics {
+            bins: (0..bins)
+                .map(|index| ReliabilityBin {
+                    lower: index as f64 * width,
+                    upper: (index + 1) as f64 * width,
+                    ..ReliabilityBin::default()
+                })
+                .collect(),
+            examples: predictions.len(),
+            ..CalibrationMetrics::default()
+        };
+        for &(predicted, gold) in predictions {
+            let index = ((predicted * bins as f64) as usize).min(bins - 1);
+            let bin = &mut metrics.bins[index];
+            bin.count += 1;
+            bin.mean_predicted += predicted;
+            bin.observed += gold;
+            metrics.brier += (predicted - gold).powi(2);
+            let clamped = predicted.clamp(CALIBRATION_EPSILON, 1f64 - CALIBRATION_EPSILON);
+            metrics.log_loss -= gold * clamped.ln() + (1f64 - gold) * (1f64 - clamped).ln();
+        }
+        if predictions.is_empty() {
+            return metrics;
+        }
+        let count = predictions.len() as f64;
+        metrics.brier /= count;
+        metrics.log_loss /= count;
+        for bin in metrics.bins.iter_mut().filter(|bin| bin.count > 0) {
+            bin.mean_predicted /= bin.count as f64;
+            bin.observed /= bin.count as f64;
+            let gap = (bin.mean_predicted - bin.observed).abs();
+            metrics.ece += bin.count as f64 / count * gap;
+            metrics.mce = metrics.mce.max(gap);
+        }
+        metrics
+    }
+
+    pub fn render_text(&self) -> String {
+        let mut text = format!(
+            "{} examples, ECE {:.4}, MCE {:.4}, Brier {:.4}, log-loss {:.4}\n",
+            self.examples, self.ece, self.mce, self.brier, self.log_loss
+        );
+        for bin in self.bins.iter().filter(|bin| bin.count > 0) {
+            text.push_str(&format!(
+                "[{:.2}, {:.2}) {:>5} predicted {:.4} observed {:.4}\n",
+                bin.lower, bin.upper, bin.count, bin.mean_predicted, bin.observed
+            ));
+        }
+        text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationMethod {
+    Platt,
+    Isotonic,
+}
+
+impl CalibrationMethod {
+    pub fn fit(&self, predictions: &[(f64, f64)]) -> Calibrator {
+        match self {
+            CalibrationMethod::Platt => Calibrator::fit_platt(predictions),
+            CalibrationMethod::Isotonic => Calibrator::fit_isotonic(predictions),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CalibrationConfig {
+    /// Bins of the reliability diagrams.
+    pub bins: usize,
+    /// Fit a calibrator of this kind to the factor predictions and store it for the
+    /// namespace. `None` only evaluates, keeping any stored calibrator.
+    pub method: Option<CalibrationMethod>,
+    /// Share of the labeled test questions, from the front of the test queue, that a
+    /// calibrator is fitted on. The rest are evaluated. Unused without a `method`.
+    pub fit_fraction: f64,
+    /// Also evaluate the marginals of full inference, which is slower.
+    pub marginals: bool,
+    /// Forward/backward passes per inference run.
+    pub passes: usize,
+    /// Settings of the models the stored weights are evaluated with.
+    pub model: ExponentialTrainingConfig,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        CalibrationConfig {
+            bins: 10,
+            method: None,
+            fit_fraction: 0.5,
+            marginals: true,
+            passes: 10,
+            model: ExponentialTrainingConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CalibrationReport {
+    /// `FactorModel::predict` of each labeled test question from its observed premises.
+    pub factor: CalibrationMetrics,
+    /// Marginal of each labeled test question from inference with its own value hidden.
+    pub marginals: Option<CalibrationMetrics>,
+    /// The calibrator fitted and stored, if a method was configured.
+    pub calibrator: Option<Calibrator>,
+    /// Factor predictions the calibrator was fitted on.
+    pub fit_examples: usize,
+    /// `factor` and `marginals` again, with the fitted calibrator applied.
+    pub calibrated_factor: Option<CalibrationMetrics>,
+    pub calibrated_marginals: Option<CalibrationMetrics>,
+}
+
+impl CalibrationReport {
+    pub fn render_text(&self) -> String {
+        let mut text = format!("factor predictions: {}", self.factor.render_text());
+        if let Some(marginals) = &self.marginals {
+            text.push_str(&format!("inference marginals: {}", marginals.render_text()));
+        }
+        if let Some(calibrator) = &self.calibrator {
+            text.push_str(&format!("fitted {:?} on {} examples\n", calibrator, self.fit_examples));
+        }
+        if let Some(factor) = &self.calibrated_factor {
+            text.push_str(&format!("calibrated factor predictions: {}", factor.render_text()));
+        }
+        if let Some(marginals) = &self.calibrated_marginals {
+            text.push_str(&format!("calibrated inference marginals: {}", marginals.render_text()));
+        }
+        text
+    }
+}
+
+/// `(predicted, gold)` for the boolean examples. Categorical ones are skipped.
+pub fn factor_predictions(
+    connection: &mut Connection,
+    factor_model: &dyn FactorModel,
+    examples: &[TrainingExample],
+) -> Result<Vec<(f64, f64)>, Box<dyn Error>> {
+    let mut predictions = vec![];
+    for example in examples {
+        if let TrainingExample::Binary(factor, gold) = example {
+            predictions.push((factor_model.predict_conclusion(connection, factor)?.probability, *gold));
+        }
+    }
+    Ok(predictions)
+}
+
+/// `(marginal, gold)` for the questions with an observed value, each inferred with
+/// `model` on its own proposition graph with that value hidden.
+pub fn marginal_predictions(
+    connection: &mut Connection,
+    model: &Arc<InferenceModel>,
+    namespace: &str,
+    questions: &[Proposition],
+    passes: usize,
+) -> Result<Vec<(f64, f64)>, Box<dyn Error>> {
+    let observed: Arc<dyn BeliefTable> = Arc::from(RedisBeliefTable::new_mutable(namespace.to_string())?);
+    let mut predictions = vec![];
+    for question in questions {
+        let Some(gold) = observed.get_proposition_probability(connection, question)? else {
+            continue;
+        };
+        let proposition_graph = PropositionGraph::new_shared(connection, &model.graph, question.clone())?;
+        let hidden = MaskedBeliefTable::new(observed.clone(), std::slice::from_ref(question));
+        let table = run_inference(connection, model, &proposition_graph, hidden, passes)?;
+        predictions.push((table.get_marginal(question).unwrap_or(0.5), gold));
+    }
+    Ok(predictions)
+}
+
+fn marginal_metrics(
+    connection: &mut Connection,
+    model: &Arc<InferenceModel>,
+    namespace: &str,
+    questions: &[Proposition],
+    config: &CalibrationConfig,
+) -> Result<Option<CalibrationMetrics>, Box<dyn Error>> {
+    if !config.marginals {
+        return Ok(None);
+    }
+    let predictions = marginal_predictions(connection, model, namespace, questions, config.passes)?;
+    Ok(Some(CalibrationMetrics::from_predictions(&predictions, config.bins)))
+}
+
+/// The stored weights with `calibrator` applied, ignoring any stored calibrator.
+fn calibrated_model(
+    namespace: &str,
+    config: &CalibrationConfig,
+    calibrator: Option<Calibrator>,
+) -> Result<Arc<InferenceModel>, Box<dyn Error>> {
+    let options = ExponentialModelOptions {
+        training: config.model,
+        calibrator: calibrator.into(),
+        ..ExponentialModelOptions::default()
+    };
+    InferenceModel::new_with_options(namespace.to_string(), options)
+}
+
+/// Evaluates the calibration of the stored weights on the labeled test questions,
+/// without any stored calibrator. If `config.method` is set, a new calibrator is fitted
+/// on the first `config.fit_fraction` of the questions and evaluated, before and after,
+/// on the rest; it is only stored once everything has succeeded.
+pub fn do_calibration(
+    resources: &ResourceContext,
+    namespace: String,
+    config: &CalibrationConfig,
+) -> Result<CalibrationReport, Box<dyn Error>> {
+    let mut connection = resources.connection.lock().unwrap();
+    let connection = &mut *connection;
+    let proposition_db = RedisBeliefTable::new_mutable(namespace.clone())?;
+    let plan = TrainingPlan::new(namespace.clone())?;
+    let questions = plan.get_test_questions(connection)?;
+    let (fit_questions, questions) = match config.method {
+        Some(_) => {
+            let fit = (questions.len() as f64 * config.fit_fraction.clamp(0f64, 1f64)).round() as usize;
+            if fit == 0 || fit == questions.len() {
+                return Err(format!(
+                    "Cannot split {} test questions into fitting and evaluation sets with fit_fraction {}",
+                    questions.len(),
+                    config.fit_fraction
+                )
+                .into());
+            }
+            questions.split_at(fit)
+        }
+        None => questions.split_at(0),
+    };
+    let uncalibrated = calibrated_model(&namespace, config, None)?;
+    let examples = extract_test_examples(connection, proposition_db.as_ref(), &uncalibrated.graph, questions)?;
+    let predictions = factor_predictions(connection, uncalibrated.model.as_ref(), &examples)?;
+    let mut report = CalibrationReport {
+        factor: CalibrationMetrics::from_predictions(&predictions, config.bins),
+        marginals: marginal_metrics(connection, &uncalibrated, &namespace, questions, config)?,
+        ..CalibrationReport::default()
+    };
+    let Some(method) = config.method else {
+        return Ok(report);
+    };
+    let fit_examples = extract_test_examples(connection, proposition_db.as_ref(), &uncalibrated.graph, fit_questions)?;
+    let fit_predictions = factor_predictions(connection, uncalibrated.model.as_ref(), &fit_examples)?;
+    let calibrator = method.fit(&fit_predictions);
+    info!("do_calibration - Fitted {:?} on {} predictions", calibrator, fit_predictions.len());
+    let calibrated = calibrated_model(&namespace, config, Some(calibrator.clone()))?;
+    let calibrated_predictions = factor_predictions(connection, calibrated.model.as_ref(), &examples)?;
+    report.calibrated_factor = Some(CalibrationMetrics::from_predictions(&calibrated_predictions, config.bins));
+    report.calibrated_marginals = marginal_metrics(connection, &calibrated, &namespace, questions, config)?;
+    Calibrator::store(connection, &namespace, Some(&calibrator))?;
+    report.fit_examples = fit_predictions.len();
+    report.calibrator = Some(calibrator);
+    Ok(report)
+}
@@ -0,0 +1,209 @@
+use crate::qbbn::{
+    common::{
+        interface::BeliefTable,
+        model::InferenceModel,
+        proposition_db::{OverlayBeliefTable, RedisBeliefTable},
+    },
+    inference::{
+        engine::{Inferencer, MarginalTable},
+        graph::PropositionGraph,
+    },
+    model::{
+        calibrator::CalibratorUpdate,
+        config::ExponentialTrainingConfig,
+        exponential::ExponentialModelOptions,
+        objects::Proposition,
+    },
+};
+use crate::qbbn::common::redis::MockConnection as Connection;
+use log::{info, trace};
+use std::error::Error;
+use std::sync::Arc;
+
+use super::graph::InferenceGraph;
+use super::resources::ResourceContext;
+use super::train::{
+    evaluate_examples, extract_question_example, open_training_model, train_on_example, TrainingExample,
+    TrainingPlan,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct EmConfig {
+    pub max_iterations: usize,
+    /// Stop once the mean log-likelihood changes by less than this between iterations.
+    pub tolerance: f64,
+    /// Forward/backward passes per inference run in the E-step.
+    pub passes: usize,
+    /// Passes over the expected examples in each M-step.
+    pub epochs_per_iteration: usize,
+    pub model: ExponentialTrainingConfig,
+}
+
+impl Default for EmConfig {
+    fn default() -> Self {
+        EmConfig {
+            max_iterations: 20,
+            tolerance: 1e-4,
+            passes: 10,
+            epochs_per_iteration: 1,
+            model: ExponentialTrainingConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EmIteration {
+    pub iteration: usize,
+    /// Mean log-likelihood of the observed training questions, each predicted by the
+    /// model from the expected values of its premises. Computed before this iteration's
+    /// M-step.
+    pub log_likelihood: f64,
+    /// Latent propositions filled in with their expected values.
+    pub expected_values: usize,
+    /// Training questions whose value is latent.
+    pub latent_questions: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EmHistory {
+    pub iterations: Vec<EmIteration>,
+    pub converged: bool,
+}
+
+pub(crate) fn run_inference(
+    connection: &mut Connection,
+    model: &Arc<InferenceModel>,
+    proposition_graph: &Arc<PropositionGraph>,
+    fact_memory: Arc<dyn BeliefTable>,
+    passes: usize,
+) -> Result<MarginalTable, Box<dyn Error>> {
+    let mut inferencer = Inferencer::new_mutable(model.clone(), proposition_graph.clone(), fact_memory)?;
+    inferencer.initialize_chart(connection)?;
+    for _ in 0..passes {
+        inferencer.do_full_forward_and_backward(connection)?;
+    }
+    inferencer.build_marginal_table()
+}
+
+struct Expectation {
+    examples: Vec<TrainingExample>,
+    log_likelihood: f64,
+    observed_questions: usize,
+    expected_values: usize,
+}
+
+/// The E-step. Every training question is answered by one inference run on its own
+/// proposition graph with the stored weights, and each unobserved proposition takes its
+/// marginal given the evidence in the first graph that reaches it. Observed questions go
+/// first, since their graphs also condition on the question itself. The examples are then
+/// extracted against those expected values, and the log-likelihood is that of the
+/// observed examples under the same weights. No calibrator is applied, so that the
+/// log-likelihood compares the weights alone across iterations.
+fn expectation(
+    connection: &mut Connection,
+    namespace: &str,
+    graph: &InferenceGraph,
+    questions: &[Proposition],
+    passes: usize,
+) -> Result<Expectation, Box<dyn Error>> {
+    let options = ExponentialModelOptions {
+        calibrator: CalibratorUpdate::Clear,
+        ..ExponentialModelOptions::default()
+    };
+    let model = InferenceModel::new_with_options(namespace.to_string(), options)?;
+    let observed: Arc<dyn BeliefTable> = Arc::from(RedisBeliefTable::new_mutable(namespace.to_string())?);
+    let expected = OverlayBeliefTable::new(observed.clone());
+    let mut ordered = vec![];
+    let mut latent = vec![];
+    for question in questions {
+        match observed.get_proposition_probability(connection, question)? {
+            Some(_) => ordered.push(question),
+            None => latent.push(question),
+        }
+    }
+    let observed_questions = ordered.len();
+    ordered.extend(latent);
+    let mut expected_values = 0;
+    for question in &ordered {
+        let proposition_graph = PropositionGraph::new_shared(connection, &model.graph, (*question).clone())?;
+        let table = run_inference(connection, &model, &proposition_graph, observed.clone(), passes)?;
+        for node in proposition_graph.get_bfs_order() {
+            if !node.is_single() {
+                continue;
+            }
+            let proposition = node.extract_single();
+            if expected.get_proposition_probability(connection, &proposition)?.is_some() {
+                continue;
+            }
+            if let Some(marginal) = table.get_marginal(&proposition) {
+                trace!("expectation - {:?}: {}", &proposition, marginal);
+                expected.store_proposition_probability(connection, &proposition, marginal)?;
+                expected_values += 1;
+            }
+        }
+    }
+    let mut examples = vec![];
+    for question in &ordered[..observed_questions] {
+        let (_, example) = extract_question_example(connection, expected.as_ref(), graph, question)?;
+        examples.extend(example);
+    }
+    let metrics = evaluate_examples(connection, model.model.as_ref(), &examples)?;
+    for question in &ordered[observed_questions..] {
+        let (_, example) = extract_question_example(connection, expected.as_ref(), graph, question)?;
+        examples.extend(example);
+    }
+    Ok(Expectation {
+        examples,
+        log_likelihood: -metrics.log_loss,
+        observed_questions,
+        expected_values,
+    })
+}
+
+/// Expectation-maximization over the training questions, for plans whose premises or
+/// conclusions are partly latent. Each iteration fills in the unobserved propositions
+/// with their marginals under the stored weights, trains on the resulting soft examples,
+/// and stores the new weights, until the log-likelihood of the observed questions
+/// converges.
+pub fn do_em_training(
+    resources: &ResourceContext,
+    namespace: String,
+    config: &EmConfig,
+) -> Result<EmHistory, Box<dyn Error>> {
+    let mut connection = resources.connection.lock().unwrap();
+    let graph = InferenceGraph::new_mutable(namespace.clone())?;
+    let plan = TrainingPlan::new(namespace.clone())?;
+    let training = ExponentialTrainingConfig {
+        seed: config.model.seed.or(resources.config.seed),
+        ..config.model
+    };
+    let mut factor_model = open_training_model(&mut connection, &graph, &namespace, training)?;
+    let questions = plan.get_training_questions(&mut connection)?;
+    let mut history = EmHistory::default();
+    let mut previous: Option<f64> = None;
+    for iteration in 0..config.max_iterations {
+        let expectation = expectation(&mut connection, &namespace, &graph, &questions, config.passes)?;
+        let log_likelihood = expectation.log_likelihood;
+        let report = EmIteration {
+            iteration,
+            log_likelihood,
+            expected_values: expectation.expected_values,
+            latent_questions: questions.len() - expectation.observed_questions,
+        };
+        info!("do_em_training - {:?}", &report);
+        history.iterations.push(report);
+        if previous.is_some_and(|previous| (log_likelihood - previous).abs() < config.tolerance) {
+            history.converged = true;
+            break;
+        }
+        previous = Some(log_likelihood);
+        for _ in 0..config.epochs_per_iteration {
+            for example in &expectation.examples {
+                train_on_example(&mut connection, factor_model.as_mut(), example)?;
+            }
+        }
+        // The next E-step reads the stored weights.
+        factor_model.flush_weights(&mut connection)?;
+    }
+    Ok(history)
+}
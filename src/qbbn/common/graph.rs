@@ -1,10 +1,11 @@
 use super::redis::set_value;
 use crate::qbbn::{
-    common::redis::{get_value, is_member, set_add, set_members},
+    common::redis::{get_value, is_member, map_get, map_insert, set_add, set_members, set_remove},
     model::{
         choose::extract_existence_factor_for_proposition,
         objects::{
-            Entity, ImplicationFactor, Predicate, Proposition, Relation,
+            Argument, BuiltinPredicate, CategoricalRelation, Entity, ImplicationFactor,
+            NumericComparison, Predicate, Proposition, PropositionChoice, Relation,
         },
     },
 };
@@ -12,9 +13,13 @@ use log::trace;
 use crate::qbbn::common::redis::MockConnection as Connection;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashSet,
     error::Error,
     sync::{Arc, Mutex},
 };
+/// A numeric comparison and the attribute value it tests, if the entity has one.
+pub type TestedComparison = (NumericComparison, Option<f64>);
+
 pub struct InferenceGraph {
     pub namespace: String,
 }
@@ -94,6 +99,73 @@ impl InferenceGraph {
             .collect()
     }
 
+    /// Declares a relation multi-valued in one of its roles. See `CategoricalRelation`.
+    pub fn register_categorical(
+        &mut self,
+        connection: &mut Connection,
+        categorical: &CategoricalRelation,
+    ) -> Result<(), Box<dyn Error>> {
+        let relation = self
+            .get_all_relations(connection)?
+            .into_iter()
+            .find(|relation| relation.relation_name == categorical.relation_name)
+            .ok_or_else(|| format!("Relation {} is not registered.", categorical.relation_name))?;
+        if categorical.outcomes.len() < 2 {
+            return Err(format!(
+                "Categorical relation {} needs at least two outcomes.",
+                relation.relation_name
+            )
+            .into());
+        }
+        let mut distinct = categorical.outcomes.clone();
+        distinct.sort();
+        distinct.dedup();
+        if distinct.len() != categorical.outcomes.len() {
+            return Err(format!("Categorical relation {} repeats an outcome.", relation.relation_name).into());
+        }
+        // Registering a relation again replaces its outcomes.
+        let index = Self::categorical_index_name();
+        if let Some(previous) = map_get(connection, &self.namespace, &index, &relation.relation_name)? {
+            set_remove(connection, &self.namespace, &Self::categorical_set_name(), &previous)?;
+        }
+        let record = serialize_record(categorical)?;
+        set_add(
+            connection,
+            &self.namespace,
+            &Self::categorical_set_name(),
+            &record,
+        )?;
+        map_insert(connection, &self.namespace, &index, &relation.relation_name, &record)?;
+        Ok(())
+    }
+
+    pub fn get_all_categoricals(
+        &self,
+        connection: &mut Connection,
+    ) -> Result<Vec<CategoricalRelation>, Box<dyn Error>> {
+        let set_members: Vec<String> =
+            set_members(connection, &self.namespace, &Self::categorical_set_name())?;
+        set_members
+            .into_iter()
+            .map(|record| serde_json::from_str(&record).map_err(|e| Box::new(e) as Box<dyn Error>))
+            .collect()
+    }
+
+    /// The categorical variable `proposition` is an outcome of, if its relation is categorical.
+    pub fn categorical_choice(
+        &self,
+        connection: &mut Connection,
+        proposition: &Proposition,
+    ) -> Result<Option<PropositionChoice>, Box<dyn Error>> {
+        let relation_name = &proposition.predicate.relation.relation_name;
+        let Some(record) = map_get(connection, &self.namespace, &Self::categorical_index_name(), relation_name)?
+        else {
+            return Ok(None);
+        };
+        let categorical: CategoricalRelation = serde_json::from_str(&record)?;
+        Ok(categorical.choice_for(proposition))
+    }
+
     pub fn register_domain(
         &mut self,
         connection: &mut Connection,
@@ -121,6 +193,81 @@ impl InferenceGraph {
         Ok(result)
     }
 
+    /// Declares every entity of `domain` to be one of `parent` too, for the `in_domain`
+    /// and `subclass_of` builtins.
+    pub fn register_subclass(
+        &mut self,
+        connection: &mut Connection,
+        domain: &str,
+        parent: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check_domain(connection, domain)?;
+        self.check_domain(connection, parent)?;
+        if self.is_subclass_of(connection, parent, domain)? {
+            return Err(format!("{} is already a subclass of {}.", parent, domain).into());
+        }
+        set_add(connection, &self.namespace, &Self::superclass_set_name(domain), parent)?;
+        Ok(())
+    }
+
+    /// Whether `domain` is `ancestor` or, transitively, a subclass of it.
+    pub fn is_subclass_of(
+        &self,
+        connection: &mut Connection,
+        domain: &str,
+        ancestor: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut frontier = vec![domain.to_string()];
+        let mut seen = HashSet::new();
+        while let Some(current) = frontier.pop() {
+            if current == ancestor {
+                return Ok(true);
+            }
+            if seen.insert(current.clone()) {
+                frontier.extend(set_members(connection, &self.namespace, &Self::superclass_set_name(&current))?);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Decides `builtin` for the arguments of the grounded premise term `proposition`.
+    pub fn evaluate_builtin(
+        &self,
+        connection: &mut Connection,
+        builtin: &BuiltinPredicate,
+        proposition: &Proposition,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut arguments = vec![];
+        for role in &proposition.predicate.roles {
+            match &role.argument {
+                Argument::Constant(constant) => arguments.push(constant),
+                Argument::Variable(_) => {
+                    return Err(format!("Builtin {:?} needs constant arguments.", proposition).into());
+                }
+            }
+        }
+        if arguments.len() != builtin.arity() {
+            return Err(format!("Builtin {:?} takes {} arguments.", proposition, builtin.arity()).into());
+        }
+        match builtin {
+            BuiltinPredicate::Eq => Ok(arguments[0] == arguments[1]),
+            BuiltinPredicate::Neq => Ok(arguments[0] != arguments[1]),
+            BuiltinPredicate::InDomain(domain) => {
+                for candidate in self.get_all_domains(connection)? {
+                    if is_member(connection, &self.namespace, &candidate, &arguments[0].entity_id)?
+                        && self.is_subclass_of(connection, &candidate, domain)?
+                    {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            BuiltinPredicate::SubclassOf(domain) => {
+                self.is_subclass_of(connection, &arguments[0].domain, domain)
+            }
+        }
+    }
+
     pub fn register_target(
         &mut self,
         connection: &mut Connection,
@@ -137,7 +284,8 @@ impl InferenceGraph {
     }
 
     pub fn get_target(&self, connection: &mut Connection) -> Result<Proposition, Box<dyn Error>> {
-        let record = get_value(connection, &self.namespace, &Self::target_key_name())?.unwrap();
+        let record = get_value(connection, &self.namespace, &Self::target_key_name())?
+            .ok_or("No target has been stored.")?;
         serde_json::from_str(&record).map_err(|e| Box::new(e) as Box<dyn Error>)
     }
 
@@ -178,6 +326,74 @@ impl InferenceGraph {
             .collect())
     }
 
+    /// Gives `entity` the value `value` for the numeric attribute `attribute`.
+    pub fn store_numeric_attribute(
+        &mut self,
+        connection: &mut Connection,
+        entity: &Entity,
+        attribute: &str,
+        value: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        if !value.is_finite() {
+            return Err(format!("Attribute {} of {} must be finite, got {}.", attribute, entity.name, value).into());
+        }
+        self.check_domain(connection, &entity.domain)?;
+        map_insert(
+            connection,
+            &self.namespace,
+            &Self::numeric_attribute_map_name(attribute),
+            &Self::entity_field_name(entity),
+            &value.to_string(),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_numeric_attribute(
+        &self,
+        connection: &mut Connection,
+        entity: &Entity,
+        attribute: &str,
+    ) -> Result<Option<f64>, Box<dyn Error>> {
+        let record = map_get(
+            connection,
+            &self.namespace,
+            &Self::numeric_attribute_map_name(attribute),
+            &Self::entity_field_name(entity),
+        )?;
+        match record {
+            Some(record) => Ok(Some(record.parse::<f64>()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The comparison `proposition` makes and the value it tests, if it is a numeric
+    /// comparison.
+    pub fn numeric_comparison(
+        &self,
+        connection: &mut Connection,
+        proposition: &Proposition,
+    ) -> Result<Option<TestedComparison>, Box<dyn Error>> {
+        match NumericComparison::from_proposition(proposition) {
+            Some((comparison, entity)) => {
+                let value = self.get_numeric_attribute(connection, &entity, &comparison.attribute)?;
+                Ok(Some((comparison, value)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn superclass_set_name(domain: &str) -> String {
+        format!("superclasses:{}", domain)
+    }
+
+    fn numeric_attribute_map_name(attribute: &str) -> String {
+        format!("numeric_attribute:{}", attribute)
+    }
+
+    fn entity_field_name(entity: &Entity) -> String {
+        format!("{}:{}", entity.domain, entity.name)
+    }
+
     fn predicate_backward_set_name(predicate: &Predicate) -> String {
         format!("predicate_backward:{}", predicate.hash_string())
     }
@@ -190,6 +406,15 @@ impl InferenceGraph {
         "relations".to_string()
     }
 
+    fn categorical_set_name() -> String {
+        "categoricals".to_string()
+    }
+
+    /// Each categorical relation's record, by relation name.
+    fn categorical_index_name() -> String {
+        "categorical_index".to_string()
+    }
+
     fn experiment_set_name() -> String {
         "experiments".to_string()
     }
@@ -245,6 +470,21 @@ impl InferenceGraph {
         connection: &mut Connection,
         implication: &ImplicationFactor,
     ) -> Result<(), Box<dyn Error>> {
+        let mut arity = 0;
+        for term in &implication.premise.terms {
+            match BuiltinPredicate::from_predicate(term) {
+                Some(builtin) if builtin.arity() != term.roles.len() => {
+                    return Err(format!("Builtin {:?} takes {} arguments.", term, builtin.arity()).into());
+                }
+                Some(_) => {}
+                None => arity += 1,
+            }
+        }
+        if arity == 0 {
+            return Err("An implication needs a premise term that is not a builtin.".into());
+        }
+        // Builtins are dropped from grounded premises, so the CPD only sees the rest.
+        implication.cpd.validate(arity)?;
         self.store_implication(connection, implication)?;
         self.store_predicate_backward_link(connection, implication)?;
         Ok(())
@@ -261,6 +501,23 @@ impl InferenceGraph {
         Ok(())
     }
 
+    /// Undoes `store_predicate_implication`. Returns whether `implication` was stored.
+    pub fn remove_predicate_implication(
+        &mut self,
+        connection: &mut Connection,
+        implication: &ImplicationFactor,
+    ) -> Result<bool, Box<dyn Error>> {
+        let record = serialize_record(implication)?;
+        let removed = set_remove(connection, &self.namespace, &Self::implication_seq_name(), &record)?;
+        set_remove(
+            connection,
+            &self.namespace,
+            &Self::predicate_backward_set_name(&implication.conclusion),
+            &record,
+        )?;
+        Ok(removed)
+    }
+
     pub fn get_all_implications(
         &self,
         connection: &mut Connection,
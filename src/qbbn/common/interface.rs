@@ -29,6 +29,13 @@ pub trait BeliefTable: Send + Sync {
         probability: f64,
     ) -> Result<(), Box<dyn Error>>;
 
+    /// Removes any stored probability, so the proposition is no longer observed.
+    fn retract_proposition_probability(
+        &self,
+        context: &mut Connection,
+        proposition: &Proposition,
+    ) -> Result<(), Box<dyn Error>>;
+
     fn store_proposition_boolean(
         &self,
         context: &mut Connection,
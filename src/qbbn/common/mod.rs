@@ -10,6 +10,10 @@ pub mod setup;
 pub mod test;
 pub mod train;
 pub mod batch_train;
+pub mod em_train;
+pub mod rule_mining;
+pub mod calibration;
+pub mod sweep;
 
 // Re-export key types
 pub use interface::BeliefTable;
@@ -1,12 +1,13 @@
 use crate::qbbn::{
     inference::graph::PropositionFactor,
     model::{
-        exponential::ExponentialModel,
-        objects::ImplicationFactor,
+        exponential::{ExponentialModel, ExponentialModelOptions},
+        objects::{ImplicationFactor, Proposition},
+        ModelWeights,
     },
 };
 use crate::qbbn::common::redis::MockConnection as Connection;
-use std::{error::Error, sync::Arc};
+use std::{collections::HashMap, error::Error, sync::Arc};
 
 use super::{
     graph::InferenceGraph,
@@ -20,16 +21,23 @@ pub struct InferenceModel {
 
 impl InferenceModel {
     pub fn new_shared(namespace: String) -> Result<Arc<Self>, Box<dyn Error>> {
+        Self::new_with_options(namespace, ExponentialModelOptions::default())
+    }
+
+    /// Like `new_shared`, with the `ExponentialModel` built from `options`.
+    pub fn new_with_options(namespace: String, options: ExponentialModelOptions) -> Result<Arc<Self>, Box<dyn Error>> {
         let graph = InferenceGraph::new_shared(namespace.clone())?;
-        let model = ExponentialModel::new_shared(namespace.clone())?;
+        let model = Arc::from(ExponentialModel::new_with_options(namespace, options)?);
         Ok(Arc::new(InferenceModel { graph, model }))
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FactorContext {
     pub factor: Vec<PropositionFactor>,
     pub probabilities: Vec<f64>,
+    /// Attribute values behind the numeric comparisons among the premise terms.
+    pub numeric_values: HashMap<Proposition, f64>,
 }
 
 /// FactorModel defines a trait for models that can predict probabilities for factors
@@ -53,7 +61,102 @@ pub trait FactorModel: Send + Sync {
         connection: &mut Connection,
         factor: &FactorContext,
     ) -> Result<PredictStatistics, Box<dyn Error>>;
+
+    /// `predict` for the factor of a conclusion from its premises, with any post-hoc
+    /// calibration of the model applied. AND gates are scored with `predict`.
+    fn predict_conclusion(
+        &self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+    ) -> Result<PredictStatistics, Box<dyn Error>> {
+        self.predict(connection, factor)
+    }
+
+    /// One update from the gradient accumulated over a mini-batch of boolean conclusions,
+    /// reporting the mean loss. By default the examples are trained one at a time.
+    fn train_batch(
+        &mut self,
+        connection: &mut Connection,
+        factors: &[FactorContext],
+        probabilities: &[f64],
+    ) -> Result<TrainStatistics, Box<dyn Error>> {
+        let mut loss = 0f64;
+        for (factor, probability) in factors.iter().zip(probabilities) {
+            loss += self.train(connection, factor, *probability)?.loss;
+        }
+        Ok(TrainStatistics {
+            loss: loss / factors.len().max(1) as f64,
+        })
+    }
     
+    /// A distribution over the `outcomes` values of a categorical conclusion, whose
+    /// `factor` holds the premises of every outcome.
+    fn predict_distribution(
+        &self,
+        _connection: &mut Connection,
+        _factor: &FactorContext,
+        _outcomes: usize,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        Err(format!("Model {} does not support categorical variables", self.model_type()).into())
+    }
+
+    /// Trains a categorical conclusion towards the distribution `gold`.
+    fn train_distribution(
+        &mut self,
+        _connection: &mut Connection,
+        _factor: &FactorContext,
+        _gold: &[f64],
+    ) -> Result<TrainStatistics, Box<dyn Error>> {
+        Err(format!("Model {} does not support categorical variables", self.model_type()).into())
+    }
+
+    /// The weight `predict` uses for `feature`.
+    fn read_weight(&self, _connection: &mut Connection, _feature: &str) -> Result<f64, Box<dyn Error>> {
+        Err(format!("Model {} does not expose its weights", self.model_type()).into())
+    }
+
+    /// Changes whenever the weights `predict` reads from `connection` are written, so
+    /// that cached scores can tell they are stale. Models that never change their scores
+    /// keep it at 0.
+    fn weight_version(&self, _connection: &Connection) -> u64 {
+        0
+    }
+
+    /// A model that predicts with `weights` in place of this model's weights for those
+    /// features. Nothing is written.
+    fn with_weight_overrides(&self, _weights: HashMap<String, f64>) -> Result<Box<dyn FactorModel>, Box<dyn Error>> {
+        Err(format!("Model {} does not expose its weights", self.model_type()).into())
+    }
+
+    /// The features `predict` uses for `factor`, one map per class label.
+    fn factor_features(
+        &self,
+        _connection: &mut Connection,
+        _factor: &FactorContext,
+    ) -> Result<Vec<HashMap<String, f64>>, Box<dyn Error>> {
+        Err(format!("Model {} does not expose its features", self.model_type()).into())
+    }
+
+    /// A copy of the current weights, to roll back to with `restore_weights`.
+    fn snapshot_weights(&self, _connection: &mut Connection) -> Result<ModelWeights, Box<dyn Error>> {
+        Err(format!("Model {} does not support weight snapshots", self.model_type()).into())
+    }
+
+    /// Stores the weights held in memory, so that models reading the namespace's stored
+    /// weights predict with them.
+    fn flush_weights(&mut self, _connection: &mut Connection) -> Result<(), Box<dyn Error>> {
+        Err(format!("Model {} does not support flushing weights", self.model_type()).into())
+    }
+
+    /// Replaces the current weights with a snapshot and stores them.
+    fn restore_weights(
+        &mut self,
+        _connection: &mut Connection,
+        _weights: &ModelWeights,
+    ) -> Result<(), Box<dyn Error>> {
+        Err(format!("Model {} does not support weight snapshots", self.model_type()).into())
+    }
+
     /// Export model weights to a file (optional implementation)
     fn save_to_file(&self, _connection: &mut Connection, _path: &str) -> Result<(), Box<dyn Error>> {
         Err("Model does not support saving to file".into())
@@ -10,9 +10,9 @@ use crate::qbbn::{
 };
 use log::trace;
 use crate::qbbn::common::redis::MockConnection as Connection;
-use std::{collections::HashMap, error::Error, sync::{Arc, Mutex}};
+use std::{collections::{HashMap, HashSet}, error::Error, sync::{Arc, Mutex}};
 
-use super::redis::map_get;
+use super::redis::{map_get, map_remove};
 
 pub struct RedisBeliefTable {
     namespace: String,
@@ -74,6 +74,21 @@ impl BeliefTable for RedisBeliefTable {
         )?;
         Ok(())
     }
+
+    fn retract_proposition_probability(
+        &self,
+        connection: &mut Connection,
+        proposition: &Proposition,
+    ) -> Result<(), Box<dyn Error>> {
+        let hash_string = proposition.predicate.hash_string();
+        map_remove(
+            connection,
+            &self.namespace,
+            Self::PROBABILITIES_KEY,
+            &hash_string,
+        )?;
+        Ok(())
+    }
 }
 
 pub struct EmptyBeliefTable;
@@ -105,6 +120,14 @@ impl BeliefTable for EmptyBeliefTable {
     ) -> Result<(), Box<dyn Error>> {
         panic!("Shouldn't call this.")
     }
+
+    fn retract_proposition_probability(
+        &self,
+        _connection: &mut Connection,
+        _proposition: &Proposition,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
 }
 
 /// HashMapBeliefTable provides thread-safe storage for proposition probabilities
@@ -161,4 +184,122 @@ impl BeliefTable for HashMapBeliefTable {
             Err("Could not acquire lock for HashMapBeliefTable".into())
         }
     }
+
+    fn retract_proposition_probability(
+        &self,
+        _connection: &mut Connection,
+        proposition: &Proposition,
+    ) -> Result<(), Box<dyn Error>> {
+        self.clear(&PropositionNode::from_single(proposition));
+        Ok(())
+    }
+}
+
+/// OverlayBeliefTable layers extra evidence over another table without modifying it.
+/// Reads check the overlay first; stores and retractions only ever touch the overlay.
+pub struct OverlayBeliefTable {
+    base: Arc<dyn BeliefTable>,
+    overlay: Mutex<HashMap<PropositionNode, f64>>,
+}
+
+impl OverlayBeliefTable {
+    pub fn new(base: Arc<dyn BeliefTable>) -> Arc<OverlayBeliefTable> {
+        Arc::new(OverlayBeliefTable {
+            base,
+            overlay: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+impl BeliefTable for OverlayBeliefTable {
+    fn get_proposition_probability(
+        &self,
+        connection: &mut Connection,
+        proposition: &Proposition,
+    ) -> Result<Option<f64>, Box<dyn Error>> {
+        let node = PropositionNode::from_single(proposition);
+        let overridden = match self.overlay.lock() {
+            Ok(map) => map.get(&node).copied(),
+            Err(_) => return Err("Could not acquire lock for OverlayBeliefTable".into()),
+        };
+        match overridden {
+            Some(probability) => Ok(Some(probability)),
+            None => self.base.get_proposition_probability(connection, proposition),
+        }
+    }
+
+    fn store_proposition_probability(
+        &self,
+        _connection: &mut Connection,
+        proposition: &Proposition,
+        probability: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        let node = PropositionNode::from_single(proposition);
+        if let Ok(mut map) = self.overlay.lock() {
+            map.insert(node, probability);
+            Ok(())
+        } else {
+            Err("Could not acquire lock for OverlayBeliefTable".into())
+        }
+    }
+
+    fn retract_proposition_probability(
+        &self,
+        _connection: &mut Connection,
+        proposition: &Proposition,
+    ) -> Result<(), Box<dyn Error>> {
+        let node = PropositionNode::from_single(proposition);
+        if let Ok(mut map) = self.overlay.lock() {
+            map.remove(&node);
+            Ok(())
+        } else {
+            Err("Could not acquire lock for OverlayBeliefTable".into())
+        }
+    }
+}
+
+/// MaskedBeliefTable reads another table as if some of its propositions were unobserved.
+/// It is read-only.
+pub struct MaskedBeliefTable {
+    base: Arc<dyn BeliefTable>,
+    hidden: HashSet<PropositionNode>,
+}
+
+impl MaskedBeliefTable {
+    pub fn new(base: Arc<dyn BeliefTable>, hidden: &[Proposition]) -> Arc<MaskedBeliefTable> {
+        Arc::new(MaskedBeliefTable {
+            base,
+            hidden: hidden.iter().map(PropositionNode::from_single).collect(),
+        })
+    }
+}
+
+impl BeliefTable for MaskedBeliefTable {
+    fn get_proposition_probability(
+        &self,
+        connection: &mut Connection,
+        proposition: &Proposition,
+    ) -> Result<Option<f64>, Box<dyn Error>> {
+        if self.hidden.contains(&PropositionNode::from_single(proposition)) {
+            return Ok(None);
+        }
+        self.base.get_proposition_probability(connection, proposition)
+    }
+
+    fn store_proposition_probability(
+        &self,
+        _connection: &mut Connection,
+        _proposition: &Proposition,
+        _probability: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        Err("MaskedBeliefTable is read-only".into())
+    }
+
+    fn retract_proposition_probability(
+        &self,
+        _connection: &mut Connection,
+        _proposition: &Proposition,
+    ) -> Result<(), Box<dyn Error>> {
+        Err("MaskedBeliefTable is read-only".into())
+    }
 }
@@ -66,10 +66,23 @@ pub fn map_get(
     conn.adapter.map_get(namespace, key, field)
 }
 
+pub fn map_remove(
+    conn: &mut MockConnection,
+    namespace: &str,
+    key: &str,
+    field: &str,
+) -> Result<bool, Box<dyn Error>> {
+    conn.adapter.map_remove(namespace, key, field)
+}
+
 pub fn set_add(conn: &mut MockConnection, namespace: &str, key: &str, member: &str) -> Result<bool, Box<dyn Error>> {
     conn.adapter.set_add(namespace, key, member)
 }
 
+pub fn set_remove(conn: &mut MockConnection, namespace: &str, key: &str, member: &str) -> Result<bool, Box<dyn Error>> {
+    conn.adapter.set_remove(namespace, key, member)
+}
+
 pub fn set_members(conn: &mut MockConnection, namespace: &str, key: &str) -> Result<Vec<String>, Box<dyn Error>> {
     conn.adapter.set_members(namespace, key)
 }
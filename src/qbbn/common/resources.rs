@@ -50,6 +50,7 @@ impl ResourceContext {
             marginal_output_file: None,
             storage_type: super::setup::StorageType::InMemory,
             db_path: None,
+            seed: None,
         };
         
         Ok(ResourceContext {
@@ -73,6 +74,7 @@ impl ResourceContext {
             marginal_output_file: None,
             storage_type: super::setup::StorageType::Persistent,
             db_path: Some(path.to_string()),
+            seed: None,
         };
         
         Ok(ResourceContext {
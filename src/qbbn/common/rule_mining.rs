@@ -0,0 +1,492 @@
+use crate::qbbn::{
+    common::interface::BeliefTable,
+    model::{
+        calibrator::CalibratorUpdate,
+        creators::{conjunction, implication, predicate, role, variable},
+        exponential::{ExponentialModel, ExponentialModelOptions},
+        objects::{ImplicationFactor, Predicate, Proposition, Relation, RoleMap},
+    },
+};
+use crate::qbbn::common::redis::MockConnection as Connection;
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use super::graph::InferenceGraph;
+use super::proposition_db::RedisBeliefTable;
+use super::resources::ResourceContext;
+use super::train::{
+    evaluate_examples, extract_test_examples, extract_training_examples, train_on_example,
+    TrainerConfig, TrainingExample, TrainingPlan,
+};
+
+/// Role names by position, as the `sub` and `obj` creators use them. Relations with more
+/// roles than this are left out of the search.
+const POSITIONAL_ROLES: [&str; 2] = ["sub", "obj"];
+
+/// Join variables are named by this and a number, which no positional role starts with.
+const JOIN_VARIABLE_PREFIX: &str = "join";
+
+#[derive(Debug, Clone)]
+pub struct RuleMiningConfig {
+    /// Most terms in a candidate's premise.
+    pub max_premise_terms: usize,
+    /// Most join variables in a candidate: variables shared by premise terms but not in
+    /// the conclusion, like `y` in `parent(x, y) & parent(y, z) -> grandparent(x, z)`.
+    pub max_join_variables: usize,
+    /// Only propose rules concluding relations with these names. `None` searches all.
+    pub conclusions: Option<Vec<String>>,
+    /// A rule has to lower the held-out log-loss by more than this to be reported.
+    pub min_improvement: f64,
+    /// Most rules reported, best first.
+    pub top_k: usize,
+    /// Training of the baseline and of each candidate. Weights are never stored.
+    pub trainer: TrainerConfig,
+    /// Store the reported rules in the graph. Off, this is a dry run.
+    pub insert: bool,
+}
+
+impl Default for RuleMiningConfig {
+    fn default() -> Self {
+        RuleMiningConfig {
+            max_premise_terms: 2,
+            max_join_variables: 1,
+            conclusions: None,
+            min_improvement: 1e-3,
+            top_k: 5,
+            trainer: TrainerConfig {
+                max_epochs: 10,
+                early_stopping_patience: 3,
+                shuffle: false,
+                ..TrainerConfig::default()
+            },
+            insert: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScoredRule {
+    pub rule: ImplicationFactor,
+    /// Best held-out log-loss on the conclusion's test questions without the rule.
+    pub baseline_log_loss: f64,
+    /// The same, with the rule added.
+    pub log_loss: f64,
+    pub held_out_examples: usize,
+}
+
+impl ScoredRule {
+    pub fn improvement(&self) -> f64 {
+        self.baseline_log_loss - self.log_loss
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RuleMiningReport {
+    pub candidates: usize,
+    /// Candidates without labeled test questions, or with a premise that is not observed
+    /// for some question.
+    pub unscorable: usize,
+    /// Largest improvement first.
+    pub rules: Vec<ScoredRule>,
+    /// The rules stored in the graph, in insert mode.
+    pub inserted: Vec<ImplicationFactor>,
+}
+
+impl RuleMiningReport {
+    pub fn render_text(&self) -> String {
+        let mut text = format!(
+            "{} candidates, {} unscorable, {} reported\n",
+            self.candidates,
+            self.unscorable,
+            self.rules.len()
+        );
+        for scored in &self.rules {
+            text.push_str(&format!(
+                "{:+.4} ({:.4} -> {:.4} on {}) {:?}\n",
+                scored.improvement(),
+                scored.baseline_log_loss,
+                scored.log_loss,
+                scored.held_out_examples,
+                scored.rule
+            ));
+        }
+        for rule in &self.inserted {
+            text.push_str(&format!("inserted {:?}\n", rule));
+        }
+        text
+    }
+}
+
+/// The role names and domains of `relation`, or `None` if it has too many roles.
+fn named_roles(relation: &Relation) -> Option<Vec<(String, String)>> {
+    if relation.types.len() > POSITIONAL_ROLES.len() {
+        return None;
+    }
+    Some(
+        POSITIONAL_ROLES
+            .iter()
+            .zip(&relation.types)
+            .map(|(name, argument)| (name.to_string(), argument.domain.clone()))
+            .collect(),
+    )
+}
+
+fn variable_predicate(relation: &Relation, roles: &[(String, String)]) -> Predicate {
+    let roles = roles
+        .iter()
+        .map(|(name, domain)| role(name.clone(), variable(domain.clone())))
+        .collect();
+    predicate(relation.clone(), roles)
+}
+
+/// A premise term's role map, as pairs of a variable and the role it fills.
+type Filling = Vec<(String, String)>;
+
+/// Every way of filling `roles` with distinct variables of their domains: the ones in
+/// `variables`, named and with their domains, or new join variables while fewer than
+/// `max_joins` are in use. Each filling comes with the variables in use after it.
+fn fillings(
+    roles: &[(String, String)],
+    variables: &[(String, String)],
+    max_joins: usize,
+) -> Vec<(Filling, Vec<(String, String)>)> {
+    let Some(((role, domain), rest)) = roles.split_first() else {
+        return vec![(vec![], variables.to_vec())];
+    };
+    let joins = variables
+        .iter()
+        .filter(|(name, _)| name.starts_with(JOIN_VARIABLE_PREFIX))
+        .count();
+    let mut choices: Vec<(String, Vec<(String, String)>)> = variables
+        .iter()
+        .filter(|(_, variable_domain)| variable_domain == domain)
+        .map(|(name, _)| (name.clone(), variables.to_vec()))
+        .collect();
+    if joins < max_joins {
+        let name = format!("{}{}", JOIN_VARIABLE_PREFIX, joins);
+        let mut extended = variables.to_vec();
+        extended.push((name.clone(), domain.clone()));
+        choices.push((name, extended));
+    }
+    let mut result = vec![];
+    for (variable, in_use) in choices {
+        for (mut filling, after) in fillings(rest, &in_use, max_joins) {
+            if filling.iter().any(|(name, _)| *name == variable) {
+                continue;
+            }
+            filling.insert(0, (variable.clone(), role.clone()));
+            result.push((filling, after));
+        }
+    }
+    result
+}
+
+/// Whether every join variable is shared by two or more of `fillings`, and every term
+/// reaches the conclusion through shared variables.
+fn is_connected(fillings: &[Filling], conclusion_roles: &[(String, String)]) -> bool {
+    let mentions = |filling: &Filling, variable: &str| filling.iter().any(|(name, _)| name == variable);
+    let joins: HashSet<&String> = fillings
+        .iter()
+        .flatten()
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with(JOIN_VARIABLE_PREFIX))
+        .collect();
+    if joins
+        .iter()
+        .any(|join| fillings.iter().filter(|filling| mentions(filling, join)).count() < 2)
+    {
+        return false;
+    }
+    let mut reached: HashSet<&String> = conclusion_roles.iter().map(|(name, _)| name).collect();
+    let mut connected = vec![false; fillings.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (index, filling) in fillings.iter().enumerate() {
+            if !connected[index] && filling.iter().any(|(name, _)| reached.contains(name)) {
+                connected[index] = true;
+                reached.extend(filling.iter().map(|(name, _)| name));
+                changed = true;
+            }
+        }
+    }
+    connected.into_iter().all(|connected| connected)
+}
+
+/// The multisets of `k` indices below `n`, so one relation can fill several terms.
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    fn run(start: usize, n: usize, k: usize, current: &mut Vec<usize>, result: &mut Vec<Vec<usize>>) {
+        if current.len() == k {
+            result.push(current.clone());
+            return;
+        }
+        for index in start..n {
+            current.push(index);
+            run(index, n, k, current, result);
+            current.pop();
+        }
+    }
+    let mut result = vec![];
+    run(0, n, k, &mut vec![], &mut result);
+    result
+}
+
+/// Premise relation to conclusion relation, for every implication.
+fn relation_edges(implications: &[ImplicationFactor]) -> HashMap<Relation, HashSet<Relation>> {
+    let mut edges: HashMap<Relation, HashSet<Relation>> = HashMap::new();
+    for implication in implications {
+        for term in &implication.premise.terms {
+            edges
+                .entry(term.relation.clone())
+                .or_default()
+                .insert(implication.conclusion.relation.clone());
+        }
+    }
+    edges
+}
+
+fn reaches(edges: &HashMap<Relation, HashSet<Relation>>, from: &Relation, to: &Relation) -> bool {
+    let mut stack = vec![from];
+    let mut seen = HashSet::new();
+    while let Some(relation) = stack.pop() {
+        if relation == to {
+            return true;
+        }
+        if seen.insert(relation) {
+            stack.extend(edges.get(relation).into_iter().flatten());
+        }
+    }
+    false
+}
+
+/// Whether adding `rule` to `implications` closes a cycle between relations.
+fn closes_cycle(implications: &[ImplicationFactor], rule: &ImplicationFactor) -> bool {
+    let edges = relation_edges(implications);
+    rule.premise
+        .terms
+        .iter()
+        .any(|term| reaches(&edges, &rule.conclusion.relation, &term.relation))
+}
+
+/// Candidate implications over `relations`: conjunctions of up to `max_premise_terms`
+/// other relations, with every premise role filled by a conclusion role of its domain or
+/// by a join variable that links two or more terms. Every term is connected to the
+/// conclusion through the variables. Rules already in `existing`, and rules that would
+/// make the relations cyclic, are left out.
+pub fn candidate_rules(
+    relations: &[Relation],
+    existing: &[ImplicationFactor],
+    config: &RuleMiningConfig,
+) -> Vec<ImplicationFactor> {
+    let mut relations: Vec<&Relation> = relations.iter().collect();
+    relations.sort_by_key(|relation| format!("{:?}", relation));
+    let known: HashSet<String> = existing.iter().map(|rule| rule.unique_key()).collect();
+    let mut result = vec![];
+    for conclusion in &relations {
+        if let Some(names) = &config.conclusions
+            && !names.contains(&conclusion.relation_name)
+        {
+            continue;
+        }
+        let Some(conclusion_roles) = named_roles(conclusion) else {
+            continue;
+        };
+        let premises: Vec<(&Relation, Vec<(String, String)>)> = relations
+            .iter()
+            .filter(|premise| premise != &conclusion)
+            .filter_map(|premise| named_roles(premise).map(|roles| (*premise, roles)))
+            .collect();
+        let conclusion_predicate = variable_predicate(conclusion, &conclusion_roles);
+        // The same terms in another order make the same rule.
+        let mut seen: HashSet<Vec<String>> = HashSet::new();
+        for size in 1..=config.max_premise_terms {
+            for chosen in combinations(premises.len(), size) {
+                // The fillings of the terms so far, with the variables in use after them.
+                let mut partial: Vec<(Vec<Filling>, Vec<(String, String)>)> =
+                    vec![(vec![], conclusion_roles.clone())];
+                for index in &chosen {
+                    let mut extended = vec![];
+                    for (terms, variables) in &partial {
+                        for (filling, after) in fillings(&premises[*index].1, variables, config.max_join_variables) {
+                            let mut terms = terms.clone();
+                            terms.push(filling);
+                            extended.push((terms, after));
+                        }
+                    }
+                    partial = extended;
+                }
+                for (terms, _) in partial {
+                    if !is_connected(&terms, &conclusion_roles) {
+                        continue;
+                    }
+                    let mut key: Vec<String> = chosen
+                        .iter()
+                        .zip(&terms)
+                        .map(|(index, filling)| format!("{}{:?}", premises[*index].0.relation_name, filling))
+                        .collect();
+                    key.sort();
+                    if key.windows(2).any(|pair| pair[0] == pair[1]) || !seen.insert(key) {
+                        continue;
+                    }
+                    let rule = implication(
+                        conjunction(
+                            chosen
+                                .iter()
+                                .map(|index| variable_predicate(premises[*index].0, &premises[*index].1))
+                                .collect(),
+                        ),
+                        conclusion_predicate.clone(),
+                        terms
+                            .into_iter()
+                            .map(|filling| RoleMap::new(filling.into_iter().collect()))
+                            .collect(),
+                    );
+                    if !known.contains(&rule.unique_key()) && !closes_cycle(existing, &rule) {
+                        result.push(rule);
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Trains a fresh `ExponentialModel` on `training` and returns its best held-out log-loss
+/// over the epochs. Only its in-memory deltas change, so nothing is stored.
+fn held_out_log_loss(
+    connection: &mut Connection,
+    namespace: &str,
+    config: &TrainerConfig,
+    training: &[TrainingExample],
+    test: &[TrainingExample],
+) -> Result<f64, Box<dyn Error>> {
+    let options = ExponentialModelOptions {
+        training: config.model,
+        calibrator: CalibratorUpdate::Clear,
+        ..ExponentialModelOptions::default()
+    };
+    let mut factor_model = ExponentialModel::new_with_options(namespace.to_string(), options)?;
+    let mut best = evaluate_examples(connection, factor_model.as_ref(), test)?.log_loss;
+    let mut epochs_without_improvement = 0;
+    for _ in 0..config.max_epochs {
+        for example in training {
+            train_on_example(connection, factor_model.as_mut(), example)?;
+        }
+        let loss = evaluate_examples(connection, factor_model.as_ref(), test)?.log_loss;
+        if loss < best - config.min_delta {
+            best = loss;
+            epochs_without_improvement = 0;
+        } else {
+            epochs_without_improvement += 1;
+            if epochs_without_improvement >= config.early_stopping_patience {
+                break;
+            }
+        }
+    }
+    Ok(best)
+}
+
+type Examples = (Vec<TrainingExample>, Vec<TrainingExample>);
+
+fn extract_examples(
+    connection: &mut Connection,
+    proposition_db: &dyn BeliefTable,
+    graph: &InferenceGraph,
+    training: &[Proposition],
+    test: &[Proposition],
+) -> Result<Examples, Box<dyn Error>> {
+    Ok((
+        extract_training_examples(connection, proposition_db, graph, training)?,
+        extract_test_examples(connection, proposition_db, graph, test)?,
+    ))
+}
+
+/// Searches for implications among the registered relations. Each candidate is stored
+/// in the graph only while the examples of its conclusion's questions are extracted, and
+/// is scored by how much it lowers the held-out log-loss of a model trained on them.
+/// Unless `config.insert` is set, the graph and the weights are left as they were.
+pub fn do_rule_mining(
+    resources: &ResourceContext,
+    namespace: String,
+    config: &RuleMiningConfig,
+) -> Result<RuleMiningReport, Box<dyn Error>> {
+    let mut connection = resources.connection.lock().unwrap();
+    let mut graph = InferenceGraph::new_mutable(namespace.clone())?;
+    let proposition_db = RedisBeliefTable::new_mutable(namespace.clone())?;
+    let plan = TrainingPlan::new(namespace.clone())?;
+    let relations = graph.get_all_relations(&mut connection)?;
+    let mut existing = graph.get_all_implications(&mut connection)?;
+    let candidates = candidate_rules(&relations, &existing, config);
+    let training_questions = plan.get_training_questions(&mut connection)?;
+    let test_questions = plan.get_test_questions(&mut connection)?;
+    let mut report = RuleMiningReport {
+        candidates: candidates.len(),
+        ..RuleMiningReport::default()
+    };
+    let mut baselines: HashMap<Relation, f64> = HashMap::new();
+    for candidate in candidates {
+        let relation = &candidate.conclusion.relation;
+        let about = |questions: &[Proposition]| -> Vec<Proposition> {
+            questions
+                .iter()
+                .filter(|question| &question.predicate.relation == relation)
+                .cloned()
+                .collect()
+        };
+        let training = about(&training_questions);
+        let test = about(&test_questions);
+        let baseline = match baselines.get(relation) {
+            Some(baseline) => *baseline,
+            None => {
+                let (training, test) =
+                    extract_examples(&mut connection, proposition_db.as_ref(), &graph, &training, &test)?;
+                let baseline = if test.is_empty() {
+                    f64::NAN
+                } else {
+                    held_out_log_loss(&mut connection, &namespace, &config.trainer, &training, &test)?
+                };
+                baselines.insert(relation.clone(), baseline);
+                baseline
+            }
+        };
+        if baseline.is_nan() {
+            report.unscorable += 1;
+            continue;
+        }
+        graph.store_predicate_implication(&mut connection, &candidate)?;
+        let extracted = extract_examples(&mut connection, proposition_db.as_ref(), &graph, &training, &test);
+        graph.remove_predicate_implication(&mut connection, &candidate)?;
+        let (training, test) = match extracted {
+            Ok(examples) => examples,
+            Err(e) => {
+                info!("do_rule_mining - Cannot score {:?}: {}", &candidate, e);
+                report.unscorable += 1;
+                continue;
+            }
+        };
+        let log_loss = held_out_log_loss(&mut connection, &namespace, &config.trainer, &training, &test)?;
+        info!("do_rule_mining - {:?}: {} -> {}", &candidate, baseline, log_loss);
+        report.rules.push(ScoredRule {
+            rule: candidate,
+            baseline_log_loss: baseline,
+            log_loss,
+            held_out_examples: test.len(),
+        });
+    }
+    report.rules.retain(|scored| scored.improvement() > config.min_improvement);
+    report.rules.sort_by(|a, b| b.improvement().total_cmp(&a.improvement()));
+    report.rules.truncate(config.top_k);
+    if config.insert {
+        for scored in &report.rules {
+            // Rules scored apart can still form a cycle together.
+            if closes_cycle(&existing, &scored.rule) {
+                continue;
+            }
+            graph.store_predicate_implication(&mut connection, &scored.rule)?;
+            existing.push(scored.rule.clone());
+            report.inserted.push(scored.rule.clone());
+        }
+    }
+    Ok(report)
+}
@@ -1,5 +1,6 @@
 use clap::{Command, Arg, builder::EnumValueParser, ValueEnum};
 use env_logger::{Builder, Env};
+use rand::{rngs::StdRng, SeedableRng};
 use serde::Deserialize;
 use std::{io::Write, path::Path};
 
@@ -27,6 +28,18 @@ pub struct CommandLineOptions {
     pub marginal_output_file: Option<String>,
     pub storage_type: StorageType,
     pub db_path: Option<String>,
+    /// Seeds scenario generation, weight initialization and shuffling. `None` draws
+    /// fresh entropy on every run.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// A generator seeded with `seed`, or from entropy without one.
+pub fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
 }
 
 #[allow(dead_code)]
@@ -106,6 +119,12 @@ pub fn parse_configuration_options() -> CommandLineOptions {
                 .value_name("PATH")
                 .help("Path to SQLite database file (only used with persistent storage)"),
         )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("NUMBER")
+                .help("Seed for reproducible runs (optional)"),
+        )
         .get_matches();
     let entities_per_domain: i32 = matches
         .get_one::<String>("entities_per_domain")
@@ -132,6 +151,10 @@ pub fn parse_configuration_options() -> CommandLineOptions {
         
     // Get the database path if provided (only relevant for persistent storage)
     let db_path = matches.get_one::<String>("db_path").map(|s| s.to_string());
+    let seed: Option<u64> = matches.get_one::<String>("seed").map(|v| {
+        v.parse()
+            .expect("seed needs to be a non-negative integer or omitted")
+    });
 
     CommandLineOptions {
         scenario_name,
@@ -142,5 +165,6 @@ pub fn parse_configuration_options() -> CommandLineOptions {
         marginal_output_file,
         storage_type,
         db_path,
+        seed,
     }
 }
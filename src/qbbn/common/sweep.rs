@@ -0,0 +1,284 @@
+use crate::qbbn::{
+    model::{config::Optimizer, weight_manager::WeightManagerConfig},
+    scenarios::factory::ScenarioMakerFactory,
+};
+use log::{info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+use super::calibration::{do_calibration, CalibrationConfig};
+use super::resources::ResourceContext;
+use super::setup::seeded_rng;
+use super::train::{do_training_epochs_with_weights, TrainerConfig};
+
+/// The values a parameter is searched over.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ParameterSpace {
+    Values(Vec<f64>),
+    /// Sampled uniformly, or log-uniformly with `log`. Only random search can use it.
+    Range {
+        min: f64,
+        max: f64,
+        #[serde(default)]
+        log: bool,
+    },
+}
+
+impl ParameterSpace {
+    /// An error unless there is a value to draw: a non-empty list, or finite bounds with
+    /// `min <= max`, and `min > 0` for a log range.
+    pub fn validate(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        match self {
+            ParameterSpace::Values(values) if values.is_empty() => {
+                Err(format!("No values given for {}.", name).into())
+            }
+            ParameterSpace::Values(_) => Ok(()),
+            ParameterSpace::Range { min, max, .. } if !min.is_finite() || !max.is_finite() => {
+                Err(format!("The range for {} has to have finite bounds.", name).into())
+            }
+            ParameterSpace::Range { min, max, .. } if min > max => {
+                Err(format!("The range for {} has min {} above max {}.", name, min, max).into())
+            }
+            ParameterSpace::Range { min, log: true, .. } if *min <= 0f64 => {
+                Err(format!("A log range for {} has to be positive.", name).into())
+            }
+            ParameterSpace::Range { .. } => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchStrategy {
+    /// Every combination of the parameters' values.
+    Grid,
+    /// `trials` independent draws from the parameters' spaces.
+    Random { trials: usize },
+}
+
+/// A search over training and inference parameters for one scenario. Parameters not
+/// named keep their defaults. The known parameters are
+/// - `entities_per_domain` of the scenario,
+/// - `learning_rate`, `l1`, `l2`, `gradient_clip` and `momentum` of the model,
+/// - `max_epochs`, `early_stopping_patience` and `min_delta` of the trainer,
+/// - `consolidation_threshold`, `max_delta_size`, `hot_feature_threshold` and
+///   `decay_factor` of the `WeightManagerConfig`,
+/// - `passes` of the inference run on the test questions.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SweepSpec {
+    pub scenario: String,
+    pub search: SearchStrategy,
+    /// Seeds every trial's scenario, weights and shuffling, and the random search.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    pub parameters: BTreeMap<String, ParameterSpace>,
+}
+
+impl SweepSpec {
+    /// Reads a TOML spec from a `.toml` file and a JSON spec from any other.
+    pub fn from_file(path: &str) -> Result<SweepSpec, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        let spec: SweepSpec = if path.ends_with(".toml") {
+            toml::from_str(&text)?
+        } else {
+            serde_json::from_str(&text)?
+        };
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// An error if any parameter's space has no value to draw.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for (name, space) in &self.parameters {
+            space.validate(name)?;
+        }
+        Ok(())
+    }
+
+    /// The parameter assignment of every trial, in order.
+    pub fn trials(&self) -> Result<Vec<BTreeMap<String, f64>>, Box<dyn Error>> {
+        self.validate()?;
+        match self.search {
+            SearchStrategy::Grid => {
+                let mut trials = vec![BTreeMap::new()];
+                for (name, space) in &self.parameters {
+                    let ParameterSpace::Values(values) = space else {
+                        return Err(format!("Grid search needs a list of values for {}.", name).into());
+                    };
+                    trials = trials
+                        .into_iter()
+                        .flat_map(|trial| {
+                            values.iter().map(move |value| {
+                                let mut trial = trial.clone();
+                                trial.insert(name.clone(), *value);
+                                trial
+                            })
+                        })
+                        .collect();
+                }
+                Ok(trials)
+            }
+            SearchStrategy::Random { trials } => {
+                let mut rng = seeded_rng(self.seed);
+                let mut result = vec![];
+                for _ in 0..trials {
+                    let mut trial = BTreeMap::new();
+                    for (name, space) in &self.parameters {
+                        let value = match space {
+                            ParameterSpace::Values(values) => values[rng.gen_range(0..values.len())],
+                            ParameterSpace::Range { min, max, log: false } => rng.gen_range(*min..=*max),
+                            ParameterSpace::Range { min, max, log: true } => {
+                                rng.gen_range(min.ln()..=max.ln()).exp()
+                            }
+                        };
+                        trial.insert(name.clone(), value);
+                    }
+                    result.push(trial);
+                }
+                Ok(result)
+            }
+        }
+    }
+}
+
+/// Everything one trial runs with.
+#[derive(Debug, Clone)]
+pub struct TrialConfig {
+    pub entities_per_domain: i32,
+    pub trainer: TrainerConfig,
+    pub weights: WeightManagerConfig,
+    pub passes: usize,
+}
+
+impl Default for TrialConfig {
+    fn default() -> Self {
+        TrialConfig {
+            entities_per_domain: 20,
+            trainer: TrainerConfig::default(),
+            weights: WeightManagerConfig::default(),
+            passes: CalibrationConfig::default().passes,
+        }
+    }
+}
+
+impl TrialConfig {
+    pub fn from_parameters(parameters: &BTreeMap<String, f64>) -> Result<TrialConfig, Box<dyn Error>> {
+        let mut config = TrialConfig::default();
+        for (name, &value) in parameters {
+            match name.as_str() {
+                "entities_per_domain" => config.entities_per_domain = value as i32,
+                "learning_rate" => config.trainer.model.learning_rate = value,
+                "l1" => config.trainer.model.l1 = value,
+                "l2" => config.trainer.model.l2 = value,
+                "gradient_clip" => config.trainer.model.gradient_clip = Some(value),
+                "momentum" => config.trainer.model.optimizer = Optimizer::Sgd { momentum: value },
+                "max_epochs" => config.trainer.max_epochs = value as usize,
+                "early_stopping_patience" => config.trainer.early_stopping_patience = value as usize,
+                "min_delta" => config.trainer.min_delta = value,
+                "consolidation_threshold" => config.weights.consolidation_threshold = value,
+                "max_delta_size" => config.weights.max_delta_size = value as usize,
+                "hot_feature_threshold" => config.weights.hot_feature_threshold = value as u32,
+                "decay_factor" => config.weights.decay_factor = value,
+                "passes" => config.passes = value as usize,
+                _ => return Err(format!("Unknown sweep parameter {}.", name).into()),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// One line of the results file.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TrialResult {
+    pub trial: usize,
+    pub parameters: BTreeMap<String, f64>,
+    pub epochs: usize,
+    pub best_epoch: usize,
+    /// The best epoch's held-out log-loss, or its training loss without labeled test
+    /// questions. Lower is better.
+    pub score: Option<f64>,
+    pub training_loss: Option<f64>,
+    pub held_out_log_loss: Option<f64>,
+    pub held_out_brier: Option<f64>,
+    pub held_out_accuracy: Option<f64>,
+    /// Log-loss and expected calibration error of the inference marginals of the labeled
+    /// test questions.
+    pub marginal_log_loss: Option<f64>,
+    pub marginal_ece: Option<f64>,
+    pub error: Option<String>,
+}
+
+fn fill_trial(result: &mut TrialResult, spec: &SweepSpec) -> Result<(), Box<dyn Error>> {
+    let config = TrialConfig::from_parameters(&result.parameters)?;
+    let scenario_maker = ScenarioMakerFactory::new_shared(&spec.scenario)?;
+    let mut resources = ResourceContext::new_in_memory(&spec.scenario)?;
+    resources.config.entities_per_domain = config.entities_per_domain;
+    resources.config.seed = spec.seed;
+    scenario_maker.setup_scenario(&resources)?;
+    let history =
+        do_training_epochs_with_weights(&resources, spec.scenario.clone(), &config.trainer, config.weights)?;
+    result.epochs = history.epochs.len();
+    result.best_epoch = history.best_epoch;
+    if let Some(best) = history.best() {
+        result.score = Some(best.monitored_loss());
+        result.training_loss = Some(best.training_loss);
+        if let Some(held_out) = best.held_out {
+            result.held_out_log_loss = Some(held_out.log_loss);
+            result.held_out_brier = Some(held_out.brier);
+            result.held_out_accuracy = Some(held_out.accuracy);
+        }
+    }
+    let calibration = CalibrationConfig {
+        passes: config.passes,
+        model: config.trainer.model,
+        ..CalibrationConfig::default()
+    };
+    let report = do_calibration(&resources, spec.scenario.clone(), &calibration)?;
+    if let Some(marginals) = report.marginals.filter(|marginals| marginals.examples > 0) {
+        result.marginal_log_loss = Some(marginals.log_loss);
+        result.marginal_ece = Some(marginals.ece);
+    }
+    Ok(())
+}
+
+/// Sets up the scenario in a fresh in-memory database, trains on it and scores the
+/// result. A failing trial is reported with its error rather than ending the sweep.
+pub fn run_trial(spec: &SweepSpec, trial: usize, parameters: BTreeMap<String, f64>) -> TrialResult {
+    let mut result = TrialResult {
+        trial,
+        parameters,
+        ..TrialResult::default()
+    };
+    if let Err(e) = fill_trial(&mut result, spec) {
+        warn!("run_trial - Trial {} failed: {}", trial, e);
+        result.error = Some(e.to_string());
+    }
+    info!("run_trial - {:?}", &result);
+    result
+}
+
+/// The scored trial with the lowest score.
+pub fn best_trial(results: &[TrialResult]) -> Option<&TrialResult> {
+    results
+        .iter()
+        .filter(|result| result.score.is_some_and(f64::is_finite))
+        .min_by(|a, b| a.score.unwrap().total_cmp(&b.score.unwrap()))
+}
+
+/// Runs every trial of `spec`, handing each result to `record` as it finishes.
+pub fn run_sweep(
+    spec: &SweepSpec,
+    mut record: impl FnMut(&TrialResult) -> Result<(), Box<dyn Error>>,
+) -> Result<Vec<TrialResult>, Box<dyn Error>> {
+    let mut results = vec![];
+    for (trial, parameters) in spec.trials()?.into_iter().enumerate() {
+        let result = run_trial(spec, trial, parameters);
+        record(&result)?;
+        results.push(result);
+    }
+    Ok(results)
+}
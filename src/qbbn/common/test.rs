@@ -9,12 +9,11 @@ use crate::qbbn::{
     inference::{
         graph::PropositionGraph,
         engine::Inferencer,
+        evidence::EvidenceSet,
         table::{PropositionNode},
     },
 };
 
-use super::interface::BeliefTable;
-
 pub struct ReplState {
     pub inferencer: Box<Inferencer>,
     pub fact_memory: Arc<HashMapBeliefTable>,
@@ -36,27 +35,27 @@ impl ReplState {
         }
     }
 
+    /// Observes every `(name, probability)` pair at once and propagates them together.
+    /// Returns the nodes that received evidence.
     pub fn set_pairs_by_name(
         &mut self,
         connection: &mut Connection,
         pairs: &Vec<(&str, f64)>,
-    ) -> Option<PropositionNode> {
-        assert!(pairs.len() <= 1);
-        if let Some(pair) = pairs.iter().next() {
-            let key = pair.0.to_string();
-            trace!("key {key}");
-            let node = self.proposition_index.get(&key).unwrap();
-            let prop = node.extract_single();
-            trace!("setting {} to {}", &key, pair.1);
-            self.fact_memory
-                .store_proposition_probability(connection, &prop, pair.1)
-                .unwrap();
+    ) -> Vec<PropositionNode> {
+        let mut evidence = EvidenceSet::new();
+        let mut nodes = vec![];
+        for (key, probability) in pairs {
+            trace!("setting {} to {}", key, probability);
+            let node = self.proposition_index.get(*key).unwrap();
+            evidence.observe_probability(&node.extract_single(), *probability);
+            nodes.push(node.clone());
+        }
+        if !evidence.is_empty() {
             self.inferencer
-                .do_fan_out_from_node(connection, node)
+                .apply_evidence(connection, &evidence)
                 .unwrap();
-            return Some(node.clone());
         }
-        None
+        nodes
     }
 }
 
@@ -4,24 +4,37 @@ use crate::qbbn::{
         redis::{seq_get_all, seq_push},
     },
     model::{
-        exponential::ExponentialModel,
+        calibrator::CalibratorUpdate,
+        config::ExponentialTrainingConfig,
+        device::TorchConfig,
+        exponential::{ExponentialModel, ExponentialModelOptions},
+        weight_manager::WeightManagerConfig,
         objects::{
+            Negation,
             Proposition,
+            PropositionChoice,
             PropositionGroup,
+            Quantifier,
         },
     },
 };
 use crate::qbbn::common::redis::MockConnection as Connection;
-use log::trace;
+use log::{info, trace};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 
+use super::batch_train::train_on_batch;
 use super::graph::InferenceGraph;
 use super::interface::ScenarioMaker;
-// use super::model::FactorModel;
+use super::model::FactorModel;
 use super::resources::ResourceContext;
 use super::model::FactorContext;
+use super::setup::seeded_rng;
 use crate::qbbn::common::proposition_db::RedisBeliefTable;
+use crate::qbbn::inference::graph::PropositionFactor;
 use crate::qbbn::model::choose::extract_backimplications_from_proposition;
 
 pub struct TrainingPlan {
@@ -136,17 +149,69 @@ where
     serde_json::from_str(record).map_err(|e| Box::new(e) as Box<dyn Error>)
 }
 
+/// The stored probability of `term`, for a numeric comparison whether it holds, and for
+/// an aggregate its CPD applied to its instances.
+pub fn term_probability_for_training(
+    connection: &mut Connection,
+    proposition_db: &dyn BeliefTable,
+    graph: &InferenceGraph,
+    term: &Proposition,
+) -> Result<Option<f64>, Box<dyn Error>> {
+    if let Some((comparison, value)) = graph.numeric_comparison(connection, term)? {
+        return Ok(Some(if comparison.holds(value) { 1f64 } else { 0f64 }));
+    }
+    if let Some((quantifier, _)) = Quantifier::from_relation(&term.predicate.relation) {
+        let mut instances = vec![];
+        for factor in extract_backimplications_from_proposition(connection, graph, term)? {
+            for instance in &factor.premise.terms {
+                let fact = term_probability_for_training(connection, proposition_db, graph, instance)?;
+                instances.push(fact.is_some_and(|probability| probability > 0.5));
+            }
+        }
+        return Ok(quantifier.cpd().probability_given_terms(&instances));
+    }
+    proposition_db.get_proposition_probability(connection, term)
+}
+
+/// The attribute values behind the numeric comparisons among the premises of `factors`.
+pub fn numeric_values_for_training(
+    connection: &mut Connection,
+    graph: &InferenceGraph,
+    factors: &[PropositionFactor],
+) -> Result<HashMap<Proposition, f64>, Box<dyn Error>> {
+    let mut result = HashMap::new();
+    for factor in factors {
+        for term in &factor.premise.terms {
+            if let Some((_, Some(value))) = graph.numeric_comparison(connection, term)? {
+                result.insert(term.clone(), value);
+            }
+        }
+    }
+    Ok(result)
+}
+
 // Probabilities are either 0 or 1, so assume independent, i.e., just boolean combine them as AND.
 fn extract_group_probability_for_training(
     connection: &mut Connection,
     proposition_db: &dyn BeliefTable,
+    graph: &InferenceGraph,
     premise: &PropositionGroup,
 ) -> Result<f64, Box<dyn Error>> {
     let mut product = 1f64;
-    for term in &premise.terms {
-        let part = proposition_db
-            .get_proposition_probability(connection, term)?
-            .unwrap();
+    for (index, term) in premise.terms.iter().enumerate() {
+        let fact = term_probability_for_training(connection, proposition_db, graph, term)?;
+        let observed = || fact.ok_or_else(|| format!("Premise {:?} has no observed value.", term));
+        let part = match premise.negation(index) {
+            None => observed()?,
+            Some(Negation::Probabilistic) => 1f64 - observed()?,
+            Some(Negation::AsFailure) => {
+                if Negation::literal_value(Some(Negation::AsFailure), false, fact) {
+                    1f64
+                } else {
+                    0f64
+                }
+            }
+        };
         product *= part;
     }
     Ok(product)
@@ -161,56 +226,484 @@ fn extract_factor_for_proposition_for_training(
     let factors = extract_backimplications_from_proposition(connection, graph, &conclusion)?;
     let mut probabilities = vec![];
     for factor in &factors {
-        let probability =
-            extract_group_probability_for_training(connection, proposition_db, &factor.premise)?;
+        let probability = extract_group_probability_for_training(
+            connection,
+            proposition_db,
+            graph,
+            &factor.premise,
+        )?;
         probabilities.push(probability);
     }
     let result = FactorContext {
+        numeric_values: numeric_values_for_training(connection, graph, &factors)?,
         factor: factors,
         probabilities,
     };
     Ok(result)
 }
 
-pub fn do_training(resources: &ResourceContext, namespace: String) -> Result<(), Box<dyn Error>> {
+/// A categorical training example: the premises and the gold outcome distribution.
+type ChoiceExample = (FactorContext, Vec<f64>);
+
+/// The premises of every outcome of `choice`, and the observed distribution over its
+/// outcomes, or `None` if no outcome has been observed.
+fn extract_choice_for_training(
+    connection: &mut Connection,
+    proposition_db: &dyn BeliefTable,
+    graph: &InferenceGraph,
+    choice: &PropositionChoice,
+) -> Result<Option<ChoiceExample>, Box<dyn Error>> {
+    let mut factors = vec![];
+    let mut probabilities = vec![];
+    let mut gold = vec![];
+    for outcome in &choice.outcomes {
+        for factor in extract_backimplications_from_proposition(connection, graph, outcome)? {
+            probabilities.push(extract_group_probability_for_training(
+                connection,
+                proposition_db,
+                graph,
+                &factor.premise,
+            )?);
+            factors.push(factor);
+        }
+        gold.push(
+            proposition_db
+                .get_proposition_probability(connection, outcome)?
+                .unwrap_or(0f64),
+        );
+    }
+    let total: f64 = gold.iter().sum();
+    if total <= 0f64 {
+        return Ok(None);
+    }
+    let gold = gold.iter().map(|probability| probability / total).collect();
+    let factor = FactorContext {
+        numeric_values: numeric_values_for_training(connection, graph, &factors)?,
+        factor: factors,
+        probabilities,
+    };
+    Ok(Some((factor, gold)))
+}
+
+/// One training or test question, ready to hand to a `FactorModel`.
+pub enum TrainingExample {
+    /// A boolean conclusion and its gold probability.
+    Binary(FactorContext, f64),
+    /// A categorical variable and the gold distribution over its outcomes.
+    Choice(FactorContext, Vec<f64>),
+}
+
+/// The example behind one question, and the categorical variable the question is an
+/// outcome of. `None` for an unobserved conclusion, a conclusion whose every
+/// backimplication has a built-in CPD, or a categorical variable none of whose outcomes
+/// is observed.
+pub fn extract_question_example(
+    connection: &mut Connection,
+    proposition_db: &dyn BeliefTable,
+    graph: &InferenceGraph,
+    proposition: &Proposition,
+) -> Result<(Option<PropositionChoice>, Option<TrainingExample>), Box<dyn Error>> {
+    trace!("extract_question_example - Processing proposition: {:?}", proposition);
+    if let Some(choice) = graph.categorical_choice(connection, proposition)? {
+        let example = extract_choice_for_training(connection, proposition_db, graph, &choice)?
+            .map(|(factor, gold)| TrainingExample::Choice(factor, gold));
+        return Ok((Some(choice), example));
+    }
+    let factor = extract_factor_for_proposition_for_training(
+        connection,
+        proposition_db,
+        graph,
+        proposition.clone(),
+    )?;
+    trace!("extract_question_example - Backimplications: {:?}", &factor);
+    if !factor.factor.is_empty()
+        && factor.factor.iter().all(|f| !f.inference.cpd.is_learned())
+    {
+        trace!("extract_question_example - Skipping, every backimplication has a built-in CPD");
+        return Ok((None, None));
+    }
+    let Some(probability) = proposition_db.get_proposition_probability(connection, proposition)? else {
+        trace!("extract_question_example - Skipping, the conclusion is unobserved");
+        return Ok((None, None));
+    };
+    Ok((None, Some(TrainingExample::Binary(factor, probability))))
+}
+
+/// The examples behind `questions`. The outcomes of a categorical variable make one
+/// example, and unobserved conclusions and those whose every backimplication has a
+/// built-in CPD are skipped.
+pub fn extract_training_examples(
+    connection: &mut Connection,
+    proposition_db: &dyn BeliefTable,
+    graph: &InferenceGraph,
+    questions: &[Proposition],
+) -> Result<Vec<TrainingExample>, Box<dyn Error>> {
+    let mut examples = vec![];
+    let mut seen_choices = HashSet::new();
+    for proposition in questions {
+        if let Some(choice) = graph.categorical_choice(connection, proposition)?
+            && !seen_choices.insert(choice)
+        {
+            continue;
+        }
+        let (_, example) = extract_question_example(connection, proposition_db, graph, proposition)?;
+        examples.extend(example);
+    }
+    Ok(examples)
+}
+
+/// Whether `proposition` and every premise term of its backimplications have a stored
+/// probability, so that it can be scored without inference.
+fn is_labeled(
+    connection: &mut Connection,
+    proposition_db: &dyn BeliefTable,
+    graph: &InferenceGraph,
+    proposition: &Proposition,
+) -> Result<bool, Box<dyn Error>> {
+    if graph.categorical_choice(connection, proposition)?.is_none()
+        && proposition_db.get_proposition_probability(connection, proposition)?.is_none()
+    {
+        return Ok(false);
+    }
+    for factor in extract_backimplications_from_proposition(connection, graph, proposition)? {
+        for term in &factor.premise.terms {
+            if term_probability_for_training(connection, proposition_db, graph, term)?.is_none() {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// The examples behind the labeled `questions`. A scenario may hide the values of its
+/// test questions from the belief table, and those cannot be scored here.
+pub fn extract_test_examples(
+    connection: &mut Connection,
+    proposition_db: &dyn BeliefTable,
+    graph: &InferenceGraph,
+    questions: &[Proposition],
+) -> Result<Vec<TrainingExample>, Box<dyn Error>> {
+    let mut labeled = vec![];
+    for proposition in questions {
+        if is_labeled(connection, proposition_db, graph, proposition)? {
+            labeled.push(proposition.clone());
+        } else {
+            trace!("extract_test_examples - Skipping unlabeled question: {:?}", proposition);
+        }
+    }
+    extract_training_examples(connection, proposition_db, graph, &labeled)
+}
+
+pub fn train_on_example(
+    connection: &mut Connection,
+    factor_model: &mut dyn FactorModel,
+    example: &TrainingExample,
+) -> Result<f64, Box<dyn Error>> {
+    let stats = match example {
+        TrainingExample::Binary(factor, probability) => factor_model.train(connection, factor, *probability)?,
+        TrainingExample::Choice(factor, gold) => factor_model.train_distribution(connection, factor, gold)?,
+    };
+    Ok(stats.loss)
+}
+
+/// Predicted probabilities are kept this far from 0 and 1 when computing log-loss.
+const EVALUATION_EPSILON: f64 = 1e-9;
+
+/// Held-out metrics, averaged over examples. A categorical example counts once, with
+/// the Brier score summed over its outcomes and accuracy judged by the most likely outcome.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EvaluationMetrics {
+    pub log_loss: f64,
+    pub brier: f64,
+    pub accuracy: f64,
+    pub examples: usize,
+}
+
+fn argmax(values: &[f64]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
+
+pub fn evaluate_examples(
+    connection: &mut Connection,
+    factor_model: &dyn FactorModel,
+    examples: &[TrainingExample],
+) -> Result<EvaluationMetrics, Box<dyn Error>> {
+    let mut metrics = EvaluationMetrics::default();
+    for example in examples {
+        let (predicted, gold) = match example {
+            TrainingExample::Binary(factor, probability) => {
+                let predicted = factor_model.predict_conclusion(connection, factor)?.probability;
+                (vec![1f64 - predicted, predicted], vec![1f64 - probability, *probability])
+            }
+            TrainingExample::Choice(factor, gold) => {
+                (factor_model.predict_distribution(connection, factor, gold.len())?, gold.clone())
+            }
+        };
+        for (p, g) in predicted.iter().zip(&gold) {
+            metrics.log_loss -= g * p.clamp(EVALUATION_EPSILON, 1f64).ln();
+        }
+        metrics.brier += match example {
+            TrainingExample::Binary(..) => (predicted[1] - gold[1]).powi(2),
+            TrainingExample::Choice(..) => predicted.iter().zip(&gold).map(|(p, g)| (p - g).powi(2)).sum(),
+        };
+        if argmax(&predicted) == argmax(&gold) {
+            metrics.accuracy += 1f64;
+        }
+        metrics.examples += 1;
+    }
+    if metrics.examples > 0 {
+        let count = metrics.examples as f64;
+        metrics.log_loss /= count;
+        metrics.brier /= count;
+        metrics.accuracy /= count;
+    }
+    Ok(metrics)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrainerConfig {
+    pub max_epochs: usize,
+    /// Stop after this many epochs without the monitored loss improving.
+    pub early_stopping_patience: usize,
+    /// An epoch has to lower the monitored loss by more than this to count as improving.
+    pub min_delta: f64,
+    /// Shuffle the training examples before every epoch, in orders drawn from `model.seed`.
+    pub shuffle: bool,
+    /// Examples per update. Larger batches are trained with `train_on_batch`.
+    pub batch_size: usize,
+    /// Learning rate schedule, regularization and optimizer of the `ExponentialModel`.
+    pub model: ExponentialTrainingConfig,
+}
+
+impl Default for TrainerConfig {
+    fn default() -> Self {
+        let torch = TorchConfig::default();
+        TrainerConfig {
+            batch_size: 1,
+            model: ExponentialTrainingConfig::default(),
+            ..TrainerConfig::from(&torch)
+        }
+    }
+}
+
+impl From<&TorchConfig> for TrainerConfig {
+    fn from(config: &TorchConfig) -> Self {
+        TrainerConfig {
+            max_epochs: config.max_epochs,
+            early_stopping_patience: config.early_stopping_patience,
+            min_delta: 1e-4,
+            shuffle: true,
+            batch_size: config.batch_size,
+            model: ExponentialTrainingConfig::from(config),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EpochReport {
+    pub epoch: usize,
+    /// Mean loss reported by the model while training on this epoch's examples.
+    pub training_loss: f64,
+    /// Metrics on the test questions after the epoch, `None` if there are none.
+    pub held_out: Option<EvaluationMetrics>,
+}
+
+impl EpochReport {
+    /// Held-out log-loss when there are test questions, training loss otherwise.
+    pub fn monitored_loss(&self) -> f64 {
+        self.held_out.map_or(self.training_loss, |metrics| metrics.log_loss)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TrainingHistory {
+    pub epochs: Vec<EpochReport>,
+    /// The epoch whose weights were kept.
+    pub best_epoch: usize,
+    pub stopped_early: bool,
+}
+
+impl TrainingHistory {
+    /// `None` if no epoch had a finite loss.
+    pub fn best(&self) -> Option<&EpochReport> {
+        self.epochs
+            .iter()
+            .find(|report| report.epoch == self.best_epoch && report.monitored_loss().is_finite())
+    }
+}
+
+/// Trains `factor_model` for up to `config.max_epochs` epochs, evaluating on `test`
+/// after each one, and leaves it with the weights of the best epoch.
+pub fn train_epochs(
+    connection: &mut Connection,
+    factor_model: &mut dyn FactorModel,
+    training: &mut [TrainingExample],
+    test: &[TrainingExample],
+    config: &TrainerConfig,
+) -> Result<TrainingHistory, Box<dyn Error>> {
+    train_epochs_with(connection, factor_model, test, config, |connection, factor_model, rng| {
+        if config.shuffle {
+            training.shuffle(rng);
+        }
+        let mut total_loss = 0f64;
+        for batch in training.chunks(config.batch_size.max(1)) {
+            total_loss += match batch {
+                [example] => train_on_example(connection, factor_model, example)?,
+                _ => train_on_batch(connection, factor_model, batch)? * batch.len() as f64,
+            };
+        }
+        Ok(total_loss / training.len().max(1) as f64)
+    })
+}
+
+/// Like `train_epochs`, with each epoch run by `train_epoch`, which is given the
+/// generator to shuffle with and returns the epoch's mean training loss.
+pub fn train_epochs_with<F>(
+    connection: &mut Connection,
+    factor_model: &mut dyn FactorModel,
+    test: &[TrainingExample],
+    config: &TrainerConfig,
+    mut train_epoch: F,
+) -> Result<TrainingHistory, Box<dyn Error>>
+where
+    F: FnMut(&mut Connection, &mut dyn FactorModel, &mut StdRng) -> Result<f64, Box<dyn Error>>,
+{
+    let mut history = TrainingHistory::default();
+    let mut best = None;
+    let mut epochs_without_improvement = 0;
+    let mut rng = seeded_rng(config.model.seed);
+    for epoch in 0..config.max_epochs {
+        let training_loss = train_epoch(connection, factor_model, &mut rng)?;
+        let report = EpochReport {
+            epoch,
+            training_loss,
+            held_out: if test.is_empty() {
+                None
+            } else {
+                Some(evaluate_examples(connection, factor_model, test)?)
+            },
+        };
+        info!("train_epochs - {:?}", &report);
+        let loss = report.monitored_loss();
+        history.epochs.push(report);
+        // A diverged epoch, with a loss that is not finite, never counts as improving.
+        let improved = match &best {
+            _ if !loss.is_finite() => false,
+            Some((best_loss, _)) => loss < best_loss - config.min_delta,
+            None => true,
+        };
+        if improved {
+            best = Some((loss, factor_model.snapshot_weights(connection)?));
+            history.best_epoch = epoch;
+            epochs_without_improvement = 0;
+        } else {
+            epochs_without_improvement += 1;
+            if epochs_without_improvement >= config.early_stopping_patience {
+                history.stopped_early = true;
+                break;
+            }
+        }
+    }
+    if let Some((_, weights)) = &best {
+        factor_model.restore_weights(connection, weights)?;
+    }
+    Ok(history)
+}
+
+pub fn open_training_model(
+    connection: &mut Connection,
+    graph: &InferenceGraph,
+    namespace: &str,
+    training: ExponentialTrainingConfig,
+) -> Result<Box<dyn FactorModel>, Box<dyn Error>> {
+    open_training_model_with_weights(connection, graph, namespace, training, WeightManagerConfig::default())
+}
+
+/// Like `open_training_model`, with the delta weights consolidated according to `weights`.
+/// Training models apply no calibrator, since one fitted to earlier weights would skew
+/// their held-out evaluation.
+pub fn open_training_model_with_weights(
+    connection: &mut Connection,
+    graph: &InferenceGraph,
+    namespace: &str,
+    training: ExponentialTrainingConfig,
+    weights: WeightManagerConfig,
+) -> Result<Box<dyn FactorModel>, Box<dyn Error>> {
+    let options = ExponentialModelOptions {
+        training,
+        weights,
+        calibrator: CalibratorUpdate::Clear,
+        ..ExponentialModelOptions::default()
+    };
+    let mut factor_model = ExponentialModel::new_with_options(namespace.to_string(), options)?;
+    trace!("open_training_model - Getting all implications");
+    let implications = graph.get_all_implications(connection)?;
+    for implication in implications {
+        print_yellow!("open_training_model - Processing implication: {:?}", implication);
+        factor_model.initialize_connection(connection, &implication)?;
+    }
+    Ok(factor_model)
+}
+
+/// Multi-epoch training one example at a time, with the epochs and early stopping of
+/// `TorchConfig::from_env`.
+pub fn do_training(resources: &ResourceContext, namespace: String) -> Result<TrainingHistory, Box<dyn Error>> {
+    let torch = TorchConfig::from_env();
+    let config = TrainerConfig {
+        max_epochs: torch.max_epochs,
+        early_stopping_patience: torch.early_stopping_patience,
+        ..TrainerConfig::default()
+    };
+    do_training_epochs(resources, namespace, &config)
+}
+
+/// Multi-epoch training on the training questions with held-out evaluation on the labeled
+/// test questions. The weights of the best epoch are stored for the namespace. Without a
+/// seed in `config.model`, the one in the resources' options is used.
+pub fn do_training_epochs(
+    resources: &ResourceContext,
+    namespace: String,
+    config: &TrainerConfig,
+) -> Result<TrainingHistory, Box<dyn Error>> {
+    do_training_epochs_with_weights(resources, namespace, config, WeightManagerConfig::default())
+}
+
+/// Like `do_training_epochs`, with the delta weights consolidated according to `weights`.
+pub fn do_training_epochs_with_weights(
+    resources: &ResourceContext,
+    namespace: String,
+    config: &TrainerConfig,
+    weights: WeightManagerConfig,
+) -> Result<TrainingHistory, Box<dyn Error>> {
     let mut connection = resources.connection.lock().unwrap();
     let graph = InferenceGraph::new_mutable(namespace.clone())?;
     let proposition_db = RedisBeliefTable::new_mutable(namespace.clone())?;
     let plan = TrainingPlan::new(namespace.clone())?;
-    let mut factor_model = ExponentialModel::new_mutable(namespace.clone())?;
-    trace!("do_training - Getting all implications");
-    let implications = graph.get_all_implications(&mut connection)?;
-    for implication in implications {
-        print_yellow!("do_training - Processing implication: {:?}", implication);
-        factor_model.initialize_connection(&mut connection, &implication)?;
-    }
-    trace!("do_training - Getting all propositions");
+    let config = &TrainerConfig {
+        model: ExponentialTrainingConfig {
+            seed: config.model.seed.or(resources.config.seed),
+            ..config.model
+        },
+        ..*config
+    };
+    let mut factor_model =
+        open_training_model_with_weights(&mut connection, &graph, &namespace, config.model, weights)?;
     let training_questions = plan.get_training_questions(&mut connection)?;
+    let test_questions = plan.get_test_questions(&mut connection)?;
+    let mut training =
+        extract_training_examples(&mut connection, proposition_db.as_ref(), &graph, &training_questions)?;
+    let test = extract_test_examples(&mut connection, proposition_db.as_ref(), &graph, &test_questions)?;
     trace!(
-        "do_training - Processing propositions: {}",
-        training_questions.len()
+        "do_training_epochs - {} training examples, {} test examples",
+        training.len(),
+        test.len()
     );
-    let mut examples_processed = 0;
-    for proposition in &training_questions {
-        trace!("do_training - Processing proposition: {:?}", proposition);
-        let factor = extract_factor_for_proposition_for_training(
-            &mut connection,
-            proposition_db.as_ref(),
-            &graph,
-            proposition.clone(),
-        )?;
-        trace!("do_training - Backimplications: {:?}", &factor);
-        let probabiity_opt =
-            proposition_db.get_proposition_probability(&mut connection, proposition)?;
-        let probability = probabiity_opt.expect("Probability should exist.");
-        let _stats = factor_model.train(&mut connection, &factor, probability)?;
-        examples_processed += 1;
-    }
-    trace!(
-        "do_training - Training complete: examples processed {}",
-        examples_processed
-    );
-    Ok(())
+    train_epochs(&mut connection, factor_model.as_mut(), &mut training, &test, config)
 }
 
 pub fn setup_and_train(
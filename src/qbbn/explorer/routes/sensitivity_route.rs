@@ -0,0 +1,27 @@
+use log::warn;
+use rocket::response::content::RawHtml as Html;
+
+use crate::qbbn::{common::resources::ResourceContext, explorer::render_utils::render_app_body, inference::rounds::run_sensitivity};
+
+/// `text` with the characters that are special in HTML escaped.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+pub fn internal_sensitivity(experiment_name: &str, test_scenario: &str, resource_context: &ResourceContext) -> Html<String> {
+    let mut connection = resource_context.connection.lock().unwrap();
+    let mut body_html = "".to_string();
+    body_html += "<div class='marginal_box'>";
+    match run_sensitivity(&mut connection, experiment_name, test_scenario) {
+        Ok(report) => body_html += &report.render_sensitivity_table(),
+        Err(e) => {
+            warn!("internal_sensitivity - Sensitivity analysis failed: {:?}", e);
+            body_html += &format!("<p>Sensitivity analysis failed: {}</p>", escape_html(&e.to_string()));
+        }
+    }
+    body_html += "</div>";
+    match render_app_body(&body_html) {
+        Ok(result) => Html(result),
+        Err(e) => Html(format!("<p>Could not render the page: {}</p>", escape_html(&e.to_string()))),
+    }
+}
@@ -232,6 +232,29 @@ impl GraphDBAdapter {
         Ok(None)
     }
 
+    /// Removes a field from a mapping, returning whether it was present
+    ///
+    /// For "probabilities" keys this clears the belief on the Proposition node
+    /// but keeps the node itself.
+    pub fn map_remove(&mut self, namespace: &str, key: &str, field: &str) -> Result<bool, Box<dyn Error>> {
+        let (nodes, property) = if key == "probabilities" {
+            (self.graph_db.find_nodes_by_property("predicate_hash", field)?, "belief")
+        } else {
+            let nskey = namespace::qualified_key(namespace, key);
+            (self.graph_db.find_nodes_by_property("mapping_key", &nskey)?, field)
+        };
+        
+        if let Some(node) = nodes.first() {
+            let mut props = node.properties.clone();
+            if props.remove(property).is_some() {
+                self.graph_db.update_node(&node.id, props)?;
+                return Ok(true);
+            }
+        }
+        
+        Ok(false)
+    }
+
     //
     // Set Operations (Redis Set Equivalents)
     //
@@ -307,6 +330,31 @@ impl GraphDBAdapter {
         Ok(true)
     }
     
+    /// Removes a member from a generic set, deleting its member node
+    pub fn set_remove(&mut self, namespace: &str, key: &str, member: &str) -> Result<bool, Box<dyn Error>> {
+        if key.starts_with("premises:") || key.starts_with("features:") || key.starts_with("evidence:") {
+            return Err(format!("set_remove is not supported for {}", key).into());
+        }
+        let nskey = namespace::qualified_key(namespace, key);
+        let collection_nodes = self.graph_db.find_nodes_by_property("collection_key", &nskey)?;
+        let Some(collection_node) = collection_nodes.first() else {
+            return Ok(false);
+        };
+        let edge_type = self.determine_edge_type(key);
+        for edge in self.graph_db.get_node_edges(&collection_node.id, Direction::Outgoing)? {
+            if edge.label != edge_type.as_str() {
+                continue;
+            }
+            if let Some(member_node) = self.graph_db.get_node(&edge.target_id)?
+                && let Some(Value::String(value)) = member_node.properties.get("value")
+                && value == member
+            {
+                return Ok(self.graph_db.delete_node(&member_node.id)?);
+            }
+        }
+        Ok(false)
+    }
+
     /// Adds a premise to a factor, creating appropriate graph relationships
     fn add_premise_to_factor(&mut self, namespace: &str, key: &str, proposition_hash: &str) -> Result<bool, Box<dyn Error>> {
         // Extract factor ID from the key (format: "premises:factor_id")
@@ -1312,6 +1360,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_map_remove() {
+        let mut adapter = GraphDBAdapter::new_in_memory("test").unwrap();
+        
+        adapter.map_insert("test", "probabilities", "prop1", "0.75").unwrap();
+        adapter.map_insert("test", "myhash", "field1", "value1").unwrap();
+        
+        assert!(adapter.map_remove("test", "probabilities", "prop1").unwrap());
+        assert_eq!(adapter.map_get("test", "probabilities", "prop1").unwrap(), None);
+        assert!(!adapter.map_remove("test", "probabilities", "prop1").unwrap());
+        
+        assert!(adapter.map_remove("test", "myhash", "field1").unwrap());
+        assert_eq!(adapter.map_get("test", "myhash", "field1").unwrap(), None);
+    }
+
     #[test]
     fn test_set_operations() {
         let mut adapter = GraphDBAdapter::new_in_memory("test").unwrap();
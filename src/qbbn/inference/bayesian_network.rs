@@ -11,6 +11,8 @@ use crate::qbbn::model::objects::Proposition;
 use super::{
     graph::PropositionGraph, 
     engine::{Inferencer, MarginalTable},
+    mpe::{MapConfig, MapResult},
+    table::PropositionNode,
 };
 
 /// BayesianNetwork provides a simplified interface to the QBBN implementation
@@ -75,12 +77,19 @@ impl BayesianNetwork {
             belief
         )?;
         
-        // Run inference
-        self.run_inference()?;
+        // Propagate only along the paths the new evidence affects
+        let node = PropositionNode::from_single(proposition);
+        self.inferencer.do_incremental_update(&mut self.connection, &[node])?;
         
         Ok(())
     }
     
+    /// Returns the `top_k` jointly most probable assignments given the current evidence
+    pub fn most_probable_explanations(&mut self, top_k: usize) -> Result<MapResult, Box<dyn Error>> {
+        let config = MapConfig { top_k, ..MapConfig::default() };
+        self.inferencer.top_k_explanations(&mut self.connection, &config)
+    }
+    
     /// Gets the belief for a given proposition
     pub fn get_belief(&mut self, proposition: &Proposition) -> Result<Option<f64>, Box<dyn Error>> {
         self.inferencer.fact_memory.get_proposition_probability(
@@ -1,6 +1,6 @@
 use super::{
     graph::{PropositionFactor, PropositionGraph},
-    table::{FactorProbabilityTable, HashMapBeliefTable, PropositionNode},
+    table::{FactorProbabilityTable, GenericNodeType, HashMapBeliefTable, PropositionNode},
 };
 use crate::qbbn::{
     common::{
@@ -8,18 +8,23 @@ use crate::qbbn::{
         model::{FactorContext, InferenceModel},
     },
     inference::table::VariableAssignment,
-    model::objects::{Proposition, PropositionGroup},
+    model::{
+        objects::{FactorizedCpd, Negation, Proposition, PropositionChoice, PropositionGroup},
+        weights::CLASS_LABELS,
+    },
 };
 use log::trace;
 use crate::qbbn::common::redis::MockConnection as Connection;
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     error::Error,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
+/// A conclusion together with its premises' (hash, value) assignment, sorted by hash.
+type FactorCacheKey = (PropositionNode, Vec<(u64, bool)>);
 
 pub struct Inferencer {
     pub model: Arc<InferenceModel>,
@@ -27,6 +32,30 @@ pub struct Inferencer {
     pub proposition_graph: Arc<PropositionGraph>,
     pub data: HashMapBeliefTable,
     pub bfs_order: Vec<PropositionNode>,
+    /// Likelihood ratios P(reading | true) / P(reading | false) for nodes with virtual
+    /// evidence. These scale lambda rather than fixing the node's value.
+    pub virtual_evidence: BTreeMap<PropositionNode, f64>,
+    /// Factor scores keyed by conclusion and premise assignment. These only depend on
+    /// the model weights, so they stay valid while evidence changes.
+    factor_cache: Mutex<FactorCache>,
+}
+
+/// Cached factor scores, with the model's `weight_version` they were computed at.
+#[derive(Default)]
+struct FactorCache {
+    weight_version: u64,
+    scores: HashMap<FactorCacheKey, f64>,
+}
+
+impl FactorCache {
+    /// The cached score, after dropping every score computed with older weights.
+    fn get(&mut self, key: &FactorCacheKey, weight_version: u64) -> Option<f64> {
+        if self.weight_version != weight_version {
+            self.scores.clear();
+            self.weight_version = weight_version;
+        }
+        self.scores.get(key).copied()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -84,6 +113,8 @@ impl Inferencer {
             proposition_graph,
             data: HashMapBeliefTable::new(bfs_order.clone()),
             bfs_order,
+            virtual_evidence: BTreeMap::new(),
+            factor_cache: Mutex::new(FactorCache::default()),
         }))
     }
 
@@ -129,6 +160,9 @@ impl Inferencer {
         println!("\nMARGINALS");
         let mut entries = vec![];
         for node in &self.bfs_order {
+            if node.is_choice() {
+                continue;
+            }
             let pi0 = self.data.get_pi_value(node, 0).unwrap();
             let pi1 = self.data.get_pi_value(node, 1).unwrap();
             let lambda0 = self.data.get_lambda_value(node, 0).unwrap();
@@ -160,6 +194,9 @@ impl Inferencer {
     pub fn build_marginal_table(&self) -> Result<MarginalTable, Box<dyn Error>> {
         let mut entries = vec![];
         for node in &self.bfs_order {
+            if node.is_choice() {
+                continue;
+            }
             let pi0 = self.data.get_pi_value(node, 0).unwrap();
             let pi1 = self.data.get_pi_value(node, 1).unwrap();
             let lambda0 = self.data.get_lambda_value(node, 0).unwrap();
@@ -180,16 +217,53 @@ impl Inferencer {
         Ok(table)
     }
 
+    /// The posterior over the outcomes of a categorical variable, in outcome order.
+    pub fn choice_marginals(&self, choice: &PropositionChoice) -> Option<Vec<f64>> {
+        let node = PropositionNode::from_choice(choice);
+        let mut potentials = vec![];
+        for outcome in 0..choice.outcomes.len() {
+            let pi = self.data.get_pi_value(&node, outcome)?;
+            let lambda = self.data.get_lambda_value(&node, outcome)?;
+            potentials.push(pi * lambda);
+        }
+        let norm: f64 = potentials.iter().sum();
+        Some(potentials.iter().map(|potential| potential / norm).collect())
+    }
+
     pub fn log_table_to_file(&self) -> Result<MarginalTable, Box<dyn Error>> {
         let table = self.build_marginal_table()?;
         Ok(table)
     }
 
+    /// How many values `node` takes: two, except for categorical variables.
+    pub fn outcome_count(&self, node: &PropositionNode) -> usize {
+        match &node.node {
+            GenericNodeType::Choice(choice) => choice.outcomes.len(),
+            _ => CLASS_LABELS.len(),
+        }
+    }
+
+    /// The CPD of `node` if it is a group under a `FactorizedCpd`, such as the group of an
+    /// aggregate's instances. Messages through it are computed in closed form instead of
+    /// summing over every assignment of its terms.
+    pub fn factorized_cpd(&self, node: &PropositionNode) -> Option<FactorizedCpd> {
+        if !node.is_group() {
+            return None;
+        }
+        let group = node.extract_group();
+        if !group.negations.is_empty() {
+            return None;
+        }
+        group.cpd.as_ref()?.factorized()
+    }
+
     pub fn is_root(&self, node: &PropositionNode) -> bool {
         if node.is_single() {
             let as_single = node.extract_single();
             
             self.proposition_graph.roots.contains(&as_single)
+                && !self.proposition_graph.frontier.contains_key(&as_single)
+                && self.proposition_graph.fixed_value(&as_single).is_none()
         } else {
             false
         }
@@ -217,8 +291,7 @@ impl Inferencer {
         if node.is_single() {
             let as_single = node.extract_single();
             let has_evidence = self
-                .fact_memory
-                .get_proposition_probability(connection, &as_single)?
+                .observed_probability(connection, &as_single)?
                 .is_some();
             trace!(
                 "is_observed? node {:?}, has_evidence {}",
@@ -231,77 +304,248 @@ impl Inferencer {
         }
     }
 
+    /// The probability `proposition` is fixed to: its value under an intervention, its
+    /// grounded truth for a numeric comparison, or else its evidence in `fact_memory`.
+    pub fn observed_probability(
+        &self,
+        connection: &mut Connection,
+        proposition: &Proposition,
+    ) -> Result<Option<f64>, Box<dyn Error>> {
+        if let Some(value) = self.proposition_graph.fixed_value(proposition) {
+            return Ok(Some(value));
+        }
+        self.fact_memory.get_proposition_probability(connection, proposition)
+    }
+
+    /// Drops cached factor scores. Writes to the model's weights invalidate them by
+    /// themselves, through its `weight_version`.
+    pub fn clear_factor_cache(&self) {
+        self.factor_cache.lock().unwrap().scores.clear();
+    }
+
+    /// P(conclusion = k | premise values) for every value k of `conclusion`. Binary
+    /// nodes defer to `score_factor_assignment`.
+    pub fn score_factor_distribution(
+        &self,
+        connection: &mut Connection,
+        premises: &Vec<PropositionNode>,
+        premise_assignment: &BTreeMap<PropositionNode, usize>,
+        conclusion: &PropositionNode,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        if conclusion.is_choice() {
+            return self.score_choice_distribution(
+                connection,
+                premises,
+                premise_assignment,
+                &conclusion.extract_choice(),
+            );
+        }
+        if let [parent] = premises.as_slice()
+            && parent.is_choice()
+        {
+            // An outcome is true exactly when its variable takes it.
+            let chosen = parent.extract_choice().index_of(&conclusion.extract_single())
+                == premise_assignment.get(parent).copied();
+            return Ok(if chosen { vec![0f64, 1f64] } else { vec![1f64, 0f64] });
+        }
+        let boolean_assignment: BTreeMap<PropositionNode, bool> = premise_assignment
+            .iter()
+            .map(|(node, value)| (node.clone(), *value != 0))
+            .collect();
+        let probability =
+            self.score_factor_assignment(connection, premises, &boolean_assignment, conclusion)?;
+        Ok(vec![1f64 - probability, probability])
+    }
+
+    /// Scores a categorical variable from the premise groups of all of its outcomes.
+    fn score_choice_distribution(
+        &self,
+        connection: &mut Connection,
+        premises: &[PropositionNode],
+        premise_assignment: &BTreeMap<PropositionNode, usize>,
+        choice: &PropositionChoice,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        let context = self.build_factor_context_for_choice(premises, premise_assignment, choice);
+        self.model
+            .model
+            .predict_distribution(connection, &context, choice.outcomes.len())
+    }
+
+    /// The context a categorical variable is scored in: every implication from one of
+    /// its premise groups to one of its outcomes.
+    pub fn build_factor_context_for_choice(
+        &self,
+        premises: &[PropositionNode],
+        premise_assignment: &BTreeMap<PropositionNode, usize>,
+        choice: &PropositionChoice,
+    ) -> FactorContext {
+        let mut factors = vec![];
+        let mut probabilities = vec![];
+        for premise in premises {
+            let group = premise.extract_group();
+            let value = if premise_assignment[premise] != 0 { 1f64 } else { 0f64 };
+            for outcome in &choice.outcomes {
+                let key = (group.clone(), outcome.clone());
+                if let Some(inference) = self.proposition_graph.inference_used.get(&key) {
+                    factors.push(PropositionFactor {
+                        premise: group.clone(),
+                        conclusion: outcome.clone(),
+                        inference: inference.clone(),
+                    });
+                    probabilities.push(value);
+                }
+            }
+        }
+        FactorContext {
+            numeric_values: self
+                .proposition_graph
+                .numeric_values_for(factors.iter().map(|factor| &factor.premise)),
+            factor: factors,
+            probabilities,
+        }
+    }
+
     pub fn score_factor_assignment(
         &self,
         connection: &mut Connection,
         premises: &Vec<PropositionNode>,
-        premise_assignment: &HashMap<PropositionNode, bool>,
+        premise_assignment: &BTreeMap<PropositionNode, bool>,
         conclusion: &PropositionNode,
     ) -> Result<f64, Box<dyn Error>> {
-        if conclusion.is_single() {
+        let mut assignment_key: Vec<(u64, bool)> = premises
+            .iter()
+            .map(|premise| (premise.underlying_hash, *premise_assignment.get(premise).unwrap()))
+            .collect();
+        assignment_key.sort();
+        let cache_key = (conclusion.clone(), assignment_key);
+        // Negation as failure reads the fact memory, which the cache key does not cover.
+        let cacheable = conclusion.is_single() || !conclusion.extract_group().has_negation_as_failure();
+        let version = self.model.model.weight_version(connection);
+        if let Some(probability) = self.factor_cache.lock().unwrap().get(&cache_key, version) {
+            return Ok(probability);
+        }
+        let probability = if conclusion.is_single() {
             self.score_factor_assignment_disjunction(
                 connection,
                 premises,
                 premise_assignment,
                 conclusion,
-            )
+            )?
         } else {
-            self.score_factor_assignment_conjunction(connection, premises, premise_assignment, conclusion)
+            self.score_factor_assignment_conjunction(connection, premises, premise_assignment, conclusion)?
+        };
+        // A score computed while the weights changed is not kept.
+        let mut cache = self.factor_cache.lock().unwrap();
+        if cacheable && cache.weight_version == version && self.model.model.weight_version(connection) == version {
+            cache.scores.insert(cache_key, probability);
         }
+        Ok(probability)
     }
 
     pub fn score_factor_assignment_disjunction(
         &self,
         connection: &mut Connection,
         premises: &Vec<PropositionNode>,
-        premise_assignment: &HashMap<PropositionNode, bool>,
+        premise_assignment: &BTreeMap<PropositionNode, bool>,
         conclusion: &PropositionNode,
     ) -> Result<f64, Box<dyn Error>> {
-        let mut proposition_premises = vec![];
+        let proposition_conclusion = conclusion.extract_single();
+        // Each premise group carries the CPD of its own implication (see
+        // `PropositionGroup::cpd`). The noise of a built-in CPD lives on its group, so a
+        // true built-in group is a cause that fired. The learned groups are scored
+        // together by the model, and the two kinds of cause are combined by OR.
+        let mut learned_premises = vec![];
+        let mut builtin_fired = false;
         for node_premise in premises {
-            proposition_premises.push(node_premise.extract_group());
+            let group = node_premise.extract_group();
+            if group.cpd.is_none() {
+                learned_premises.push(group);
+            } else if *premise_assignment.get(node_premise).unwrap() {
+                trace!("score_factor_assignment_disjunction; built-in premise {:?} is true", node_premise);
+                builtin_fired = true;
+            }
         }
-        let proposition_conclusion = conclusion.extract_single();
-        let context = build_factor_context_for_assignment(
-            &self.proposition_graph,
-            &proposition_premises,
-            premise_assignment,
-            &proposition_conclusion,
-        );
-        let statistics = self.model.model.predict(connection, &context)?;
-        trace!("score_factor_assignment_disjunction; premises: {:?}, assignment: {:?}, conclusion {:?}, probability {}", premises, premise_assignment, conclusion, statistics.probability);
-        Ok(statistics.probability)
+        let builtin_probability = if builtin_fired { 1f64 } else { 0f64 };
+        let learned_probability = if learned_premises.is_empty() {
+            0f64
+        } else {
+            let context = build_factor_context_for_assignment(
+                &self.proposition_graph,
+                &learned_premises,
+                premise_assignment,
+                &proposition_conclusion,
+            );
+            self.model.model.predict_conclusion(connection, &context)?.probability
+        };
+        let probability = 1f64 - (1f64 - learned_probability) * (1f64 - builtin_probability);
+        trace!("score_factor_assignment_disjunction; premises: {:?}, assignment: {:?}, conclusion {:?}, learned {}, built-in {}, probability {}", premises, premise_assignment, conclusion, learned_probability, builtin_probability, probability);
+        Ok(probability)
     }
 
     pub fn score_factor_assignment_conjunction(
         &self,
         connection: &mut Connection,
         premises: &[PropositionNode],
-        premise_assignment: &HashMap<PropositionNode, bool>,
+        premise_assignment: &BTreeMap<PropositionNode, bool>,
         conclusion: &PropositionNode,
     ) -> Result<f64, Box<dyn Error>> {
-        // Use the same ExponentialModel for AND gates as we do for OR gates
-        let mut proposition_premises = vec![];
-        for node_premise in premises {
-            proposition_premises.push(node_premise.extract_group());
+        let literal_assignment =
+            self.literal_assignment(connection, premises, premise_assignment, &conclusion.extract_group())?;
+        let premise_assignment = &literal_assignment;
+        if let Some(cpd) = &conclusion.extract_group().cpd {
+            let terms: Vec<bool> = premises
+                .iter()
+                .map(|premise| *premise_assignment.get(premise).unwrap())
+                .collect();
+            if let Some(probability) = cpd.probability_given_terms(&terms) {
+                trace!("score_factor_assignment_conjunction; built-in {:?} gives {}", cpd, probability);
+                return Ok(probability);
+            }
         }
-        let proposition_conclusion = conclusion.extract_single();
-        let context = build_factor_context_for_assignment(
+        // Use the same ExponentialModel for AND gates as we do for OR gates
+        let context = build_factor_context_for_conjunction(
             &self.proposition_graph,
-            &proposition_premises,
+            premises,
             premise_assignment,
-            &proposition_conclusion,
+            &conclusion.extract_group(),
         );
         let statistics = self.model.model.predict(connection, &context)?;
         trace!("score_factor_assignment_conjunction; premises: {:?}, assignment: {:?}, conclusion {:?}, probability {}", premises, premise_assignment, conclusion, statistics.probability);
         Ok(statistics.probability)
     }
+
+    /// The truth of each literal of `group` when its terms take the values in
+    /// `premise_assignment`.
+    fn literal_assignment(
+        &self,
+        connection: &mut Connection,
+        premises: &[PropositionNode],
+        premise_assignment: &BTreeMap<PropositionNode, bool>,
+        group: &PropositionGroup,
+    ) -> Result<BTreeMap<PropositionNode, bool>, Box<dyn Error>> {
+        let mut result = premise_assignment.clone();
+        if group.negations.is_empty() {
+            return Ok(result);
+        }
+        for premise in premises {
+            let term = premise.extract_single();
+            let negation = group.negation_of(&term);
+            let fact = if negation == Some(Negation::AsFailure) {
+                self.observed_probability(connection, &term)?
+            } else {
+                None
+            };
+            let value = *premise_assignment.get(premise).unwrap();
+            result.insert(premise.clone(), Negation::literal_value(negation, value, fact));
+        }
+        Ok(result)
+    }
 }
 
 pub fn build_factor_context_for_assignment(
     proposition_graph: &PropositionGraph,
     premises: &Vec<PropositionGroup>,
-    premise_assignment: &HashMap<PropositionNode, bool>,
+    premise_assignment: &BTreeMap<PropositionNode, bool>,
     conclusion: &Proposition,
 ) -> FactorContext {
     let mut probabilities = vec![];
@@ -326,17 +570,57 @@ pub fn build_factor_context_for_assignment(
     FactorContext {
         factor: factors,
         probabilities,
+        numeric_values: proposition_graph.numeric_values_for(premises),
     }
 }
 
+/// Builds the context for an AND node: one probability per premise term, and the
+/// implication the group feeds into as the factor (see `features_from_factor`).
+pub fn build_factor_context_for_conjunction(
+    proposition_graph: &PropositionGraph,
+    premises: &[PropositionNode],
+    premise_assignment: &BTreeMap<PropositionNode, bool>,
+    conclusion: &PropositionGroup,
+) -> FactorContext {
+    let mut probabilities = vec![];
+    for premise in premises {
+        let assignment = *premise_assignment.get(premise).unwrap();
+        probabilities.push(if assignment { 1f64 } else { 0f64 });
+    }
+    FactorContext {
+        factor: context_implication(proposition_graph, conclusion).into_iter().collect(),
+        probabilities,
+        numeric_values: proposition_graph.numeric_values_for([conclusion]),
+    }
+}
+
+/// The implication an AND node is scored under. A group that feeds several conclusions
+/// uses the first one in hash order. Those implications all have the group's CPD, since
+/// it is part of the group's identity.
+pub fn context_implication(
+    proposition_graph: &PropositionGraph,
+    group: &PropositionGroup,
+) -> Option<PropositionFactor> {
+    let mut implied: Vec<Proposition> = proposition_graph
+        .get_group_forward(group)
+        .into_iter()
+        .collect();
+    implied.sort_by_key(|proposition| proposition.hash_string());
+    implied.first().map(|implied| PropositionFactor {
+        premise: group.clone(),
+        conclusion: implied.clone(),
+        inference: proposition_graph.get_inference_used(group, implied),
+    })
+}
+
 pub fn compute_each_combination(
     propositions: &Vec<PropositionNode>,
-) -> Vec<HashMap<PropositionNode, bool>> {
+) -> Vec<BTreeMap<PropositionNode, bool>> {
     trace!("compute_each_combination: propositions={:?}", &propositions);
     let n = propositions.len();
     let mut all_combinations = Vec::new();
     for i in 0..(1 << n) {
-        let mut current_combination = HashMap::new();
+        let mut current_combination = BTreeMap::new();
         for (j, prop) in propositions.iter().enumerate().take(n) {
             let state = i & (1 << j) != 0;
             current_combination.insert(prop.clone(), state);
@@ -346,6 +630,26 @@ pub fn compute_each_combination(
     all_combinations
 }
 
+/// Every joint assignment of `nodes`, where node `i` takes values `0..counts[i]`. The
+/// first node varies fastest, as in `compute_each_combination`.
+pub fn compute_each_outcome_combination(
+    nodes: &[PropositionNode],
+    counts: &[usize],
+) -> Vec<BTreeMap<PropositionNode, usize>> {
+    let total: usize = counts.iter().product();
+    let mut all_combinations = Vec::with_capacity(total);
+    for index in 0..total {
+        let mut remainder = index;
+        let mut combination = BTreeMap::new();
+        for (node, count) in nodes.iter().zip(counts) {
+            combination.insert(node.clone(), remainder % count);
+            remainder /= count;
+        }
+        all_combinations.push(combination);
+    }
+    all_combinations
+}
+
 pub fn groups_from_backlinks(backlinks: &Vec<PropositionNode>) -> Vec<PropositionGroup> {
     let mut result = vec![];
     for backlink in backlinks {
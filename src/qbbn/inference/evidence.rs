@@ -0,0 +1,141 @@
+use crate::qbbn::common::redis::MockConnection as Connection;
+use log::trace;
+
+use super::{engine::Inferencer, incremental::IncrementalUpdateStats, table::PropositionNode};
+use crate::qbbn::model::objects::Proposition;
+use std::error::Error;
+
+/// One reading about a proposition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Observation {
+    /// The proposition is known to be true or false.
+    Hard(bool),
+    /// A probability stored in the fact memory. Like `Hard`, this fixes both pi and
+    /// lambda of the node, so its parents no longer influence it.
+    Soft(f64),
+    /// Likelihood ratio P(reading | true) / P(reading | false) of an uncertain sensor.
+    /// Enters through lambda only, so the node's parents still shape its belief.
+    Virtual(f64),
+}
+
+/// Observations to apply together.
+#[derive(Debug, Clone, Default)]
+pub struct EvidenceSet {
+    pub observations: Vec<(Proposition, Observation)>,
+}
+
+impl EvidenceSet {
+    pub fn new() -> EvidenceSet {
+        EvidenceSet::default()
+    }
+
+    pub fn observe(&mut self, proposition: &Proposition, value: bool) {
+        self.observations
+            .push((proposition.clone(), Observation::Hard(value)));
+    }
+
+    pub fn observe_probability(&mut self, proposition: &Proposition, probability: f64) {
+        self.observations
+            .push((proposition.clone(), Observation::Soft(probability)));
+    }
+
+    pub fn observe_likelihood_ratio(&mut self, proposition: &Proposition, ratio: f64) {
+        self.observations
+            .push((proposition.clone(), Observation::Virtual(ratio)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observations.is_empty()
+    }
+}
+
+/// What an evidence change did.
+#[derive(Debug, Clone, Default)]
+pub struct EvidenceReport {
+    pub applied: Vec<(Proposition, Observation)>,
+    pub retracted: Vec<Proposition>,
+    pub update: IncrementalUpdateStats,
+}
+
+impl Inferencer {
+    /// Applies every observation in `evidence` and then propagates them in one pass.
+    ///
+    /// Each observation replaces whatever was previously known about its proposition.
+    pub fn apply_evidence(
+        &mut self,
+        connection: &mut Connection,
+        evidence: &EvidenceSet,
+    ) -> Result<EvidenceReport, Box<dyn Error>> {
+        for (proposition, observation) in &evidence.observations {
+            match observation {
+                Observation::Soft(probability) if !(0f64..=1f64).contains(probability) => {
+                    return Err(format!("Probability {} for {:?} is not in [0, 1].", probability, proposition).into());
+                }
+                Observation::Virtual(ratio) if !(ratio.is_finite() && *ratio > 0f64) => {
+                    return Err(format!("Likelihood ratio {} for {:?} must be positive.", ratio, proposition).into());
+                }
+                _ => {}
+            }
+        }
+        let mut changed = vec![];
+        for (proposition, observation) in &evidence.observations {
+            let node = PropositionNode::from_single(proposition);
+            trace!("apply_evidence {:?} {:?}", &node, observation);
+            match observation {
+                Observation::Hard(value) => {
+                    self.virtual_evidence.remove(&node);
+                    self.fact_memory
+                        .store_proposition_boolean(connection, proposition, *value)?;
+                }
+                Observation::Soft(probability) => {
+                    self.virtual_evidence.remove(&node);
+                    self.fact_memory
+                        .store_proposition_probability(connection, proposition, *probability)?;
+                }
+                Observation::Virtual(ratio) => {
+                    self.fact_memory
+                        .retract_proposition_probability(connection, proposition)?;
+                    self.virtual_evidence.insert(node.clone(), *ratio);
+                }
+            }
+            changed.push(node);
+        }
+        let update = self.do_incremental_update(connection, &changed)?;
+        Ok(EvidenceReport {
+            applied: evidence.observations.clone(),
+            retracted: vec![],
+            update,
+        })
+    }
+
+    /// Forgets all evidence, hard, soft or virtual, on `propositions` and re-propagates.
+    pub fn retract_evidence(
+        &mut self,
+        connection: &mut Connection,
+        propositions: &[Proposition],
+    ) -> Result<EvidenceReport, Box<dyn Error>> {
+        let mut changed = vec![];
+        for proposition in propositions {
+            let node = PropositionNode::from_single(proposition);
+            trace!("retract_evidence {:?}", &node);
+            self.fact_memory
+                .retract_proposition_probability(connection, proposition)?;
+            self.virtual_evidence.remove(&node);
+            changed.push(node);
+        }
+        let update = self.do_incremental_update(connection, &changed)?;
+        Ok(EvidenceReport {
+            applied: vec![],
+            retracted: propositions.to_vec(),
+            update,
+        })
+    }
+
+    /// The lambda multiplier contributed by virtual evidence on `node`, 1 if there is none.
+    pub fn virtual_likelihood(&self, node: &PropositionNode, class_label: usize) -> f64 {
+        match self.virtual_evidence.get(node) {
+            Some(ratio) if class_label == 1 => *ratio,
+            _ => 1f64,
+        }
+    }
+}
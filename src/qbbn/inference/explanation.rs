@@ -0,0 +1,315 @@
+use crate::qbbn::common::redis::MockConnection as Connection;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashSet, VecDeque},
+    error::Error,
+};
+
+use super::{
+    engine::{compute_each_outcome_combination, context_implication, Inferencer, MarginalTable},
+    pi::factorized_pi,
+    table::PropositionNode,
+};
+use crate::qbbn::model::objects::{CpdType, Proposition, PropositionGroup};
+
+/// Probabilities are kept this far from 0 and 1 when taking log-odds.
+const LOG_ODDS_EPSILON: f64 = 1e-9;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+    /// An implication concluding the proposition, measured by its pi message.
+    Cause,
+    /// An implication the proposition is a premise term of, measured by its lambda message.
+    Effect,
+    /// Virtual evidence on the proposition itself.
+    Reading,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExplanationPath {
+    pub kind: PathKind,
+    /// The implication's premise. `None` for a reading.
+    pub group: Option<PropositionGroup>,
+    /// The implication's CPD. `None` for a reading.
+    pub cpd: Option<CpdType>,
+    /// How far this path moves the log-odds of the proposition. Positive supports it.
+    pub contribution: f64,
+    /// For a cause, its premise terms; for an effect, the implication's conclusions.
+    pub children: Vec<Explanation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Explanation {
+    pub proposition: Proposition,
+    pub probability: f64,
+    /// The observed value, if the proposition is evidence. Evidence is not explained further.
+    pub observed: Option<f64>,
+    /// Largest absolute contribution first. Empty past the depth limit.
+    pub paths: Vec<ExplanationPath>,
+}
+
+impl Explanation {
+    pub fn supporting(&self) -> impl Iterator<Item = &ExplanationPath> {
+        self.paths.iter().filter(|path| path.contribution > 0f64)
+    }
+
+    pub fn opposing(&self) -> impl Iterator<Item = &ExplanationPath> {
+        self.paths.iter().filter(|path| path.contribution < 0f64)
+    }
+
+    fn render_into(&self, indent: usize, lines: &mut Vec<String>) {
+        let padding = "  ".repeat(indent);
+        let mut line = format!("{}{}: {:.3}", padding, self.proposition.natural_language(), self.probability);
+        if let Some(observed) = self.observed {
+            line.push_str(&format!(" (observed {:.3})", observed));
+        }
+        lines.push(line);
+        for path in &self.paths {
+            let label = match (&path.kind, &path.group) {
+                (PathKind::Reading, _) | (_, None) => "reading".to_string(),
+                (kind, Some(group)) => {
+                    let terms: Vec<String> = group
+                        .terms
+                        .iter()
+                        .enumerate()
+                        .map(|(index, term)| match group.negation(index) {
+                            Some(_) => format!("not {}", term.natural_language()),
+                            None => term.natural_language(),
+                        })
+                        .collect();
+                    let word = if *kind == PathKind::Cause { "because" } else { "premise of" };
+                    format!("{} {} [{:?}]", word, terms.join(" and "), path.cpd.clone().unwrap_or_default())
+                }
+            };
+            lines.push(format!("{}  {:+.3} {}", padding, path.contribution, label));
+            for child in &path.children {
+                child.render_into(indent + 2, lines);
+            }
+        }
+    }
+}
+
+/// Why a proposition has its marginal: the implications around it, each scored by the
+/// pi or lambda message it sends, recursively, plus the evidence that reaches it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExplanationTree {
+    pub root: Explanation,
+    /// Observed propositions with a path to the root that does not pass through other
+    /// evidence, with their observed values.
+    pub evidence: Vec<(Proposition, f64)>,
+}
+
+impl ExplanationTree {
+    pub fn render_text(&self) -> String {
+        let mut lines = vec![];
+        self.root.render_into(0, &mut lines);
+        if !self.evidence.is_empty() {
+            lines.push("evidence:".to_string());
+            for (proposition, value) in &self.evidence {
+                lines.push(format!("  {}: {:.3}", proposition.natural_language(), value));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExplanationConfig {
+    /// How many implications deep to follow from the proposition.
+    pub depth: usize,
+}
+
+impl Default for ExplanationConfig {
+    fn default() -> Self {
+        ExplanationConfig { depth: 2 }
+    }
+}
+
+fn log_odds(probability: f64) -> f64 {
+    let clamped = probability.clamp(LOG_ODDS_EPSILON, 1f64 - LOG_ODDS_EPSILON);
+    (clamped / (1f64 - clamped)).ln()
+}
+
+fn by_contribution(paths: &mut [ExplanationPath]) {
+    paths.sort_by(|a, b| b.contribution.abs().total_cmp(&a.contribution.abs()));
+}
+
+impl Inferencer {
+    /// Explains the current marginal of `proposition` from the messages of the last
+    /// belief propagation pass. Categorical parents are not broken down.
+    pub fn explain(
+        &self,
+        connection: &mut Connection,
+        proposition: &Proposition,
+        config: &ExplanationConfig,
+    ) -> Result<ExplanationTree, Box<dyn Error>> {
+        let node = PropositionNode::from_single(proposition);
+        if !self.bfs_order.contains(&node) {
+            return Err(format!("{:?} is not in the proposition graph.", proposition).into());
+        }
+        let table = self.build_marginal_table()?;
+        let mut stack = vec![];
+        let root = self.explain_proposition(connection, &table, proposition, config.depth, &mut stack)?;
+        let evidence = self.evidence_reaching(connection, &node)?;
+        Ok(ExplanationTree { root, evidence })
+    }
+
+    fn explain_proposition(
+        &self,
+        connection: &mut Connection,
+        table: &MarginalTable,
+        proposition: &Proposition,
+        depth: usize,
+        stack: &mut Vec<Proposition>,
+    ) -> Result<Explanation, Box<dyn Error>> {
+        let node = PropositionNode::from_single(proposition);
+        let observed = self.observed_probability(connection, proposition)?;
+        let mut explanation = Explanation {
+            proposition: proposition.clone(),
+            probability: table.get_marginal(proposition).unwrap_or(0f64),
+            observed,
+            paths: vec![],
+        };
+        if depth == 0 || observed.is_some() || self.is_root(&node) || stack.contains(proposition) {
+            return Ok(explanation);
+        }
+        stack.push(proposition.clone());
+        let graph = &self.proposition_graph;
+        if graph.frontier_prior(&node).is_none() {
+            for parent in graph.get_all_backward(&node) {
+                if !parent.is_group() {
+                    continue;
+                }
+                let group = parent.extract_group();
+                let contribution = self.pi_log_odds(&node)? - self.pi_log_odds_without(connection, &node, &parent)?;
+                let mut children = vec![];
+                for term in &group.terms {
+                    children.push(self.explain_proposition(connection, table, term, depth - 1, stack)?);
+                }
+                explanation.paths.push(ExplanationPath {
+                    kind: PathKind::Cause,
+                    cpd: Some(graph.get_inference_used(&group, proposition).cpd),
+                    group: Some(group),
+                    contribution,
+                    children,
+                });
+            }
+        }
+        for child in graph.get_all_forward(&node) {
+            let group = child.extract_group();
+            let message = |outcome| {
+                self.data
+                    .get_lambda_message(&child, &node, outcome)
+                    .unwrap_or(1f64)
+                    .max(LOG_ODDS_EPSILON)
+            };
+            let contribution = (message(1) / message(0)).ln();
+            let mut conclusions: Vec<Proposition> = graph.get_group_forward(&group).into_iter().collect();
+            conclusions.sort_by_key(|conclusion| conclusion.hash_string());
+            let mut children = vec![];
+            for conclusion in &conclusions {
+                children.push(self.explain_proposition(connection, table, conclusion, depth - 1, stack)?);
+            }
+            explanation.paths.push(ExplanationPath {
+                kind: PathKind::Effect,
+                cpd: context_implication(graph, &group).map(|factor| factor.inference.cpd),
+                group: Some(group),
+                contribution,
+                children,
+            });
+        }
+        if let Some(ratio) = self.virtual_evidence.get(&node) {
+            explanation.paths.push(ExplanationPath {
+                kind: PathKind::Reading,
+                group: None,
+                cpd: None,
+                contribution: ratio.max(LOG_ODDS_EPSILON).ln(),
+                children: vec![],
+            });
+        }
+        stack.pop();
+        by_contribution(&mut explanation.paths);
+        Ok(explanation)
+    }
+
+    fn pi_log_odds(&self, node: &PropositionNode) -> Result<f64, Box<dyn Error>> {
+        let pi0 = self.data.get_pi_value(node, 0).ok_or("pi has not been computed")?;
+        let pi1 = self.data.get_pi_value(node, 1).ok_or("pi has not been computed")?;
+        Ok(log_odds(pi1 / (pi0 + pi1)))
+    }
+
+    /// The log-odds of pi at `node` if `parent` were known to be false, everything else
+    /// as in `pi_compute_value`.
+    fn pi_log_odds_without(
+        &self,
+        connection: &mut Connection,
+        node: &PropositionNode,
+        parent: &PropositionNode,
+    ) -> Result<f64, Box<dyn Error>> {
+        let parent_nodes = self.proposition_graph.get_all_backward(node);
+        if let Some(cpd) = self.factorized_cpd(node) {
+            let messages: Vec<[f64; 2]> = parent_nodes
+                .iter()
+                .map(|other| {
+                    if other == parent {
+                        [1f64, 0f64]
+                    } else {
+                        [0, 1].map(|outcome| self.data.get_pi_message(other, node, outcome).unwrap_or(0f64))
+                    }
+                })
+                .collect();
+            let [pi0, pi1] = factorized_pi(&cpd, &messages);
+            return Ok(log_odds(pi1 / (pi0 + pi1)));
+        }
+        let parent_counts: Vec<usize> = parent_nodes.iter().map(|other| self.outcome_count(other)).collect();
+        let mut sums = [0f64; 2];
+        for combination in &compute_each_outcome_combination(&parent_nodes, &parent_counts) {
+            let mut product = 1f64;
+            for other in &parent_nodes {
+                let outcome = *combination.get(other).unwrap();
+                product *= if other == parent {
+                    if outcome == 0 { 1f64 } else { 0f64 }
+                } else {
+                    self.data.get_pi_message(other, node, outcome).unwrap_or(0f64)
+                };
+            }
+            if product == 0f64 {
+                continue;
+            }
+            let distribution = self.score_factor_distribution(connection, &parent_nodes, combination, node)?;
+            for (outcome, probability) in distribution.iter().enumerate().take(2) {
+                sums[outcome] += probability * product;
+            }
+        }
+        Ok(log_odds(sums[1] / (sums[0] + sums[1])))
+    }
+
+    /// Observed propositions reachable from `node` without passing through other evidence.
+    fn evidence_reaching(
+        &self,
+        connection: &mut Connection,
+        node: &PropositionNode,
+    ) -> Result<Vec<(Proposition, f64)>, Box<dyn Error>> {
+        let graph = &self.proposition_graph;
+        let mut evidence = vec![];
+        let mut seen = HashSet::from([node.clone()]);
+        let mut queue = VecDeque::from([node.clone()]);
+        while let Some(current) = queue.pop_front() {
+            for next in graph.get_all_backward(&current).into_iter().chain(graph.get_all_forward(&current)) {
+                if !seen.insert(next.clone()) {
+                    continue;
+                }
+                if next.is_single() {
+                    let proposition = next.extract_single();
+                    if let Some(value) = self.observed_probability(connection, &proposition)? {
+                        evidence.push((proposition, value));
+                        continue;
+                    }
+                }
+                queue.push_back(next);
+            }
+        }
+        evidence.sort_by_key(|(proposition, _)| proposition.hash_string());
+        Ok(evidence)
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     error::Error, sync::Arc,
 };
 
@@ -11,7 +11,10 @@ use crate::qbbn::{
     common::graph::InferenceGraph,
     model::{
         choose::extract_backimplications_from_proposition,
-        objects::{ImplicationFactor, Proposition, PropositionGroup},
+        objects::{
+            ImplicationFactor, NumericComparison, Proposition, PropositionChoice,
+            PropositionGroup,
+        },
     },
 };
 
@@ -36,14 +39,52 @@ impl PropositionFactor {
 
 /// This class does NOT store a link to any database.
 /// It is EXPENSIVE to copy, though.. should just be moved.
+///
+/// The edges, roots and nodes are in ordered collections, so messages are sent and
+/// summed in the same order in every run and a seeded run is reproducible bit for bit.
+#[derive(Clone)]
 pub struct PropositionGraph {
-    pub single_forward: HashMap<Proposition, HashSet<PropositionGroup>>,
-    pub single_backward: HashMap<Proposition, HashSet<PropositionGroup>>,
-    pub group_forward: HashMap<PropositionGroup, HashSet<Proposition>>,
+    pub single_forward: HashMap<Proposition, BTreeSet<PropositionGroup>>,
+    pub single_backward: HashMap<Proposition, BTreeSet<PropositionGroup>>,
+    pub group_forward: HashMap<PropositionGroup, BTreeSet<Proposition>>,
     pub inference_used: HashMap<(PropositionGroup, Proposition), ImplicationFactor>,
-    pub roots: HashSet<Proposition>,
-    pub all_nodes: HashSet<PropositionNode>,
+    pub roots: BTreeSet<Proposition>,
+    pub all_nodes: BTreeSet<PropositionNode>,
     pub target: Proposition,
+    /// Propositions whose backimplications were cut off by a grounding depth bound,
+    /// with the prior that stands in for them. They are also in `roots`, which seeds
+    /// the BFS order, but are otherwise ordinary nodes that can take evidence.
+    pub frontier: HashMap<Proposition, f64>,
+    /// The categorical variable each outcome proposition belongs to.
+    pub choices: HashMap<Proposition, PropositionChoice>,
+    /// Premise groups of any outcome of a categorical variable. These are the parents
+    /// of the variable; its outcomes have the variable as their only parent.
+    pub choice_backward: BTreeMap<PropositionChoice, BTreeSet<PropositionGroup>>,
+    /// Numeric comparisons, with the truth value grounding decided for them. They are
+    /// in `roots` too, but are held fixed like evidence rather than assumed true.
+    pub comparisons: HashMap<Proposition, f64>,
+    /// The attribute value each comparison tested, where the entity has one.
+    pub numeric_values: HashMap<Proposition, f64>,
+    /// Propositions set by intervention, with their incoming factors cut (see
+    /// `with_interventions`). Like comparisons, they are roots held at their value.
+    pub interventions: HashMap<Proposition, f64>,
+}
+
+/// Grounds `single` if it is a numeric comparison, and says whether it was.
+pub fn ground_numeric_comparison(
+    connection: &mut Connection,
+    predicate_graph: &InferenceGraph,
+    graph: &mut PropositionGraph,
+    single: &Proposition,
+) -> Result<bool, Box<dyn Error>> {
+    match predicate_graph.numeric_comparison(connection, single)? {
+        Some((comparison, value)) => {
+            trace!("Grounding numeric comparison {:?} with value {:?}", single, value);
+            graph.insert_comparison(single, &comparison, value);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
 }
 
 fn initialize_visit_single(
@@ -56,6 +97,12 @@ fn initialize_visit_single(
         "\x1b[32mInitializing visit for proposition: {:?}\x1b[0m",
         single.hash_string()
     );
+    if let Some(choice) = predicate_graph.categorical_choice(connection, single)? {
+        return initialize_visit_choice(connection, predicate_graph, graph, &choice);
+    }
+    if ground_numeric_comparison(connection, predicate_graph, graph, single)? {
+        return Ok(());
+    }
     graph
         .all_nodes
         .insert(PropositionNode::from_single(single));
@@ -75,40 +122,8 @@ fn initialize_visit_single(
                 "\x1b[36mProcessing inference factor: {:?}\x1b[0m",
                 inference_factor.debug_string()
             );
-            let inference_used_key = (inference_factor.premise.clone(), inference_factor.conclusion.clone());
-            graph.inference_used.insert(inference_used_key, inference_factor.inference.clone());
-
-            trace!(
-                "\x1b[36mUpdating single_backward for conclusion: {:?}\x1b[0m",
-                inference_factor.conclusion.hash_string()
-            );
-            graph
-                .single_backward
-                .entry(inference_factor.conclusion.clone())
-                .or_default()
-                .insert(inference_factor.premise.clone());
-
-            trace!(
-                "\x1b[36mUpdating group_forward for premise: {:?}\x1b[0m",
-                inference_factor.premise.hash_string()
-            );
-            graph
-                .group_forward
-                .entry(inference_factor.premise.clone())
-                .or_default()
-                .insert(inference_factor.conclusion.clone());
-
-            graph
-                .all_nodes
-                .insert(PropositionNode::from_group(&inference_factor.premise));
-
+            graph.insert_factor(inference_factor);
             for term in &inference_factor.premise.terms {
-                trace!("\x1b[35mProcessing term: {:?}\x1b[0m", term.hash_string());
-                graph
-                    .single_forward
-                    .entry(term.clone())
-                    .or_default()
-                    .insert(inference_factor.premise.clone());
                 trace!(
                     "\x1b[35mRecursively initializing visit for term: {:?}\x1b[0m",
                     term.hash_string()
@@ -124,23 +139,200 @@ fn initialize_visit_single(
     Ok(())
 }
 
+fn initialize_visit_choice(
+    connection: &mut Connection,
+    predicate_graph: &InferenceGraph,
+    graph: &mut PropositionGraph,
+    choice: &PropositionChoice,
+) -> Result<(), Box<dyn Error>> {
+    if graph.choice_backward.contains_key(choice) {
+        return Ok(());
+    }
+    trace!("Initializing visit for categorical variable: {:?}", choice);
+    graph.insert_choice(choice);
+    for outcome in &choice.outcomes {
+        let inference_factors =
+            extract_backimplications_from_proposition(connection, predicate_graph, outcome)?;
+        for inference_factor in &inference_factors {
+            graph.insert_factor(inference_factor);
+            for term in &inference_factor.premise.terms {
+                initialize_visit_single(connection, predicate_graph, graph, term)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 impl PropositionGraph {
     pub fn new_shared(
         connection: &mut Connection, 
         predicate_graph: &InferenceGraph,
         target: Proposition,
     ) -> Result<Arc<PropositionGraph>, Box<dyn Error>> {
-        let mut graph = PropositionGraph {
+        let mut graph = PropositionGraph::new_empty(target.clone());
+        initialize_visit_single(connection, predicate_graph, &mut graph, &target)?;
+        Ok(Arc::new(graph))
+    }
+
+    pub fn new_empty(target: Proposition) -> PropositionGraph {
+        PropositionGraph {
             single_forward: HashMap::new(),
             single_backward: HashMap::new(),
             group_forward: HashMap::new(),
             inference_used: HashMap::new(),
-            roots: HashSet::new(),
-            all_nodes: HashSet::new(),
-            target: target.clone(),
+            roots: BTreeSet::new(),
+            all_nodes: BTreeSet::new(),
+            target,
+            frontier: HashMap::new(),
+            choices: HashMap::new(),
+            choice_backward: BTreeMap::new(),
+            comparisons: HashMap::new(),
+            numeric_values: HashMap::new(),
+            interventions: HashMap::new(),
+        }
+    }
+
+    /// The value `proposition` is held at regardless of evidence: an intervention, or
+    /// the truth of a numeric comparison.
+    pub fn fixed_value(&self, proposition: &Proposition) -> Option<f64> {
+        self.interventions
+            .get(proposition)
+            .or_else(|| self.comparisons.get(proposition))
+            .copied()
+    }
+
+    /// A copy of this graph mutilated for do(proposition = value) on each intervention:
+    /// the factors into each intervened proposition are removed, premise groups left
+    /// with no conclusion are dropped, and the proposition becomes a fixed root. The
+    /// ancestors cut off this way stay in the graph, disconnected from the rest.
+    pub fn with_interventions(
+        &self,
+        interventions: &[(Proposition, bool)],
+    ) -> Result<PropositionGraph, Box<dyn Error>> {
+        let mut graph = self.clone();
+        for (proposition, value) in interventions {
+            if !graph.all_nodes.contains(&PropositionNode::from_single(proposition)) {
+                return Err(format!("{:?} is not in the proposition graph.", proposition).into());
+            }
+            if graph.choices.contains_key(proposition) {
+                return Err(format!("{:?} is an outcome of a categorical variable.", proposition).into());
+            }
+            for group in graph.single_backward.remove(proposition).unwrap_or_default() {
+                graph.inference_used.remove(&(group.clone(), proposition.clone()));
+                let conclusions = graph.group_forward.entry(group.clone()).or_default();
+                conclusions.remove(proposition);
+                if !conclusions.is_empty() {
+                    continue;
+                }
+                graph.group_forward.remove(&group);
+                graph.all_nodes.remove(&PropositionNode::from_group(&group));
+                for term in &group.terms {
+                    if let Some(groups) = graph.single_forward.get_mut(term) {
+                        groups.remove(&group);
+                    }
+                }
+            }
+            graph.roots.insert(proposition.clone());
+            graph.frontier.remove(proposition);
+            graph
+                .interventions
+                .insert(proposition.clone(), if *value { 1f64 } else { 0f64 });
+        }
+        Ok(graph)
+    }
+
+    /// Adds a numeric comparison as a root fixed by the attribute `value` it tests.
+    pub fn insert_comparison(
+        &mut self,
+        single: &Proposition,
+        comparison: &NumericComparison,
+        value: Option<f64>,
+    ) {
+        let truth = if comparison.holds(value) { 1f64 } else { 0f64 };
+        self.all_nodes.insert(PropositionNode::from_single(single));
+        self.roots.insert(single.clone());
+        self.comparisons.insert(single.clone(), truth);
+        if let Some(value) = value {
+            self.numeric_values.insert(single.clone(), value);
+        }
+    }
+
+    /// The attribute values behind the numeric comparisons among the terms of `groups`.
+    pub fn numeric_values_for<'a>(
+        &self,
+        groups: impl IntoIterator<Item = &'a PropositionGroup>,
+    ) -> HashMap<Proposition, f64> {
+        let mut result = HashMap::new();
+        for group in groups {
+            for term in &group.terms {
+                if let Some(value) = self.numeric_values.get(term) {
+                    result.insert(term.clone(), *value);
+                }
+            }
+        }
+        result
+    }
+
+    /// Adds a categorical variable and its outcomes. Call this before inserting the
+    /// factors that conclude its outcomes.
+    pub fn insert_choice(&mut self, choice: &PropositionChoice) {
+        self.choice_backward.entry(choice.clone()).or_default();
+        self.all_nodes.insert(PropositionNode::from_choice(choice));
+        for outcome in &choice.outcomes {
+            self.choices.insert(outcome.clone(), choice.clone());
+            self.all_nodes.insert(PropositionNode::from_single(outcome));
+        }
+    }
+
+    /// Links `inference_factor.premise` into the graph as a parent of its conclusion.
+    pub fn insert_factor(&mut self, inference_factor: &PropositionFactor) {
+        let inference_used_key = (inference_factor.premise.clone(), inference_factor.conclusion.clone());
+        self.inference_used.insert(inference_used_key, inference_factor.inference.clone());
+
+        trace!(
+            "\x1b[36mUpdating single_backward for conclusion: {:?}\x1b[0m",
+            inference_factor.conclusion.hash_string()
+        );
+        match self.choices.get(&inference_factor.conclusion) {
+            Some(choice) => self
+                .choice_backward
+                .entry(choice.clone())
+                .or_default()
+                .insert(inference_factor.premise.clone()),
+            None => self
+                .single_backward
+                .entry(inference_factor.conclusion.clone())
+                .or_default()
+                .insert(inference_factor.premise.clone()),
         };
-        initialize_visit_single(connection, predicate_graph, &mut graph, &target)?;
-        Ok(Arc::new(graph))
+
+        trace!(
+            "\x1b[36mUpdating group_forward for premise: {:?}\x1b[0m",
+            inference_factor.premise.hash_string()
+        );
+        self.group_forward
+            .entry(inference_factor.premise.clone())
+            .or_default()
+            .insert(inference_factor.conclusion.clone());
+
+        self.all_nodes
+            .insert(PropositionNode::from_group(&inference_factor.premise));
+
+        for term in &inference_factor.premise.terms {
+            trace!("\x1b[35mProcessing term: {:?}\x1b[0m", term.hash_string());
+            self.single_forward
+                .entry(term.clone())
+                .or_default()
+                .insert(inference_factor.premise.clone());
+        }
+    }
+
+    /// The stand-in prior for a node on the grounding frontier, if it is on it.
+    pub fn frontier_prior(&self, node: &PropositionNode) -> Option<f64> {
+        match &node.node {
+            GenericNodeType::Single(proposition) => self.frontier.get(proposition).copied(),
+            GenericNodeType::Group(_) | GenericNodeType::Choice(_) => None,
+        }
     }
 
     pub fn get_inference_used(&self, premise:&PropositionGroup, conclusion: &Proposition) -> ImplicationFactor {
@@ -149,21 +341,21 @@ impl PropositionGraph {
             .get(&key).unwrap().clone()
     }
 
-    pub fn get_single_forward(&self, key: &Proposition) -> HashSet<PropositionGroup> {
+    pub fn get_single_forward(&self, key: &Proposition) -> BTreeSet<PropositionGroup> {
         self.single_forward
             .get(key)
             .cloned()
-            .unwrap_or_else(HashSet::new)
+            .unwrap_or_else(BTreeSet::new)
     }
 
-    pub fn get_single_backward(&self, key: &Proposition) -> HashSet<PropositionGroup> {
+    pub fn get_single_backward(&self, key: &Proposition) -> BTreeSet<PropositionGroup> {
         self.single_backward
             .get(key)
             .cloned()
-            .unwrap_or_else(HashSet::new)
+            .unwrap_or_else(BTreeSet::new)
     }
 
-    pub fn get_group_forward(&self, key: &PropositionGroup) -> HashSet<Proposition> {
+    pub fn get_group_forward(&self, key: &PropositionGroup) -> BTreeSet<Proposition> {
         self.group_forward.get(key).unwrap().clone()
     }
 
@@ -175,6 +367,9 @@ impl PropositionGraph {
         trace!("get_all_backward called for node: {:?}", node.debug_string());
         let mut r = vec![];
         match &node.node {
+            GenericNodeType::Single(proposition) if self.choices.contains_key(proposition) => {
+                r.push(PropositionNode::from_choice(&self.choices[proposition]));
+            }
             GenericNodeType::Single(proposition) => {
                 trace!("Processing as Single: {:?}", proposition.debug_string());
                 let initial = self.get_single_backward(proposition);
@@ -193,6 +388,11 @@ impl PropositionGraph {
                     r.push(PropositionNode::from_single(single));
                 }
             }
+            GenericNodeType::Choice(choice) => {
+                for group in self.choice_backward.get(choice).into_iter().flatten() {
+                    r.push(PropositionNode::from_group(group));
+                }
+            }
         }
         trace!("Resulting vector: {:?}", r);
         r
@@ -217,7 +417,18 @@ impl PropositionGraph {
                 trace!("Initial groups: {}", initial.len());
                 for single in &initial {
                     trace!("Adding single from initial groups: {:?}", single.debug_string());
-                    r.push(PropositionNode::from_single(single));
+                    let child = match self.choices.get(single) {
+                        Some(choice) => PropositionNode::from_choice(choice),
+                        None => PropositionNode::from_single(single),
+                    };
+                    if !r.contains(&child) {
+                        r.push(child);
+                    }
+                }
+            }
+            GenericNodeType::Choice(choice) => {
+                for outcome in &choice.outcomes {
+                    r.push(PropositionNode::from_single(outcome));
                 }
             }
         }
@@ -225,7 +436,7 @@ impl PropositionGraph {
         r
     }
 
-    pub fn get_roots(&self) -> HashSet<Proposition> {
+    pub fn get_roots(&self) -> BTreeSet<Proposition> {
         self.roots.clone()
     }
 
@@ -280,6 +491,11 @@ fn create_bfs_order(proposition_graph: &PropositionGraph) -> Vec<PropositionNode
     for root in &proposition_graph.roots {
         queue.push_back((0, PropositionNode::from_single(root)));
     }
+    for (choice, parents) in &proposition_graph.choice_backward {
+        if parents.is_empty() {
+            queue.push_back((0, PropositionNode::from_choice(choice)));
+        }
+    }
     while let Some((depth, node)) = queue.pop_front() {
         buffer.push((depth, node.clone()));
         let forward = proposition_graph.get_all_forward(&node);
@@ -0,0 +1,203 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    sync::Arc,
+};
+
+use log::trace;
+use crate::qbbn::common::redis::MockConnection as Connection;
+
+use crate::qbbn::{
+    common::{graph::InferenceGraph, interface::BeliefTable, model::InferenceModel},
+    model::{choose::extract_backimplications_from_proposition, objects::Proposition},
+};
+
+use super::{
+    engine::{Inferencer, MarginalTable},
+    graph::{ground_numeric_comparison, PropositionFactor, PropositionGraph},
+    table::PropositionNode,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct GroundingConfig {
+    /// How many backimplication steps to expand from a query. `None` grounds everything.
+    pub max_depth: Option<usize>,
+    /// Prior for propositions whose backimplications were not expanded.
+    pub frontier_prior: f64,
+}
+
+impl Default for GroundingConfig {
+    fn default() -> Self {
+        GroundingConfig {
+            max_depth: None,
+            frontier_prior: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GroundingStats {
+    /// Propositions whose backimplications were looked up in the database.
+    pub lookups: usize,
+    /// Propositions whose backimplications came from the cache.
+    pub lookup_cache_hits: usize,
+    /// Queries answered with an already grounded graph.
+    pub graph_cache_hits: usize,
+    /// Propositions left on the frontier by the depth bound.
+    pub truncated: usize,
+}
+
+/// The sorted query hashes, with the depth bound and the bits of the frontier prior
+/// the graph was grounded with.
+type GraphCacheKey = (Vec<String>, Option<usize>, u64);
+
+/// Grounds proposition graphs on demand for arbitrary query propositions.
+///
+/// Unlike `PropositionGraph::new_shared`, no target has to be registered: the graph is
+/// the union of what is reachable backwards from the queries, up to `max_depth`.
+/// Backimplications and whole graphs are cached, so call `clear` after the rule base
+/// changes.
+pub struct LazyGrounder {
+    pub config: GroundingConfig,
+    pub stats: GroundingStats,
+    backimplications: HashMap<Proposition, Vec<PropositionFactor>>,
+    graphs: HashMap<GraphCacheKey, Arc<PropositionGraph>>,
+}
+
+impl LazyGrounder {
+    pub fn new(config: GroundingConfig) -> LazyGrounder {
+        LazyGrounder {
+            config,
+            stats: GroundingStats::default(),
+            backimplications: HashMap::new(),
+            graphs: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.backimplications.clear();
+        self.graphs.clear();
+    }
+
+    /// The graph needed to answer `queries`. Its `target` is the first query.
+    pub fn ground(
+        &mut self,
+        connection: &mut Connection,
+        predicate_graph: &InferenceGraph,
+        queries: &[Proposition],
+    ) -> Result<Arc<PropositionGraph>, Box<dyn Error>> {
+        if queries.is_empty() {
+            return Err("LazyGrounder::ground needs at least one query.".into());
+        }
+        let mut hashes: Vec<String> = queries.iter().map(|query| query.hash_string()).collect();
+        hashes.sort();
+        hashes.dedup();
+        let key = (hashes, self.config.max_depth, self.config.frontier_prior.to_bits());
+        if let Some(graph) = self.graphs.get(&key) {
+            self.stats.graph_cache_hits += 1;
+            return Ok(graph.clone());
+        }
+
+        let mut graph = PropositionGraph::new_empty(queries[0].clone());
+        let mut queue = VecDeque::new();
+        let mut visited = HashSet::new();
+        for query in queries {
+            if visited.insert(query.clone()) {
+                queue.push_back((query.clone(), 0usize));
+            }
+        }
+        while let Some((single, depth)) = queue.pop_front() {
+            if let Some(choice) = predicate_graph.categorical_choice(connection, &single)? {
+                // The outcomes of a categorical variable are grounded together. Past the
+                // depth bound the variable keeps no parents, which makes it uniform.
+                graph.insert_choice(&choice);
+                for outcome in &choice.outcomes {
+                    visited.insert(outcome.clone());
+                }
+                if self.config.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                    self.stats.truncated += 1;
+                    continue;
+                }
+                for outcome in &choice.outcomes {
+                    let inference_factors = self.backimplications_for(connection, predicate_graph, outcome)?;
+                    for inference_factor in &inference_factors {
+                        graph.insert_factor(inference_factor);
+                        for term in &inference_factor.premise.terms {
+                            if visited.insert(term.clone()) {
+                                queue.push_back((term.clone(), depth + 1));
+                            }
+                        }
+                    }
+                }
+                continue;
+            }
+            if ground_numeric_comparison(connection, predicate_graph, &mut graph, &single)? {
+                continue;
+            }
+            graph.all_nodes.insert(PropositionNode::from_single(&single));
+            let inference_factors = self.backimplications_for(connection, predicate_graph, &single)?;
+            if inference_factors.is_empty() {
+                graph.roots.insert(single);
+                continue;
+            }
+            if self.config.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                trace!("LazyGrounder: truncating at {:?}", &single);
+                self.stats.truncated += 1;
+                graph.frontier.insert(single.clone(), self.config.frontier_prior);
+                graph.roots.insert(single);
+                continue;
+            }
+            for inference_factor in &inference_factors {
+                graph.insert_factor(inference_factor);
+                for term in &inference_factor.premise.terms {
+                    if visited.insert(term.clone()) {
+                        queue.push_back((term.clone(), depth + 1));
+                    }
+                }
+            }
+        }
+        trace!(
+            "LazyGrounder: grounded {} nodes for {} queries",
+            graph.all_nodes.len(),
+            queries.len()
+        );
+        let graph = Arc::new(graph);
+        self.graphs.insert(key, graph.clone());
+        Ok(graph)
+    }
+
+    fn backimplications_for(
+        &mut self,
+        connection: &mut Connection,
+        predicate_graph: &InferenceGraph,
+        single: &Proposition,
+    ) -> Result<Vec<PropositionFactor>, Box<dyn Error>> {
+        if let Some(cached) = self.backimplications.get(single) {
+            self.stats.lookup_cache_hits += 1;
+            return Ok(cached.clone());
+        }
+        self.stats.lookups += 1;
+        let factors = extract_backimplications_from_proposition(connection, predicate_graph, single)?;
+        self.backimplications.insert(single.clone(), factors.clone());
+        Ok(factors)
+    }
+
+    /// Grounds `queries`, runs `passes` rounds of belief propagation against
+    /// `fact_memory`, and returns the marginals of every grounded node.
+    pub fn query_marginals(
+        &mut self,
+        connection: &mut Connection,
+        model: Arc<InferenceModel>,
+        fact_memory: Arc<dyn BeliefTable>,
+        queries: &[Proposition],
+        passes: usize,
+    ) -> Result<MarginalTable, Box<dyn Error>> {
+        let proposition_graph = self.ground(connection, &model.graph, queries)?;
+        let mut inferencer = Inferencer::new_mutable(model, proposition_graph, fact_memory)?;
+        inferencer.initialize_chart(connection)?;
+        for _ in 0..passes {
+            inferencer.do_full_forward_and_backward(connection)?;
+        }
+        inferencer.build_marginal_table()
+    }
+}
@@ -0,0 +1,170 @@
+use crate::qbbn::common::redis::MockConnection as Connection;
+use log::trace;
+
+use super::{engine::Inferencer, table::PropositionNode};
+use std::{
+    collections::{HashSet, VecDeque},
+    error::Error,
+};
+
+/// Controls how far an incremental update is allowed to spread.
+#[derive(Debug, Clone, Copy)]
+pub struct IncrementalConfig {
+    /// Messages that move by less than this are not propagated any further.
+    pub tolerance: f64,
+    /// Upper bound on node visits for one update, as a guard against oscillation.
+    pub max_node_visits: usize,
+}
+
+impl Default for IncrementalConfig {
+    fn default() -> Self {
+        IncrementalConfig {
+            tolerance: 1e-6,
+            max_node_visits: 10_000,
+        }
+    }
+}
+
+/// What an incremental update touched.
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalUpdateStats {
+    pub nodes_visited: usize,
+    pub messages_propagated: usize,
+    /// False if `max_node_visits` was hit before the worklist drained.
+    pub converged: bool,
+}
+
+impl Inferencer {
+    /// Re-propagates beliefs after the evidence on `changed` nodes was modified.
+    ///
+    /// Unlike `do_fan_out_from_node`, this reuses the messages already in `self.data`
+    /// and only visits neighbours whose incoming message moved by more than the tolerance.
+    pub fn do_incremental_update(
+        &mut self,
+        connection: &mut Connection,
+        changed: &[PropositionNode],
+    ) -> Result<IncrementalUpdateStats, Box<dyn Error>> {
+        self.do_incremental_update_with_config(connection, changed, &IncrementalConfig::default())
+    }
+
+    pub fn do_incremental_update_with_config(
+        &mut self,
+        connection: &mut Connection,
+        changed: &[PropositionNode],
+        config: &IncrementalConfig,
+    ) -> Result<IncrementalUpdateStats, Box<dyn Error>> {
+        if !self.is_chart_initialized() {
+            trace!("do_incremental_update: chart is cold, initializing");
+            self.initialize_chart(connection)?;
+        }
+        let mut stats = IncrementalUpdateStats::default();
+        let mut queue = VecDeque::new();
+        let mut queued = HashSet::new();
+        for node in changed {
+            if queued.insert(node.clone()) {
+                queue.push_back(node.clone());
+            }
+        }
+        while let Some(node) = queue.pop_front() {
+            queued.remove(&node);
+            if stats.nodes_visited >= config.max_node_visits {
+                trace!("do_incremental_update: visit budget exhausted");
+                return Ok(stats);
+            }
+            stats.nodes_visited += 1;
+            let recipients = self.incremental_visit_node(connection, &node, config.tolerance)?;
+            for recipient in recipients {
+                stats.messages_propagated += 1;
+                if queued.insert(recipient.clone()) {
+                    queue.push_back(recipient);
+                }
+            }
+        }
+        stats.converged = true;
+        trace!("do_incremental_update: {:?}", &stats);
+        Ok(stats)
+    }
+
+    /// True once `initialize_chart` has filled in pi and lambda for every node.
+    pub fn is_chart_initialized(&self) -> bool {
+        self.bfs_order.iter().all(|node| {
+            self.data.get_pi_value(node, 1).is_some() && self.data.get_lambda_value(node, 1).is_some()
+        })
+    }
+
+    /// Recomputes one node and sends its messages, returning the neighbours whose
+    /// incoming message changed by more than `tolerance`.
+    fn incremental_visit_node(
+        &mut self,
+        connection: &mut Connection,
+        node: &PropositionNode,
+        tolerance: f64,
+    ) -> Result<Vec<PropositionNode>, Box<dyn Error>> {
+        let children = self.proposition_graph.get_all_forward(node);
+        let parents = self.proposition_graph.get_all_backward(node);
+        let old_pi_messages: Vec<Vec<Option<f64>>> = children
+            .iter()
+            .map(|child| {
+                (0..self.outcome_count(node))
+                    .map(|label| self.data.get_pi_message(node, child, label))
+                    .collect()
+            })
+            .collect();
+        let old_lambda_messages: Vec<Vec<Option<f64>>> = parents
+            .iter()
+            .map(|parent| {
+                (0..self.outcome_count(parent))
+                    .map(|label| self.data.get_lambda_message(node, parent, label))
+                    .collect()
+            })
+            .collect();
+
+        let is_observed = self.is_observed(connection, node)?;
+        if self.is_root(node) {
+            self.pi_compute_root(node)?;
+        } else if is_observed {
+            self.pi_set_from_evidence(connection, node)?;
+        } else {
+            self.pi_compute_value(connection, node)?;
+        }
+        if is_observed {
+            self.lambda_set_from_evidence(connection, node)?;
+        } else {
+            self.lambda_compute_value(connection, node)?;
+        }
+        self.pi_send_messages(node)?;
+        self.lambda_send_messages(connection, node)?;
+
+        let mut recipients = vec![];
+        for (index, child) in children.iter().enumerate() {
+            let new_values: Vec<Option<f64>> = (0..self.outcome_count(node))
+                .map(|label| self.data.get_pi_message(node, child, label))
+                .collect();
+            if message_moved(&old_pi_messages[index], &new_values, tolerance) {
+                recipients.push(child.clone());
+            }
+        }
+        for (index, parent) in parents.iter().enumerate() {
+            let new_values: Vec<Option<f64>> = (0..self.outcome_count(parent))
+                .map(|label| self.data.get_lambda_message(node, parent, label))
+                .collect();
+            if message_moved(&old_lambda_messages[index], &new_values, tolerance) {
+                recipients.push(parent.clone());
+            }
+        }
+        trace!(
+            "incremental_visit_node {:?} propagates to {:?}",
+            node,
+            &recipients
+        );
+        Ok(recipients)
+    }
+}
+
+fn message_moved(old: &[Option<f64>], new: &[Option<f64>], tolerance: f64) -> bool {
+    old.iter().zip(new.iter()).any(|pair| match pair {
+        (Some(before), Some(after)) => (before - after).abs() > tolerance,
+        (None, None) => false,
+        _ => true,
+    })
+}
@@ -0,0 +1,280 @@
+use crate::qbbn::common::redis::MockConnection as Connection;
+use log::trace;
+
+use super::{engine::Inferencer, table::PropositionNode};
+use crate::qbbn::model::objects::FactorizedCpd;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+};
+
+/// Enumerating more free variables than this would take 2^31 steps or more, which does not
+/// finish in any useful time, so settings that allow it are rejected.
+pub const MAX_ENUMERATED_VARIABLES: usize = 30;
+
+/// A conditional with more parents than this is not tabulated: that would score every
+/// one of its 2^parents premise assignments. Factorized CPDs have no such limit.
+pub const MAX_TABULATED_PARENTS: usize = 16;
+
+/// How a `LogFactor` gives `log P(child | parents)`.
+#[derive(Debug, Clone)]
+pub enum LogTable {
+    /// Entry `i` is the assignment where bit `j` of `i` is the value of `scope[j]`.
+    Dense(Vec<f64>),
+    /// The child is a group under this CPD and the parents are its terms, so the value
+    /// only depends on how many terms are true. This is how aggregates over a whole
+    /// domain stay tractable, as in `factorized_pi`.
+    Factorized(FactorizedCpd),
+}
+
+/// One conditional `log P(child | parents)` of the network. `scope[0]` is the child and
+/// the rest are its parents.
+#[derive(Debug, Clone)]
+pub struct LogFactor {
+    pub scope: Vec<usize>,
+    pub table: LogTable,
+}
+
+impl LogFactor {
+    pub fn value(&self, values: &[bool]) -> f64 {
+        match &self.table {
+            LogTable::Dense(table) => {
+                let mut index = 0usize;
+                for (position, variable) in self.scope.iter().enumerate() {
+                    if values[*variable] {
+                        index |= 1 << position;
+                    }
+                }
+                table[index]
+            }
+            LogTable::Factorized(cpd) => {
+                let true_terms = self.scope[1..].iter().filter(|variable| values[**variable]).count();
+                factorized_log_value(cpd, values[self.scope[0]], true_terms, self.scope.len() - 1)
+            }
+        }
+    }
+}
+
+/// `log P(child | terms)` under `cpd`, when `true_terms` of its `terms` are true.
+pub fn factorized_log_value(cpd: &FactorizedCpd, child: bool, true_terms: usize, terms: usize) -> f64 {
+    let product = cpd.term_factor(1f64).powi(true_terms as i32)
+        * cpd.term_factor(0f64).powi((terms - true_terms) as i32);
+    let probability = cpd.probability(product);
+    if child {
+        probability.ln()
+    } else {
+        (1f64 - probability).ln()
+    }
+}
+
+/// A log-space snapshot of the joint distribution encoded by an `Inferencer`.
+///
+/// Roots are clamped to true, as in `pi_compute_root`. Evidence enters as a likelihood
+/// `[1 - p, p]` on the observed node, which clamps it when `p` is 0 or 1, and virtual
+/// evidence as `[1, ratio]`.
+#[derive(Debug, Clone)]
+pub struct JointFactorGraph {
+    pub variables: Vec<PropositionNode>,
+    pub clamped: Vec<Option<bool>>,
+    /// Evidence log-likelihood of `[false, true]` per variable.
+    pub unary: Vec<[f64; 2]>,
+    pub factors: Vec<LogFactor>,
+    index: HashMap<PropositionNode, usize>,
+}
+
+impl JointFactorGraph {
+    pub fn variable_index(&self, node: &PropositionNode) -> Option<usize> {
+        self.index.get(node).copied()
+    }
+
+    /// Variables that are neither roots nor pinned by hard evidence.
+    pub fn free_variables(&self) -> Vec<usize> {
+        (0..self.variables.len())
+            .filter(|variable| self.clamped[*variable].is_none())
+            .collect()
+    }
+
+    /// An assignment with every clamped variable set and every free variable false.
+    pub fn initial_values(&self) -> Vec<bool> {
+        self.clamped.iter().map(|value| value.unwrap_or(false)).collect()
+    }
+
+    /// Unnormalized log probability of a full assignment.
+    pub fn log_score(&self, values: &[bool]) -> f64 {
+        let mut total = 0f64;
+        for (variable, value) in values.iter().enumerate() {
+            if self.clamped[variable].is_some_and(|clamped| clamped != *value) {
+                return f64::NEG_INFINITY;
+            }
+            total += self.unary[variable][*value as usize];
+        }
+        for factor in &self.factors {
+            total += factor.value(values);
+        }
+        total
+    }
+
+    /// Calls `visit` once for every setting of the free variables. There must be at most
+    /// `MAX_ENUMERATED_VARIABLES` of them.
+    pub fn for_each_assignment(&self, mut visit: impl FnMut(&[bool])) {
+        let free = self.free_variables();
+        let mut values = self.initial_values();
+        for bits in 0u64..(1u64 << free.len()) {
+            for (position, variable) in free.iter().enumerate() {
+                values[*variable] = bits & (1 << position) != 0;
+            }
+            visit(&values);
+        }
+    }
+
+    pub fn assignment_map(&self, values: &[bool]) -> BTreeMap<PropositionNode, bool> {
+        self.variables
+            .iter()
+            .cloned()
+            .zip(values.iter().copied())
+            .collect()
+    }
+}
+
+impl Inferencer {
+    /// Whether the graph has a categorical variable, which the boolean
+    /// `JointFactorGraph` cannot represent.
+    pub fn has_categorical_variables(&self) -> bool {
+        self.bfs_order.iter().any(|node| node.is_choice())
+    }
+
+    /// Tabulates every conditional in the graph against the current evidence. Errors if
+    /// the graph has a categorical variable, since every variable here is boolean, or a
+    /// conditional that is not factorized has more than `MAX_TABULATED_PARENTS` parents.
+    pub fn build_joint_factor_graph(
+        &self,
+        connection: &mut Connection,
+    ) -> Result<JointFactorGraph, Box<dyn Error>> {
+        if self.has_categorical_variables() {
+            return Err("Joint factor graphs over categorical variables are not supported.".into());
+        }
+        let variables = self.bfs_order.clone();
+        let index: HashMap<PropositionNode, usize> = variables
+            .iter()
+            .enumerate()
+            .map(|(position, node)| (node.clone(), position))
+            .collect();
+        let mut clamped = vec![None; variables.len()];
+        let mut unary = vec![[0f64, 0f64]; variables.len()];
+        let mut factors = vec![];
+        for (variable, node) in variables.iter().enumerate() {
+            if self.is_root(node) {
+                clamped[variable] = Some(true);
+                continue;
+            }
+            if let Some(ratio) = self.virtual_evidence.get(node) {
+                unary[variable] = [0f64, ratio.ln()];
+            }
+            if self.is_observed(connection, node)? {
+                let probability = self
+                    .observed_probability(connection, &node.extract_single())?
+                    .unwrap();
+                unary[variable] = [(1f64 - probability).ln(), probability.ln()];
+                if probability <= 0f64 {
+                    clamped[variable] = Some(false);
+                } else if probability >= 1f64 {
+                    clamped[variable] = Some(true);
+                }
+            }
+            let parents = self.proposition_graph.get_all_backward(node);
+            let mut scope = vec![variable];
+            for parent in &parents {
+                scope.push(*index.get(parent).unwrap());
+            }
+            if let Some(prior) = self.proposition_graph.frontier_prior(node) {
+                let table = LogTable::Dense(vec![(1f64 - prior).ln(), prior.ln()]);
+                factors.push(LogFactor { scope, table });
+                continue;
+            }
+            if let Some(cpd) = self.factorized_cpd(node) {
+                factors.push(LogFactor { scope, table: LogTable::Factorized(cpd) });
+                continue;
+            }
+            if parents.len() > MAX_TABULATED_PARENTS {
+                return Err(format!(
+                    "{} has {} parents; conditionals with more than {} cannot be tabulated.",
+                    node.debug_string(),
+                    parents.len(),
+                    MAX_TABULATED_PARENTS
+                )
+                .into());
+            }
+            let mut table = vec![0f64; 1 << scope.len()];
+            for parent_bits in 0usize..(1 << parents.len()) {
+                let mut assignment = BTreeMap::new();
+                for (position, parent) in parents.iter().enumerate() {
+                    assignment.insert(parent.clone(), parent_bits & (1 << position) != 0);
+                }
+                let true_probability =
+                    self.score_factor_assignment(connection, &parents, &assignment, node)?;
+                table[parent_bits << 1] = (1f64 - true_probability).ln();
+                table[(parent_bits << 1) | 1] = true_probability.ln();
+            }
+            factors.push(LogFactor { scope, table: LogTable::Dense(table) });
+        }
+        trace!(
+            "build_joint_factor_graph: {} variables, {} factors",
+            variables.len(),
+            factors.len()
+        );
+        Ok(JointFactorGraph {
+            variables,
+            clamped,
+            unary,
+            factors,
+            index,
+        })
+    }
+}
+
+/// `ln(sum(exp(x)))`, stable for large magnitudes.
+pub fn log_sum_exp(values: &[f64]) -> f64 {
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return f64::NEG_INFINITY;
+    }
+    max + values.iter().map(|value| (value - max).exp()).sum::<f64>().ln()
+}
+
+/// `log_sum_exp` of values added one at a time, without keeping them.
+#[derive(Debug, Clone, Copy)]
+pub struct LogSumExp {
+    max: f64,
+    /// The sum of `exp(value - max)`.
+    scaled_sum: f64,
+}
+
+impl Default for LogSumExp {
+    fn default() -> Self {
+        LogSumExp {
+            max: f64::NEG_INFINITY,
+            scaled_sum: 0f64,
+        }
+    }
+}
+
+impl LogSumExp {
+    pub fn add(&mut self, value: f64) {
+        if value == f64::NEG_INFINITY {
+            return;
+        }
+        if value > self.max {
+            self.scaled_sum = self.scaled_sum * (self.max - value).exp() + 1f64;
+            self.max = value;
+        } else {
+            self.scaled_sum += (value - self.max).exp();
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        if self.max == f64::NEG_INFINITY {
+            return f64::NEG_INFINITY;
+        }
+        self.max + self.scaled_sum.ln()
+    }
+}
@@ -2,10 +2,10 @@ use crate::qbbn::common::redis::MockConnection as Connection;
 use log::trace;
 
 use super::{
-    engine::{compute_each_combination, Inferencer},
+    engine::{compute_each_outcome_combination, Inferencer},
     table::PropositionNode,
 };
-use crate::qbbn::model::weights::CLASS_LABELS;
+use crate::qbbn::model::objects::FactorizedCpd;
 use std::error::Error;
 
 impl Inferencer {
@@ -13,7 +13,7 @@ impl Inferencer {
         trace!("initialize_lambda: proposition");
         for node in &self.proposition_graph.all_nodes {
             trace!("initializing: {}", node.debug_string());
-            for outcome in CLASS_LABELS {
+            for outcome in 0..self.outcome_count(node) {
                 self.data.set_lambda_value(node, outcome, 1f64);
             }
             for parent in &self.proposition_graph.get_all_backward(node) {
@@ -22,7 +22,7 @@ impl Inferencer {
                     node.debug_string(),
                     parent.debug_string()
                 );
-                for outcome in CLASS_LABELS {
+                for outcome in 0..self.outcome_count(parent) {
                     self.data.set_lambda_message(node, parent, outcome, 1f64);
                 }
             }
@@ -71,8 +71,7 @@ impl Inferencer {
     ) -> Result<(), Box<dyn Error>> {
         let as_single = node.extract_single();
         let probability = self
-            .fact_memory
-            .get_proposition_probability(connection, &as_single)?
+            .observed_probability(connection, &as_single)?
             .unwrap();
         trace!("set from evidence {:?} {}", node, probability);
         self.data.set_lambda_value(node, 1, probability);
@@ -88,16 +87,17 @@ impl Inferencer {
         let is_observed = self.is_observed(connection, node)?;
         assert!(!is_observed);
         let children = self.proposition_graph.get_all_forward(node);
-        for class_label in &CLASS_LABELS {
+        for class_label in 0..self.outcome_count(node) {
             let mut product = 1f64;
             for child_node in children.iter() {
                 let child_lambda = self
                     .data
-                    .get_lambda_message(child_node, node, *class_label)
+                    .get_lambda_message(child_node, node, class_label)
                     .unwrap();
                 product *= child_lambda;
             }
-            self.data.set_lambda_value(node, *class_label, product);
+            product *= self.virtual_likelihood(node, class_label);
+            self.data.set_lambda_value(node, class_label, product);
         }
         Ok(())
     }
@@ -113,19 +113,37 @@ impl Inferencer {
             node,
             &parent_nodes
         );
-        let all_combinations = compute_each_combination(&parent_nodes);
-        let lambda_true = self.data.get_lambda_value(node, 1).unwrap();
-        let lambda_false = self.data.get_lambda_value(node, 0).unwrap();
+        let lambdas: Vec<f64> = (0..self.outcome_count(node))
+            .map(|outcome| self.data.get_lambda_value(node, outcome).unwrap())
+            .collect();
+        if let Some(cpd) = self.factorized_cpd(node) {
+            let messages: Vec<[f64; 2]> = parent_nodes
+                .iter()
+                .map(|parent| {
+                    [0, 1].map(|outcome| self.data.get_pi_message(parent, node, outcome).unwrap())
+                })
+                .collect();
+            let lambda = [lambdas[0], lambdas[1]];
+            for (parent, message) in parent_nodes.iter().zip(factorized_lambda_messages(&cpd, &messages, lambda)) {
+                for (outcome, value) in message.into_iter().enumerate() {
+                    self.data.set_lambda_message(node, parent, outcome, value);
+                }
+            }
+            return Ok(());
+        }
+        let parent_counts: Vec<usize> = parent_nodes
+            .iter()
+            .map(|parent| self.outcome_count(parent))
+            .collect();
+        let all_combinations = compute_each_outcome_combination(&parent_nodes, &parent_counts);
         for (to_index, to_parent) in parent_nodes.iter().enumerate() {
             trace!("to_index {} to_parent {:?}", to_index, to_parent);
-            let mut sum_true = 0f64;
-            let mut sum_false = 0f64;
+            let mut sums = vec![0f64; parent_counts[to_index]];
             for combination in &all_combinations {
                 let mut pi_product = 1f64;
                 for (other_index, other_parent) in parent_nodes.iter().enumerate() {
                     if other_index != to_index {
-                        let class_bool = combination.get(other_parent).unwrap();
-                        let class_label = if *class_bool { 1 } else { 0 };
+                        let class_label = *combination.get(other_parent).unwrap();
                         let this_pi = self
                             .data
                             .get_pi_message(other_parent, node, class_label)
@@ -140,39 +158,68 @@ impl Inferencer {
                         pi_product *= this_pi;
                     }
                 }
-                let probability_true =
-                    self.score_factor_assignment(connection, &parent_nodes, combination, node)?;
-                let probability_false = 1f64 - probability_true;
+                let distribution =
+                    self.score_factor_distribution(connection, &parent_nodes, combination, node)?;
                 trace!(
-                    "probability {} for {:?} on assignment {:?}",
-                    probability_true,
+                    "distribution {:?} for {:?} on assignment {:?}",
+                    &distribution,
                     node,
                     combination
                 );
-                let parent_assignment = combination.get(to_parent).unwrap();
-                let true_factor = probability_true * pi_product * lambda_true;
-                let false_factor = probability_false * pi_product * lambda_false;
-                if *parent_assignment {
-                    sum_true += true_factor + false_factor;
-                } else {
-                    sum_false += true_factor + false_factor;
-                }
+                let parent_assignment = *combination.get(to_parent).unwrap();
+                let total: f64 = distribution
+                    .iter()
+                    .enumerate()
+                    .map(|(outcome, probability)| probability * pi_product * lambdas[outcome])
+                    .sum();
+                sums[parent_assignment] += total;
+            }
+            for (outcome, sum) in sums.into_iter().enumerate() {
+                trace!(
+                    "final {} lambda message {} from {:?} to {:?}",
+                    outcome,
+                    sum,
+                    node,
+                    to_parent
+                );
+                self.data.set_lambda_message(node, to_parent, outcome, sum);
             }
-            trace!(
-                "final 1 lambda message {} from {:?} to {:?}",
-                sum_true,
-                node,
-                to_parent
-            );
-            trace!(
-                "final 0 lambda message {} from {:?} to {:?}",
-                sum_false,
-                node,
-                to_parent
-            );
-            self.data.set_lambda_message(node, to_parent, 1, sum_true);
-            self.data.set_lambda_message(node, to_parent, 0, sum_false);
         }
         Ok(())
     }
 }
+
+/// The `[false, true]` lambda message from a group under `cpd` to each of its terms,
+/// given the terms' pi messages and the group's lambda: the same sums as
+/// `lambda_send_messages`, in closed form. Products over the other terms come from
+/// prefix and suffix products, so this is linear in the number of terms.
+pub fn factorized_lambda_messages(
+    cpd: &FactorizedCpd,
+    messages: &[[f64; 2]],
+    lambda: [f64; 2],
+) -> Vec<[f64; 2]> {
+    let count = messages.len();
+    let masses: Vec<f64> = messages.iter().map(|[pi0, pi1]| pi0 + pi1).collect();
+    let factors: Vec<f64> = messages
+        .iter()
+        .zip(&masses)
+        .map(|([_, pi1], mass)| cpd.term_factor(if *mass > 0f64 { pi1 / mass } else { 0f64 }))
+        .collect();
+    // suffix[i] is the product over terms i.., as (mass, factor).
+    let mut suffix = vec![(1f64, 1f64); count + 1];
+    for index in (0..count).rev() {
+        suffix[index] = (suffix[index + 1].0 * masses[index], suffix[index + 1].1 * factors[index]);
+    }
+    let mut prefix = (1f64, 1f64);
+    let mut result = Vec::with_capacity(count);
+    for index in 0..count {
+        let other_mass = prefix.0 * suffix[index + 1].0;
+        let other_factor = prefix.1 * suffix[index + 1].1;
+        result.push([0f64, 1f64].map(|value| {
+            let probability = cpd.probability(other_factor * cpd.term_factor(value));
+            other_mass * ((1f64 - probability) * lambda[0] + probability * lambda[1])
+        }));
+        prefix = (prefix.0 * masses[index], prefix.1 * factors[index]);
+    }
+    result
+}
@@ -1,9 +1,18 @@
 pub mod table;
 pub mod engine;
 pub mod graph;
+pub mod grounding;
 pub mod pi;
 pub mod lambda;
 pub mod rounds;
+pub mod incremental;
+pub mod evidence;
+pub mod joint;
+pub mod mpe;
+pub mod query;
+pub mod sensitivity;
+pub mod value_of_information;
+pub mod explanation;
 pub mod bayesian_network;
 
 // Re-export the BayesianNetwork for easy access
@@ -0,0 +1,385 @@
+use crate::qbbn::common::redis::MockConnection as Connection;
+use log::trace;
+
+use super::{
+    engine::Inferencer,
+    joint::{factorized_log_value, JointFactorGraph, LogFactor, LogSumExp, LogTable, MAX_ENUMERATED_VARIABLES},
+    table::PropositionNode,
+};
+use crate::qbbn::model::objects::Proposition;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BinaryHeap, HashSet},
+    error::Error,
+};
+
+/// Settings for most-probable-explanation queries.
+#[derive(Debug, Clone, Copy)]
+pub struct MapConfig {
+    /// How many ranked assignments to return.
+    pub top_k: usize,
+    /// Graphs with at most this many free variables are solved by enumeration.
+    pub max_exact_variables: usize,
+    /// Iteration cap for max-product message passing.
+    pub max_iterations: usize,
+    /// Max-product stops once no message moves by more than this.
+    pub tolerance: f64,
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        MapConfig {
+            top_k: 1,
+            max_exact_variables: 16,
+            max_iterations: 100,
+            tolerance: 1e-9,
+        }
+    }
+}
+
+impl MapConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.max_exact_variables > MAX_ENUMERATED_VARIABLES {
+            return Err(format!(
+                "max_exact_variables is {}, but at most {} variables can be enumerated.",
+                self.max_exact_variables, MAX_ENUMERATED_VARIABLES
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapMethod {
+    Exact,
+    MaxProduct,
+}
+
+/// One full assignment of the graph, with its score.
+#[derive(Debug, Clone)]
+pub struct RankedAssignment {
+    pub assignment: BTreeMap<PropositionNode, bool>,
+    /// Unnormalized log joint probability, evidence included.
+    pub log_score: f64,
+    /// Posterior probability of this assignment given the evidence. Only known for exact queries.
+    pub probability: Option<f64>,
+}
+
+impl RankedAssignment {
+    pub fn value_of(&self, proposition: &Proposition) -> Option<bool> {
+        self.assignment
+            .get(&PropositionNode::from_single(proposition))
+            .copied()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MapResult {
+    /// Best first.
+    pub assignments: Vec<RankedAssignment>,
+    pub method: MapMethod,
+    /// False if max-product hit `max_iterations`. Always true for exact queries.
+    pub converged: bool,
+}
+
+impl Inferencer {
+    /// The jointly most probable assignment to all unobserved nodes given the evidence.
+    pub fn most_probable_explanation(
+        &self,
+        connection: &mut Connection,
+    ) -> Result<RankedAssignment, Box<dyn Error>> {
+        let result = self.top_k_explanations(connection, &MapConfig::default())?;
+        Ok(result.assignments.into_iter().next().unwrap())
+    }
+
+    /// The `config.top_k` most probable full assignments, best first.
+    ///
+    /// Small graphs are enumerated exactly. Larger ones use max-product message passing,
+    /// and the runners-up are found by a best-first search over single flips from the
+    /// decoded optimum, so they are approximate. Graphs with a categorical variable are
+    /// rejected with an error (see `build_joint_factor_graph`).
+    pub fn top_k_explanations(
+        &self,
+        connection: &mut Connection,
+        config: &MapConfig,
+    ) -> Result<MapResult, Box<dyn Error>> {
+        config.validate()?;
+        let joint = self.build_joint_factor_graph(connection)?;
+        let free_count = joint.free_variables().len();
+        trace!("top_k_explanations: {} free variables", free_count);
+        if free_count <= config.max_exact_variables {
+            Ok(exact_top_k(&joint, config.top_k))
+        } else {
+            Ok(max_product_top_k(&joint, config))
+        }
+    }
+}
+
+fn ranked(joint: &JointFactorGraph, values: &[bool], log_score: f64, probability: Option<f64>) -> RankedAssignment {
+    RankedAssignment {
+        assignment: joint.assignment_map(values),
+        log_score,
+        probability,
+    }
+}
+
+/// An assignment and its log score, ordered by score.
+struct Scored(f64, Vec<bool>);
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Enumerates every assignment, keeping only the `top_k` best, so memory does not grow
+/// with the number of assignments.
+pub fn exact_top_k(joint: &JointFactorGraph, top_k: usize) -> MapResult {
+    let top_k = top_k.max(1);
+    let mut best: BinaryHeap<Reverse<Scored>> = BinaryHeap::with_capacity(top_k + 1);
+    let mut normalizer = LogSumExp::default();
+    joint.for_each_assignment(|values| {
+        let score = joint.log_score(values);
+        normalizer.add(score);
+        if best.len() < top_k {
+            best.push(Reverse(Scored(score, values.to_vec())));
+        } else if best.peek().is_some_and(|worst| score > worst.0 .0) {
+            best.pop();
+            best.push(Reverse(Scored(score, values.to_vec())));
+        }
+    });
+    let log_normalizer = normalizer.value();
+    let assignments = best
+        .into_sorted_vec()
+        .iter()
+        .map(|Reverse(Scored(score, values))| ranked(joint, values, *score, Some((score - log_normalizer).exp())))
+        .collect();
+    MapResult {
+        assignments,
+        method: MapMethod::Exact,
+        converged: true,
+    }
+}
+
+pub fn max_product_top_k(joint: &JointFactorGraph, config: &MapConfig) -> MapResult {
+    let (mut best, converged) = max_product_decode(joint, config);
+    improve_by_flips(joint, &mut best);
+
+    // Best-first search outward from the decoded optimum.
+    let free = joint.free_variables();
+    let mut seen: HashSet<Vec<bool>> = HashSet::new();
+    let mut frontier: Vec<(f64, Vec<bool>)> = vec![(joint.log_score(&best), best.clone())];
+    seen.insert(best);
+    let mut assignments = vec![];
+    while assignments.len() < config.top_k.max(1) && !frontier.is_empty() {
+        let best_index = (0..frontier.len())
+            .max_by(|a, b| frontier[*a].0.total_cmp(&frontier[*b].0))
+            .unwrap();
+        let (score, values) = frontier.swap_remove(best_index);
+        for variable in &free {
+            let mut neighbour = values.clone();
+            neighbour[*variable] = !neighbour[*variable];
+            if seen.insert(neighbour.clone()) {
+                frontier.push((joint.log_score(&neighbour), neighbour));
+            }
+        }
+        assignments.push(ranked(joint, &values, score, None));
+    }
+    assignments.sort_by(|a, b| b.log_score.total_cmp(&a.log_score));
+    MapResult {
+        assignments,
+        method: MapMethod::MaxProduct,
+        converged,
+    }
+}
+
+/// Loopy max-product in log space. Returns the arg-max of the max-marginals.
+fn max_product_decode(joint: &JointFactorGraph, config: &MapConfig) -> (Vec<bool>, bool) {
+    let variable_count = joint.variables.len();
+    let mut variable_factors: Vec<Vec<(usize, usize)>> = vec![vec![]; variable_count];
+    for (factor_index, factor) in joint.factors.iter().enumerate() {
+        for (position, variable) in factor.scope.iter().enumerate() {
+            variable_factors[*variable].push((factor_index, position));
+        }
+    }
+    let unary: Vec<[f64; 2]> = (0..variable_count)
+        .map(|variable| match joint.clamped[variable] {
+            Some(true) => [f64::NEG_INFINITY, 0f64],
+            Some(false) => [0f64, f64::NEG_INFINITY],
+            None => joint.unary[variable],
+        })
+        .collect();
+    let mut to_factor: Vec<Vec<[f64; 2]>> = joint
+        .factors
+        .iter()
+        .map(|factor| vec![[0f64, 0f64]; factor.scope.len()])
+        .collect();
+    let mut to_variable = to_factor.clone();
+
+    let mut converged = false;
+    for iteration in 0..config.max_iterations {
+        let mut largest_change = 0f64;
+        for (factor_index, factor) in joint.factors.iter().enumerate() {
+            let incoming_messages = &to_factor[factor_index];
+            for (position, outgoing) in to_variable[factor_index].iter_mut().enumerate() {
+                let message = normalize(factor_max_message(factor, incoming_messages, position));
+                largest_change = largest_change.max(message_change(outgoing, &message));
+                *outgoing = message;
+            }
+        }
+        for (variable, links) in variable_factors.iter().enumerate() {
+            for (this_factor, this_position) in links {
+                let mut message = unary[variable];
+                for (other_factor, other_position) in links {
+                    if other_factor != this_factor {
+                        for outcome in 0..2 {
+                            message[outcome] += to_variable[*other_factor][*other_position][outcome];
+                        }
+                    }
+                }
+                to_factor[*this_factor][*this_position] = normalize(message);
+            }
+        }
+        trace!("max_product iteration {} change {}", iteration, largest_change);
+        if largest_change <= config.tolerance {
+            converged = true;
+            break;
+        }
+    }
+
+    let values = (0..variable_count)
+        .map(|variable| {
+            let mut belief = unary[variable];
+            for (factor_index, position) in &variable_factors[variable] {
+                for outcome in 0..2 {
+                    belief[outcome] += to_variable[*factor_index][*position][outcome];
+                }
+            }
+            belief[1] > belief[0]
+        })
+        .collect();
+    (values, converged)
+}
+
+/// Greedy single-variable flips until no flip raises the joint score. Loopy max-product
+/// can decode an inconsistent assignment; this repairs it to a local optimum.
+fn improve_by_flips(joint: &JointFactorGraph, values: &mut [bool]) {
+    let free = joint.free_variables();
+    let mut score = joint.log_score(values);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for variable in &free {
+            values[*variable] = !values[*variable];
+            let flipped = joint.log_score(values);
+            if flipped > score {
+                score = flipped;
+                improved = true;
+            } else {
+                values[*variable] = !values[*variable];
+            }
+        }
+    }
+}
+
+/// The max-product message from `factor` to its variable at `position`.
+fn factor_max_message(factor: &LogFactor, incoming_messages: &[[f64; 2]], position: usize) -> [f64; 2] {
+    let mut message = [f64::NEG_INFINITY, f64::NEG_INFINITY];
+    match &factor.table {
+        LogTable::Dense(table) => {
+            for (entry, value) in table.iter().enumerate() {
+                let mut total = *value;
+                for (other, incoming) in incoming_messages.iter().enumerate() {
+                    if other != position {
+                        total += incoming[(entry >> other) & 1];
+                    }
+                }
+                let outcome = (entry >> position) & 1;
+                message[outcome] = message[outcome].max(total);
+            }
+        }
+        LogTable::Factorized(cpd) => {
+            let terms = incoming_messages.len() - 1;
+            let others: Vec<[f64; 2]> = incoming_messages[1..]
+                .iter()
+                .enumerate()
+                .filter(|(term, _)| term + 1 != position)
+                .map(|(_, incoming)| *incoming)
+                .collect();
+            let best = best_with_true_count(&others);
+            for child in [false, true] {
+                for (true_others, others_score) in best.iter().enumerate() {
+                    if position == 0 {
+                        let total = factorized_log_value(cpd, child, true_others, terms) + others_score;
+                        message[child as usize] = message[child as usize].max(total);
+                        continue;
+                    }
+                    let child_score = incoming_messages[0][child as usize] + others_score;
+                    for term in [false, true] {
+                        let true_terms = true_others + term as usize;
+                        let total = factorized_log_value(cpd, child, true_terms, terms) + child_score;
+                        message[term as usize] = message[term as usize].max(total);
+                    }
+                }
+            }
+        }
+    }
+    message
+}
+
+/// Entry `k` is the best total of `incoming` messages over the assignments with exactly
+/// `k` true variables: the `k` that gain the most from being true are set true.
+fn best_with_true_count(incoming: &[[f64; 2]]) -> Vec<f64> {
+    let mut order: Vec<&[f64; 2]> = incoming.iter().collect();
+    order.sort_by(|a, b| (b[1] - b[0]).total_cmp(&(a[1] - a[0])));
+    // Sums of true values over a prefix and of false values over the rest, so that no
+    // infinite gains are subtracted.
+    let mut false_suffix = vec![0f64; order.len() + 1];
+    for index in (0..order.len()).rev() {
+        false_suffix[index] = false_suffix[index + 1] + order[index][0];
+    }
+    let mut true_prefix = 0f64;
+    let mut best = Vec::with_capacity(order.len() + 1);
+    for (index, message) in order.iter().enumerate() {
+        best.push(true_prefix + false_suffix[index]);
+        true_prefix += message[1];
+    }
+    best.push(true_prefix);
+    best
+}
+
+fn normalize(message: [f64; 2]) -> [f64; 2] {
+    let max = message[0].max(message[1]);
+    if max == f64::NEG_INFINITY {
+        return [0f64, 0f64];
+    }
+    [message[0] - max, message[1] - max]
+}
+
+fn message_change(old: &[f64; 2], new: &[f64; 2]) -> f64 {
+    let mut change = 0f64;
+    for outcome in 0..2 {
+        let difference = if old[outcome] == new[outcome] {
+            0f64
+        } else {
+            (old[outcome] - new[outcome]).abs()
+        };
+        change = change.max(difference);
+    }
+    change
+}
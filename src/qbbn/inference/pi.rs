@@ -2,10 +2,10 @@ use crate::qbbn::common::redis::MockConnection as Connection;
 use log::trace;
 
 use super::{
-    engine::{compute_each_combination, Inferencer},
+    engine::{compute_each_outcome_combination, Inferencer},
     table::PropositionNode,
 };
-use crate::qbbn::model::weights::CLASS_LABELS;
+use crate::qbbn::model::objects::FactorizedCpd;
 use std::error::Error;
 
 impl Inferencer {
@@ -36,7 +36,7 @@ impl Inferencer {
         Ok(())
     }
 
-    fn pi_compute_root(&mut self, node: &PropositionNode) -> Result<(), Box<dyn Error>> {
+    pub fn pi_compute_root(&mut self, node: &PropositionNode) -> Result<(), Box<dyn Error>> {
         let root = node.extract_single();
         self.data
             .set_pi_value(&PropositionNode::from_single(&root), 1, 1.0f64);
@@ -52,8 +52,7 @@ impl Inferencer {
     ) -> Result<(), Box<dyn Error>> {
         let as_single = node.extract_single();
         let probability = self
-            .fact_memory
-            .get_proposition_probability(connection, &as_single)?
+            .observed_probability(connection, &as_single)?
             .unwrap();
         self.data.set_pi_value(node, 1, probability);
         self.data.set_pi_value(node, 0, 1f64 - probability);
@@ -67,15 +66,35 @@ impl Inferencer {
     ) -> Result<(), Box<dyn Error>> {
         let is_observed = self.is_observed(connection, node)?;
         assert!(!is_observed);
+        if let Some(prior) = self.proposition_graph.frontier_prior(node) {
+            self.data.set_pi_value(node, 1, prior);
+            self.data.set_pi_value(node, 0, 1f64 - prior);
+            return Ok(());
+        }
         let parent_nodes = self.proposition_graph.get_all_backward(node);
-        let all_combinations = compute_each_combination(&parent_nodes);
-        let mut sum_true = 0f64;
-        let mut sum_false = 0f64;
+        if let Some(cpd) = self.factorized_cpd(node) {
+            let messages: Vec<[f64; 2]> = parent_nodes
+                .iter()
+                .map(|parent| {
+                    [0, 1].map(|outcome| self.data.get_pi_message(parent, node, outcome).unwrap())
+                })
+                .collect();
+            let [pi0, pi1] = factorized_pi(&cpd, &messages);
+            trace!("factorized pi for {:?} over {} terms: {} {}", node, messages.len(), pi0, pi1);
+            self.data.set_pi_value(node, 0, pi0);
+            self.data.set_pi_value(node, 1, pi1);
+            return Ok(());
+        }
+        let parent_counts: Vec<usize> = parent_nodes
+            .iter()
+            .map(|parent| self.outcome_count(parent))
+            .collect();
+        let all_combinations = compute_each_outcome_combination(&parent_nodes, &parent_counts);
+        let mut sums = vec![0f64; self.outcome_count(node)];
         for combination in &all_combinations {
             let mut product = 1f64;
             for parent_node in parent_nodes.iter() {
-                let boolean_outcome = combination.get(parent_node).unwrap();
-                let usize_outcome = if *boolean_outcome { 1 } else { 0 };
+                let usize_outcome = *combination.get(parent_node).unwrap();
                 let pi_x_z = self
                     .data
                     .get_pi_message(parent_node, node, usize_outcome)
@@ -89,36 +108,52 @@ impl Inferencer {
                 );
                 product *= pi_x_z;
             }
-            let true_marginal = self.score_factor_assignment(connection, &parent_nodes, combination, node)?;
-            let false_marginal = 1f64 - true_marginal;
-            sum_true += true_marginal * product;
-            sum_false += false_marginal * product;
+            let distribution =
+                self.score_factor_distribution(connection, &parent_nodes, combination, node)?;
+            for (outcome, probability) in distribution.iter().enumerate() {
+                sums[outcome] += probability * product;
+            }
+        }
+        for (outcome, sum) in sums.into_iter().enumerate() {
+            self.data.set_pi_value(node, outcome, sum);
         }
-        self.data.set_pi_value(node, 1, sum_true);
-        self.data.set_pi_value(node, 0, sum_false);
         Ok(())
     }
 
     pub fn pi_send_messages(&mut self, node: &PropositionNode) -> Result<(), Box<dyn Error>> {
         let forward_groups = self.proposition_graph.get_all_forward(node);
         for (this_index, to_node) in forward_groups.iter().enumerate() {
-            for class_label in &CLASS_LABELS {
+            for class_label in 0..self.outcome_count(node) {
                 let mut lambda_part = 1f64;
                 for (other_index, other_child) in forward_groups.iter().enumerate() {
                     if other_index != this_index {
                         let this_lambda = self
                             .data
-                            .get_lambda_message(other_child, node, *class_label)
+                            .get_lambda_message(other_child, node, class_label)
                             .unwrap();
                         lambda_part *= this_lambda;
                     }
                 }
-                let pi_part = self.data.get_pi_value(node, *class_label).unwrap();
+                let pi_part = self.data.get_pi_value(node, class_label).unwrap();
                 let message = pi_part * lambda_part;
                 self.data
-                    .set_pi_message(node, to_node, *class_label, message);
+                    .set_pi_message(node, to_node, class_label, message);
             }
         }
         Ok(())
     }
 }
+
+/// The `[false, true]` pi of a group under `cpd`, from the `[false, true]` pi messages of
+/// its terms: the same sum as `pi_compute_value`, in closed form.
+pub fn factorized_pi(cpd: &FactorizedCpd, messages: &[[f64; 2]]) -> [f64; 2] {
+    let mut mass = 1f64;
+    let mut product = 1f64;
+    for [pi0, pi1] in messages {
+        let total = pi0 + pi1;
+        mass *= total;
+        product *= cpd.term_factor(if total > 0f64 { pi1 / total } else { 0f64 });
+    }
+    let probability = cpd.probability(product);
+    [mass * (1f64 - probability), mass * probability]
+}
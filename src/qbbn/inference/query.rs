@@ -0,0 +1,356 @@
+use crate::qbbn::common::redis::MockConnection as Connection;
+use log::trace;
+
+use super::{
+    engine::{Inferencer, MarginalTable},
+    joint::{log_sum_exp, JointFactorGraph, LogSumExp, MAX_ENUMERATED_VARIABLES},
+    table::PropositionNode,
+};
+use crate::qbbn::{
+    common::{interface::BeliefTable, proposition_db::OverlayBeliefTable},
+    model::objects::Proposition,
+};
+use std::{error::Error, sync::Arc};
+
+/// How a multi-proposition query was answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryMethod {
+    /// Summed over every assignment of the graph. Exact.
+    Exact,
+    /// Chain rule, with each factor read off a fresh run of belief propagation in which
+    /// the earlier propositions are clamped as evidence. Approximate on loopy graphs.
+    Clamping,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueryConfig {
+    /// Graphs with at most this many free variables are answered by enumeration.
+    pub max_exact_variables: usize,
+    /// Forward/backward passes per clamped run.
+    pub clamping_passes: usize,
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        QueryConfig {
+            max_exact_variables: 16,
+            clamping_passes: 10,
+        }
+    }
+}
+
+impl QueryConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.max_exact_variables > MAX_ENUMERATED_VARIABLES {
+            return Err(format!(
+                "max_exact_variables is {}, but at most {} variables can be enumerated.",
+                self.max_exact_variables, MAX_ENUMERATED_VARIABLES
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// A joint table has 2^n rows, so it is only built for this many propositions.
+pub const MAX_JOINT_TABLE_PROPOSITIONS: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProbabilityQueryResult {
+    pub probability: f64,
+    pub method: QueryMethod,
+}
+
+/// The joint distribution of a set of propositions given the current evidence.
+#[derive(Debug, Clone)]
+pub struct JointTable {
+    pub propositions: Vec<Proposition>,
+    /// One entry per assignment to `propositions`, in the same order.
+    pub entries: Vec<(Vec<bool>, f64)>,
+    pub method: QueryMethod,
+}
+
+impl JointTable {
+    pub fn probability_of(&self, values: &[bool]) -> Option<f64> {
+        self.entries
+            .iter()
+            .find(|(assignment, _)| assignment.as_slice() == values)
+            .map(|(_, probability)| *probability)
+    }
+
+    /// P(propositions[index] = true).
+    pub fn marginal(&self, index: usize) -> f64 {
+        self.entries
+            .iter()
+            .filter(|(assignment, _)| assignment[index])
+            .map(|(_, probability)| probability)
+            .sum()
+    }
+
+    /// Pearson correlation between two of the table's propositions.
+    pub fn correlation(&self, first: usize, second: usize) -> f64 {
+        let p_first = self.marginal(first);
+        let p_second = self.marginal(second);
+        let p_both: f64 = self
+            .entries
+            .iter()
+            .filter(|(assignment, _)| assignment[first] && assignment[second])
+            .map(|(_, probability)| probability)
+            .sum();
+        let spread = (p_first * (1f64 - p_first) * p_second * (1f64 - p_second)).sqrt();
+        if spread == 0f64 {
+            return 0f64;
+        }
+        (p_both - p_first * p_second) / spread
+    }
+}
+
+impl Inferencer {
+    /// P(targets | evidence), e.g. P(A ∧ ¬B | E).
+    pub fn joint_probability(
+        &self,
+        connection: &mut Connection,
+        targets: &[(Proposition, bool)],
+        config: &QueryConfig,
+    ) -> Result<ProbabilityQueryResult, Box<dyn Error>> {
+        self.conditional_probability(connection, targets, &[], config)
+    }
+
+    /// P(targets | given, evidence). `given` is conditioned on in addition to the fact memory.
+    ///
+    /// A graph with a categorical variable is always answered by clamping, since exact
+    /// enumeration is over boolean variables only.
+    pub fn conditional_probability(
+        &self,
+        connection: &mut Connection,
+        targets: &[(Proposition, bool)],
+        given: &[(Proposition, bool)],
+        config: &QueryConfig,
+    ) -> Result<ProbabilityQueryResult, Box<dyn Error>> {
+        config.validate()?;
+        if let Some(joint) = self.exact_joint(connection, config)? {
+            let target_indices = variable_indices(&joint, targets)?;
+            let given_indices = variable_indices(&joint, given)?;
+            let mut numerator = LogSumExp::default();
+            let mut denominator = LogSumExp::default();
+            joint.for_each_assignment(|values| {
+                if !matches_all(values, &given_indices) {
+                    return;
+                }
+                let score = joint.log_score(values);
+                denominator.add(score);
+                if matches_all(values, &target_indices) {
+                    numerator.add(score);
+                }
+            });
+            let log_denominator = denominator.value();
+            if log_denominator == f64::NEG_INFINITY {
+                return Err("Conditioning event has zero probability.".into());
+            }
+            let probability = (numerator.value() - log_denominator).exp();
+            return Ok(ProbabilityQueryResult {
+                probability,
+                method: QueryMethod::Exact,
+            });
+        }
+        let probability = self.chain_rule_probability(connection, targets, given, config)?;
+        Ok(ProbabilityQueryResult {
+            probability,
+            method: QueryMethod::Clamping,
+        })
+    }
+
+    /// The full joint table of `propositions` given the evidence. Two propositions give
+    /// the pairwise table. Like `conditional_probability`, graphs with a categorical
+    /// variable are answered by clamping.
+    pub fn joint_table(
+        &self,
+        connection: &mut Connection,
+        propositions: &[Proposition],
+        config: &QueryConfig,
+    ) -> Result<JointTable, Box<dyn Error>> {
+        config.validate()?;
+        let assignments = all_assignments(propositions.len())?;
+        if let Some(joint) = self.exact_joint(connection, config)? {
+            let unassigned: Vec<(Proposition, bool)> =
+                propositions.iter().map(|p| (p.clone(), true)).collect();
+            let indices: Vec<usize> = variable_indices(&joint, &unassigned)?
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect();
+            let mut buckets = vec![LogSumExp::default(); assignments.len()];
+            joint.for_each_assignment(|values| {
+                let mut bucket = 0usize;
+                for (position, index) in indices.iter().enumerate() {
+                    if values[*index] {
+                        bucket |= 1 << position;
+                    }
+                }
+                buckets[bucket].add(joint.log_score(values));
+            });
+            let bucket_scores: Vec<f64> = buckets.iter().map(LogSumExp::value).collect();
+            let log_normalizer = log_sum_exp(&bucket_scores);
+            let entries = assignments
+                .into_iter()
+                .zip(bucket_scores)
+                .map(|(assignment, score)| (assignment, (score - log_normalizer).exp()))
+                .collect();
+            return Ok(JointTable {
+                propositions: propositions.to_vec(),
+                entries,
+                method: QueryMethod::Exact,
+            });
+        }
+        let mut entries = vec![];
+        for assignment in assignments {
+            let targets: Vec<(Proposition, bool)> = propositions
+                .iter()
+                .cloned()
+                .zip(assignment.iter().copied())
+                .collect();
+            let probability = self.chain_rule_probability(connection, &targets, &[], config)?;
+            entries.push((assignment, probability));
+        }
+        Ok(JointTable {
+            propositions: propositions.to_vec(),
+            entries,
+            method: QueryMethod::Clamping,
+        })
+    }
+
+    /// The joint factor graph, if the query can be answered by enumerating it.
+    fn exact_joint(
+        &self,
+        connection: &mut Connection,
+        config: &QueryConfig,
+    ) -> Result<Option<JointFactorGraph>, Box<dyn Error>> {
+        if self.has_categorical_variables() {
+            return Ok(None);
+        }
+        let joint = self.build_joint_factor_graph(connection)?;
+        Ok((joint.free_variables().len() <= config.max_exact_variables).then_some(joint))
+    }
+
+    /// P(t1 ∧ ... ∧ tn | given) as the product of P(ti | t1..ti-1, given), each read from
+    /// a separate inferencer whose fact memory overlays the clamped values.
+    fn chain_rule_probability(
+        &self,
+        connection: &mut Connection,
+        targets: &[(Proposition, bool)],
+        given: &[(Proposition, bool)],
+        config: &QueryConfig,
+    ) -> Result<f64, Box<dyn Error>> {
+        let mut clamped: Vec<(Proposition, bool)> = given.to_vec();
+        let mut probability = 1f64;
+        for (proposition, value) in targets {
+            let marginal = self.clamped_marginal(connection, &clamped, proposition, config)?;
+            probability *= if *value { marginal } else { 1f64 - marginal };
+            trace!(
+                "chain_rule_probability: P({:?}={} | {:?}) = {}",
+                proposition,
+                value,
+                &clamped,
+                marginal
+            );
+            if probability == 0f64 {
+                break;
+            }
+            clamped.push((proposition.clone(), *value));
+        }
+        Ok(probability)
+    }
+
+    fn clamped_marginal(
+        &self,
+        connection: &mut Connection,
+        clamped: &[(Proposition, bool)],
+        proposition: &Proposition,
+        config: &QueryConfig,
+    ) -> Result<f64, Box<dyn Error>> {
+        let table = self.clamped_marginal_table(connection, clamped, config.clamping_passes)?;
+        table
+            .get_marginal(proposition)
+            .ok_or_else(|| format!("{:?} is not in the proposition graph.", proposition).into())
+    }
+
+    /// Every marginal under do(interventions), given `evidence` on top of the fact memory
+    /// and the virtual evidence: the factors into each intervened proposition are cut (see
+    /// `PropositionGraph::with_interventions`) before belief propagation. Unlike
+    /// observing a proposition, intervening on it says nothing about its causes, so any
+    /// virtual evidence on it is dropped. This inferencer and its graph are left untouched.
+    pub fn intervention_marginals(
+        &self,
+        connection: &mut Connection,
+        interventions: &[(Proposition, bool)],
+        evidence: &[(Proposition, bool)],
+        config: &QueryConfig,
+    ) -> Result<MarginalTable, Box<dyn Error>> {
+        let graph = self.proposition_graph.with_interventions(interventions)?;
+        let mut surgery = Inferencer::new_mutable(self.model.clone(), Arc::new(graph), self.fact_memory.clone())?;
+        surgery.virtual_evidence = self.virtual_evidence.clone();
+        for (intervened, _) in interventions {
+            surgery.virtual_evidence.remove(&PropositionNode::from_single(intervened));
+        }
+        surgery.clamped_marginal_table(connection, evidence, config.clamping_passes)
+    }
+
+    /// Every marginal after `passes` of belief propagation with `clamped` observed on
+    /// top of the fact memory and this inferencer's virtual evidence. As with a hard
+    /// observation, virtual evidence on a clamped proposition is dropped. This inferencer
+    /// and its fact memory are left untouched.
+    pub fn clamped_marginal_table(
+        &self,
+        connection: &mut Connection,
+        clamped: &[(Proposition, bool)],
+        passes: usize,
+    ) -> Result<MarginalTable, Box<dyn Error>> {
+        let fact_memory = OverlayBeliefTable::new(self.fact_memory.clone());
+        let mut virtual_evidence = self.virtual_evidence.clone();
+        for (clamped_proposition, value) in clamped {
+            fact_memory.store_proposition_boolean(connection, clamped_proposition, *value)?;
+            virtual_evidence.remove(&PropositionNode::from_single(clamped_proposition));
+        }
+        let mut inferencer = Inferencer::new_mutable(
+            self.model.clone(),
+            self.proposition_graph.clone(),
+            fact_memory,
+        )?;
+        inferencer.virtual_evidence = virtual_evidence;
+        inferencer.initialize_chart(connection)?;
+        for _ in 0..passes {
+            inferencer.do_full_forward_and_backward(connection)?;
+        }
+        inferencer.build_marginal_table()
+    }
+}
+
+fn variable_indices(
+    joint: &JointFactorGraph,
+    propositions: &[(Proposition, bool)],
+) -> Result<Vec<(usize, bool)>, Box<dyn Error>> {
+    let mut result = vec![];
+    for (proposition, value) in propositions {
+        let index = joint
+            .variable_index(&PropositionNode::from_single(proposition))
+            .ok_or_else(|| format!("{:?} is not in the proposition graph.", proposition))?;
+        result.push((index, *value));
+    }
+    Ok(result)
+}
+
+fn matches_all(values: &[bool], required: &[(usize, bool)]) -> bool {
+    required.iter().all(|(index, value)| values[*index] == *value)
+}
+
+fn all_assignments(size: usize) -> Result<Vec<Vec<bool>>, Box<dyn Error>> {
+    if size > MAX_JOINT_TABLE_PROPOSITIONS {
+        return Err(format!(
+            "A joint table of {} propositions is too large, the limit is {}.",
+            size, MAX_JOINT_TABLE_PROPOSITIONS
+        )
+        .into());
+    }
+    Ok((0..(1usize << size))
+        .map(|bits| (0..size).map(|position| bits & (1 << position) != 0).collect())
+        .collect())
+}
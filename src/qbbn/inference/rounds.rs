@@ -4,14 +4,14 @@ use crate::qbbn::common::redis::MockConnection as Connection;
 
 use crate::qbbn::common::{model::InferenceModel, proposition_db::EmptyBeliefTable, test::ReplState};
 
-use super::{graph::PropositionGraph, engine::{Inferencer, MarginalTable}, table::PropositionNode};
+use super::{graph::PropositionGraph, engine::{Inferencer, MarginalTable}, sensitivity::{SensitivityConfig, SensitivityReport}, table::PropositionNode};
 
 fn setup_test_scenario(
     connection: &mut Connection,
     scenario_name: &str,
     test_scenario: &str,
     repl_state: &mut ReplState,
-) -> Result<Option<PropositionNode>, Box<dyn Error>> {
+) -> Result<Vec<PropositionNode>, Box<dyn Error>> {
     let pairs = match (scenario_name, test_scenario) {
         ("dating_simple", "prior") => vec![],
         ("dating_simple", "jack_lonely") => vec![("lonely[sub=test_Man0]", 1f64)],
@@ -48,11 +48,13 @@ pub fn run_inference_rounds(
     let mut repl = ReplState::new(inferencer);
     let mut buffer = vec![];
     buffer.push(repl.inferencer.log_table_to_file()?);
-    let evidence_node = setup_test_scenario(connection, scenario_name, test_scenario, &mut repl)?;
-    if evidence_node.is_some() {
+    let evidence_nodes = setup_test_scenario(connection, scenario_name, test_scenario, &mut repl)?;
+    if !evidence_nodes.is_empty() {
         for _i in 0..50 {
-            repl.inferencer
-                .do_fan_out_from_node(connection, &evidence_node.clone().unwrap())?;
+            for evidence_node in &evidence_nodes {
+                repl.inferencer
+                    .do_fan_out_from_node(connection, evidence_node)?;
+            }
             buffer.push(repl.inferencer.log_table_to_file()?);
         }
     } else {
@@ -64,3 +66,23 @@ pub fn run_inference_rounds(
     }
     Ok(buffer)
 }
+
+/// Sensitivity of the scenario's target to its evidence and weights, under the evidence
+/// of `test_scenario`.
+pub fn run_sensitivity(
+    connection: &mut Connection,
+    scenario_name: &str,
+    test_scenario: &str,
+) -> Result<SensitivityReport, Box<dyn Error>> {
+    let model = InferenceModel::new_shared(scenario_name.to_string()).unwrap();
+    let fact_memory = EmptyBeliefTable::new_shared(scenario_name)?;
+    let target = model.graph.get_target(connection)?;
+    let proposition_graph = PropositionGraph::new_shared(connection, &model.graph, target.clone())?;
+    let mut inferencer =
+        Inferencer::new_mutable(model.clone(), proposition_graph.clone(), fact_memory)?;
+    inferencer.initialize_chart(connection)?;
+    let mut repl = ReplState::new(inferencer);
+    setup_test_scenario(connection, scenario_name, test_scenario, &mut repl)?;
+    repl.inferencer
+        .sensitivity(connection, &target, &SensitivityConfig::default())
+}
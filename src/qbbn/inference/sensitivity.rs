@@ -0,0 +1,309 @@
+use crate::qbbn::common::redis::MockConnection as Connection;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    sync::Arc,
+};
+
+use super::{
+    engine::{
+        build_factor_context_for_assignment, build_factor_context_for_conjunction,
+        Inferencer,
+    },
+    table::{GenericNodeType, PropositionNode},
+};
+use crate::qbbn::common::{
+    interface::BeliefTable,
+    model::InferenceModel,
+    proposition_db::OverlayBeliefTable,
+};
+use crate::qbbn::model::{
+    exponential::features_for_outcomes,
+    objects::{Proposition, PropositionGroup},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct SensitivityConfig {
+    /// Evidence probabilities are moved this far either way, within [0, 1].
+    pub evidence_step: f64,
+    /// Feature weights are moved this far either way.
+    pub weight_step: f64,
+    /// Forward/backward passes per perturbed run.
+    pub passes: usize,
+}
+
+impl Default for SensitivityConfig {
+    fn default() -> Self {
+        SensitivityConfig {
+            evidence_step: 0.05,
+            weight_step: 0.1,
+            passes: 10,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SensitivitySource {
+    /// The probability of an observed proposition.
+    Evidence(Proposition),
+    /// A feature weight of the `ExponentialModel`.
+    Weight(String),
+}
+
+impl SensitivitySource {
+    pub fn label(&self) -> String {
+        match self {
+            SensitivitySource::Evidence(proposition) => proposition.hash_string(),
+            SensitivitySource::Weight(feature) => feature.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SensitivityEntry {
+    pub source: SensitivitySource,
+    /// The current evidence probability or weight.
+    pub value: f64,
+    /// Central finite difference of the target's marginal with respect to `value`.
+    pub derivative: f64,
+    /// The target's marginal at the low and the high end of the perturbation.
+    pub low: f64,
+    pub high: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SensitivityReport {
+    pub target: Proposition,
+    pub marginal: f64,
+    /// Largest absolute derivative first.
+    pub entries: Vec<SensitivityEntry>,
+}
+
+impl SensitivityReport {
+    pub fn render_sensitivity_table(&self) -> String {
+        let mut html_table = format!(
+            "<table><tr><th>{:?}</th><th>{}</th></tr><tr><th>Source</th><th>Value</th><th>Derivative</th><th>Low</th><th>High</th></tr>",
+            self.target, self.marginal
+        );
+        for entry in &self.entries {
+            let kind = match entry.source {
+                SensitivitySource::Evidence(_) => "evidence",
+                SensitivitySource::Weight(_) => "weight",
+            };
+            html_table.push_str(&format!(
+                "<tr><td>{} {}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                kind,
+                entry.source.label(),
+                entry.value,
+                entry.derivative,
+                entry.low,
+                entry.high
+            ));
+        }
+        html_table.push_str("</table>");
+        html_table
+    }
+}
+
+fn rank(entries: &mut [SensitivityEntry]) {
+    entries.sort_by(|a, b| b.derivative.abs().total_cmp(&a.derivative.abs()));
+}
+
+fn entry(source: SensitivitySource, value: f64, low_value: f64, high_value: f64, low: f64, high: f64) -> SensitivityEntry {
+    let derivative = if high_value > low_value {
+        (high - low) / (high_value - low_value)
+    } else {
+        0f64
+    };
+    SensitivityEntry {
+        source,
+        value,
+        derivative,
+        low,
+        high,
+    }
+}
+
+impl Inferencer {
+    /// How the marginal of `target` responds to each piece of evidence and each feature
+    /// weight, by finite differences. Every perturbation reruns belief propagation from
+    /// scratch in a separate inferencer, so neither the fact memory nor the stored
+    /// weights are written.
+    pub fn sensitivity(
+        &mut self,
+        connection: &mut Connection,
+        target: &Proposition,
+        config: &SensitivityConfig,
+    ) -> Result<SensitivityReport, Box<dyn Error>> {
+        let marginal = self.rerun_for_marginal(connection, target, config.passes)?;
+        let mut entries = self.evidence_sensitivity(connection, target, config)?;
+        entries.extend(self.weight_sensitivity(connection, target, config)?);
+        rank(&mut entries);
+        Ok(SensitivityReport {
+            target: target.clone(),
+            marginal,
+            entries,
+        })
+    }
+
+    /// Sensitivity of `target` to the probability of every observed proposition in the
+    /// graph. Roots, numeric comparisons and interventions are held fixed, so they are left out.
+    /// The perturbed probabilities are layered over the fact memory.
+    pub fn evidence_sensitivity(
+        &self,
+        connection: &mut Connection,
+        target: &Proposition,
+        config: &SensitivityConfig,
+    ) -> Result<Vec<SensitivityEntry>, Box<dyn Error>> {
+        let mut entries = vec![];
+        for node in &self.bfs_order {
+            if !node.is_single() || self.is_root(node) {
+                continue;
+            }
+            let proposition = node.extract_single();
+            if proposition == *target || self.proposition_graph.fixed_value(&proposition).is_some() {
+                continue;
+            }
+            let Some(value) = self.fact_memory.get_proposition_probability(connection, &proposition)? else {
+                continue;
+            };
+            let low_value = (value - config.evidence_step).max(0f64);
+            let high_value = (value + config.evidence_step).min(1f64);
+            let mut marginals = [0f64; 2];
+            for (marginal, probability) in marginals.iter_mut().zip([low_value, high_value]) {
+                let fact_memory = OverlayBeliefTable::new(self.fact_memory.clone());
+                fact_memory.store_proposition_probability(connection, &proposition, probability)?;
+                *marginal = self.scratch_marginal(connection, target, config.passes, self.model.clone(), fact_memory)?;
+            }
+            trace!("evidence_sensitivity {:?}: {:?}", &proposition, &marginals);
+            entries.push(entry(
+                SensitivitySource::Evidence(proposition),
+                value,
+                low_value,
+                high_value,
+                marginals[0],
+                marginals[1],
+            ));
+        }
+        rank(&mut entries);
+        Ok(entries)
+    }
+
+    /// Sensitivity of `target` to every weight that the graph's learned factors read.
+    /// Values are the weights the model predicts with, so they include any delta not
+    /// consolidated yet. The perturbed weights are overrides on a copy of the model.
+    pub fn weight_sensitivity(
+        &self,
+        connection: &mut Connection,
+        target: &Proposition,
+        config: &SensitivityConfig,
+    ) -> Result<Vec<SensitivityEntry>, Box<dyn Error>> {
+        let mut entries = vec![];
+        for feature in self.model_features(connection)? {
+            let value = self.model.model.read_weight(connection, &feature)?;
+            let low_value = value - config.weight_step;
+            let high_value = value + config.weight_step;
+            let mut marginals = [0f64; 2];
+            for (marginal, weight) in marginals.iter_mut().zip([low_value, high_value]) {
+                let model = self
+                    .model
+                    .model
+                    .with_weight_overrides(HashMap::from([(feature.clone(), weight)]))?;
+                let model = Arc::new(InferenceModel {
+                    graph: self.model.graph.clone(),
+                    model: Arc::from(model),
+                });
+                *marginal = self.scratch_marginal(connection, target, config.passes, model, self.fact_memory.clone())?;
+            }
+            trace!("weight_sensitivity {}: {:?}", &feature, &marginals);
+            entries.push(entry(
+                SensitivitySource::Weight(feature),
+                value,
+                low_value,
+                high_value,
+                marginals[0],
+                marginals[1],
+            ));
+        }
+        rank(&mut entries);
+        Ok(entries)
+    }
+
+    /// The marginal of `target` from a fresh inferencer over this graph and its virtual
+    /// evidence, with `model` and `fact_memory` in place of this inferencer's.
+    fn scratch_marginal(
+        &self,
+        connection: &mut Connection,
+        target: &Proposition,
+        passes: usize,
+        model: Arc<InferenceModel>,
+        fact_memory: Arc<dyn BeliefTable>,
+    ) -> Result<f64, Box<dyn Error>> {
+        let mut scratch = Inferencer::new_mutable(model, self.proposition_graph.clone(), fact_memory)?;
+        scratch.virtual_evidence = self.virtual_evidence.clone();
+        scratch.rerun_for_marginal(connection, target, passes)
+    }
+
+    /// The `ExponentialModel` features read when scoring the graph's learned factors,
+    /// sorted by name.
+    pub fn model_features(&self, connection: &mut Connection) -> Result<Vec<String>, Box<dyn Error>> {
+        let graph = &self.proposition_graph;
+        let mut features = BTreeSet::new();
+        for node in &self.bfs_order {
+            let premises = graph.get_all_backward(node);
+            if premises.is_empty() || premises.iter().any(|premise| premise.is_choice()) {
+                continue;
+            }
+            let assignment: BTreeMap<PropositionNode, bool> =
+                premises.iter().map(|premise| (premise.clone(), true)).collect();
+            let context = match &node.node {
+                GenericNodeType::Single(proposition) => {
+                    let groups: Vec<PropositionGroup> = premises
+                        .iter()
+                        .map(|premise| premise.extract_group())
+                        .filter(|group| group.cpd.is_none())
+                        .collect();
+                    if groups.is_empty() {
+                        continue;
+                    }
+                    build_factor_context_for_assignment(graph, &groups, &assignment, proposition)
+                }
+                GenericNodeType::Group(group) => {
+                    if group.cpd.is_some() {
+                        continue;
+                    }
+                    build_factor_context_for_conjunction(graph, &premises, &assignment, group)
+                }
+                GenericNodeType::Choice(choice) => {
+                    let assignment = premises.iter().map(|premise| (premise.clone(), 1)).collect();
+                    let context = self.build_factor_context_for_choice(&premises, &assignment, choice);
+                    for outcome_features in features_for_outcomes(&context, choice.outcomes.len()) {
+                        features.extend(outcome_features.into_keys());
+                    }
+                    continue;
+                }
+            };
+            for class_features in self.model.model.factor_features(connection, &context)? {
+                features.extend(class_features.into_keys());
+            }
+        }
+        Ok(features.into_iter().collect())
+    }
+
+    fn rerun_for_marginal(
+        &mut self,
+        connection: &mut Connection,
+        target: &Proposition,
+        passes: usize,
+    ) -> Result<f64, Box<dyn Error>> {
+        self.initialize_chart(connection)?;
+        for _ in 0..passes {
+            self.do_full_forward_and_backward(connection)?;
+        }
+        self.build_marginal_table()?
+            .get_marginal(target)
+            .ok_or_else(|| format!("{:?} is not in the proposition graph.", target).into())
+    }
+}
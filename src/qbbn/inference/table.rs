@@ -1,6 +1,7 @@
-use crate::qbbn::model::objects::{Proposition, PropositionGroup};
+use crate::qbbn::model::objects::{Proposition, PropositionChoice, PropositionGroup};
 use log::trace;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 
 use colored::*;
 use std::collections::hash_map::DefaultHasher;
@@ -11,6 +12,9 @@ use std::hash::{Hash, Hasher};
 pub enum GenericNodeType {
     Single(Proposition),
     Group(PropositionGroup),
+    /// A categorical variable. Its outcomes are `Single` children, each true exactly
+    /// when the variable takes that value.
+    Choice(PropositionChoice),
 }
 
 #[derive(PartialEq, Eq, Clone)]
@@ -31,12 +35,34 @@ fn hash_group(group: &PropositionGroup) -> u64 {
     hasher.finish() // This returns the hash as u64
 }
 
+fn hash_choice(choice: &PropositionChoice) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    choice.hash(&mut hasher);
+    hasher.finish()
+}
+
 impl Hash for PropositionNode {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.underlying_hash.hash(state);
     }
 }
 
+/// Ordered by the underlying hash, which does not depend on the run, then by the node
+/// itself where hashes collide.
+impl Ord for PropositionNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.underlying_hash
+            .cmp(&other.underlying_hash)
+            .then_with(|| self.debug_string().cmp(&other.debug_string()))
+    }
+}
+
+impl PartialOrd for PropositionNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl PropositionNode {
     pub fn from_single(proposition: &Proposition) -> PropositionNode {
         let underlying_hash = hash_proposition(proposition);
@@ -55,10 +81,18 @@ impl PropositionNode {
         }
     }
 
+    pub fn from_choice(choice: &PropositionChoice) -> PropositionNode {
+        PropositionNode {
+            node: GenericNodeType::Choice(choice.clone()),
+            underlying_hash: hash_choice(choice),
+        }
+    }
+
     pub fn debug_string(&self) -> String {
         let string_part = match &self.node {
             GenericNodeType::Single(proposition) => proposition.debug_string(),
             GenericNodeType::Group(group) => group.debug_string(),
+            GenericNodeType::Choice(choice) => choice.debug_string(),
         };
         string_part.to_string()
     }
@@ -71,6 +105,10 @@ impl PropositionNode {
         matches!(self.node, GenericNodeType::Group(_))
     }
 
+    pub fn is_choice(&self) -> bool {
+        matches!(self.node, GenericNodeType::Choice(_))
+    }
+
     pub fn extract_single(&self) -> Proposition {
         match &self.node {
             GenericNodeType::Single(proposition) => proposition.clone(),
@@ -84,6 +122,13 @@ impl PropositionNode {
             _ => panic!("This is not a group."),
         }
     }
+
+    pub fn extract_choice(&self) -> PropositionChoice {
+        match &self.node {
+            GenericNodeType::Choice(choice) => choice.clone(),
+            _ => panic!("This is not a choice."),
+        }
+    }
 }
 impl fmt::Debug for PropositionNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -251,11 +296,11 @@ impl HashMapBeliefTable {
 }
 
 pub struct VariableAssignment {
-    pub assignment_map: HashMap<PropositionNode, bool>,
+    pub assignment_map: BTreeMap<PropositionNode, bool>,
 }
 
 impl VariableAssignment {
-    pub fn new(assignment_map: HashMap<PropositionNode, bool>) -> VariableAssignment {
+    pub fn new(assignment_map: BTreeMap<PropositionNode, bool>) -> VariableAssignment {
         VariableAssignment { assignment_map }
     }
 }
@@ -0,0 +1,150 @@
+use crate::qbbn::common::redis::MockConnection as Connection;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+use super::engine::{Inferencer, MarginalTable};
+use crate::qbbn::model::objects::{Argument, Entity, Proposition};
+
+/// What a question should help pin down.
+#[derive(Debug, Clone)]
+pub enum QuestionFocus {
+    /// One proposition, usually the graph's target.
+    Target(Proposition),
+    /// Every proposition in the graph with the entity in one of its roles.
+    Entity(Entity),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuestionConfig {
+    /// Forward/backward passes per hypothetical observation.
+    pub passes: usize,
+    /// Fill in `RankedQuestion::text`.
+    pub render_text: bool,
+    /// Keep only this many questions.
+    pub limit: Option<usize>,
+}
+
+impl Default for QuestionConfig {
+    fn default() -> Self {
+        QuestionConfig {
+            passes: 10,
+            render_text: false,
+            limit: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RankedQuestion {
+    pub proposition: Proposition,
+    /// The current marginal of `proposition`.
+    pub probability: f64,
+    /// Expected reduction, in bits, of the entropy of the focus from observing `proposition`.
+    pub value: f64,
+    /// "Is it true that ...?", when `QuestionConfig::render_text` is set.
+    pub text: Option<String>,
+}
+
+/// Entropy in bits of a binary variable that is true with probability `p`.
+pub fn binary_entropy(p: f64) -> f64 {
+    [p, 1f64 - p]
+        .iter()
+        .filter(|q| **q > 0f64)
+        .map(|q| -q * q.log2())
+        .sum()
+}
+
+pub fn question_text(proposition: &Proposition) -> String {
+    format!("Is it true that {}?", proposition.natural_language())
+}
+
+fn mentions(proposition: &Proposition, entity: &Entity) -> bool {
+    proposition.predicate.roles.iter().any(|role| match &role.argument {
+        Argument::Constant(constant) => {
+            constant.domain == entity.domain && constant.entity_id == entity.name
+        }
+        Argument::Variable(_) => false,
+    })
+}
+
+/// Total entropy of `focus` under `table`. An observed proposition contributes nothing.
+fn focus_entropy(table: &MarginalTable, focus: &[Proposition], observed: Option<&Proposition>) -> f64 {
+    focus
+        .iter()
+        .filter(|proposition| Some(*proposition) != observed)
+        .map(|proposition| binary_entropy(table.get_marginal(proposition).unwrap_or(0f64)))
+        .sum()
+}
+
+impl Inferencer {
+    /// The propositions of the graph whose uncertainty `focus` is about.
+    pub fn focus_propositions(&self, focus: &QuestionFocus) -> Vec<Proposition> {
+        match focus {
+            QuestionFocus::Target(target) => vec![target.clone()],
+            QuestionFocus::Entity(entity) => self
+                .bfs_order
+                .iter()
+                .filter(|node| node.is_single())
+                .map(|node| node.extract_single())
+                .filter(|proposition| mentions(proposition, entity))
+                .collect(),
+        }
+    }
+
+    /// Ranks the unobserved propositions of the graph by value of information: how much
+    /// observing each one is expected to reduce the entropy of the focus, averaging the
+    /// true and false answers by the current marginal. Roots and numeric comparisons
+    /// cannot take evidence, so they are never asked about.
+    pub fn value_of_information(
+        &self,
+        connection: &mut Connection,
+        focus: &QuestionFocus,
+        config: &QuestionConfig,
+    ) -> Result<Vec<RankedQuestion>, Box<dyn Error>> {
+        let focus_propositions = self.focus_propositions(focus);
+        if focus_propositions.is_empty() {
+            return Err(format!("Nothing in the proposition graph is about {:?}.", focus).into());
+        }
+        let table = self.clamped_marginal_table(connection, &[], config.passes)?;
+        let prior_entropy = focus_entropy(&table, &focus_propositions, None);
+        let mut questions = vec![];
+        for node in &self.bfs_order {
+            if !node.is_single() || self.is_root(node) {
+                continue;
+            }
+            let proposition = node.extract_single();
+            if matches!(focus, QuestionFocus::Target(target) if *target == proposition)
+                || self.observed_probability(connection, &proposition)?.is_some()
+            {
+                continue;
+            }
+            let probability = table.get_marginal(&proposition).unwrap_or(0f64);
+            let mut expected_entropy = 0f64;
+            for (value, weight) in [(true, probability), (false, 1f64 - probability)] {
+                if weight <= 0f64 {
+                    continue;
+                }
+                let answered = self.clamped_marginal_table(
+                    connection,
+                    &[(proposition.clone(), value)],
+                    config.passes,
+                )?;
+                expected_entropy += weight * focus_entropy(&answered, &focus_propositions, Some(&proposition));
+            }
+            let value = prior_entropy - expected_entropy;
+            trace!("value_of_information {:?}: {}", &proposition, value);
+            questions.push(RankedQuestion {
+                text: config.render_text.then(|| question_text(&proposition)),
+                proposition,
+                probability,
+                value,
+            });
+        }
+        questions.sort_by(|a, b| b.value.total_cmp(&a.value));
+        if let Some(limit) = config.limit {
+            questions.truncate(limit);
+        }
+        Ok(questions)
+    }
+}
@@ -0,0 +1,164 @@
+use crate::qbbn::common::redis::{get_value, set_value, MockConnection as Connection};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Probabilities are kept this far from 0 and 1 before taking their logit.
+const LOGIT_EPSILON: f64 = 1e-9;
+/// Newton steps taken when fitting a Platt calibrator.
+const PLATT_ITERATIONS: usize = 100;
+
+fn logit(probability: f64) -> f64 {
+    let p = probability.clamp(LOGIT_EPSILON, 1f64 - LOGIT_EPSILON);
+    (p / (1f64 - p)).ln()
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1f64 / (1f64 + (-x).exp())
+}
+
+/// A monotone map from predicted probabilities to calibrated ones, fitted on held-out
+/// `(predicted, gold)` pairs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Calibrator {
+    /// `sigmoid(slope * logit(p) + intercept)`.
+    Platt { slope: f64, intercept: f64 },
+    /// Piecewise linear through `(thresholds[i], values[i])`, constant beyond the ends.
+    /// `thresholds` is increasing and `values` non-decreasing.
+    Isotonic { thresholds: Vec<f64>, values: Vec<f64> },
+}
+
+/// Which calibrator a model applies, relative to the one stored for its namespace. The
+/// stored calibrator itself is left as it is.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum CalibratorUpdate {
+    /// Apply the calibrator stored for the namespace, if any.
+    #[default]
+    Keep,
+    /// Apply no calibrator at all.
+    Clear,
+    /// Apply this calibrator instead.
+    Set(Calibrator),
+}
+
+impl From<Option<Calibrator>> for CalibratorUpdate {
+    /// `Set` for a calibrator, and `Clear` for none.
+    fn from(calibrator: Option<Calibrator>) -> Self {
+        match calibrator {
+            Some(calibrator) => CalibratorUpdate::Set(calibrator),
+            None => CalibratorUpdate::Clear,
+        }
+    }
+}
+
+impl Calibrator {
+    pub const CALIBRATOR_KEY: &'static str = "calibrator";
+
+    /// Logistic regression of the gold values on the logit of the predictions, with
+    /// Platt's smoothed targets so that separable data still gives finite parameters.
+    pub fn fit_platt(pairs: &[(f64, f64)]) -> Calibrator {
+        let positives: f64 = pairs.iter().map(|(_, gold)| gold).sum();
+        let negatives = pairs.len() as f64 - positives;
+        let high = (positives + 1f64) / (positives + 2f64);
+        let low = 1f64 / (negatives + 2f64);
+        let (mut slope, mut intercept) = (1f64, 0f64);
+        for _ in 0..PLATT_ITERATIONS {
+            // Gradient and Hessian of the log-loss in (slope, intercept).
+            let (mut ga, mut gb) = (0f64, 0f64);
+            let (mut haa, mut hab, mut hbb) = (1e-12, 0f64, 1e-12);
+            for &(predicted, gold) in pairs {
+                let x = logit(predicted);
+                let target = gold * high + (1f64 - gold) * low;
+                let q = sigmoid(slope * x + intercept);
+                let w = q * (1f64 - q);
+                ga += (q - target) * x;
+                gb += q - target;
+                haa += w * x * x;
+                hab += w * x;
+                hbb += w;
+            }
+            let determinant = haa * hbb - hab * hab;
+            if determinant.abs() < 1e-12 {
+                break;
+            }
+            let da = (hbb * ga - hab * gb) / determinant;
+            let db = (haa * gb - hab * ga) / determinant;
+            slope -= da;
+            intercept -= db;
+            if da.abs() < 1e-10 && db.abs() < 1e-10 {
+                break;
+            }
+        }
+        Calibrator::Platt { slope, intercept }
+    }
+
+    /// Pool-adjacent-violators on the pairs sorted by prediction. Each pooled block
+    /// contributes a knot at its mean prediction and mean gold value.
+    pub fn fit_isotonic(pairs: &[(f64, f64)]) -> Calibrator {
+        let mut sorted = pairs.to_vec();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+        // (sum of predictions, sum of gold values, count) per block.
+        let mut blocks: Vec<(f64, f64, f64)> = vec![];
+        for (predicted, gold) in sorted {
+            blocks.push((predicted, gold, 1f64));
+            while blocks.len() > 1 {
+                let (p2, g2, n2) = blocks[blocks.len() - 1];
+                let (p1, g1, n1) = blocks[blocks.len() - 2];
+                if g1 / n1 < g2 / n2 {
+                    break;
+                }
+                blocks.pop();
+                *blocks.last_mut().unwrap() = (p1 + p2, g1 + g2, n1 + n2);
+            }
+        }
+        let mut thresholds: Vec<f64> = vec![];
+        let mut values: Vec<f64> = vec![];
+        for (predicted, gold, count) in blocks {
+            let threshold = predicted / count;
+            // Blocks with the same mean prediction would make a vertical step.
+            if thresholds.last() == Some(&threshold) {
+                *values.last_mut().unwrap() = gold / count;
+            } else {
+                thresholds.push(threshold);
+                values.push(gold / count);
+            }
+        }
+        Calibrator::Isotonic { thresholds, values }
+    }
+
+    pub fn apply(&self, probability: f64) -> f64 {
+        match self {
+            Calibrator::Platt { slope, intercept } => sigmoid(slope * logit(probability) + intercept),
+            Calibrator::Isotonic { thresholds, values } => {
+                let upper = thresholds.partition_point(|&threshold| threshold < probability);
+                if thresholds.is_empty() {
+                    probability
+                } else if upper == 0 {
+                    values[0]
+                } else if upper == thresholds.len() {
+                    values[upper - 1]
+                } else {
+                    let (x0, x1) = (thresholds[upper - 1], thresholds[upper]);
+                    let (y0, y1) = (values[upper - 1], values[upper]);
+                    y0 + (y1 - y0) * (probability - x0) / (x1 - x0)
+                }
+            }
+        }
+    }
+
+    /// The calibrator stored for `namespace`, if any.
+    pub fn load(connection: &mut Connection, namespace: &str) -> Result<Option<Calibrator>, Box<dyn Error>> {
+        match get_value(connection, namespace, Self::CALIBRATOR_KEY)? {
+            Some(record) => Ok(serde_json::from_str(&record)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores `calibrator` for `namespace`. `None` removes the stored one.
+    pub fn store(
+        connection: &mut Connection,
+        namespace: &str,
+        calibrator: Option<&Calibrator>,
+    ) -> Result<(), Box<dyn Error>> {
+        set_value(connection, namespace, Self::CALIBRATOR_KEY, &serde_json::to_string(&calibrator)?)
+    }
+}
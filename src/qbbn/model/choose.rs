@@ -1,8 +1,13 @@
 use crate::qbbn::common::redis::MockConnection as Connection;
 use log::{debug, trace};
 
-use super::objects::{ImplicationFactor, Proposition};
-use super::ops::{convert_to_proposition, convert_to_quantified, extract_premise_role_map};
+use super::objects::{
+    Argument, BuiltinPredicate, ConstantArgument, CpdType, ImplicationFactor, LabeledArgument, Proposition,
+    Quantifier, Relation,
+};
+use super::ops::{
+    convert_to_aggregate, convert_to_proposition, convert_to_quantified, extract_premise_role_map,
+};
 use crate::qbbn::common::graph::InferenceGraph;
 use crate::qbbn::inference::graph::PropositionFactor;
 use crate::qbbn::model::objects::{GroupRoleMap, PropositionGroup, RoleMap, existence_predicate_name};
@@ -70,6 +75,56 @@ pub fn compute_search_predicates(
     Ok(result)
 }
 
+/// Every way of filling the join variables of `implication` with entities of their
+/// domains. A join variable is a role map key that is not a role of `conclusion`, so it
+/// is shared by the premise terms that map it and grounded apart from the conclusion.
+/// Without join variables, this is the one empty assignment.
+fn join_assignments(
+    connection: &mut Connection,
+    graph: &InferenceGraph,
+    conclusion: &Proposition,
+    implication: &ImplicationFactor,
+) -> Result<Vec<HashMap<String, Argument>>, Box<dyn Error>> {
+    let conclusion_roles = conclusion.predicate.role_names();
+    let mut join_domains: Vec<(String, String)> = vec![];
+    for (term, role_map) in implication.premise.terms.iter().zip(&implication.role_maps.role_maps) {
+        for (variable, premise_role_name) in &role_map.role_map {
+            if conclusion_roles.contains(variable) || join_domains.iter().any(|(name, _)| name == variable) {
+                continue;
+            }
+            let domain = term
+                .roles
+                .iter()
+                .find(|role| &role.role_name == premise_role_name)
+                .and_then(|role| match &role.argument {
+                    Argument::Variable(argument) => Some(argument.domain.clone()),
+                    Argument::Constant(_) => None,
+                })
+                .ok_or_else(|| {
+                    format!("Join variable {} of {:?} fills no variable role.", variable, implication)
+                })?;
+            join_domains.push((variable.clone(), domain));
+        }
+    }
+    let mut assignments = vec![HashMap::new()];
+    for (variable, domain) in &join_domains {
+        let entities = graph.get_entities_in_domain(connection, domain)?;
+        let mut extended = vec![];
+        for assignment in &assignments {
+            for entity in &entities {
+                let mut assignment = assignment.clone();
+                assignment.insert(
+                    variable.clone(),
+                    Argument::Constant(ConstantArgument::new(entity.domain.clone(), entity.name.clone())),
+                );
+                extended.push(assignment);
+            }
+        }
+        assignments = extended;
+    }
+    Ok(assignments)
+}
+
 pub fn extract_backimplications_from_proposition(
     connection: &mut Connection,
     graph: &InferenceGraph,
@@ -79,6 +134,9 @@ pub fn extract_backimplications_from_proposition(
         "Computing backimplications for proposition {:?}",
         conclusion
     );
+    if let Some((quantifier, inner)) = Quantifier::from_relation(&conclusion.predicate.relation) {
+        return extract_aggregate_factor(connection, graph, conclusion, quantifier, inner);
+    }
     let search_keys = compute_search_predicates(conclusion)?;
     trace!("Computed search_keys {:?}", &search_keys);
     let mut backimplications = Vec::new();
@@ -87,30 +145,53 @@ pub fn extract_backimplications_from_proposition(
         let implications = graph.predicate_backward_links(connection, predicate)?;
         trace!("Found implications {:?}", &implications);
         for implication in &implications {
-            let mut terms = Vec::new();
-            for (index, proposition) in implication.premise.terms.iter().enumerate() {
-                trace!("Processing term {}: {:?}", index, proposition);
-                let extracted_mapping =
-                    extract_premise_role_map(conclusion, &implication.role_maps.role_maps[index]);
-                trace!(
-                    "Extracted mapping for term {}: {:?}",
-                    index,
-                    &extracted_mapping
-                );
-                let extracted_proposition =
-                    convert_to_proposition(proposition, &extracted_mapping)?;
-                trace!(
-                    "Converted to proposition for term {}: {:?}",
-                    index,
-                    extracted_proposition
-                );
-                terms.push(extracted_proposition);
+            'groundings: for joins in join_assignments(connection, graph, conclusion, implication)? {
+                let mut terms = Vec::new();
+                let mut negations = Vec::new();
+                for (index, proposition) in implication.premise.terms.iter().enumerate() {
+                    trace!("Processing term {}: {:?}", index, proposition);
+                    let role_map = &implication.role_maps.role_maps[index];
+                    let mut extracted_mapping = extract_premise_role_map(conclusion, role_map);
+                    for (variable, premise_role_name) in &role_map.role_map {
+                        if let Some(entity) = joins.get(variable) {
+                            extracted_mapping.insert(premise_role_name.clone(), entity.clone());
+                        }
+                    }
+                    trace!(
+                        "Extracted mapping for term {}: {:?}",
+                        index,
+                        &extracted_mapping
+                    );
+                    let extracted_proposition =
+                        if Quantifier::from_relation(&proposition.relation).is_some() {
+                            convert_to_aggregate(proposition, &extracted_mapping)
+                        } else {
+                            convert_to_proposition(proposition, &extracted_mapping)?
+                        };
+                    trace!(
+                        "Converted to proposition for term {}: {:?}",
+                        index,
+                        extracted_proposition
+                    );
+                    let negation = implication.premise.negation(index);
+                    if let Some(builtin) = BuiltinPredicate::from_predicate(proposition) {
+                        // Either kind of negation flips a builtin, which is never uncertain.
+                        let holds = graph.evaluate_builtin(connection, &builtin, &extracted_proposition)?;
+                        if holds == negation.is_some() {
+                            trace!("Builtin term {} fails, pruning {:?}", index, &extracted_proposition);
+                            continue 'groundings;
+                        }
+                        continue;
+                    }
+                    terms.push(extracted_proposition);
+                    negations.push(negation);
+                }
+                backimplications.push(PropositionFactor {
+                    premise: PropositionGroup::new_with_negations(terms, negations).with_cpd(&implication.cpd),
+                    conclusion: conclusion.clone(),
+                    inference: implication.clone(),
+                });
             }
-            backimplications.push(PropositionFactor {
-                premise: PropositionGroup { terms },
-                conclusion: conclusion.clone(),
-                inference: implication.clone(),
-            });
         }
     }
     trace!("Returning backimplications {:?}", &backimplications);
@@ -121,6 +202,78 @@ pub fn extract_backimplications_from_proposition(
     Ok(backimplications)
 }
 
+/// The factor of an aggregate: its instances, one for every way of filling its variable
+/// roles with entities of their domains, as one group under the quantifier's CPD.
+fn extract_aggregate_factor(
+    connection: &mut Connection,
+    graph: &InferenceGraph,
+    aggregate: &Proposition,
+    quantifier: Quantifier,
+    inner: Relation,
+) -> Result<Vec<PropositionFactor>, Box<dyn Error>> {
+    let mut instances: Vec<Vec<LabeledArgument>> = vec![vec![]];
+    let mut mapping = HashMap::new();
+    for role in &aggregate.predicate.roles {
+        let fillers = match &role.argument {
+            Argument::Constant(_) => {
+                mapping.insert(role.role_name.clone(), role.role_name.clone());
+                vec![role.clone()]
+            }
+            Argument::Variable(variable) => {
+                let entities = graph.get_entities_in_domain(connection, &variable.domain)?;
+                if entities.is_empty() {
+                    return Err(format!(
+                        "Quantified role {} of {:?} ranges over the empty domain {}.",
+                        role.role_name, aggregate, variable.domain
+                    )
+                    .into());
+                }
+                entities
+                    .into_iter()
+                    .map(|entity| {
+                        role.do_substitution(Argument::Constant(ConstantArgument::new(
+                            entity.domain,
+                            entity.name,
+                        )))
+                    })
+                    .collect()
+            }
+        };
+        instances = instances
+            .iter()
+            .flat_map(|prefix| {
+                fillers.iter().map(move |filler| {
+                    let mut roles = prefix.clone();
+                    roles.push(filler.clone());
+                    roles
+                })
+            })
+            .collect();
+    }
+    let terms = instances
+        .into_iter()
+        .map(|roles| Proposition::from(Predicate::new_from_relation(inner.clone(), roles)))
+        .collect();
+    let quantified_roles: Vec<LabeledArgument> = aggregate
+        .predicate
+        .roles
+        .iter()
+        .map(|role| role.convert_to_quantified())
+        .collect();
+    let inference = ImplicationFactor {
+        premise: PredicateGroup::new(vec![Predicate::new_from_relation(inner, quantified_roles.clone())]),
+        role_maps: GroupRoleMap::new(vec![RoleMap::new(mapping)]),
+        conclusion: Predicate::new_from_relation(aggregate.predicate.relation.clone(), quantified_roles),
+        cpd: quantifier.cpd(),
+    };
+    trace!("extracted aggregate factor {:?}", &inference);
+    Ok(vec![PropositionFactor {
+        premise: PropositionGroup::new(terms).with_cpd(&inference.cpd),
+        conclusion: aggregate.clone(),
+        inference,
+    }])
+}
+
 pub fn extract_existence_factor_for_predicate(
     conclusion: &Predicate,
 ) -> Result<ImplicationFactor, Box<dyn Error>> {
@@ -138,6 +291,7 @@ pub fn extract_existence_factor_for_predicate(
         premise: premise_group,
         role_maps: mapping_group,
         conclusion: conclusion.clone(),
+        cpd: CpdType::Learned,
     };
     trace!("extracted existence predicate {:?}", &factor);
     Ok(factor)
@@ -161,6 +315,7 @@ pub fn extract_existence_factor_for_proposition(
         premise: premise_group,
         role_maps: mapping_group,
         conclusion,
+        cpd: CpdType::Learned,
     };
     trace!("extracted existence predicate {:?}", &factor);
     Ok(factor)
@@ -0,0 +1,96 @@
+use super::device::{OptimizerType, TorchConfig};
+
+/// The learning rate used when nothing else is configured.
+pub const DEFAULT_LEARNING_RATE: f64 = 0.05;
+
+/// How the learning rate changes with the number of training steps taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LearningRateSchedule {
+    Constant,
+    /// Multiply the rate by `factor` every `every` steps.
+    Step { every: usize, factor: f64 },
+    /// `rate / (1 + decay * step)`.
+    InverseTime { decay: f64 },
+}
+
+/// Update rules for the CPU `ExponentialModel`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Optimizer {
+    /// Plain SGD when `momentum` is 0.
+    Sgd { momentum: f64 },
+    AdaGrad { epsilon: f64 },
+    Adam { beta1: f64, beta2: f64, epsilon: f64 },
+    /// Adam with weight decay applied to the weight directly rather than to the gradient.
+    AdamW { beta1: f64, beta2: f64, epsilon: f64, weight_decay: f64 },
+}
+
+impl From<OptimizerType> for Optimizer {
+    fn from(optimizer: OptimizerType) -> Self {
+        match optimizer {
+            OptimizerType::SGD { momentum } => Optimizer::Sgd { momentum },
+            OptimizerType::Adam { beta1, beta2, epsilon } => Optimizer::Adam { beta1, beta2, epsilon },
+            OptimizerType::AdamW { beta1, beta2, epsilon, weight_decay } => Optimizer::AdamW {
+                beta1,
+                beta2,
+                epsilon,
+                weight_decay,
+            },
+        }
+    }
+}
+
+/// Training settings for the CPU `ExponentialModel`. The default is plain SGD at
+/// `DEFAULT_LEARNING_RATE` without regularization.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExponentialTrainingConfig {
+    pub learning_rate: f64,
+    pub schedule: LearningRateSchedule,
+    pub optimizer: Optimizer,
+    /// Strength of the L1 penalty on the weights.
+    pub l1: f64,
+    /// Strength of the L2 penalty on the weights.
+    pub l2: f64,
+    /// Rescale each update's gradient to at most this L2 norm.
+    pub gradient_clip: Option<f64>,
+    /// Seeds the initial weights. `None` draws them from entropy.
+    pub seed: Option<u64>,
+}
+
+impl Default for ExponentialTrainingConfig {
+    fn default() -> Self {
+        ExponentialTrainingConfig {
+            learning_rate: DEFAULT_LEARNING_RATE,
+            schedule: LearningRateSchedule::Constant,
+            optimizer: Optimizer::Sgd { momentum: 0f64 },
+            l1: 0f64,
+            l2: 0f64,
+            gradient_clip: None,
+            seed: None,
+        }
+    }
+}
+
+impl From<&TorchConfig> for ExponentialTrainingConfig {
+    fn from(config: &TorchConfig) -> Self {
+        ExponentialTrainingConfig {
+            learning_rate: config.learning_rate,
+            optimizer: config.optimizer.into(),
+            gradient_clip: config.gradient_clip,
+            seed: config.seed,
+            ..ExponentialTrainingConfig::default()
+        }
+    }
+}
+
+impl ExponentialTrainingConfig {
+    /// The learning rate for the update after `step` earlier ones.
+    pub fn learning_rate_at(&self, step: usize) -> f64 {
+        match self.schedule {
+            LearningRateSchedule::Constant => self.learning_rate,
+            LearningRateSchedule::Step { every, factor } => {
+                self.learning_rate * factor.powi((step / every.max(1)) as i32)
+            }
+            LearningRateSchedule::InverseTime { decay } => self.learning_rate / (1f64 + decay * step as f64),
+        }
+    }
+}
@@ -6,7 +6,27 @@ use crate::qbbn::model::objects::{
 };
 
 pub fn conjunction(terms: Vec<Predicate>) -> PredicateGroup {
-    PredicateGroup { terms }
+    PredicateGroup::new(terms)
+}
+
+/// A premise of possibly negated terms, built with `positive`, `not` and `not_provable`.
+pub fn conjunction_of_literals(literals: Vec<(Predicate, Option<Negation>)>) -> PredicateGroup {
+    let (terms, negations) = literals.into_iter().unzip();
+    PredicateGroup::new_with_negations(terms, negations)
+}
+
+pub fn positive(predicate: Predicate) -> (Predicate, Option<Negation>) {
+    (predicate, None)
+}
+
+/// Probabilistic negation: the literal holds with probability 1 - P(predicate).
+pub fn not(predicate: Predicate) -> (Predicate, Option<Negation>) {
+    (predicate, Some(Negation::Probabilistic))
+}
+
+/// Negation as failure: the literal holds unless the predicate is believed true.
+pub fn not_provable(predicate: Predicate) -> (Predicate, Option<Negation>) {
+    (predicate, Some(Negation::AsFailure))
 }
 
 pub fn implication(
@@ -19,6 +39,20 @@ pub fn implication(
         premise,
         conclusion,
         role_maps,
+        cpd: CpdType::Learned,
+    }
+}
+
+/// An implication whose conditional distribution is fixed rather than learned.
+pub fn implication_with_cpd(
+    premise: PredicateGroup,
+    conclusion: Predicate,
+    role_maps: Vec<RoleMap>,
+    cpd: CpdType,
+) -> ImplicationFactor {
+    ImplicationFactor {
+        cpd,
+        ..implication(premise, conclusion, role_maps)
     }
 }
 
@@ -68,3 +102,30 @@ pub fn sub(argument: Argument) -> LabeledArgument {
 pub fn obj(argument: Argument) -> LabeledArgument {
     role("obj".to_string(), argument)
 }
+
+/// The built-in predicate `attribute op threshold` about `subject`, which must be the
+/// only role. See `NumericComparison`.
+pub fn comparison(
+    attribute: &str,
+    op: ComparisonOp,
+    threshold: f64,
+    subject: LabeledArgument,
+) -> Predicate {
+    let domain = match &subject.argument {
+        Argument::Constant(constant) => constant.domain.clone(),
+        Argument::Variable(variable) => variable.domain.clone(),
+    };
+    let name = NumericComparison::new(attribute.to_string(), op, threshold).relation_name();
+    predicate(relation(name, vec![variable_argument(domain)]), vec![subject])
+}
+
+/// The premise test `builtin` over `roles`. See `BuiltinPredicate`.
+pub fn builtin(builtin: BuiltinPredicate, roles: Vec<LabeledArgument>) -> Predicate {
+    Predicate::new_from_just_name(builtin.relation_name(), roles)
+}
+
+/// `predicate` quantified over the roles the rule's role maps leave unbound. See
+/// `Quantifier`.
+pub fn quantified(quantifier: Quantifier, predicate: Predicate) -> Predicate {
+    Predicate::new_from_relation(quantifier.aggregate_relation(&predicate.relation), predicate.roles)
+}
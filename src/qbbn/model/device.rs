@@ -64,6 +64,8 @@ pub struct TorchConfig {
     pub max_epochs: usize,
     pub early_stopping_patience: usize,
     pub gradient_clip: Option<f64>,
+    /// Seeds libtorch's generator, which draws the initial weights.
+    pub seed: Option<u64>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -87,6 +89,7 @@ impl Default for TorchConfig {
             max_epochs: 100,
             early_stopping_patience: 10,
             gradient_clip: Some(1.0),
+            seed: None,
         }
     }
 }
@@ -108,6 +111,18 @@ impl TorchConfig {
             }
         }
         
+        if let Ok(epochs) = env::var("BAYESLOG_MAX_EPOCHS") {
+            if let Ok(epochs_val) = epochs.parse::<usize>() {
+                config.max_epochs = epochs_val;
+            }
+        }
+        
+        if let Ok(patience) = env::var("BAYESLOG_EARLY_STOPPING_PATIENCE") {
+            if let Ok(patience_val) = patience.parse::<usize>() {
+                config.early_stopping_patience = patience_val;
+            }
+        }
+        
         if let Ok(opt) = env::var("BAYESLOG_OPTIMIZER") {
             config.optimizer = match opt.to_lowercase().as_str() {
                 "sgd" => OptimizerType::SGD { momentum: 0.9 },
@@ -126,6 +141,10 @@ impl TorchConfig {
             };
         }
         
+        if let Ok(seed) = env::var("BAYESLOG_SEED") {
+            config.seed = seed.parse::<u64>().ok();
+        }
+        
         config
     }
-}
\ No newline at end of file
+}
@@ -1,40 +1,82 @@
+use super::calibrator::{Calibrator, CalibratorUpdate};
+use super::config::ExponentialTrainingConfig;
+use super::features::{default_feature_extractors, extract_features, FeatureExtractors};
 use super::objects::ImplicationFactor;
-use super::weights::{negative_feature, positive_feature, ExponentialWeights, CLASS_LABELS};
+use super::optimizer::OptimizerState;
+use super::tying::WeightTying;
+use super::weights::{
+    negative_outcome_feature, positive_outcome_feature, ExponentialWeights, CLASS_LABELS,
+};
 use super::weight_manager::{WeightManager, WeightManagerConfig};
 use super::ModelWeights;
 use crate::qbbn::common::interface::{PredictStatistics, TrainStatistics};
 use crate::qbbn::common::model::{FactorContext, FactorModel};
 use crate::qbbn::common::redis::MockConnection as Connection;
-use log::{debug, info, trace};
+use log::{info, trace};
 use std::collections::HashMap;
 use std::error::Error;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+/// Predicted probabilities are kept this far from 0 and 1 when computing the loss.
+const LOSS_EPSILON: f64 = 1e-9;
+
 pub struct ExponentialModel {
     print_training_loss: bool,
     weights: Arc<RwLock<WeightManager>>,
     /// Whether to enable online learning with delta weights
     online_learning: bool,
+    training: ExponentialTrainingConfig,
+    optimizer: OptimizerState,
+    /// The calibrator applied to the predictions of conclusions.
+    calibrator: CalibratorUpdate,
+    /// The calibrator stored for the namespace, with the weight version it was read at.
+    stored_calibrator: Mutex<Option<(u64, Option<Calibrator>)>>,
+    /// Names the features of tied implications. Read from the namespace when first
+    /// needed unless the model was loaded from a file.
+    tying: OnceLock<Option<Arc<WeightTying>>>,
+    /// Extractors used besides the built-in ones.
+    extractors: FeatureExtractors,
+    /// Read in place of the weights of these features, which are left as they are.
+    weight_overrides: HashMap<String, f64>,
+}
+
+/// How to build an `ExponentialModel`. Every constructor goes through
+/// `ExponentialModel::new_with_options`, so the settings compose.
+#[derive(Clone)]
+pub struct ExponentialModelOptions {
+    pub training: ExponentialTrainingConfig,
+    /// When the delta weights are consolidated.
+    pub weights: WeightManagerConfig,
+    /// Whether to enable online learning with delta weights
+    pub online_learning: bool,
+    /// Extractors used besides the built-in ones.
+    pub extractors: FeatureExtractors,
+    /// The calibrator applied to the predictions of conclusions, the stored one by default.
+    pub calibrator: CalibratorUpdate,
+    /// Used instead of the weight tying stored for the namespace, if set.
+    pub tying: Option<Option<WeightTying>>,
+}
+
+impl Default for ExponentialModelOptions {
+    fn default() -> Self {
+        ExponentialModelOptions {
+            training: ExponentialTrainingConfig::default(),
+            weights: WeightManagerConfig::default(),
+            online_learning: true,
+            extractors: FeatureExtractors::default(),
+            calibrator: CalibratorUpdate::Keep,
+            tying: None,
+        }
+    }
 }
 
 impl ExponentialModel {
     pub fn new_mutable(namespace: String) -> Result<Box<dyn FactorModel>, Box<dyn Error>> {
-        let base_weights = ExponentialWeights::new(namespace.clone())?;
-        let weights = Arc::new(RwLock::new(WeightManager::new(base_weights, namespace.clone())));
-        Ok(Box::new(ExponentialModel {
-            print_training_loss: false,
-            weights,
-            online_learning: true, // Enable by default
-        }))
+        Self::new_with_options(namespace, ExponentialModelOptions::default())
     }
     
     pub fn new_shared(namespace: String) -> Result<Arc<dyn FactorModel>, Box<dyn Error>> {
-        let base_weights = ExponentialWeights::new(namespace.clone())?;
-        let weights = Arc::new(RwLock::new(WeightManager::new(base_weights, namespace.clone())));
-        Ok(Arc::new(ExponentialModel {
-            print_training_loss: false,
-            weights,
-            online_learning: true,
-        }))
+        Ok(Arc::new(Self::build(namespace, ExponentialModelOptions::default())?))
     }
     
     /// Create with custom configuration
@@ -43,13 +85,38 @@ impl ExponentialModel {
         config: WeightManagerConfig,
         online_learning: bool,
     ) -> Result<Box<dyn FactorModel>, Box<dyn Error>> {
-        let base_weights = ExponentialWeights::new(namespace.clone())?;
-        let weights = Arc::new(RwLock::new(WeightManager::with_config(base_weights, namespace.clone(), config)));
-        Ok(Box::new(ExponentialModel {
-            print_training_loss: false,
-            weights,
+        let options = ExponentialModelOptions {
+            weights: config,
             online_learning,
-        }))
+            ..ExponentialModelOptions::default()
+        };
+        Self::new_with_options(namespace, options)
+    }
+
+    /// Create with any combination of settings
+    pub fn new_with_options(
+        namespace: String,
+        options: ExponentialModelOptions,
+    ) -> Result<Box<dyn FactorModel>, Box<dyn Error>> {
+        Ok(Box::new(Self::build(namespace, options)?))
+    }
+
+    fn build(namespace: String, options: ExponentialModelOptions) -> Result<ExponentialModel, Box<dyn Error>> {
+        let base_weights = ExponentialWeights::new_with_seed(namespace.clone(), options.training.seed)?;
+        let weights = WeightManager::with_config(base_weights, namespace, options.weights);
+        let tying = options.tying.map(|tying| OnceLock::from(tying.map(Arc::new))).unwrap_or_default();
+        Ok(ExponentialModel {
+            print_training_loss: false,
+            weights: Arc::new(RwLock::new(weights)),
+            online_learning: options.online_learning,
+            training: options.training,
+            optimizer: OptimizerState::default(),
+            calibrator: options.calibrator,
+            stored_calibrator: Mutex::new(None),
+            tying,
+            extractors: options.extractors,
+            weight_overrides: HashMap::new(),
+        })
     }
     
     /// Create a new ExponentialModel from a saved file
@@ -65,18 +132,53 @@ impl ExponentialModel {
             ).into());
         }
         
-        let mut base_weights = ExponentialWeights::new(namespace.clone())?;
+        let options = ExponentialModelOptions {
+            calibrator: model_weights.calibrator.clone().into(),
+            tying: Some(model_weights.weight_tying.clone()),
+            ..ExponentialModelOptions::default()
+        };
+        let model = Self::build(namespace, options)?;
         // Create a temporary connection for loading weights
         let mut temp_conn = Connection::new_in_memory()?;
-        base_weights.load_from_model_weights(&mut temp_conn, &model_weights)?;
-        
-        let weights = Arc::new(RwLock::new(WeightManager::new(base_weights, namespace)));
-        
-        Ok(Box::new(ExponentialModel {
-            print_training_loss: false,
-            weights,
-            online_learning: true,
-        }))
+        model.weights.write().unwrap().replace_weights(&mut temp_conn, &model_weights)?;
+        Ok(Box::new(model))
+    }
+
+    /// The calibrator applied to the predictions of conclusions. The stored one is read
+    /// from `connection` again whenever the weights have changed since it was last read.
+    fn calibrator(&self, connection: &mut Connection) -> Result<Option<Calibrator>, Box<dyn Error>> {
+        match &self.calibrator {
+            CalibratorUpdate::Keep => {}
+            CalibratorUpdate::Clear => return Ok(None),
+            CalibratorUpdate::Set(calibrator) => return Ok(Some(calibrator.clone())),
+        }
+        let version = self.weight_version(connection);
+        let mut cache = self.stored_calibrator.lock().unwrap();
+        match &*cache {
+            Some((cached_version, calibrator)) if *cached_version == version => Ok(calibrator.clone()),
+            _ => {
+                let stored = Calibrator::load(connection, &self.namespace())?;
+                *cache = Some((version, stored.clone()));
+                Ok(stored)
+            }
+        }
+    }
+
+    /// Drops the calibrator, which was fitted to the predictions of the old weights.
+    fn drop_calibrator(&mut self, connection: &mut Connection) -> Result<(), Box<dyn Error>> {
+        Calibrator::store(connection, &self.namespace(), None)?;
+        self.calibrator = CalibratorUpdate::Keep;
+        *self.stored_calibrator.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// The weight tying features are named with, read from `connection` the first time.
+    fn tying(&self, connection: &mut Connection) -> Result<Option<&Arc<WeightTying>>, Box<dyn Error>> {
+        if self.tying.get().is_none() {
+            let stored = WeightTying::load(connection, &self.namespace())?.map(Arc::new);
+            let _ = self.tying.set(stored);
+        }
+        Ok(self.tying.get().and_then(Option::as_ref))
     }
     
     /// Save the model weights to a file
@@ -92,8 +194,10 @@ impl ExponentialModel {
             }
         }
         
-        // Export all weights (base + delta)
-        let model_weights = self.weights.read().unwrap().export_weights(connection)?;
+        // Export all weights (base + delta), with the model's calibrator and weight tying
+        let mut model_weights = self.weights.read().unwrap().export_weights(connection)?;
+        model_weights.calibrator = self.calibrator(connection)?;
+        model_weights.weight_tying = self.tying(connection)?.map(|tying| (**tying).clone());
         model_weights.save_to_file(path)?;
         
         Ok(())
@@ -102,7 +206,11 @@ impl ExponentialModel {
 
 fn dot_product(dict1: &HashMap<String, f64>, dict2: &HashMap<String, f64>) -> f64 {
     let mut result = 0.0;
-    for (key, &v1) in dict1 {
+    // Summed in key order, so the result does not depend on the maps' iteration order.
+    let mut keys: Vec<&String> = dict1.keys().collect();
+    keys.sort();
+    for key in keys {
+        let v1 = dict1[key];
         if let Some(&v2) = dict2.get(key) {
             let product = v1 * v2;
             trace!(
@@ -124,73 +232,31 @@ pub fn compute_potential(weights: &HashMap<String, f64>, features: &HashMap<Stri
     dot.exp()
 }
 
+/// The features of `factor` from the built-in extractors alone, one map per class label.
+/// The models also apply the extractors they were created with.
 pub fn features_from_factor(
     factor: &FactorContext,
 ) -> Result<Vec<HashMap<String, f64>>, Box<dyn Error>> {
-    // Check if this is a conjunction (AND gate) or disjunction (OR gate)
-    // by looking at the structure of the factor
-    let is_conjunction = factor.factor.iter().any(|f| {
-        // If any premise is a group (multiple propositions), it's likely a conjunction
-        f.premise.terms.len() > 1
-    });
-    
-    let mut vec_result = vec![];
-    for class_label in CLASS_LABELS {
-        let mut result = HashMap::new();
-        
-        if is_conjunction {
-            // Extract AND-gate specific features
-            debug!("Extracting features for AND gate");
-            
-            // Feature 1: Number of premises (conjunction size)
-            let num_premises = factor.probabilities.len() as f64;
-            result.insert(format!("and_size_{}", class_label), num_premises);
-            
-            // Feature 2: Number of true premises
-            let num_true = factor.probabilities.iter().filter(|&&p| p > 0.5).count() as f64;
-            result.insert(format!("and_num_true_{}", class_label), num_true);
-            
-            // Feature 3: All true indicator
-            let all_true = factor.probabilities.iter().all(|&p| p > 0.5);
-            result.insert(format!("and_all_true_{}", class_label), if all_true { 1.0 } else { 0.0 });
-            
-            // Feature 4: Any false indicator
-            let any_false = factor.probabilities.iter().any(|&p| p <= 0.5);
-            result.insert(format!("and_any_false_{}", class_label), if any_false { 1.0 } else { 0.0 });
-            
-            // Feature 5: Product of probabilities (soft AND)
-            let soft_and = factor.probabilities.iter().product::<f64>();
-            result.insert(format!("and_soft_{}", class_label), soft_and);
-            
-            // Feature 6: Min probability (weakest link)
-            let min_prob = factor.probabilities.iter().fold(1.0f64, |a, &b| a.min(b));
-            result.insert(format!("and_min_{}", class_label), min_prob);
-        }
-        
-        // Always include the original features for backward compatibility
-        for (i, premise) in factor.factor.iter().enumerate() {
-            debug!("Processing backimplication {}", i);
-            let feature = premise.inference.unique_key();
-            debug!("Generated unique key for feature: {}", feature);
-            let probability = factor.probabilities[i];
-            debug!(
-                "Conjunction probability for backimplication {}: {}",
-                i, probability
-            );
-            let posf = positive_feature(&feature, class_label);
-            let negf = negative_feature(&feature, class_label);
-            result.insert(posf.clone(), probability);
-            result.insert(negf.clone(), 1.0 - probability);
-            debug!(
-                "Inserted features for backimplication {}: positive - {}, negative - {}",
-                i, posf, negf
-            );
-        }
-        
-        vec_result.push(result);
-    }
+    let result = extract_features(&default_feature_extractors(), factor)?;
     trace!("features_from_factor completed successfully");
-    Ok(vec_result)
+    Ok(result)
+}
+
+/// Features for a categorical conclusion: one map per outcome, so every implication
+/// gets its own weights for every outcome.
+pub fn features_for_outcomes(factor: &FactorContext, outcomes: usize) -> Vec<HashMap<String, f64>> {
+    (0..outcomes)
+        .map(|outcome| {
+            let mut result = HashMap::new();
+            for (premise, probability) in factor.factor.iter().zip(&factor.probabilities) {
+                let feature = premise.inference.unique_key();
+                // The groundings of an implication with join variables add up.
+                *result.entry(positive_outcome_feature(&feature, outcome)).or_insert(0.0) += probability;
+                *result.entry(negative_outcome_feature(&feature, outcome)).or_insert(0.0) += 1.0 - probability;
+            }
+            result
+        })
+        .collect()
 }
 
 pub fn compute_expected_features(
@@ -204,34 +270,65 @@ pub fn compute_expected_features(
     result
 }
 
-const LEARNING_RATE: f64 = 0.05;
+/// Adds `features / count` into `total`, for averaging over a mini-batch.
+fn accumulate(total: &mut HashMap<String, f64>, features: &HashMap<String, f64>, count: f64) {
+    for (feature, value) in features {
+        *total.entry(feature.clone()).or_insert(0f64) += value / count;
+    }
+}
 
-pub fn do_sgd_update(
-    weights: &HashMap<String, f64>,
-    gold_features: &HashMap<String, f64>,
-    expected_features: &HashMap<String, f64>,
-    print_training_loss: bool,
-) -> HashMap<String, f64> {
-    let mut new_weights = HashMap::new();
-    for (feature, &wv) in weights {
-        let gv = gold_features.get(feature).unwrap_or(&0.0);
-        let ev = expected_features.get(feature).unwrap_or(&0.0);
-        let new_weight = wv + LEARNING_RATE * (gv - ev);
-        let loss = (gv - ev).abs();
-        if print_training_loss {
-            trace!(
-                "feature: {}, gv: {}, ev: {}, loss: {}, old_weight: {}, new_weight: {}",
-                feature,
-                gv,
-                ev,
-                loss,
-                wv,
-                new_weight
-            );
+/// Per outcome: the weights read for its features, and its probability.
+type OutcomeDistribution = (Vec<HashMap<String, f64>>, Vec<f64>);
+
+impl ExponentialModel {
+    fn namespace(&self) -> String {
+        self.weights.read().unwrap().namespace().to_string()
+    }
+
+    /// The features of `factor`, with the model's extractors.
+    fn features(
+        &self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+    ) -> Result<Vec<HashMap<String, f64>>, Box<dyn Error>> {
+        self.extractors.features(self.tying(connection)?, factor)
+    }
+
+    /// The weights of `features`, base plus delta unless overridden.
+    fn read_weight_vector(
+        &self,
+        connection: &mut Connection,
+        features: &[String],
+    ) -> Result<HashMap<String, f64>, Box<dyn Error>> {
+        let mut weights = self.weights.read().unwrap().read_weight_vector(connection, features)?;
+        for (feature, weight) in weights.iter_mut() {
+            if let Some(overridden) = self.weight_overrides.get(feature) {
+                *weight = *overridden;
+            }
         }
-        new_weights.insert(feature.clone(), new_weight);
+        Ok(weights)
+    }
+
+    /// The weights read for each outcome's features, and each outcome's probability.
+    fn outcome_distribution(
+        &self,
+        connection: &mut Connection,
+        features: &[HashMap<String, f64>],
+    ) -> Result<OutcomeDistribution, Box<dyn Error>> {
+        let mut weight_vectors = vec![];
+        let mut potentials = vec![];
+        for this_features in features {
+            let weight_vector = self.read_weight_vector(
+                connection,
+                &this_features.keys().cloned().collect::<Vec<_>>(),
+            )?;
+            potentials.push(compute_potential(&weight_vector, this_features));
+            weight_vectors.push(weight_vector);
+        }
+        let normalization: f64 = potentials.iter().sum();
+        let probabilities = potentials.iter().map(|potential| potential / normalization).collect();
+        Ok((weight_vectors, probabilities))
     }
-    new_weights
 }
 
 impl FactorModel for ExponentialModel {
@@ -240,8 +337,13 @@ impl FactorModel for ExponentialModel {
         connection: &mut Connection,
         implication: &ImplicationFactor,
     ) -> Result<(), Box<dyn Error>> {
-        self.weights.write().unwrap().initialize_weights(connection, implication)?;
-        Ok(())
+        let tying = self.tying(connection)?.cloned();
+        let features: Vec<String> = CLASS_LABELS
+            .iter()
+            .flat_map(|&class_label| self.extractors.feature_names(tying.as_ref(), implication, class_label))
+            .collect();
+        self.weights.write().unwrap().initialize_weights(connection, &features)?;
+        self.drop_calibrator(connection)
     }
 
     fn train(
@@ -251,7 +353,7 @@ impl FactorModel for ExponentialModel {
         gold_probability: f64,
     ) -> Result<TrainStatistics, Box<dyn Error>> {
         trace!("train_on_example - Getting features from backimplications");
-        let features = match features_from_factor(factor) {
+        let features = match self.features(connection, factor) {
             Ok(f) => f,
             Err(e) => {
                 trace!(
@@ -271,7 +373,7 @@ impl FactorModel for ExponentialModel {
                 "train_on_example - Reading weights for class {}",
                 class_label
             );
-            let weight_vector = match self.weights.read().unwrap().read_weight_vector(
+            let weight_vector = match self.read_weight_vector(
                 connection,
                 &features[class_label].keys().cloned().collect::<Vec<_>>(),
             ) {
@@ -288,6 +390,8 @@ impl FactorModel for ExponentialModel {
             weight_vectors.push(weight_vector);
         }
         let normalization = potentials[0] + potentials[1];
+        let predicted = (potentials[1] / normalization).clamp(LOSS_EPSILON, 1f64 - LOSS_EPSILON);
+        let loss = -gold_probability * predicted.ln() - (1f64 - gold_probability) * (1f64 - predicted).ln();
         for class_label in CLASS_LABELS {
             let probability = potentials[class_label] / normalization;
             trace!("train_on_example - Computing expected features");
@@ -299,7 +403,8 @@ impl FactorModel for ExponentialModel {
             let gold = compute_expected_features(this_true_prob, &features[class_label]);
             let expected = compute_expected_features(probability, &features[class_label]);
             trace!("train_on_example - Performing SGD update");
-            let new_weights = do_sgd_update(
+            let new_weights = self.optimizer.update(
+                &self.training,
                 &weight_vectors[class_label],
                 &gold,
                 &expected,
@@ -325,15 +430,65 @@ impl FactorModel for ExponentialModel {
                 return Err("Batch training mode not yet implemented with WeightManager".into());
             }
         }
+        self.optimizer.advance();
         trace!("train_on_example - End");
-        Ok(TrainStatistics { loss: 1f64 })
+        Ok(TrainStatistics { loss })
+    }
+    fn train_batch(
+        &mut self,
+        connection: &mut Connection,
+        factors: &[FactorContext],
+        probabilities: &[f64],
+    ) -> Result<TrainStatistics, Box<dyn Error>> {
+        if !self.online_learning {
+            return Err("Batch training mode not yet implemented with WeightManager".into());
+        }
+        let count = factors.len().max(1) as f64;
+        let mut weights = HashMap::new();
+        let mut gold = HashMap::new();
+        let mut expected = HashMap::new();
+        let mut loss = 0f64;
+        for (factor, &gold_probability) in factors.iter().zip(probabilities) {
+            let features = self.features(connection, factor)?;
+            let (weight_vectors, predicted) = self.outcome_distribution(connection, &features)?;
+            let probability = predicted[1].clamp(LOSS_EPSILON, 1f64 - LOSS_EPSILON);
+            loss -= gold_probability * probability.ln() + (1f64 - gold_probability) * (1f64 - probability).ln();
+            for class_label in CLASS_LABELS {
+                let this_true_prob = if class_label == 0 {
+                    1f64 - gold_probability
+                } else {
+                    gold_probability
+                };
+                accumulate(&mut gold, &compute_expected_features(this_true_prob, &features[class_label]), count);
+                accumulate(&mut expected, &compute_expected_features(predicted[class_label], &features[class_label]), count);
+                weights.extend(weight_vectors[class_label].clone());
+            }
+        }
+        let new_weights = self.optimizer.update(
+            &self.training,
+            &weights,
+            &gold,
+            &expected,
+            self.print_training_loss,
+        );
+        let mut weight_deltas = HashMap::new();
+        for (feature, new_weight) in &new_weights {
+            let delta = new_weight - weights[feature];
+            if delta.abs() > 1e-8 {
+                weight_deltas.insert(feature.clone(), delta);
+            }
+        }
+        self.weights.write().unwrap().update_weights(&weight_deltas);
+        self.optimizer.advance();
+        Ok(TrainStatistics { loss: loss / count })
     }
+
     fn predict(
         &self,
         connection: &mut Connection,
         factor: &FactorContext,
     ) -> Result<PredictStatistics, Box<dyn Error>> {
-        let features = match features_from_factor(factor) {
+        let features = match self.features(connection, factor) {
             Ok(f) => f,
             Err(e) => {
                 trace!(
@@ -350,7 +505,7 @@ impl FactorModel for ExponentialModel {
                 trace!("feature {:?} {}", &feature, weight);
             }
             trace!("inference_probability - Reading weights");
-            let weight_vector = match self.weights.read().unwrap().read_weight_vector(
+            let weight_vector = match self.read_weight_vector(
                 connection,
                 &this_features.keys().cloned().collect::<Vec<_>>(),
             ) {
@@ -376,7 +531,125 @@ impl FactorModel for ExponentialModel {
         );
         Ok(PredictStatistics { probability })
     }
+
+    fn predict_conclusion(
+        &self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+    ) -> Result<PredictStatistics, Box<dyn Error>> {
+        let mut statistics = self.predict(connection, factor)?;
+        if let Some(calibrator) = self.calibrator(connection)? {
+            statistics.probability = calibrator.apply(statistics.probability);
+            trace!("calibrated marginal {}", statistics.probability);
+        }
+        Ok(statistics)
+    }
     
+    fn predict_distribution(
+        &self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+        outcomes: usize,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        let features = features_for_outcomes(factor, outcomes);
+        let (_, probabilities) = self.outcome_distribution(connection, &features)?;
+        trace!("predict_distribution {:?} for {:?}", &probabilities, &factor);
+        Ok(probabilities)
+    }
+
+    fn train_distribution(
+        &mut self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+        gold: &[f64],
+    ) -> Result<TrainStatistics, Box<dyn Error>> {
+        if !self.online_learning {
+            return Err("Batch training mode not yet implemented with WeightManager".into());
+        }
+        let features = features_for_outcomes(factor, gold.len());
+        let (weight_vectors, probabilities) = self.outcome_distribution(connection, &features)?;
+        let mut loss = 0f64;
+        for (outcome, this_features) in features.iter().enumerate() {
+            loss -= gold[outcome] * probabilities[outcome].clamp(LOSS_EPSILON, 1f64).ln();
+            let gold_features = compute_expected_features(gold[outcome], this_features);
+            let expected = compute_expected_features(probabilities[outcome], this_features);
+            let new_weights = self.optimizer.update(
+                &self.training,
+                &weight_vectors[outcome],
+                &gold_features,
+                &expected,
+                self.print_training_loss,
+            );
+            let mut weight_deltas = HashMap::new();
+            for (feature, new_weight) in &new_weights {
+                let delta = new_weight - weight_vectors[outcome][feature];
+                if delta.abs() > 1e-8 {
+                    weight_deltas.insert(feature.clone(), delta);
+                }
+            }
+            self.weights.write().unwrap().update_weights(&weight_deltas);
+        }
+        self.optimizer.advance();
+        Ok(TrainStatistics { loss })
+    }
+
+    /// The base weight plus the delta not yet consolidated.
+    fn read_weight(&self, connection: &mut Connection, feature: &str) -> Result<f64, Box<dyn Error>> {
+        match self.weight_overrides.get(feature) {
+            Some(weight) => Ok(*weight),
+            None => self.weights.read().unwrap().read_weight(connection, feature),
+        }
+    }
+
+    fn weight_version(&self, connection: &Connection) -> u64 {
+        self.weights.read().unwrap().version(connection)
+    }
+
+    /// Shares this model's weights, so later writes to them show through.
+    fn with_weight_overrides(&self, weights: HashMap<String, f64>) -> Result<Box<dyn FactorModel>, Box<dyn Error>> {
+        let mut weight_overrides = self.weight_overrides.clone();
+        weight_overrides.extend(weights);
+        Ok(Box::new(ExponentialModel {
+            print_training_loss: self.print_training_loss,
+            weights: self.weights.clone(),
+            online_learning: self.online_learning,
+            training: self.training,
+            optimizer: self.optimizer.clone(),
+            calibrator: self.calibrator.clone(),
+            stored_calibrator: Mutex::new(self.stored_calibrator.lock().unwrap().clone()),
+            tying: self.tying.clone(),
+            extractors: self.extractors.clone(),
+            weight_overrides,
+        }))
+    }
+
+    fn factor_features(
+        &self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+    ) -> Result<Vec<HashMap<String, f64>>, Box<dyn Error>> {
+        self.features(connection, factor)
+    }
+
+    fn snapshot_weights(&self, connection: &mut Connection) -> Result<ModelWeights, Box<dyn Error>> {
+        self.weights.read().unwrap().export_weights(connection)
+    }
+
+    /// Consolidates every delta weight, without touching the calibrator.
+    fn flush_weights(&mut self, connection: &mut Connection) -> Result<(), Box<dyn Error>> {
+        self.weights.write().unwrap().flush(connection)?;
+        Ok(())
+    }
+
+    fn restore_weights(
+        &mut self,
+        connection: &mut Connection,
+        weights: &ModelWeights,
+    ) -> Result<(), Box<dyn Error>> {
+        self.weights.write().unwrap().replace_weights(connection, weights)?;
+        self.drop_calibrator(connection)
+    }
+
     fn save_to_file(&self, connection: &mut Connection, path: &str) -> Result<(), Box<dyn Error>> {
         self.save_to_file(connection, path)
     }
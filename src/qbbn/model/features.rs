@@ -0,0 +1,250 @@
+use super::objects::{ImplicationFactor, NumericComparison};
+use super::tying::WeightTying;
+use super::weights::{negative_feature, numeric_feature, positive_feature, CLASS_LABELS};
+use crate::qbbn::common::model::FactorContext;
+use crate::qbbn::inference::graph::PropositionFactor;
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::Arc;
+
+/// Computes some of the features the exponential models score a factor with. Features
+/// are specific to a class label, so the names an extractor produces should include it.
+pub trait FeatureExtractor: Send + Sync {
+    /// The features `extract` may produce for `class_label` of a factor with `implication`
+    /// among its premises. Their weights are initialized along with the implication's;
+    /// features left out here start at weight 0 when first used.
+    fn feature_names(&self, _implication: &ImplicationFactor, _class_label: usize) -> Vec<String> {
+        vec![]
+    }
+
+    /// Adds the features of `factor` for `class_label` to `features`.
+    fn extract(
+        &self,
+        factor: &FactorContext,
+        class_label: usize,
+        features: &mut HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// Each implication's premise probability and its complement, and the numeric margins of
+/// its comparisons. The features are named by the implication's `unique_key`, or by the
+/// key `tying` gives it.
+#[derive(Default)]
+pub struct ImplicationFeatures {
+    pub tying: Option<Arc<WeightTying>>,
+}
+
+impl ImplicationFeatures {
+    fn weight_key(&self, implication: &ImplicationFactor) -> String {
+        match &self.tying {
+            Some(tying) => tying.weight_key(implication),
+            None => implication.unique_key(),
+        }
+    }
+}
+
+impl FeatureExtractor for ImplicationFeatures {
+    fn feature_names(&self, implication: &ImplicationFactor, class_label: usize) -> Vec<String> {
+        let feature = self.weight_key(implication);
+        vec![
+            positive_feature(&feature, class_label),
+            negative_feature(&feature, class_label),
+        ]
+    }
+
+    fn extract(
+        &self,
+        factor: &FactorContext,
+        class_label: usize,
+        features: &mut HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error>> {
+        for (i, premise) in factor.factor.iter().enumerate() {
+            let feature = self.weight_key(&premise.inference);
+            let probability = factor.probabilities[i];
+            debug!("Conjunction probability for backimplication {}: {}", i, probability);
+            // Tied implications of one factor add up, as the weight stands for each, and so
+            // do the groundings of an implication with join variables.
+            *features.entry(positive_feature(&feature, class_label)).or_insert(0.0) += probability;
+            *features.entry(negative_feature(&feature, class_label)).or_insert(0.0) += 1.0 - probability;
+            insert_numeric_features(features, factor, premise, &feature, class_label);
+        }
+        Ok(())
+    }
+}
+
+/// Margins past this many thresholds' worth are all alike.
+const NUMERIC_MARGIN_LIMIT: f64 = 2.0;
+
+/// For each numeric comparison among the premise terms whose value is known: its margin
+/// past the threshold, linearly, and the half-threshold-wide bin the margin falls in.
+/// The comparison itself is already a 0/1 premise, so these learn how much the size of
+/// the margin matters.
+fn insert_numeric_features(
+    result: &mut HashMap<String, f64>,
+    factor: &FactorContext,
+    premise: &PropositionFactor,
+    feature: &str,
+    class_label: usize,
+) {
+    for (term_index, term) in premise.premise.terms.iter().enumerate() {
+        if let Some(value) = factor.numeric_values.get(term)
+            && let Some((comparison, _)) = NumericComparison::from_proposition(term)
+        {
+            let margin = comparison
+                .margin(*value)
+                .clamp(-NUMERIC_MARGIN_LIMIT, NUMERIC_MARGIN_LIMIT);
+            result.insert(numeric_feature(feature, term_index, "linear", class_label), margin);
+            let bin = (margin * 2.0).floor().min(2.0 * NUMERIC_MARGIN_LIMIT - 1.0) as i64;
+            let kind = format!("bin{}", bin);
+            result.insert(numeric_feature(feature, term_index, &kind, class_label), 1.0);
+        }
+    }
+}
+
+/// Summaries of the premise probabilities of factors with a conjunction among them.
+/// Their weights are not initialized with the implications', so they start at 0.
+pub struct AndGateFeatures;
+
+impl FeatureExtractor for AndGateFeatures {
+    fn extract(
+        &self,
+        factor: &FactorContext,
+        class_label: usize,
+        result: &mut HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error>> {
+        // If any premise is a group (multiple propositions), it's likely a conjunction
+        if !factor.factor.iter().any(|f| f.premise.terms.len() > 1) {
+            return Ok(());
+        }
+        debug!("Extracting features for AND gate");
+
+        // Feature 1: Number of premises (conjunction size)
+        let num_premises = factor.probabilities.len() as f64;
+        result.insert(format!("and_size_{}", class_label), num_premises);
+
+        // Feature 2: Number of true premises
+        let num_true = factor.probabilities.iter().filter(|&&p| p > 0.5).count() as f64;
+        result.insert(format!("and_num_true_{}", class_label), num_true);
+
+        // Feature 3: All true indicator
+        let all_true = factor.probabilities.iter().all(|&p| p > 0.5);
+        result.insert(format!("and_all_true_{}", class_label), if all_true { 1.0 } else { 0.0 });
+
+        // Feature 4: Any false indicator
+        let any_false = factor.probabilities.iter().any(|&p| p <= 0.5);
+        result.insert(format!("and_any_false_{}", class_label), if any_false { 1.0 } else { 0.0 });
+
+        // Feature 5: Product of probabilities (soft AND)
+        let soft_and = factor.probabilities.iter().product::<f64>();
+        result.insert(format!("and_soft_{}", class_label), soft_and);
+
+        // Feature 6: Min probability (weakest link)
+        let min_prob = factor.probabilities.iter().fold(1.0f64, |a, &b| a.min(b));
+        result.insert(format!("and_min_{}", class_label), min_prob);
+
+        // Feature 7: Number of negated literals, only when there are any, so models
+        // trained on positive-only rules keep their feature set
+        let num_negated = factor
+            .factor
+            .iter()
+            .map(|f| f.premise.negations.iter().filter(|n| n.is_some()).count())
+            .max()
+            .unwrap_or(0);
+        if num_negated > 0 {
+            result.insert(format!("and_num_negated_{}", class_label), num_negated as f64);
+        }
+        Ok(())
+    }
+}
+
+/// The extractors a model adds to the built-in ones, for all of its factors or for
+/// those concluding one relation. Held by the model, so models of the same namespace
+/// can use different features.
+#[derive(Clone, Default)]
+pub struct FeatureExtractors {
+    all: Vec<Arc<dyn FeatureExtractor>>,
+    /// Keyed by the name of the conclusion's relation.
+    by_relation: HashMap<String, Vec<Arc<dyn FeatureExtractor>>>,
+}
+
+impl FeatureExtractors {
+    /// Adds `extractor` to every factor.
+    pub fn add(&mut self, extractor: Arc<dyn FeatureExtractor>) {
+        self.all.push(extractor);
+    }
+
+    /// Adds `extractor` to the factors whose conclusion is of `relation_name`.
+    pub fn add_for_relation(&mut self, relation_name: &str, extractor: Arc<dyn FeatureExtractor>) {
+        self.by_relation.entry(relation_name.to_string()).or_default().push(extractor);
+    }
+
+    /// The built-in extractors, with `tying`, then those added for all factors, then
+    /// those added for conclusions of `relation_name`.
+    pub fn extractors(
+        &self,
+        tying: Option<&Arc<WeightTying>>,
+        relation_name: Option<&str>,
+    ) -> Vec<Arc<dyn FeatureExtractor>> {
+        let mut extractors: Vec<Arc<dyn FeatureExtractor>> = vec![
+            Arc::new(ImplicationFeatures { tying: tying.cloned() }),
+            Arc::new(AndGateFeatures),
+        ];
+        extractors.extend(self.all.iter().cloned());
+        if let Some(for_relation) = relation_name.and_then(|name| self.by_relation.get(name)) {
+            extractors.extend(for_relation.iter().cloned());
+        }
+        extractors
+    }
+
+    /// The features of `factor`, one map per class label.
+    pub fn features(
+        &self,
+        tying: Option<&Arc<WeightTying>>,
+        factor: &FactorContext,
+    ) -> Result<Vec<HashMap<String, f64>>, Box<dyn Error>> {
+        let relation_name = factor
+            .factor
+            .first()
+            .map(|premise| premise.conclusion.predicate.relation.relation_name.as_str());
+        extract_features(&self.extractors(tying, relation_name), factor)
+    }
+
+    /// The names of the features `implication` may contribute to for `class_label`, in
+    /// extractor order and without repeats.
+    pub fn feature_names(
+        &self,
+        tying: Option<&Arc<WeightTying>>,
+        implication: &ImplicationFactor,
+        class_label: usize,
+    ) -> Vec<String> {
+        let relation_name = implication.conclusion.relation.relation_name.as_str();
+        let mut seen = HashSet::new();
+        self.extractors(tying, Some(relation_name))
+            .iter()
+            .flat_map(|extractor| extractor.feature_names(implication, class_label))
+            .filter(|name| seen.insert(name.clone()))
+            .collect()
+    }
+}
+
+/// The features every model uses, without weight tying.
+pub fn default_feature_extractors() -> Vec<Arc<dyn FeatureExtractor>> {
+    FeatureExtractors::default().extractors(None, None)
+}
+
+/// One feature map per class label.
+pub fn extract_features(
+    extractors: &[Arc<dyn FeatureExtractor>],
+    factor: &FactorContext,
+) -> Result<Vec<HashMap<String, f64>>, Box<dyn Error>> {
+    let mut result = vec![];
+    for class_label in CLASS_LABELS {
+        let mut features = HashMap::new();
+        for extractor in extractors {
+            extractor.extract(factor, class_label, &mut features)?;
+        }
+        result.push(features);
+    }
+    Ok(result)
+}
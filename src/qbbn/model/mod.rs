@@ -3,8 +3,12 @@ pub mod creators;
 pub mod choose;
 pub mod ops;
 pub mod weights;
+pub mod features;
+pub mod tying;
+pub mod calibrator;
 pub mod exponential;
 pub mod config;
+pub mod optimizer;
 pub mod device;
 pub mod torch_weights;
 pub mod torch_exponential;
@@ -12,6 +16,8 @@ pub mod unified;
 pub mod delta_weights;
 pub mod weight_manager;
 
+use self::calibrator::Calibrator;
+use self::tying::WeightTying;
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use std::error::Error;
@@ -44,6 +50,12 @@ pub struct ModelWeights {
     pub entity_node_ids: Option<HashMap<String, String>>,
     /// Graph node IDs for related propositions
     pub proposition_node_ids: Option<HashMap<String, String>>,
+    /// Post-hoc calibrator applied to the model's predictions
+    #[serde(default)]
+    pub calibrator: Option<Calibrator>,
+    /// Groups of implications sharing weights
+    #[serde(default)]
+    pub weight_tying: Option<WeightTying>,
 }
 
 impl ModelWeights {
@@ -62,6 +74,8 @@ impl ModelWeights {
             training_loss: None,
             entity_node_ids: None,
             proposition_node_ids: None,
+            calibrator: None,
+            weight_tying: None,
         }
     }
     
@@ -106,6 +120,8 @@ impl ModelWeights {
             training_loss: None,
             entity_node_ids: None,
             proposition_node_ids: None,
+            calibrator: None,
+            weight_tying: None,
         }
     }
     
@@ -126,6 +142,8 @@ impl ModelWeights {
             training_loss: None,
             entity_node_ids: None,
             proposition_node_ids: None,
+            calibrator: None,
+            weight_tying: None,
         }
     }
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::collections::hash_map::DefaultHasher;
 use std::fmt;
@@ -223,6 +224,20 @@ impl fmt::Debug for Proposition {
     }
 }
 
+/// Ordered by `hash_string`, so ordered collections of them iterate the same way in
+/// every run.
+impl Ord for Proposition {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.hash_string().cmp(&other.hash_string())
+    }
+}
+
+impl PartialOrd for Proposition {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 #[allow(dead_code)]
 fn hash_proposition(proposition: &Proposition) -> u64 {
     let mut hasher = DefaultHasher::new();
@@ -248,11 +263,441 @@ impl Proposition {
     pub fn debug_string(&self) -> String {
         self.predicate.hash_string()
     }
+
+    /// A plain English reading: the subject, the relation, the object, then the other
+    /// roles by name, e.g. "jack likes jill" or "jack gives book to jill".
+    pub fn natural_language(&self) -> String {
+        let words = |argument: &Argument| match argument {
+            Argument::Constant(constant) => constant.entity_id.replace('_', " "),
+            Argument::Variable(variable) => format!("some {}", variable.domain),
+        };
+        let role = |name: &str| {
+            self.predicate
+                .roles
+                .iter()
+                .find(|role| role.role_name == name)
+                .map(|role| words(&role.argument))
+        };
+        let mut parts: Vec<String> = role("sub").into_iter().collect();
+        parts.push(self.predicate.relation.relation_name.replace('_', " "));
+        parts.extend(role("obj"));
+        for other in &self.predicate.roles {
+            if other.role_name != "sub" && other.role_name != "obj" {
+                parts.push(format!("{} {}", other.role_name, words(&other.argument)));
+            }
+        }
+        parts.join(" ")
+    }
+}
+
+/// Declares `relation_name` categorical in `value_role`: with the other roles fixed,
+/// exactly one of the `outcomes` (entity ids) fills it. The outcome propositions are
+/// then grounded together as one multi-valued variable.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CategoricalRelation {
+    pub relation_name: String,
+    pub value_role: String,
+    pub outcomes: Vec<String>,
+}
+
+impl CategoricalRelation {
+    pub fn new(relation_name: String, value_role: String, outcomes: Vec<String>) -> Self {
+        CategoricalRelation {
+            relation_name,
+            value_role,
+            outcomes,
+        }
+    }
+
+    /// The variable `proposition` is an outcome of, if it is an instance of this relation.
+    pub fn choice_for(&self, proposition: &Proposition) -> Option<PropositionChoice> {
+        let predicate = &proposition.predicate;
+        if predicate.relation.relation_name != self.relation_name {
+            return None;
+        }
+        let value = predicate
+            .roles
+            .iter()
+            .find(|role| role.role_name == self.value_role)?;
+        let domain = match &value.argument {
+            Argument::Constant(constant) => constant.domain.clone(),
+            Argument::Variable(_) => return None,
+        };
+        let outcomes = self
+            .outcomes
+            .iter()
+            .map(|entity_id| {
+                let roles = predicate
+                    .roles
+                    .iter()
+                    .map(|role| {
+                        if role.role_name == self.value_role {
+                            role.do_substitution(Argument::Constant(ConstantArgument::new(
+                                domain.clone(),
+                                entity_id.clone(),
+                            )))
+                        } else {
+                            role.clone()
+                        }
+                    })
+                    .collect();
+                Proposition::from(Predicate::new_from_relation(predicate.relation.clone(), roles))
+            })
+            .collect();
+        Some(PropositionChoice { outcomes })
+    }
+}
+
+/// The mutually exclusive outcomes of one grounded categorical variable, in the
+/// order of `CategoricalRelation::outcomes`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct PropositionChoice {
+    pub outcomes: Vec<Proposition>,
+}
+
+impl fmt::Debug for PropositionChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.debug_string())
+    }
+}
+
+/// Ordered by `hash_string`, like `Proposition`.
+impl Ord for PropositionChoice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.hash_string().cmp(&other.hash_string())
+    }
+}
+
+impl PartialOrd for PropositionChoice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PropositionChoice {
+    pub fn index_of(&self, outcome: &Proposition) -> Option<usize> {
+        self.outcomes.iter().position(|candidate| candidate == outcome)
+    }
+
+    pub fn hash_string(&self) -> String {
+        let hash_strings: Vec<String> = self
+            .outcomes
+            .iter()
+            .map(|outcome| outcome.hash_string())
+            .collect();
+        format!("one_of{{{}}}", hash_strings.join("|"))
+    }
+
+    pub fn debug_string(&self) -> String {
+        self.hash_string()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ComparisonOp {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl ComparisonOp {
+    /// Two-character symbols first, so that `<=` is not read as `<`.
+    const ALL: [ComparisonOp; 5] = [
+        ComparisonOp::Le,
+        ComparisonOp::Ge,
+        ComparisonOp::Eq,
+        ComparisonOp::Lt,
+        ComparisonOp::Gt,
+    ];
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ComparisonOp::Lt => "<",
+            ComparisonOp::Le => "<=",
+            ComparisonOp::Eq => "==",
+            ComparisonOp::Ge => ">=",
+            ComparisonOp::Gt => ">",
+        }
+    }
+
+    pub fn holds(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            ComparisonOp::Lt => value < threshold,
+            ComparisonOp::Le => value <= threshold,
+            ComparisonOp::Eq => value == threshold,
+            ComparisonOp::Ge => value >= threshold,
+            ComparisonOp::Gt => value > threshold,
+        }
+    }
+}
+
+/// A built-in predicate comparing a numeric attribute of its one argument against a
+/// threshold, e.g. `gt(age(x), 30)`. It is a relation named like `age>30`, so it can be
+/// used as a premise term like any other; grounding decides it from the stored
+/// attribute value instead of expanding it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumericComparison {
+    pub attribute: String,
+    pub op: ComparisonOp,
+    pub threshold: f64,
+}
+
+impl NumericComparison {
+    pub fn new(attribute: String, op: ComparisonOp, threshold: f64) -> Self {
+        NumericComparison {
+            attribute,
+            op,
+            threshold,
+        }
+    }
+
+    pub fn relation_name(&self) -> String {
+        format!("{}{}{}", self.attribute, self.op.symbol(), self.threshold)
+    }
+
+    pub fn from_relation_name(relation_name: &str) -> Option<NumericComparison> {
+        for op in ComparisonOp::ALL {
+            if let Some((attribute, threshold)) = relation_name.split_once(op.symbol()) {
+                let valid_attribute = !attribute.is_empty()
+                    && attribute.chars().all(|c| c.is_alphanumeric() || c == '_');
+                return match threshold.parse::<f64>() {
+                    Ok(threshold) if valid_attribute && threshold.is_finite() => {
+                        Some(NumericComparison::new(attribute.to_string(), op, threshold))
+                    }
+                    _ => None,
+                };
+            }
+        }
+        None
+    }
+
+    /// The comparison `proposition` makes and the entity it is about, if it is one.
+    pub fn from_proposition(proposition: &Proposition) -> Option<(NumericComparison, Entity)> {
+        let comparison = Self::from_relation_name(&proposition.predicate.relation.relation_name)?;
+        match proposition.predicate.roles.as_slice() {
+            [LabeledArgument {
+                argument: Argument::Constant(constant),
+                ..
+            }] => Some((
+                comparison,
+                Entity {
+                    domain: constant.domain.clone(),
+                    name: constant.entity_id.clone(),
+                },
+            )),
+            _ => None,
+        }
+    }
+
+    /// Whether the comparison holds of an entity whose attribute is `value`. An entity
+    /// without the attribute satisfies no comparison.
+    pub fn holds(&self, value: Option<f64>) -> bool {
+        value.is_some_and(|value| self.op.holds(value, self.threshold))
+    }
+
+    /// How far `value` is past the threshold, relative to the threshold's size.
+    pub fn margin(&self, value: f64) -> f64 {
+        (value - self.threshold) / self.threshold.abs().max(1f64)
+    }
+}
+
+/// A premise test decided while a rule is grounded, from the substituted arguments alone.
+/// A failing test prunes the substitution, a passing one is dropped from the premise, so
+/// builtins never become variables of the proposition graph. Like `NumericComparison`,
+/// each is a reserved relation name: `eq` and `neq` over two roles, and `in_domain:D`
+/// and `subclass_of:D` over one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BuiltinPredicate {
+    /// Both arguments are the same entity of the same domain.
+    Eq,
+    Neq,
+    /// The argument is a stored entity of the domain or of one of its subclasses.
+    InDomain(String),
+    /// The argument's declared domain is the domain or one of its subclasses.
+    SubclassOf(String),
+}
+
+impl BuiltinPredicate {
+    pub fn relation_name(&self) -> String {
+        match self {
+            BuiltinPredicate::Eq => "eq".to_string(),
+            BuiltinPredicate::Neq => "neq".to_string(),
+            BuiltinPredicate::InDomain(domain) => format!("in_domain:{}", domain),
+            BuiltinPredicate::SubclassOf(domain) => format!("subclass_of:{}", domain),
+        }
+    }
+
+    pub fn from_relation_name(relation_name: &str) -> Option<BuiltinPredicate> {
+        match relation_name.split_once(':') {
+            None if relation_name == "eq" => Some(BuiltinPredicate::Eq),
+            None if relation_name == "neq" => Some(BuiltinPredicate::Neq),
+            Some(("in_domain", domain)) if !domain.is_empty() => {
+                Some(BuiltinPredicate::InDomain(domain.to_string()))
+            }
+            Some(("subclass_of", domain)) if !domain.is_empty() => {
+                Some(BuiltinPredicate::SubclassOf(domain.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn from_predicate(predicate: &Predicate) -> Option<BuiltinPredicate> {
+        Self::from_relation_name(&predicate.relation.relation_name)
+    }
+
+    /// How many roles the builtin takes.
+    pub fn arity(&self) -> usize {
+        match self {
+            BuiltinPredicate::Eq | BuiltinPredicate::Neq => 2,
+            BuiltinPredicate::InDomain(_) | BuiltinPredicate::SubclassOf(_) => 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuantifierKind {
+    Exists,
+    ForAll,
+}
+
+/// Quantifies the roles a premise term leaves unbound by the rule's role maps. The
+/// term grounds to one aggregate proposition, which keeps those roles as variables and
+/// whose only parent is the group of every instance over the roles' domains, combined
+/// with a noisy-OR for `Exists` and a noisy-AND for `ForAll`. Like the builtins, it is a
+/// relation name: `exists[0.9,0.05]:likes` wraps `likes`. The CPD is part of the
+/// group's identity, so aggregates over the same instances under different quantifiers
+/// or thresholds get groups of their own.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Quantifier {
+    pub kind: QuantifierKind,
+    pub strength: f64,
+    pub leak: f64,
+}
+
+impl Quantifier {
+    pub fn exists(strength: f64, leak: f64) -> Self {
+        Quantifier {
+            kind: QuantifierKind::Exists,
+            strength,
+            leak,
+        }
+    }
+
+    pub fn for_all(strength: f64, leak: f64) -> Self {
+        Quantifier {
+            kind: QuantifierKind::ForAll,
+            strength,
+            leak,
+        }
+    }
+
+    fn prefix(&self) -> &'static str {
+        match self.kind {
+            QuantifierKind::Exists => "exists",
+            QuantifierKind::ForAll => "forall",
+        }
+    }
+
+    /// How the aggregate depends on its instances.
+    pub fn cpd(&self) -> CpdType {
+        match self.kind {
+            QuantifierKind::Exists => CpdType::NoisyOr {
+                strength: self.strength,
+                leak: self.leak,
+            },
+            QuantifierKind::ForAll => CpdType::NoisyAnd {
+                strength: self.strength,
+                leak: self.leak,
+            },
+        }
+    }
+
+    /// The relation of the aggregate over `inner`.
+    pub fn aggregate_relation(&self, inner: &Relation) -> Relation {
+        let name = format!(
+            "{}[{},{}]:{}",
+            self.prefix(),
+            self.strength,
+            self.leak,
+            inner.relation_name
+        );
+        Relation::new(name, inner.types.clone())
+    }
+
+    /// The quantifier and the quantified relation, if `relation` is an aggregate.
+    pub fn from_relation(relation: &Relation) -> Option<(Quantifier, Relation)> {
+        let (prefix, rest) = relation.relation_name.split_once('[')?;
+        let (parameters, inner) = rest.split_once("]:")?;
+        let (strength, leak) = parameters.split_once(',')?;
+        let quantifier = Quantifier {
+            kind: match prefix {
+                "exists" => QuantifierKind::Exists,
+                "forall" => QuantifierKind::ForAll,
+                _ => return None,
+            },
+            strength: strength.parse().ok()?,
+            leak: leak.parse().ok()?,
+        };
+        if inner.is_empty() || quantifier.cpd().validate(1).is_err() {
+            return None;
+        }
+        Some((quantifier, Relation::new(inner.to_string(), relation.types.clone())))
+    }
+}
+
+/// How a negated premise term is read.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Negation {
+    /// `¬p` holds with probability `1 - P(p)`. `p` stays a parent in the graph, so
+    /// belief flows through it in both directions.
+    Probabilistic,
+    /// `¬p` holds unless `p` is believed true in the fact memory (closed world).
+    /// Nothing is inferred about `p` through this term.
+    AsFailure,
+}
+
+impl Negation {
+    pub fn marker(&self) -> &'static str {
+        match self {
+            Negation::Probabilistic => "¬",
+            Negation::AsFailure => "~",
+        }
+    }
+
+    /// The truth of the literal given the truth of its term, or, for negation as
+    /// failure, given what the fact memory holds for the term.
+    pub fn literal_value(negation: Option<Negation>, term: bool, fact: Option<f64>) -> bool {
+        match negation {
+            None => term,
+            Some(Negation::Probabilistic) => !term,
+            Some(Negation::AsFailure) => !fact.is_some_and(|probability| probability > 0.5),
+        }
+    }
+}
+
+/// An empty list means every term is positive; otherwise it is parallel to the terms.
+fn normalize_negations(negations: Vec<Option<Negation>>) -> Vec<Option<Negation>> {
+    if negations.iter().all(|negation| negation.is_none()) {
+        vec![]
+    } else {
+        negations
+    }
+}
+
+fn literal_string(negations: &[Option<Negation>], index: usize, term: String) -> String {
+    match negations.get(index).copied().flatten() {
+        Some(negation) => format!("{}{}", negation.marker(), term),
+        None => term,
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct PredicateGroup {
     pub terms: Vec<Predicate>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub negations: Vec<Option<Negation>>,
 }
 
 impl fmt::Debug for PredicateGroup {
@@ -263,14 +708,27 @@ impl fmt::Debug for PredicateGroup {
 
 impl PredicateGroup {
     pub fn new(terms: Vec<Predicate>) -> Self {
-        PredicateGroup { terms }
+        PredicateGroup { terms, negations: vec![] }
+    }
+
+    /// `negations[i]` says whether and how `terms[i]` is negated.
+    pub fn new_with_negations(terms: Vec<Predicate>, negations: Vec<Option<Negation>>) -> Self {
+        PredicateGroup {
+            terms,
+            negations: normalize_negations(negations),
+        }
+    }
+
+    pub fn negation(&self, index: usize) -> Option<Negation> {
+        self.negations.get(index).copied().flatten()
     }
 
     pub fn hash_string(&self) -> String {
         let mut hash_strings: Vec<String> = self
             .terms
             .iter()
-            .map(|term| term.hash_string()) // Map each term to its search string
+            .enumerate()
+            .map(|(index, term)| literal_string(&self.negations, index, term.hash_string())) // Map each term to its search string
             .collect();
         hash_strings.sort(); // Sort the search strings in ascending order
         hash_strings.join(";") // Join the sorted strings, separated by a comma and a space
@@ -283,6 +741,14 @@ impl PredicateGroup {
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct PropositionGroup {
     pub terms: Vec<Proposition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub negations: Vec<Option<Negation>>,
+    /// The built-in CPD of the implication this group is the premise of, or `None` if
+    /// it is learned. Part of the group's identity, so a premise shared by a learned and
+    /// a built-in rule, or by rules with different built-in CPDs, grounds to one group
+    /// node per CPD.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpd: Option<CpdType>,
 }
 
 impl fmt::Debug for PropositionGroup {
@@ -291,21 +757,67 @@ impl fmt::Debug for PropositionGroup {
     }
 }
 
+/// Ordered by `hash_string`, like `Proposition`.
+impl Ord for PropositionGroup {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.hash_string().cmp(&other.hash_string())
+    }
+}
+
+impl PartialOrd for PropositionGroup {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl PropositionGroup {
     pub fn new(terms: Vec<Proposition>) -> Self {
         let mut buffer = terms.clone();
         buffer.sort_by(|a, b| a.predicate.relation.relation_name.cmp(&b.predicate.relation.relation_name));
-        PropositionGroup { terms }
+        PropositionGroup { terms, negations: vec![], cpd: None }
+    }
+
+    /// `negations[i]` says whether and how `terms[i]` is negated.
+    pub fn new_with_negations(terms: Vec<Proposition>, negations: Vec<Option<Negation>>) -> Self {
+        PropositionGroup {
+            terms,
+            negations: normalize_negations(negations),
+            cpd: None,
+        }
+    }
+
+    /// This group as the premise of an implication with `cpd`.
+    pub fn with_cpd(mut self, cpd: &CpdType) -> Self {
+        self.cpd = if cpd.is_learned() { None } else { Some(cpd.clone()) };
+        self
+    }
+
+    pub fn negation(&self, index: usize) -> Option<Negation> {
+        self.negations.get(index).copied().flatten()
+    }
+
+    /// The negation of `term` in this group, if it is one of the terms.
+    pub fn negation_of(&self, term: &Proposition) -> Option<Negation> {
+        let index = self.terms.iter().position(|candidate| candidate == term)?;
+        self.negation(index)
+    }
+
+    pub fn has_negation_as_failure(&self) -> bool {
+        self.negations.contains(&Some(Negation::AsFailure))
     }
 
     pub fn hash_string(&self) -> String {
         let hash_strings: Vec<String> = self
             .terms
             .iter()
-            .map(|term| term.predicate.hash_string()) // Map each term to its search string
+            .enumerate()
+            .map(|(index, term)| literal_string(&self.negations, index, term.predicate.hash_string())) // Map each term to its search string
             .collect();
         let join = hash_strings.join("&"); // Join the sorted strings, separated by a comma and a space
-        format!("{{{}}}", &join)
+        match &self.cpd {
+            Some(cpd) => format!("{{{}}}@{:?}", &join, cpd),
+            None => format!("{{{}}}", &join),
+        }
     }
 
     pub fn debug_string(&self) -> String {
@@ -313,11 +825,151 @@ impl PropositionGroup {
     }
 }
 
+/// How an implication's conditional probability distribution is computed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum CpdType {
+    /// Scored by the learned `FactorModel`.
+    #[default]
+    Learned,
+    /// True exactly when every premise term is true.
+    And,
+    /// True exactly when some premise term is true.
+    Or,
+    /// Each true term independently causes the conclusion with probability `strength`;
+    /// `leak` is the chance of the conclusion with no true terms.
+    NoisyOr { strength: f64, leak: f64 },
+    /// `strength` when every term is true, scaled by `leak` for each false term.
+    NoisyAnd { strength: f64, leak: f64 },
+    /// P(true) for each premise assignment, where bit `j` of the index is term `j`.
+    Table(Vec<f64>),
+}
+
+// Parameters are validated to lie in [0, 1], so they are never NaN.
+impl Eq for CpdType {}
+
+impl Hash for CpdType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            CpdType::Learned | CpdType::And | CpdType::Or => {}
+            CpdType::NoisyOr { strength, leak } | CpdType::NoisyAnd { strength, leak } => {
+                strength.to_bits().hash(state);
+                leak.to_bits().hash(state);
+            }
+            CpdType::Table(table) => {
+                for entry in table {
+                    entry.to_bits().hash(state);
+                }
+            }
+        }
+    }
+}
+
+impl CpdType {
+    pub fn is_learned(&self) -> bool {
+        matches!(self, CpdType::Learned)
+    }
+
+    /// This CPD as a `FactorizedCpd`, if it is one: OR and AND are the noiseless noisy-OR
+    /// and noisy-AND.
+    pub fn factorized(&self) -> Option<FactorizedCpd> {
+        match *self {
+            CpdType::Or => Some(FactorizedCpd::NoisyOr { strength: 1f64, leak: 0f64 }),
+            CpdType::And => Some(FactorizedCpd::NoisyAnd { strength: 1f64, leak: 0f64 }),
+            CpdType::NoisyOr { strength, leak } => Some(FactorizedCpd::NoisyOr { strength, leak }),
+            CpdType::NoisyAnd { strength, leak } => Some(FactorizedCpd::NoisyAnd { strength, leak }),
+            CpdType::Learned | CpdType::Table(_) => None,
+        }
+    }
+
+    /// P(conclusion | premise terms), or `None` if the distribution is learned.
+    pub fn probability_given_terms(&self, terms: &[bool]) -> Option<f64> {
+        let num_true = terms.iter().filter(|term| **term).count() as i32;
+        let num_false = terms.len() as i32 - num_true;
+        match self {
+            CpdType::Learned => None,
+            CpdType::And => Some(if num_false == 0 { 1f64 } else { 0f64 }),
+            CpdType::Or => Some(if num_true > 0 { 1f64 } else { 0f64 }),
+            CpdType::NoisyOr { strength, leak } => {
+                Some(1f64 - (1f64 - leak) * (1f64 - strength).powi(num_true))
+            }
+            CpdType::NoisyAnd { strength, leak } => Some(strength * leak.powi(num_false)),
+            CpdType::Table(table) => {
+                let mut index = 0usize;
+                for (position, term) in terms.iter().enumerate() {
+                    if *term {
+                        index |= 1 << position;
+                    }
+                }
+                table.get(index).copied()
+            }
+        }
+    }
+
+    /// Checks the parameters against a premise with `arity` terms.
+    pub fn validate(&self, arity: usize) -> Result<(), String> {
+        let in_range = |value: &f64| (0f64..=1f64).contains(value);
+        match self {
+            CpdType::NoisyOr { strength, leak } | CpdType::NoisyAnd { strength, leak }
+                if !in_range(strength) || !in_range(leak) =>
+            {
+                return Err(format!("{:?} parameters must be in [0, 1]", self));
+            }
+            CpdType::Table(table) => {
+                if table.len() != 1 << arity {
+                    return Err(format!(
+                        "CPT has {} rows but a premise of {} terms needs {}",
+                        table.len(),
+                        arity,
+                        1 << arity
+                    ));
+                }
+                if !table.iter().all(in_range) {
+                    return Err("CPT entries must be in [0, 1]".to_string());
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// A CPD with P(true | terms) = `probability(Π term_factor(x_j))`. Since the expectation
+/// of a product of independent factors is the product of their expectations, the same
+/// expression over the terms' probabilities of being true gives the CPD's expectation,
+/// in time linear in the number of terms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FactorizedCpd {
+    NoisyOr { strength: f64, leak: f64 },
+    NoisyAnd { strength: f64, leak: f64 },
+}
+
+impl FactorizedCpd {
+    /// The factor of a term that is true with probability `probability`.
+    pub fn term_factor(&self, probability: f64) -> f64 {
+        match self {
+            FactorizedCpd::NoisyOr { strength, .. } => 1f64 - probability * strength,
+            FactorizedCpd::NoisyAnd { leak, .. } => probability + (1f64 - probability) * leak,
+        }
+    }
+
+    /// P(true) given the product of the terms' factors.
+    pub fn probability(&self, product: f64) -> f64 {
+        match self {
+            FactorizedCpd::NoisyOr { leak, .. } => 1f64 - (1f64 - leak) * product,
+            FactorizedCpd::NoisyAnd { strength, .. } => strength * product,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ImplicationFactor {
     pub premise: PredicateGroup,
     pub role_maps: GroupRoleMap,
     pub conclusion: Predicate,
+    /// Left out of the serialized record when learned, so existing records are unchanged.
+    #[serde(default, skip_serializing_if = "CpdType::is_learned")]
+    pub cpd: CpdType,
 }
 
 impl ImplicationFactor {
@@ -348,6 +1000,9 @@ pub struct Entity {
     pub name: String,
 }
 
+/// Maps the roles of a conclusion to the roles of one premise term. A key that is not a
+/// role of the conclusion is a join variable: the premise terms that map it share it, and
+/// the implication is grounded once for every entity it can take.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RoleMap {
     pub role_map: Vec<(String, String)>,
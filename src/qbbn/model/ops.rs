@@ -97,3 +97,22 @@ pub fn extract_premise_role_map(
     debug!("Extraction complete, result: {:?}", result);
     result
 }
+
+/// Like `convert_to_proposition`, for a quantified premise term: roles missing from
+/// `role_map` are the quantified ones and stay variables.
+pub fn convert_to_aggregate(
+    predicate: &Predicate,
+    role_map: &HashMap<String, Argument>,
+) -> Proposition {
+    let roles = predicate
+        .roles()
+        .into_iter()
+        .map(|role| match role_map.get(&role.role_name) {
+            Some(substitute) if role.argument.is_variable() => role.do_substitution(substitute.clone()),
+            _ => role,
+        })
+        .collect();
+    Proposition {
+        predicate: Predicate::new_from_relation(predicate.relation.clone(), roles),
+    }
+}
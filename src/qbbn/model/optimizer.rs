@@ -0,0 +1,109 @@
+use super::config::{ExponentialTrainingConfig, Optimizer};
+use log::trace;
+use std::collections::HashMap;
+
+/// Per-feature state of the optimizer, carried between training examples.
+#[derive(Debug, Clone, Default)]
+pub struct OptimizerState {
+    step: usize,
+    /// Momentum velocity, or Adam's first moment.
+    first_moment: HashMap<String, f64>,
+    /// AdaGrad's sum of squared gradients, or Adam's second moment.
+    second_moment: HashMap<String, f64>,
+    /// Updates each feature has had, for Adam's bias correction: a feature first seen
+    /// late has moments as young as its own updates, not the run's.
+    updates: HashMap<String, i32>,
+}
+
+fn sign(value: f64) -> f64 {
+    if value > 0f64 {
+        1f64
+    } else if value < 0f64 {
+        -1f64
+    } else {
+        0f64
+    }
+}
+
+impl OptimizerState {
+    /// Training examples seen so far.
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// Moves on to the next training example.
+    pub fn advance(&mut self) {
+        self.step += 1;
+    }
+
+    /// New values for `weights`, moving them towards `gold_features` and away from
+    /// `expected_features`, with the regularization, clipping and update rule of `config`.
+    pub fn update(
+        &mut self,
+        config: &ExponentialTrainingConfig,
+        weights: &HashMap<String, f64>,
+        gold_features: &HashMap<String, f64>,
+        expected_features: &HashMap<String, f64>,
+        print_training_loss: bool,
+    ) -> HashMap<String, f64> {
+        let mut gradients = HashMap::new();
+        for (feature, &wv) in weights {
+            let gv = gold_features.get(feature).unwrap_or(&0.0);
+            let ev = expected_features.get(feature).unwrap_or(&0.0);
+            gradients.insert(feature.clone(), gv - ev - config.l2 * wv - config.l1 * sign(wv));
+        }
+        if let Some(clip) = config.gradient_clip {
+            // Summed in feature order, so the norm does not depend on the map's iteration order.
+            let mut features: Vec<&String> = gradients.keys().collect();
+            features.sort();
+            let norm = features.iter().map(|f| gradients[*f].powi(2)).sum::<f64>().sqrt();
+            if norm > clip {
+                for gradient in gradients.values_mut() {
+                    *gradient *= clip / norm;
+                }
+            }
+        }
+        let rate = config.learning_rate_at(self.step);
+        let mut new_weights = HashMap::new();
+        for (feature, &wv) in weights {
+            let gradient = gradients[feature];
+            let first = self.first_moment.entry(feature.clone()).or_insert(0f64);
+            let second = self.second_moment.entry(feature.clone()).or_insert(0f64);
+            let updates = self.updates.entry(feature.clone()).or_insert(0);
+            *updates += 1;
+            let new_weight = match config.optimizer {
+                Optimizer::Sgd { momentum } => {
+                    *first = momentum * *first + gradient;
+                    wv + rate * *first
+                }
+                Optimizer::AdaGrad { epsilon } => {
+                    *second += gradient * gradient;
+                    wv + rate * gradient / (second.sqrt() + epsilon)
+                }
+                Optimizer::Adam { beta1, beta2, epsilon } | Optimizer::AdamW { beta1, beta2, epsilon, .. } => {
+                    *first = beta1 * *first + (1f64 - beta1) * gradient;
+                    *second = beta2 * *second + (1f64 - beta2) * gradient * gradient;
+                    let first_hat = *first / (1f64 - beta1.powi(*updates));
+                    let second_hat = *second / (1f64 - beta2.powi(*updates));
+                    let decay = match config.optimizer {
+                        Optimizer::AdamW { weight_decay, .. } => weight_decay * wv,
+                        _ => 0f64,
+                    };
+                    wv + rate * (first_hat / (second_hat.sqrt() + epsilon) - decay)
+                }
+            };
+            if print_training_loss {
+                trace!(
+                    "feature: {}, gradient: {}, rate: {}, old_weight: {}, new_weight: {}",
+                    feature,
+                    gradient,
+                    rate,
+                    wv,
+                    new_weight
+                );
+            }
+            new_weights.insert(feature.clone(), new_weight);
+        }
+        new_weights
+    }
+}
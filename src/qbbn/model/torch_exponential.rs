@@ -5,12 +5,13 @@ use super::ModelWeights;
 use crate::qbbn::common::interface::{PredictStatistics, TrainStatistics};
 use crate::qbbn::common::model::{FactorContext, FactorModel};
 use crate::qbbn::common::redis::MockConnection as Connection;
-use crate::qbbn::model::exponential::features_from_factor;
+use crate::qbbn::model::features::FeatureExtractors;
+use crate::qbbn::model::tying::WeightTying;
 use crate::qbbn::model::weights::CLASS_LABELS;
 use log::{info, trace};
 use std::collections::HashMap;
 use std::error::Error;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock};
 use tch::{nn, nn::OptimizerConfig, Kind, Tensor};
 
 pub struct TorchExponentialModel {
@@ -19,12 +20,28 @@ pub struct TorchExponentialModel {
     var_store: Arc<Mutex<Option<nn::VarStore>>>,
     optimizer: Arc<Mutex<Option<nn::Optimizer>>>,
     training_step: Arc<Mutex<i64>>,
+    /// Names the features of tied implications. Read from the namespace when first
+    /// needed unless the model was loaded from a file.
+    tying: OnceLock<Option<Arc<WeightTying>>>,
+    /// Extractors used besides the built-in ones.
+    extractors: FeatureExtractors,
+}
+
+/// Seeds libtorch's global generator, if `config` has a seed.
+fn seed_torch(config: &TorchConfig) {
+    if let Some(seed) = config.seed {
+        tch::manual_seed(seed as i64);
+    }
 }
 
 impl TorchExponentialModel {
     pub fn new_mutable(namespace: String) -> Result<Box<dyn FactorModel>, Box<dyn Error>> {
-        let config = TorchConfig::from_env();
+        Self::new_with_config(namespace, TorchConfig::from_env())
+    }
+
+    pub fn new_with_config(namespace: String, config: TorchConfig) -> Result<Box<dyn FactorModel>, Box<dyn Error>> {
         info!("Initializing TorchExponentialModel with config: {:?}", config);
+        seed_torch(&config);
         
         let weights = TorchWeights::new(namespace.clone(), config.device)?;
         
@@ -34,12 +51,15 @@ impl TorchExponentialModel {
             var_store: Arc::new(Mutex::new(None)),
             optimizer: Arc::new(Mutex::new(None)),
             training_step: Arc::new(Mutex::new(0)),
+            tying: OnceLock::new(),
+            extractors: FeatureExtractors::default(),
         }))
     }
 
     pub fn new_shared(namespace: String) -> Result<Arc<dyn FactorModel>, Box<dyn Error>> {
         let config = TorchConfig::from_env();
         info!("Initializing shared TorchExponentialModel with config: {:?}", config);
+        seed_torch(&config);
         
         let weights = TorchWeights::new(namespace.clone(), config.device)?;
         
@@ -49,6 +69,8 @@ impl TorchExponentialModel {
             var_store: Arc::new(Mutex::new(None)),
             optimizer: Arc::new(Mutex::new(None)),
             training_step: Arc::new(Mutex::new(0)),
+            tying: OnceLock::new(),
+            extractors: FeatureExtractors::default(),
         }))
     }
     
@@ -75,13 +97,16 @@ impl TorchExponentialModel {
             var_store: Arc::new(Mutex::new(None)),
             optimizer: Arc::new(Mutex::new(None)),
             training_step: Arc::new(Mutex::new(0)),
+            tying: OnceLock::from(model_weights.weight_tying.map(Arc::new)),
+            extractors: FeatureExtractors::default(),
         }))
     }
     
     /// Save the model weights to a file
-    pub fn save_to_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
+    pub fn save_to_file(&self, connection: &mut Connection, path: &str) -> Result<(), Box<dyn Error>> {
         info!("Saving TorchExponentialModel to file: {}", path);
-        let model_weights = self.weights.to_model_weights()?;
+        let mut model_weights = self.weights.to_model_weights()?;
+        model_weights.weight_tying = self.tying(connection)?.map(|tying| (**tying).clone());
         model_weights.save_to_file(path)?;
         Ok(())
     }
@@ -144,6 +169,24 @@ impl TorchExponentialModel {
         Ok(())
     }
 
+    /// The weight tying features are named with, read from `connection` the first time.
+    fn tying(&self, connection: &mut Connection) -> Result<Option<&Arc<WeightTying>>, Box<dyn Error>> {
+        if self.tying.get().is_none() {
+            let stored = WeightTying::load(connection, self.weights.namespace())?.map(Arc::new);
+            let _ = self.tying.set(stored);
+        }
+        Ok(self.tying.get().and_then(Option::as_ref))
+    }
+
+    /// The features of `factor`, with the model's extractors.
+    fn features(
+        &self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+    ) -> Result<Vec<HashMap<String, f64>>, Box<dyn Error>> {
+        self.extractors.features(self.tying(connection)?, factor)
+    }
+
     /// Compute potential using tensor operations
     fn compute_potential_tensor(&self, features: &HashMap<String, f64>) -> Result<f64, Box<dyn Error>> {
         let feature_tensor = self.weights.features_to_tensor(features)?;
@@ -177,7 +220,18 @@ impl TorchExponentialModel {
 
         let weight_tensor = self.weights.get_weight_tensor()
             .ok_or("Weights not initialized")?;
+        let potentials = self.batch_dot_products(feature_list, &weight_tensor)?.exp();
+        
+        // Convert back to Vec<f64>
+        let potentials_vec: Vec<f64> = (0..potentials.size()[0])
+            .map(|i| potentials.double_value(&[i]))
+            .collect();
         
+        Ok(potentials_vec)
+    }
+
+    /// The dot product of each feature vector with `weights`, as one tensor
+    fn batch_dot_products(&self, feature_list: &[HashMap<String, f64>], weights: &Tensor) -> Result<Tensor, Box<dyn Error>> {
         // Convert all features to tensors
         let feature_tensors: Result<Vec<_>, _> = feature_list.iter()
             .map(|f| self.weights.features_to_tensor(f))
@@ -189,25 +243,17 @@ impl TorchExponentialModel {
         
         // Ensure dimensions match
         let feature_size = batch_features.size()[1];
-        let weight_size = weight_tensor.size()[0];
+        let weight_size = weights.size()[0];
         
         let dot_products = if feature_size <= weight_size {
-            let relevant_weights = weight_tensor.narrow(0, 0, feature_size);
+            let relevant_weights = weights.narrow(0, 0, feature_size);
             batch_features.matmul(&relevant_weights.unsqueeze(1)).squeeze_dim(1)
         } else {
             let padded_weights = Tensor::zeros(&[feature_size], (Kind::Float, self.config.device));
-            padded_weights.narrow(0, 0, weight_size).copy_(&weight_tensor);
+            padded_weights.narrow(0, 0, weight_size).copy_(weights);
             batch_features.matmul(&padded_weights.unsqueeze(1)).squeeze_dim(1)
         };
-        
-        let potentials = dot_products.exp();
-        
-        // Convert back to Vec<f64>
-        let potentials_vec: Vec<f64> = (0..potentials.size()[0])
-            .map(|i| potentials.double_value(&[i]))
-            .collect();
-        
-        Ok(potentials_vec)
+        Ok(dot_products)
     }
 }
 
@@ -217,20 +263,25 @@ impl FactorModel for TorchExponentialModel {
         connection: &mut Connection,
         implication: &ImplicationFactor,
     ) -> Result<(), Box<dyn Error>> {
-        self.weights.initialize_weights(connection, implication)?;
+        let tying = self.tying(connection)?.cloned();
+        let features: Vec<String> = CLASS_LABELS
+            .iter()
+            .flat_map(|&class_label| self.extractors.feature_names(tying.as_ref(), implication, class_label))
+            .collect();
+        self.weights.initialize_weights(connection, &features)?;
         Ok(())
     }
 
     fn train(
         &mut self,
-        _connection: &mut Connection,
+        connection: &mut Connection,
         factor: &FactorContext,
         gold_probability: f64,
     ) -> Result<TrainStatistics, Box<dyn Error>> {
         trace!("TorchExponentialModel::train - Start");
         
         // Get features
-        let features = features_from_factor(factor)?;
+        let features = self.features(connection, factor)?;
         
         // Ensure optimizer is initialized
         self.ensure_optimizer()?;
@@ -309,14 +360,80 @@ impl FactorModel for TorchExponentialModel {
         Ok(TrainStatistics { loss: loss_value })
     }
 
+    fn train_batch(
+        &mut self,
+        connection: &mut Connection,
+        factors: &[FactorContext],
+        probabilities: &[f64],
+    ) -> Result<TrainStatistics, Box<dyn Error>> {
+        trace!("TorchExponentialModel::train_batch - Start, {} examples", factors.len());
+        if factors.is_empty() {
+            return Ok(TrainStatistics { loss: 0f64 });
+        }
+        
+        // Both classes of every example, in order: [class 0, class 1, class 0, ...]
+        let mut feature_list = Vec::with_capacity(2 * factors.len());
+        for factor in factors {
+            let features = self.features(connection, factor)?;
+            for &class_label in &CLASS_LABELS {
+                feature_list.push(features[class_label].clone());
+            }
+        }
+        
+        self.ensure_optimizer()?;
+        
+        let loss_value = {
+            let var_store_guard = self.var_store.lock().unwrap();
+            let mut opt_guard = self.optimizer.lock().unwrap();
+            
+            if let (Some(vars), Some(optimizer)) = (var_store_guard.as_ref(), opt_guard.as_mut()) {
+                let vars_variables = vars.variables();
+                let weights = vars_variables.get("weights")
+                    .ok_or("Weights not found in VarStore")?;
+                
+                let log_probs = self
+                    .batch_dot_products(&feature_list, weights)?
+                    .reshape([factors.len() as i64, 2])
+                    .log_softmax(1, Kind::Float);
+                let gold: Vec<f32> = probabilities.iter().map(|&p| p as f32).collect();
+                let gold = Tensor::from_slice(&gold).to_device(self.config.device);
+                let complement = gold.ones_like() - &gold;
+                
+                // Mean cross-entropy against the gold probabilities
+                let loss_tensor = -(&gold * log_probs.select(1, 1) + complement * log_probs.select(1, 0))
+                    .mean(Kind::Float);
+                
+                optimizer.zero_grad();
+                loss_tensor.backward();
+                if let Some(clip) = self.config.gradient_clip {
+                    optimizer.clip_grad_norm(clip);
+                }
+                optimizer.step();
+                
+                let loss = loss_tensor.double_value(&[]);
+                self.weights.update_from_tensor(weights)?;
+                loss
+            } else {
+                return Err("Optimizer not initialized".into());
+            }
+        };
+        
+        let mut step_guard = self.training_step.lock().unwrap();
+        *step_guard += 1;
+        info!("Training batch {}: loss = {:.6}", *step_guard, loss_value);
+        
+        trace!("TorchExponentialModel::train_batch - End");
+        Ok(TrainStatistics { loss: loss_value })
+    }
+
     fn predict(
         &self,
-        _connection: &mut Connection,
+        connection: &mut Connection,
         factor: &FactorContext,
     ) -> Result<PredictStatistics, Box<dyn Error>> {
         trace!("TorchExponentialModel::predict - Start");
         
-        let features = features_from_factor(factor)?;
+        let features = self.features(connection, factor)?;
         
         // Use batch computation even for single prediction for consistency
         let feature_list: Vec<_> = CLASS_LABELS.iter()
@@ -332,10 +449,39 @@ impl FactorModel for TorchExponentialModel {
         Ok(PredictStatistics { probability })
     }
     
-    fn save_to_file(&self, _connection: &mut Connection, path: &str) -> Result<(), Box<dyn Error>> {
-        self.save_to_file(path)
+    fn weight_version(&self, _connection: &Connection) -> u64 {
+        self.weights.version().get()
     }
-    
+
+    fn factor_features(
+        &self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+    ) -> Result<Vec<HashMap<String, f64>>, Box<dyn Error>> {
+        self.features(connection, factor)
+    }
+
+    fn snapshot_weights(&self, _connection: &mut Connection) -> Result<ModelWeights, Box<dyn Error>> {
+        self.weights.to_model_weights()
+    }
+
+    /// Also drops the optimizer, so the next step rebuilds it from the restored weights
+    /// with fresh moments.
+    fn restore_weights(
+        &mut self,
+        _connection: &mut Connection,
+        weights: &ModelWeights,
+    ) -> Result<(), Box<dyn Error>> {
+        self.weights.load_from_model_weights(weights)?;
+        *self.optimizer.lock().unwrap() = None;
+        *self.var_store.lock().unwrap() = None;
+        Ok(())
+    }
+
+    fn save_to_file(&self, connection: &mut Connection, path: &str) -> Result<(), Box<dyn Error>> {
+        self.save_to_file(connection, path)
+    }
+
     fn model_type(&self) -> &str {
         "torch_exponential"
     }
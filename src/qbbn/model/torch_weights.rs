@@ -1,6 +1,5 @@
 use crate::qbbn::common::redis::MockConnection as Connection;
-use crate::qbbn::model::objects::ImplicationFactor;
-use crate::qbbn::model::weights::{positive_feature, negative_feature, CLASS_LABELS};
+use crate::qbbn::model::weights::WeightVersion;
 use crate::qbbn::model::ModelWeights;
 use log::trace;
 use std::collections::HashMap;
@@ -18,11 +17,13 @@ pub struct TorchWeights {
     weights: Arc<Mutex<Option<Tensor>>>,
     /// Next available index for new features
     next_index: Arc<Mutex<i64>>,
+    version: Arc<WeightVersion>,
 }
 
 impl TorchWeights {
     pub fn new(namespace: String, device: Device) -> Result<Self, Box<dyn Error>> {
         Ok(TorchWeights {
+            version: Arc::default(),
             namespace,
             device,
             feature_indices: Arc::new(Mutex::new(HashMap::new())),
@@ -31,32 +32,33 @@ impl TorchWeights {
         })
     }
 
-    /// Initialize weights for a new implication
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// The write counter of these weights, which are kept in memory rather than stored.
+    pub fn version(&self) -> &Arc<WeightVersion> {
+        &self.version
+    }
+
+    /// Initialize weights for the features of a new implication
     pub fn initialize_weights(
         &mut self,
         _connection: &mut Connection,
-        implication: &ImplicationFactor,
+        features: &[String],
     ) -> Result<(), Box<dyn Error>> {
-        trace!("TorchWeights::initialize_weights - Start: {:?}", implication);
+        trace!("TorchWeights::initialize_weights - Start: {:?}", features);
         
-        let feature = implication.unique_key();
         let mut feature_indices = self.feature_indices.lock().unwrap();
         let mut next_index = self.next_index.lock().unwrap();
         let mut weights = self.weights.lock().unwrap();
         
-        for class_label in CLASS_LABELS {
-            let posf = positive_feature(&feature, class_label);
-            let negf = negative_feature(&feature, class_label);
-            
-            // Add features to index if not already present
-            if !feature_indices.contains_key(&posf) {
-                feature_indices.insert(posf.clone(), *next_index);
-                *next_index += 1;
-            }
-            if !feature_indices.contains_key(&negf) {
-                feature_indices.insert(negf.clone(), *next_index);
+        // Add features to the index if not already present
+        for feature in features {
+            feature_indices.entry(feature.clone()).or_insert_with(|| {
                 *next_index += 1;
-            }
+                *next_index - 1
+            });
         }
         
         // Resize weight tensor if needed
@@ -76,6 +78,7 @@ impl TorchWeights {
                 *weights = Some(Tensor::randn(&[required_size], (Kind::Float, self.device)) * 0.2);
             }
         }
+        self.version.bump();
         
         trace!("TorchWeights::initialize_weights - End");
         Ok(())
@@ -134,6 +137,7 @@ impl TorchWeights {
             }
         }
         
+        self.version.bump();
         trace!("TorchWeights::save_weight_vector - End");
         Ok(())
     }
@@ -147,6 +151,7 @@ impl TorchWeights {
     pub fn update_from_tensor(&mut self, new_weights: &Tensor) -> Result<(), Box<dyn Error>> {
         let mut weights = self.weights.lock().unwrap();
         *weights = Some(new_weights.shallow_clone());
+        self.version.bump();
         Ok(())
     }
 
@@ -257,6 +262,7 @@ impl TorchWeights {
             *weights = Some(Tensor::from_slice(&weight_values).to_device(self.device));
         }
         
+        self.version.bump();
         trace!("TorchWeights::load_from_model_weights - End");
         Ok(())
     }
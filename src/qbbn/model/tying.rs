@@ -0,0 +1,144 @@
+use super::objects::ImplicationFactor;
+use crate::qbbn::common::redis::{get_value, set_value, MockConnection as Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+/// Which implications a tying group covers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TyingSelector {
+    /// Every implication.
+    All,
+    /// The implications with these `unique_key`s.
+    Implications(Vec<String>),
+    /// The implications concluding one of these relations.
+    ConclusionRelations(Vec<String>),
+    /// Rules `r(x, y) -> r(y, x)`: one premise, of the conclusion's relation, with its
+    /// roles permuted.
+    Symmetric,
+}
+
+impl TyingSelector {
+    pub fn matches(&self, implication: &ImplicationFactor) -> bool {
+        match self {
+            TyingSelector::All => true,
+            TyingSelector::Implications(keys) => keys.contains(&implication.unique_key()),
+            TyingSelector::ConclusionRelations(names) => {
+                names.contains(&implication.conclusion.relation.relation_name)
+            }
+            TyingSelector::Symmetric => {
+                let premise = &implication.premise;
+                premise.terms.len() == 1
+                    && premise.negations.iter().all(Option::is_none)
+                    && premise.terms[0].relation.relation_name == implication.conclusion.relation.relation_name
+                    && implication
+                        .role_maps
+                        .role_maps
+                        .iter()
+                        .any(|role_map| role_map.role_map.iter().any(|(from, to)| from != to))
+            }
+        }
+    }
+}
+
+/// How the implications of a group share weights.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TyingScope {
+    /// One set of weights for the whole group.
+    #[default]
+    Group,
+    /// One set per conclusion relation.
+    ConclusionRelation,
+    /// One set per rule shape: implications that differ only in their relation names
+    /// share weights.
+    Template,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TyingGroup {
+    /// Part of the name of the shared features, so it should be unique in the namespace.
+    pub name: String,
+    pub selector: TyingSelector,
+    #[serde(default)]
+    pub scope: TyingScope,
+}
+
+/// Groups of implications whose features share weights, so that rules of the same
+/// shape learn from each other's data. An implication belongs to the first group that
+/// selects it; the others keep weights of their own.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct WeightTying {
+    pub groups: Vec<TyingGroup>,
+}
+
+impl WeightTying {
+    pub const TYING_KEY: &'static str = "weight_tying";
+
+    pub fn new(groups: Vec<TyingGroup>) -> Self {
+        WeightTying { groups }
+    }
+
+    /// Reads the groups from a JSON file.
+    pub fn from_file(path: &str) -> Result<WeightTying, Box<dyn Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// The key `implication`'s features are named with: its `unique_key`, unless a
+    /// group ties it to others.
+    pub fn weight_key(&self, implication: &ImplicationFactor) -> String {
+        let Some(group) = self.groups.iter().find(|group| group.selector.matches(implication)) else {
+            return implication.unique_key();
+        };
+        match group.scope {
+            TyingScope::Group => format!("tied[{}]", group.name),
+            TyingScope::ConclusionRelation => {
+                format!("tied[{}]{}", group.name, implication.conclusion.relation.relation_name)
+            }
+            TyingScope::Template => format!("tied[{}]{}", group.name, template_key(implication)),
+        }
+    }
+
+    /// Whether a group ties `implication` to others.
+    pub fn is_tied(&self, implication: &ImplicationFactor) -> bool {
+        self.groups.iter().any(|group| group.selector.matches(implication))
+    }
+
+    /// The weight tying stored for `namespace`, if any.
+    pub fn load(connection: &mut Connection, namespace: &str) -> Result<Option<WeightTying>, Box<dyn Error>> {
+        match get_value(connection, namespace, Self::TYING_KEY)? {
+            Some(record) => Ok(serde_json::from_str(&record)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores `tying` for `namespace`; `None` unties every implication. Store it before
+    /// any weights are initialized: weights already there keep their names.
+    pub fn store(
+        connection: &mut Connection,
+        namespace: &str,
+        tying: Option<&WeightTying>,
+    ) -> Result<(), Box<dyn Error>> {
+        set_value(connection, namespace, Self::TYING_KEY, &serde_json::to_string(&tying)?)
+    }
+}
+
+/// The `unique_key` of `implication` with each relation name replaced by its position
+/// among the distinct names, in order of appearance.
+pub fn template_key(implication: &ImplicationFactor) -> String {
+    let mut template = implication.clone();
+    let mut names: HashMap<String, String> = HashMap::new();
+    let predicates = template
+        .premise
+        .terms
+        .iter_mut()
+        .chain(std::iter::once(&mut template.conclusion));
+    for predicate in predicates {
+        let next = format!("R{}", names.len());
+        let name = names.entry(predicate.relation.relation_name.clone()).or_insert(next);
+        predicate.relation.relation_name = name.clone();
+    }
+    template.unique_key()
+}
@@ -4,6 +4,7 @@ use crate::qbbn::common::model::{FactorContext, FactorModel};
 use crate::qbbn::common::redis::MockConnection as Connection;
 use crate::qbbn::model::objects::ImplicationFactor;
 use log::info;
+use std::collections::HashMap;
 use std::error::Error;
 // use std::sync::Arc;
 use std::env;
@@ -173,4 +174,77 @@ impl FactorModel for UnifiedExponentialModel {
     ) -> Result<PredictStatistics, Box<dyn Error>> {
         self.model.predict(connection, factor)
     }
+
+    fn predict_conclusion(
+        &self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+    ) -> Result<PredictStatistics, Box<dyn Error>> {
+        self.model.predict_conclusion(connection, factor)
+    }
+
+    fn train_batch(
+        &mut self,
+        connection: &mut Connection,
+        factors: &[FactorContext],
+        probabilities: &[f64],
+    ) -> Result<TrainStatistics, Box<dyn Error>> {
+        let num_entities = factors.iter().map(|factor| factor.factor.len()).sum();
+        self.auto_select_backend(num_entities, connection)?;
+        self.model.train_batch(connection, factors, probabilities)
+    }
+
+    fn predict_distribution(
+        &self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+        outcomes: usize,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        self.model.predict_distribution(connection, factor, outcomes)
+    }
+
+    fn train_distribution(
+        &mut self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+        gold: &[f64],
+    ) -> Result<TrainStatistics, Box<dyn Error>> {
+        self.model.train_distribution(connection, factor, gold)
+    }
+
+    fn read_weight(&self, connection: &mut Connection, feature: &str) -> Result<f64, Box<dyn Error>> {
+        self.model.read_weight(connection, feature)
+    }
+
+    fn weight_version(&self, connection: &Connection) -> u64 {
+        self.model.weight_version(connection)
+    }
+
+    fn with_weight_overrides(&self, weights: HashMap<String, f64>) -> Result<Box<dyn FactorModel>, Box<dyn Error>> {
+        self.model.with_weight_overrides(weights)
+    }
+
+    fn factor_features(
+        &self,
+        connection: &mut Connection,
+        factor: &FactorContext,
+    ) -> Result<Vec<HashMap<String, f64>>, Box<dyn Error>> {
+        self.model.factor_features(connection, factor)
+    }
+
+    fn snapshot_weights(&self, connection: &mut Connection) -> Result<ModelWeights, Box<dyn Error>> {
+        self.model.snapshot_weights(connection)
+    }
+
+    fn flush_weights(&mut self, connection: &mut Connection) -> Result<(), Box<dyn Error>> {
+        self.model.flush_weights(connection)
+    }
+
+    fn restore_weights(
+        &mut self,
+        connection: &mut Connection,
+        weights: &ModelWeights,
+    ) -> Result<(), Box<dyn Error>> {
+        self.model.restore_weights(connection, weights)
+    }
 }
\ No newline at end of file
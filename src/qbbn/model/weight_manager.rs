@@ -1,7 +1,6 @@
 use super::{
     delta_weights::{DeltaWeights, DeltaWeightStats},
-    weights::ExponentialWeights,
-    objects::ImplicationFactor,
+    weights::{ExponentialWeights, WeightVersion},
     ModelWeights,
 };
 use crate::qbbn::common::redis::MockConnection as Connection;
@@ -20,6 +19,9 @@ pub struct WeightManager {
     /// Delta weights (from online learning)
     delta_weights: Arc<Mutex<DeltaWeights>>,
     
+    /// Write counter of the delta weights
+    delta_version: WeightVersion,
+    
     /// Configuration
     config: WeightManagerConfig,
     
@@ -91,6 +93,7 @@ impl WeightManager {
         WeightManager {
             base_weights,
             delta_weights,
+            delta_version: WeightVersion::default(),
             config,
             namespace,
         }
@@ -141,6 +144,7 @@ impl WeightManager {
     pub fn update_weight(&mut self, feature: &str, delta: f64) {
         let mut delta_weights = self.delta_weights.lock().unwrap();
         delta_weights.update_weight(feature, delta);
+        self.delta_version.bump();
         
         // Check if consolidation is needed
         if self.should_consolidate(&delta_weights) {
@@ -157,6 +161,7 @@ impl WeightManager {
         for (feature, delta) in updates {
             delta_weights.update_weight(feature, *delta);
         }
+        self.delta_version.bump();
         
         if self.should_consolidate(&delta_weights) {
             drop(delta_weights);
@@ -199,6 +204,21 @@ impl WeightManager {
         &mut self,
         connection: &mut Connection,
         trigger: ConsolidationTrigger,
+    ) -> Result<ConsolidationResult, Box<dyn Error>> {
+        self.consolidate_features(connection, trigger, true)
+    }
+    
+    /// Consolidate every delta weight into base weights, hot features included, so that
+    /// models reading only the base weights see the effective weights
+    pub fn flush(&mut self, connection: &mut Connection) -> Result<ConsolidationResult, Box<dyn Error>> {
+        self.consolidate_features(connection, ConsolidationTrigger::Manual, false)
+    }
+    
+    fn consolidate_features(
+        &mut self,
+        connection: &mut Connection,
+        trigger: ConsolidationTrigger,
+        keep_hot: bool,
     ) -> Result<ConsolidationResult, Box<dyn Error>> {
         let start = std::time::Instant::now();
         info!("Starting weight consolidation, trigger: {:?}", trigger);
@@ -206,7 +226,11 @@ impl WeightManager {
         // Get features to consolidate
         let (features_to_consolidate, hot_features) = {
             let delta = self.delta_weights.lock().unwrap();
-            let hot = delta.get_hot_features(self.config.hot_feature_threshold);
+            let hot = if keep_hot {
+                delta.get_hot_features(self.config.hot_feature_threshold)
+            } else {
+                vec![]
+            };
             let all_features: Vec<String> = delta.get_features()
                 .into_iter()
                 .cloned()
@@ -238,6 +262,7 @@ impl WeightManager {
                 .collect();
             
             delta.clear_features(&features_to_clear);
+            self.delta_version.bump();
             
             // Decay hot features instead of clearing
             for hot_feature in &hot_features {
@@ -299,18 +324,41 @@ impl WeightManager {
         Ok(WeightManager {
             base_weights,
             delta_weights: Arc::new(Mutex::new(delta_weights)),
+            delta_version: WeightVersion::default(),
             config: WeightManagerConfig::default(),
             namespace,
         })
     }
     
-    /// Initialize weights for a new implication
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Changes whenever the base weights in the database of `connection` or the delta
+    /// weights are written. Both counters only grow, so their sum does too.
+    pub fn version(&self, connection: &Connection) -> u64 {
+        self.base_weights.version(connection) + self.delta_version.get()
+    }
+
+    /// Initialize weights for the features of a new implication
     pub fn initialize_weights(
         &mut self,
         connection: &mut Connection,
-        implication: &ImplicationFactor,
+        features: &[String],
+    ) -> Result<(), Box<dyn Error>> {
+        self.base_weights.initialize_weights(connection, features)
+    }
+    
+    /// Replace the base weights with `model_weights` and drop all deltas
+    pub fn replace_weights(
+        &mut self,
+        connection: &mut Connection,
+        model_weights: &ModelWeights,
     ) -> Result<(), Box<dyn Error>> {
-        self.base_weights.initialize_weights(connection, implication)
+        self.base_weights.load_from_model_weights(connection, model_weights)?;
+        self.delta_weights.lock().unwrap().clear_all();
+        self.delta_version.bump();
+        Ok(())
     }
     
     /// Export all weights (base + delta) as ModelWeights
@@ -346,6 +394,8 @@ impl WeightManager {
             training_loss: base_model.training_loss,
             entity_node_ids: base_model.entity_node_ids,
             proposition_node_ids: base_model.proposition_node_ids,
+            calibrator: base_model.calibrator,
+            weight_tying: base_model.weight_tying,
         })
     }
 }
\ No newline at end of file
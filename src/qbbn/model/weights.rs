@@ -1,19 +1,56 @@
 use crate::qbbn::{
-    common::redis::{map_get, map_insert},
-    model::objects::ImplicationFactor,
+    common::{
+        redis::{map_get, map_insert},
+        setup::seeded_rng,
+    },
     model::ModelWeights,
 };
 use log::trace;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng};
 use crate::qbbn::common::redis::MockConnection as Connection;
 use std::error::Error;
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 pub const CLASS_LABELS: [usize; 2] = [0, 1];
 
-fn random_weight() -> f64 {
-    let mut rng = rand::thread_rng();
+/// Changes whenever a weight kept in memory, like a delta weight, is written, so that
+/// values computed from the weights can tell they are stale. Writes of the stored weights
+/// are counted by `stored_version`.
+#[derive(Debug, Default)]
+pub struct WeightVersion(AtomicU64);
+
+impl WeightVersion {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Marks every value computed from the weights as stale.
+    pub fn bump(&self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// The database counter of the writes of a namespace's stored weights.
+fn stored_version_counter(namespace: &str) -> String {
+    format!("weights:{}", namespace)
+}
+
+/// Changes whenever a stored weight of `namespace` is written. The counter is kept by the
+/// database of `connection`, so every model of the namespace there shares it and a write
+/// through one model makes values computed with another stale, while the same namespace
+/// in another database counts its own writes.
+pub fn stored_version(connection: &Connection, namespace: &str) -> u64 {
+    connection.adapter.graph_db.counter(&stored_version_counter(namespace))
+}
+
+/// Marks every value computed from the stored weights of `namespace` as stale.
+pub fn bump_stored_version(connection: &Connection, namespace: &str) {
+    connection.adapter.graph_db.bump_counter(&stored_version_counter(namespace));
+}
+
+fn random_weight(rng: &mut StdRng) -> f64 {
     (rng.r#gen::<f64>() - rng.r#gen::<f64>()) / 5.0
 }
 
@@ -33,70 +70,75 @@ pub fn negative_feature(feature: &str, class_label: usize) -> String {
     format!("->{} {}", sign_char(class_label), feature)
 }
 
+/// A feature of the attribute value behind numeric comparison number `term` of the
+/// premise of `feature`; `kind` says how the value is encoded.
+pub fn numeric_feature(feature: &str, term: usize, kind: &str, class_label: usize) -> String {
+    format!("n>{} {}@{}:{}", sign_char(class_label), feature, term, kind)
+}
+
+/// Like `positive_feature`, for outcome `outcome` of a categorical variable.
+pub fn positive_outcome_feature(feature: &str, outcome: usize) -> String {
+    format!("+>#{} {}", outcome, feature)
+}
+
+pub fn negative_outcome_feature(feature: &str, outcome: usize) -> String {
+    format!("->#{} {}", outcome, feature)
+}
+
 pub struct ExponentialWeights {
     namespace: String,
     /// Track all features that have been initialized or accessed
     /// Arc<Mutex<>> allows thread-safe updates during concurrent training
     known_features: Arc<Mutex<HashSet<String>>>,
+    /// Draws the initial weights.
+    rng: StdRng,
 }
 
 impl ExponentialWeights {
     pub fn new(namespace: String) -> Result<ExponentialWeights, Box<dyn Error>> {
-        Ok(ExponentialWeights { 
+        Self::new_with_seed(namespace, None)
+    }
+
+    /// Initial weights are drawn from `seed`, so they repeat for the same seed and the
+    /// same order of `initialize_weights` calls.
+    pub fn new_with_seed(namespace: String, seed: Option<u64>) -> Result<ExponentialWeights, Box<dyn Error>> {
+        Ok(ExponentialWeights {
             namespace,
             known_features: Arc::new(Mutex::new(HashSet::new())),
+            rng: seeded_rng(seed),
         })
     }
+
+    /// The write counter of the namespace's weights in the database of `connection`.
+    pub fn version(&self, connection: &Connection) -> u64 {
+        stored_version(connection, &self.namespace)
+    }
 }
 
 impl ExponentialWeights {
     pub const WEIGHTS_KEY: &'static str = "weights";
 
+    /// Draws initial weights for `features`, in order.
     pub fn initialize_weights(
         &mut self,
         connection: &mut Connection,
-        implication: &ImplicationFactor,
+        features: &[String],
     ) -> Result<(), Box<dyn Error>> {
-        trace!("initialize_weights - Start: {:?}", implication);
-        let feature = implication.unique_key();
-        trace!("initialize_weights - Unique key: {}", feature);
-        for class_label in CLASS_LABELS {
-            let posf = positive_feature(&feature, class_label);
-            let negf = negative_feature(&feature, class_label);
-            trace!(
-                "initialize_weights - Positive feature: {}, Negative feature: {}",
-                posf,
-                negf
-            );
-            let weight1 = random_weight();
-            let weight2 = random_weight();
-            trace!(
-                "initialize_weights - Generated weights: {}, {}",
-                weight1,
-                weight2
-            );
+        trace!("initialize_weights - Start: {:?}", features);
+        for feature in features {
+            let weight = random_weight(&mut self.rng);
+            trace!("initialize_weights - Generated weight {} for {}", weight, feature);
             // Track the features
-            {
-                let mut features = self.known_features.lock().unwrap();
-                features.insert(posf.clone());
-                features.insert(negf.clone());
-            }
-            
+            self.known_features.lock().unwrap().insert(feature.clone());
             map_insert(
                 connection,
                 &self.namespace,
                 Self::WEIGHTS_KEY,
-                &posf,
-                &weight1.to_string(),
-            )?;
-            map_insert(
-                connection,
-                &self.namespace,
-                Self::WEIGHTS_KEY,
-                &negf,
-                &weight2.to_string(),
+                feature,
+                &weight.to_string(),
             )?;
         }
+        bump_stored_version(connection, &self.namespace);
         trace!("initialize_weights - End");
         Ok(())
     }
@@ -178,6 +220,7 @@ impl ExponentialWeights {
                 &value.to_string(),
             )?;
         }
+        bump_stored_version(connection, &self.namespace);
         trace!("save_weights - End");
         Ok(())
     }
@@ -232,6 +275,7 @@ impl ExponentialWeights {
                 &weight.to_string(),
             )?;
         }
+        bump_stored_version(connection, &self.namespace);
         
         trace!("load_from_model_weights - End");
         Ok(())
@@ -1,6 +1,7 @@
 use crate::qbbn::common::graph::InferenceGraph;
 // use crate::qbbn::common::interface::BeliefTable;
 use crate::qbbn::common::proposition_db::RedisBeliefTable;
+use crate::qbbn::common::setup::seeded_rng;
 use crate::qbbn::common::resources::{ResourceContext};
 use crate::qbbn::common::train::TrainingPlan;
 use crate::qbbn::model::creators::{predicate, relation, variable_argument};
@@ -14,8 +15,7 @@ use crate::qbbn::{
 use rand::Rng; // Import Rng trait
 use std::{collections::HashMap, error::Error};
 #[allow(dead_code)]
-fn cointoss() -> f64 {
-    let mut rng = rand::thread_rng(); // Get a random number generator
+fn cointoss(rng: &mut impl Rng) -> f64 {
     if rng.r#gen::<f64>() < 0.5 {
         1.0
     } else {
@@ -24,8 +24,7 @@ fn cointoss() -> f64 {
 }
 
 #[allow(dead_code)]
-fn weighted_cointoss(threshold: f64) -> f64 {
-    let mut rng = rand::thread_rng(); // Get a random number generator
+fn weighted_cointoss(rng: &mut impl Rng, threshold: f64) -> f64 {
     if rng.r#gen::<f64>() < threshold {
         1.0
     } else {
@@ -38,6 +37,7 @@ pub struct SimpleDating {}
 impl ScenarioMaker for SimpleDating {
     fn setup_scenario(&self, resources: &ResourceContext) -> Result<(), Box<dyn Error>> {
         let mut connection = resources.connection.lock().unwrap();
+        let mut rng = seeded_rng(resources.config.seed);
         let namespace = "dating_simple".to_string();
         let mut graph = InferenceGraph::new_mutable(namespace.clone())?;
         let proposition_db = RedisBeliefTable::new_mutable(namespace.clone())?;
@@ -124,10 +124,10 @@ impl ScenarioMaker for SimpleDating {
             let jack_entity = &domain_entity_map[&Domain::MAN.to_string()];
             let jill_entity = &domain_entity_map[&Domain::WOMAN.to_string()];
 
-            let p_jack_lonely = weighted_cointoss(0.3f64);
-            let p_jill_exciting: f64 = weighted_cointoss(0.6f64);
-            let p_jill_likes_jack: f64 = weighted_cointoss(0.4f64);
-            let p_jack_likes_jill = weighted_cointoss(numeric_or(p_jack_lonely, p_jill_exciting));
+            let p_jack_lonely = weighted_cointoss(&mut rng, 0.3f64);
+            let p_jill_exciting: f64 = weighted_cointoss(&mut rng, 0.6f64);
+            let p_jill_likes_jack: f64 = weighted_cointoss(&mut rng, 0.4f64);
+            let p_jack_likes_jill = weighted_cointoss(&mut rng, numeric_or(p_jack_lonely, p_jill_exciting));
             let p_jack_dates_jill = numeric_and(p_jack_likes_jill, p_jill_likes_jack);
 
             {
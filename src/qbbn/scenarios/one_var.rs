@@ -1,6 +1,7 @@
 use crate::qbbn::common::graph::InferenceGraph;
 use crate::qbbn::common::interface::ScenarioMaker;
 use crate::qbbn::common::proposition_db::RedisBeliefTable;
+use crate::qbbn::common::setup::seeded_rng;
 use crate::qbbn::common::resources::ResourceContext;
 use crate::qbbn::common::train::TrainingPlan;
 use crate::qbbn::model::creators::{
@@ -11,8 +12,7 @@ use rand::Rng; // Import Rng trait
 use std::error::Error;
 
 #[allow(dead_code)]
-fn cointoss() -> f64 {
-    let mut rng = rand::thread_rng(); // Get a random number generator
+fn cointoss(rng: &mut impl Rng) -> f64 {
     if rng.r#gen::<f64>() < 0.5 {
         1.0
     } else {
@@ -21,8 +21,7 @@ fn cointoss() -> f64 {
 }
 
 #[allow(dead_code)]
-fn weighted_cointoss(threshold: f64) -> f64 {
-    let mut rng = rand::thread_rng(); // Get a random number generator
+fn weighted_cointoss(rng: &mut impl Rng, threshold: f64) -> f64 {
     if rng.r#gen::<f64>() < threshold {
         1.0
     } else {
@@ -35,6 +34,7 @@ pub struct OneVariable {}
 impl ScenarioMaker for OneVariable {
     fn setup_scenario(&self, resources: &ResourceContext) -> Result<(), Box<dyn Error>> {
         let mut connection = resources.connection.lock().unwrap();
+        let mut rng = seeded_rng(resources.config.seed);
         let namespace = "one_var".to_string();
         let mut graph = InferenceGraph::new_mutable(namespace.clone())?;
         let proposition_db = RedisBeliefTable::new_mutable(namespace.clone())?;
@@ -64,7 +64,7 @@ impl ScenarioMaker for OneVariable {
             graph.store_entity(&mut connection, &entity)?;
             
             // Generate random probability (biased toward 0.3)
-            let probability = weighted_cointoss(0.3f64);
+            let probability = weighted_cointoss(&mut rng, 0.3f64);
             
             // Create proposition: exciting(entity)
             let entity_constant = constant(entity.domain, entity.name.clone());
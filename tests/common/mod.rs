@@ -0,0 +1,125 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use bayeslog::qbbn::{
+    common::{
+        graph::InferenceGraph, interface::ScenarioMaker, model::FactorContext, resources::ResourceContext,
+        train::TrainingPlan,
+    },
+    inference::graph::PropositionFactor,
+    model::{
+        creators::{conjunction, constant, implication, predicate, proposition, relation, sub, variable, variable_argument},
+        objects::{Entity, ImplicationFactor, Proposition, PropositionGroup, Relation, RoleMap},
+    },
+    scenarios::dating_simple::SimpleDating,
+};
+use std::collections::HashMap;
+
+/// A relation with one argument, of `domain`.
+pub fn unary(name: &str, domain: &str) -> Relation {
+    relation(name.to_string(), vec![variable_argument(domain.to_string())])
+}
+
+/// `premise(x) -> conclusion(x)`, for two unary relations.
+pub fn implies(premise: &Relation, conclusion: &Relation) -> ImplicationFactor {
+    let x = variable(premise.types[0].domain.clone());
+    implication(
+        conjunction(vec![predicate(premise.clone(), vec![sub(x.clone())])]),
+        predicate(conclusion.clone(), vec![sub(x)]),
+        vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))],
+    )
+}
+
+/// `premise(x) -> conclusion(x)` over the domain `Thing`.
+pub fn rule(premise: &str, conclusion: &str) -> ImplicationFactor {
+    implies(&unary(premise, "Thing"), &unary(conclusion, "Thing"))
+}
+
+/// `rule` grounded at the `Thing` called `a`, with its premise true with `probability`.
+pub fn context(rule: &ImplicationFactor, probability: f64) -> FactorContext {
+    let thing = || vec![sub(constant("Thing".to_string(), "a".to_string()))];
+    let premise = proposition(rule.premise.terms[0].relation.clone(), thing());
+    let conclusion = proposition(rule.conclusion.relation.clone(), thing());
+    FactorContext {
+        factor: vec![PropositionFactor {
+            premise: PropositionGroup::new(vec![premise]),
+            conclusion,
+            inference: rule.clone(),
+        }],
+        probabilities: vec![probability],
+        numeric_values: HashMap::new(),
+    }
+}
+
+/// `SimpleDating` with `entities_per_domain`, seeded with `seed`. The scenario hides the values of its own
+/// test questions, so its first `held_out` `date` training questions are queued for
+/// testing as well.
+pub fn dating_with_held_out_dates(
+    namespace: &str,
+    entities_per_domain: i32,
+    seed: Option<u64>,
+    held_out: usize,
+) -> ResourceContext {
+    let mut resources = ResourceContext::new_in_memory(namespace).unwrap();
+    resources.config.entities_per_domain = entities_per_domain;
+    resources.config.seed = seed;
+    SimpleDating {}.setup_scenario(&resources).unwrap();
+    {
+        let mut connection = resources.connection.lock().unwrap();
+        let mut plan = TrainingPlan::new(namespace.to_string()).unwrap();
+        let dates: Vec<_> = plan
+            .get_training_questions(&mut connection)
+            .unwrap()
+            .into_iter()
+            .filter(|proposition| proposition.predicate.relation.relation_name == "date")
+            .take(held_out)
+            .collect();
+        for proposition in &dates {
+            plan.add_proposition_to_queue(&mut connection, "test_queue", proposition).unwrap();
+        }
+    }
+    resources
+}
+
+/// `relation` about the `Person` called `person{index}`.
+pub fn about(relation: &Relation, index: usize) -> Proposition {
+    proposition(relation.clone(), vec![sub(constant("Person".to_string(), format!("person{}", index)))])
+}
+
+/// `entities` people, with `rules` between `relations` and a proposition of each relation
+/// about each of them. The values of the propositions and the queues are left to the
+/// caller.
+pub fn people(
+    namespace: &str,
+    relations: &[&Relation],
+    rules: &[ImplicationFactor],
+    entities: usize,
+) -> ResourceContext {
+    let resources = ResourceContext::new_in_memory(namespace).unwrap();
+    {
+        let mut connection = resources.connection.lock().unwrap();
+        let connection = &mut *connection;
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        let person = "Person".to_string();
+        graph.register_domain(connection, &person).unwrap();
+        for relation in relations {
+            graph.register_relation(connection, relation).unwrap();
+        }
+        for rule in rules {
+            graph.store_predicate_implication(connection, rule).unwrap();
+        }
+        for index in 0..entities {
+            let entity = Entity {
+                domain: person.clone(),
+                name: format!("person{}", index),
+            };
+            graph.store_entity(connection, &entity).unwrap();
+            for relation in relations {
+                graph
+                    .ensure_existence_backlinks_for_proposition(connection, &about(relation, index))
+                    .unwrap();
+            }
+        }
+    }
+    resources
+}
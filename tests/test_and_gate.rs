@@ -13,6 +13,7 @@ mod test_and_gate {
     };
     use bayeslog::GraphDatabase;
     use std::sync::{Arc, Mutex};
+    use std::collections::HashMap;
     
     #[test]
     fn test_and_gate_behavior() {
@@ -29,6 +30,7 @@ mod test_and_gate {
             scenario_name: "test_and".to_string(),
             storage_type: StorageType::InMemory,
             db_path: None,
+            seed: None,
             test_scenario: None,
             print_training_loss: false,
             test_example: None,
@@ -73,7 +75,7 @@ mod test_and_gate {
         use bayeslog::qbbn::model::exponential::features_from_factor;
         use bayeslog::qbbn::common::model::FactorContext;
         use bayeslog::qbbn::inference::graph::PropositionFactor;
-        use bayeslog::qbbn::model::objects::{ImplicationFactor, PredicateGroup, GroupRoleMap, PropositionGroup};
+        use bayeslog::qbbn::model::objects::{CpdType, ImplicationFactor, PredicateGroup, GroupRoleMap, PropositionGroup};
         
         // Create a mock factor context for AND gate
         let implication = ImplicationFactor {
@@ -82,9 +84,11 @@ mod test_and_gate {
                     input_a.predicate.clone(),
                     input_b.predicate.clone(),
                 ],
+                negations: vec![],
             },
             conclusion: output_a.predicate.clone(),
             role_maps: GroupRoleMap { role_maps: vec![] },
+            cpd: CpdType::Learned,
         };
         
         let factor = PropositionFactor {
@@ -96,6 +100,7 @@ mod test_and_gate {
         let context = FactorContext {
             factor: vec![factor.clone()],
             probabilities: vec![1.0, 1.0], // Both inputs are true
+            numeric_values: HashMap::new(),
         };
         
         // Extract features
@@ -116,6 +121,7 @@ mod test_and_gate {
         let context_one_false = FactorContext {
             factor: vec![factor.clone()],
             probabilities: vec![1.0, 0.0], // Second input is false
+            numeric_values: HashMap::new(),
         };
         
         let features_one_false = features_from_factor(&context_one_false).unwrap();
@@ -130,7 +136,7 @@ mod test_and_gate {
         use bayeslog::qbbn::model::exponential::features_from_factor;
         use bayeslog::qbbn::common::model::FactorContext;
         use bayeslog::qbbn::inference::graph::PropositionFactor;
-        use bayeslog::qbbn::model::objects::{ImplicationFactor, PredicateGroup, GroupRoleMap, PropositionGroup};
+        use bayeslog::qbbn::model::objects::{CpdType, ImplicationFactor, PredicateGroup, GroupRoleMap, PropositionGroup};
         use bayeslog::qbbn::model::creators::{relation, variable_argument, constant, sub, proposition};
         
         // Create a proper 3-input AND gate
@@ -168,9 +174,11 @@ mod test_and_gate {
                     input_b.predicate.clone(),
                     input_c.predicate.clone(),
                 ],
+                negations: vec![],
             },
             conclusion: output_a.predicate.clone(),
             role_maps: GroupRoleMap { role_maps: vec![] },
+            cpd: CpdType::Learned,
         };
         
         let factor = PropositionFactor {
@@ -183,6 +191,7 @@ mod test_and_gate {
         let context = FactorContext {
             factor: vec![factor.clone()],
             probabilities: vec![0.8, 0.9, 0.7], // Three probabilistic inputs
+            numeric_values: HashMap::new(),
         };
         
         let features = features_from_factor(&context).unwrap();
@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod test_batch_training {
+    use bayeslog::qbbn::{
+        common::{
+            batch_train::{do_batch_training_with_batch_size, BatchTrainer},
+            graph::InferenceGraph,
+            interface::ScenarioMaker,
+            proposition_db::RedisBeliefTable,
+            resources::ResourceContext,
+            train::{extract_training_examples, TrainingExample, TrainingPlan},
+        },
+        model::{device::TorchConfig, exponential::ExponentialModel},
+        scenarios::dating_simple::SimpleDating,
+    };
+    use std::{sync::mpsc, thread, time::Duration};
+
+    fn setup() -> ResourceContext {
+        let mut resources = ResourceContext::new_in_memory("dating_simple").unwrap();
+        resources.config.entities_per_domain = 6;
+        SimpleDating {}.setup_scenario(&resources).unwrap();
+        resources
+    }
+
+    #[test]
+    fn test_batch_of_one_matches_single_update() {
+        let resources = setup();
+        let mut connection = resources.connection.lock().unwrap();
+        let graph = InferenceGraph::new_mutable("dating_simple".to_string()).unwrap();
+        let proposition_db = RedisBeliefTable::new_mutable("dating_simple".to_string()).unwrap();
+        let plan = TrainingPlan::new("dating_simple".to_string()).unwrap();
+        let questions = plan.get_training_questions(&mut connection).unwrap();
+        let examples =
+            extract_training_examples(&mut connection, proposition_db.as_ref(), &graph, &questions).unwrap();
+        let (factor, probability) = examples
+            .iter()
+            .find_map(|example| match example {
+                TrainingExample::Binary(factor, probability) if !factor.factor.is_empty() => {
+                    Some((factor, *probability))
+                }
+                _ => None,
+            })
+            .unwrap();
+
+        let mut single = ExponentialModel::new_mutable("dating_simple".to_string()).unwrap();
+        for implication in graph.get_all_implications(&mut connection).unwrap() {
+            single.initialize_connection(&mut connection, &implication).unwrap();
+        }
+        let mut batched = ExponentialModel::new_mutable("dating_simple".to_string()).unwrap();
+        let before = batched.predict(&mut connection, factor).unwrap().probability;
+
+        let single_loss = single.train(&mut connection, factor, probability).unwrap().loss;
+        let batch_loss = batched
+            .train_batch(&mut connection, std::slice::from_ref(factor), &[probability])
+            .unwrap()
+            .loss;
+        assert!((single_loss - batch_loss).abs() < 1e-9);
+        let after_single = single.predict(&mut connection, factor).unwrap().probability;
+        let after_batched = batched.predict(&mut connection, factor).unwrap().probability;
+        assert!((after_single - after_batched).abs() < 1e-9);
+        assert!((after_batched - probability).abs() < (before - probability).abs());
+    }
+
+    #[test]
+    fn test_streamed_batches_cover_the_plan() {
+        let resources = setup();
+        let mut connection = resources.connection.lock().unwrap();
+        let graph = InferenceGraph::new_mutable("dating_simple".to_string()).unwrap();
+        let proposition_db = RedisBeliefTable::new_mutable("dating_simple".to_string()).unwrap();
+        let plan = TrainingPlan::new("dating_simple".to_string()).unwrap();
+        let questions = plan.get_training_questions(&mut connection).unwrap();
+        let serial =
+            extract_training_examples(&mut connection, proposition_db.as_ref(), &graph, &questions).unwrap();
+
+        // Several workers, so extraction really reads the database concurrently.
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let batches: Vec<_> = pool.install(|| {
+            BatchTrainer::new(4)
+                .batches(&connection, proposition_db.as_ref(), &graph, &questions)
+                .map(|batch| batch.unwrap())
+                .collect()
+        });
+        assert_eq!(batches.len(), questions.len().div_ceil(4));
+        let streamed: Vec<_> = batches.into_iter().flatten().collect();
+        assert_eq!(streamed.len(), serial.len());
+        for (left, right) in streamed.iter().zip(&serial) {
+            match (left, right) {
+                (TrainingExample::Binary(a, p), TrainingExample::Binary(b, q)) => {
+                    assert_eq!(a.probabilities, b.probabilities);
+                    assert_eq!(p, q);
+                }
+                _ => panic!("examples differ"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_extraction_waits_for_a_write_in_flight() {
+        let resources = setup();
+        let mut connection = resources.connection.lock().unwrap();
+        let graph = InferenceGraph::new_mutable("dating_simple".to_string()).unwrap();
+        let proposition_db = RedisBeliefTable::new_mutable("dating_simple".to_string()).unwrap();
+        let plan = TrainingPlan::new("dating_simple".to_string()).unwrap();
+        let questions = plan.get_training_questions(&mut connection).unwrap();
+        let serial =
+            extract_training_examples(&mut connection, proposition_db.as_ref(), &graph, &questions).unwrap();
+
+        // The workers read through their own connections, waiting for a write in flight
+        // on another one.
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let graph_db = connection.adapter.graph_db.clone();
+        let (started, write_started) = mpsc::channel();
+        let streamed = thread::scope(|scope| {
+            let writer = scope.spawn(move || {
+                graph_db.with_transaction(|tx| {
+                    tx.execute(
+                        "INSERT INTO nodes (id, label, properties) VALUES ('pending', 'Pending', '{}')",
+                        [],
+                    )?;
+                    started.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(200));
+                    Ok(())
+                })
+            });
+            write_started.recv().unwrap();
+            let streamed = pool.install(|| {
+                BatchTrainer::new(4)
+                    .batches(&connection, proposition_db.as_ref(), &graph, &questions)
+                    .flat_map(|batch| batch.unwrap())
+                    .count()
+            });
+            writer.join().unwrap().unwrap();
+            streamed
+        });
+        assert_eq!(streamed, serial.len());
+    }
+
+    #[test]
+    fn test_do_batch_training() {
+        let resources = setup();
+        let history = do_batch_training_with_batch_size(&resources, "dating_simple".to_string(), false, 8).unwrap();
+        // The epochs and early stopping come from the torch configuration.
+        let config = TorchConfig::from_env();
+        assert!(!history.epochs.is_empty() && history.epochs.len() <= config.max_epochs);
+        assert!(history.stopped_early || history.epochs.len() == config.max_epochs);
+    }
+}
@@ -0,0 +1,294 @@
+#[cfg(test)]
+mod test_builtin_cpds {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph,
+            interface::BeliefTable,
+            model::InferenceModel,
+            proposition_db::HashMapBeliefTable,
+            redis::MockConnection,
+            resources::ResourceContext,
+        },
+        inference::{
+            engine::Inferencer, graph::PropositionGraph, lambda::factorized_lambda_messages,
+            pi::factorized_pi,
+        },
+        model::{
+            choose::extract_existence_factor_for_proposition,
+            exponential::ExponentialModel,
+            creators::{
+                conjunction, constant, implication, implication_with_cpd, predicate, proposition,
+                relation, sub, variable, variable_argument,
+            },
+            objects::{CpdType, Entity, ImplicationFactor, Proposition, Relation, RoleMap},
+        },
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_cpd_probabilities() {
+        assert_eq!(CpdType::And.probability_given_terms(&[true, false]), Some(0.0));
+        assert_eq!(CpdType::Or.probability_given_terms(&[true, false]), Some(1.0));
+        let noisy_or = CpdType::NoisyOr { strength: 0.8, leak: 0.1 };
+        assert!((noisy_or.probability_given_terms(&[false, false]).unwrap() - 0.1).abs() < 1e-12);
+        assert!((noisy_or.probability_given_terms(&[true, true]).unwrap() - (1.0 - 0.9 * 0.04)).abs() < 1e-12);
+        let noisy_and = CpdType::NoisyAnd { strength: 0.9, leak: 0.5 };
+        assert!((noisy_and.probability_given_terms(&[true, false]).unwrap() - 0.45).abs() < 1e-12);
+        let table = CpdType::Table(vec![0.0, 0.2, 0.3, 0.9]);
+        assert_eq!(table.probability_given_terms(&[false, true]), Some(0.3));
+        assert_eq!(CpdType::Learned.probability_given_terms(&[true]), None);
+        assert!(table.validate(2).is_ok());
+        assert!(table.validate(1).is_err());
+        assert!(CpdType::NoisyOr { strength: 1.5, leak: 0.0 }.validate(1).is_err());
+    }
+
+    #[test]
+    fn test_factorized_messages_match_enumeration() {
+        let messages = [[0.2, 0.6], [0.5, 0.5], [0.9, 0.05], [0.3, 1.2]];
+        let lambda = [0.7, 0.2];
+        for cpd in [
+            CpdType::Or,
+            CpdType::And,
+            CpdType::NoisyOr { strength: 0.8, leak: 0.1 },
+            CpdType::NoisyAnd { strength: 0.9, leak: 0.3 },
+        ] {
+            let factorized = cpd.factorized().unwrap();
+            // Sum over all 16 assignments of the terms, as the general messages do.
+            let mut pi = [0.0; 2];
+            let mut lambdas = vec![[0.0; 2]; messages.len()];
+            for bits in 0..1 << messages.len() {
+                let terms: Vec<bool> = (0..messages.len()).map(|term| bits & (1 << term) != 0).collect();
+                let probability = cpd.probability_given_terms(&terms).unwrap();
+                let weight: f64 = terms.iter().zip(&messages).map(|(term, message)| message[*term as usize]).product();
+                pi[0] += weight * (1.0 - probability);
+                pi[1] += weight * probability;
+                for (to, message) in messages.iter().enumerate() {
+                    let others = weight / message[terms[to] as usize];
+                    lambdas[to][terms[to] as usize] +=
+                        others * ((1.0 - probability) * lambda[0] + probability * lambda[1]);
+                }
+            }
+            let closed = factorized_pi(&factorized, &messages);
+            assert!((closed[0] - pi[0]).abs() < 1e-12 && (closed[1] - pi[1]).abs() < 1e-12, "{:?}", cpd);
+            for (closed, enumerated) in factorized_lambda_messages(&factorized, &messages, lambda).iter().zip(&lambdas) {
+                assert!((closed[0] - enumerated[0]).abs() < 1e-12, "{:?}", cpd);
+                assert!((closed[1] - enumerated[1]).abs() < 1e-12, "{:?}", cpd);
+            }
+        }
+        assert!(CpdType::Table(vec![0.0, 1.0]).factorized().is_none());
+        assert!(CpdType::Learned.factorized().is_none());
+    }
+
+    #[test]
+    fn test_inference_with_builtin_cpds() {
+        let namespace = "builtin_cpds";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+
+        let lawn_domain = "Lawn".to_string();
+        graph.register_domain(&mut connection, &lawn_domain).unwrap();
+        let rain = relation("rain".to_string(), vec![variable_argument(lawn_domain.clone())]);
+        let sprinkler = relation("sprinkler".to_string(), vec![variable_argument(lawn_domain.clone())]);
+        let wet = relation("wet".to_string(), vec![variable_argument(lawn_domain.clone())]);
+        let summer = relation("summer".to_string(), vec![variable_argument(lawn_domain.clone())]);
+        for relation in [&rain, &sprinkler, &wet, &summer] {
+            graph.register_relation(&mut connection, relation).unwrap();
+        }
+        let entity = Entity {
+            domain: lawn_domain.clone(),
+            name: "lawn0".to_string(),
+        };
+        graph.store_entity(&mut connection, &entity).unwrap();
+        let lawn = constant(lawn_domain.clone(), entity.name.clone());
+
+        // Rain has a known prior hung off its existence link. The sprinkler runs half the
+        // time in summer, which is a root and so always true.
+        let rain_lawn = proposition(rain.clone(), vec![sub(lawn.clone())]);
+        let mut existence = extract_existence_factor_for_proposition(&rain_lawn).unwrap();
+        existence.cpd = CpdType::Table(vec![0.0, 0.3]);
+        graph.store_predicate_implication(&mut connection, &existence).unwrap();
+
+        // Rain makes the lawn wet with noise and a leak, the sprinkler always does.
+        let x = variable(lawn_domain.clone());
+        let same_subject = || vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))];
+        graph
+            .store_predicate_implication(
+                &mut connection,
+                &implication_with_cpd(
+                    conjunction(vec![predicate(summer.clone(), vec![sub(x.clone())])]),
+                    predicate(sprinkler.clone(), vec![sub(x.clone())]),
+                    same_subject(),
+                    CpdType::NoisyOr { strength: 0.5, leak: 0.0 },
+                ),
+            )
+            .unwrap();
+        let wet_predicate = predicate(wet.clone(), vec![sub(x.clone())]);
+        graph
+            .store_predicate_implication(
+                &mut connection,
+                &implication_with_cpd(
+                    conjunction(vec![predicate(rain.clone(), vec![sub(x.clone())])]),
+                    wet_predicate.clone(),
+                    same_subject(),
+                    CpdType::NoisyOr { strength: 0.9, leak: 0.1 },
+                ),
+            )
+            .unwrap();
+        graph
+            .store_predicate_implication(
+                &mut connection,
+                &implication_with_cpd(
+                    conjunction(vec![predicate(sprinkler.clone(), vec![sub(x.clone())])]),
+                    wet_predicate.clone(),
+                    same_subject(),
+                    CpdType::Or,
+                ),
+            )
+            .unwrap();
+
+        // A table of the wrong size is rejected.
+        let mut bad = implication(
+            conjunction(vec![predicate(rain.clone(), vec![sub(x.clone())])]),
+            wet_predicate,
+            same_subject(),
+        );
+        bad.cpd = CpdType::Table(vec![0.5]);
+        assert!(graph.store_predicate_implication(&mut connection, &bad).is_err());
+
+        let wet_lawn = proposition(wet, vec![sub(lawn.clone())]);
+        graph.register_target(&mut connection, &wet_lawn).unwrap();
+
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, wet_lawn.clone()).unwrap();
+        let fact_memory = HashMapBeliefTable::new();
+        let mut inferencer =
+            Inferencer::new_mutable(model, proposition_graph, fact_memory.clone()).unwrap();
+        inferencer.initialize_chart(&mut connection).unwrap();
+        for _ in 0..3 {
+            inferencer.do_full_forward_and_backward(&mut connection).unwrap();
+        }
+        let marginals = inferencer.build_marginal_table().unwrap();
+
+        assert!((marginals.get_marginal(&rain_lawn).unwrap() - 0.3).abs() < 1e-9);
+        // P(rain group) = 0.3 * 0.91 + 0.7 * 0.1, then OR with the sprinkler.
+        let rain_group = 0.3 * 0.91 + 0.7 * 0.1;
+        let expected = 1.0 - (1.0 - rain_group) * (1.0 - 0.5);
+        assert!((marginals.get_marginal(&wet_lawn).unwrap() - expected).abs() < 1e-9);
+
+        // With the sprinkler off, only the noisy rain link is left.
+        let sprinkler_lawn = proposition(sprinkler, vec![sub(lawn.clone())]);
+        fact_memory
+            .store_proposition_probability(&mut connection, &sprinkler_lawn, 0.0)
+            .unwrap();
+        inferencer.initialize_chart(&mut connection).unwrap();
+        for _ in 0..3 {
+            inferencer.do_full_forward_and_backward(&mut connection).unwrap();
+        }
+        let marginals = inferencer.build_marginal_table().unwrap();
+        assert!((marginals.get_marginal(&wet_lawn).unwrap() - rain_group).abs() < 1e-9);
+    }
+
+    /// The marginals of `probed` with smoke grounded as a root of prior 0.3, smoke
+    /// causing the alarm under a learned rule and the sprinklers under `sprinkler_rule`.
+    fn shared_premise_marginals(
+        connection: &mut MockConnection,
+        graph: &mut InferenceGraph,
+        namespace: &str,
+        sprinkler_rule: &ImplicationFactor,
+        probed: &[Proposition],
+    ) -> Vec<f64> {
+        graph.store_predicate_implication(connection, sprinkler_rule).unwrap();
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let target = graph.get_target(connection).unwrap();
+        let proposition_graph = PropositionGraph::new_shared(connection, &model.graph, target).unwrap();
+        // The shared premise grounds to one group per CPD.
+        let smoke_groups = proposition_graph
+            .all_nodes
+            .iter()
+            .filter(|node| !node.is_single() && node.extract_group().terms == vec![probed[0].clone()])
+            .count();
+        assert_eq!(smoke_groups, 2);
+        let mut inferencer =
+            Inferencer::new_mutable(model, proposition_graph, HashMapBeliefTable::new()).unwrap();
+        inferencer.initialize_chart(connection).unwrap();
+        for _ in 0..3 {
+            inferencer.do_full_forward_and_backward(connection).unwrap();
+        }
+        let marginals = inferencer.build_marginal_table().unwrap();
+        graph.remove_predicate_implication(connection, sprinkler_rule).unwrap();
+        probed.iter().map(|proposition| marginals.get_marginal(proposition).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_learned_and_builtin_rules_share_a_premise() {
+        let namespace = "builtin_cpds_shared_premise";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+
+        let domain = "Building".to_string();
+        graph.register_domain(&mut connection, &domain).unwrap();
+        let unary = |name: &str| relation(name.to_string(), vec![variable_argument(domain.clone())]);
+        let (smoke, alarm, sprinklers, evacuate) =
+            (unary("smoke"), unary("alarm"), unary("sprinklers"), unary("evacuate"));
+        for relation in [&smoke, &alarm, &sprinklers, &evacuate] {
+            graph.register_relation(&mut connection, relation).unwrap();
+        }
+        let entity = Entity {
+            domain: domain.clone(),
+            name: "office".to_string(),
+        };
+        graph.store_entity(&mut connection, &entity).unwrap();
+        let office = || vec![sub(constant(domain.clone(), entity.name.clone()))];
+
+        let smoke_office = proposition(smoke.clone(), office());
+        let mut existence = extract_existence_factor_for_proposition(&smoke_office).unwrap();
+        existence.cpd = CpdType::Table(vec![0.0, 0.3]);
+        graph.store_predicate_implication(&mut connection, &existence).unwrap();
+
+        // Smoke sets off the alarm under learned weights; both it and the sprinklers
+        // lead to an evacuation, which keeps both conclusions in the target's graph.
+        let x = variable(domain.clone());
+        let same_subject = || vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))];
+        let rule = |premise: &Relation, conclusion: &Relation| {
+            implication(
+                conjunction(vec![predicate(premise.clone(), vec![sub(x.clone())])]),
+                predicate(conclusion.clone(), vec![sub(x.clone())]),
+                same_subject(),
+            )
+        };
+        let mut model = ExponentialModel::new_mutable(namespace.to_string()).unwrap();
+        for learned in [rule(&smoke, &alarm), rule(&alarm, &evacuate), rule(&sprinklers, &evacuate)] {
+            graph.store_predicate_implication(&mut connection, &learned).unwrap();
+            model.initialize_connection(&mut connection, &learned).unwrap();
+        }
+        graph
+            .register_target(&mut connection, &proposition(evacuate.clone(), office()))
+            .unwrap();
+
+        let sprinkler_rule = |strength| {
+            implication_with_cpd(
+                conjunction(vec![predicate(smoke.clone(), vec![sub(x.clone())])]),
+                predicate(sprinklers.clone(), vec![sub(x.clone())]),
+                same_subject(),
+                CpdType::NoisyOr { strength, leak: 0.0 },
+            )
+        };
+        let probed = [
+            smoke_office.clone(),
+            proposition(alarm.clone(), office()),
+            proposition(sprinklers.clone(), office()),
+        ];
+        let strong = shared_premise_marginals(&mut connection, &mut graph, namespace, &sprinkler_rule(0.8), &probed);
+        let weak = shared_premise_marginals(&mut connection, &mut graph, namespace, &sprinkler_rule(0.4), &probed);
+
+        // The sprinklers follow their own noisy-OR, and the alarm only its learned
+        // weights, whatever the other rule on the same premise says.
+        assert!((strong[0] - 0.3).abs() < 1e-9);
+        assert!((strong[2] - 0.3 * 0.8).abs() < 1e-9, "{:?}", strong);
+        assert!((weak[2] - 0.3 * 0.4).abs() < 1e-9, "{:?}", weak);
+        assert!((strong[1] - weak[1]).abs() < 1e-9, "{:?} {:?}", strong, weak);
+    }
+}
@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod test_builtins {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph, model::InferenceModel, redis::MockConnection,
+            resources::ResourceContext,
+        },
+        inference::graph::PropositionGraph,
+        model::{
+            choose::extract_backimplications_from_proposition,
+            creators::{
+                builtin, conjunction, conjunction_of_literals, constant, implication, not, obj,
+                positive, predicate, proposition, relation, sub, variable, variable_argument,
+            },
+            objects::{Argument, BuiltinPredicate, Entity, Predicate, Proposition, Relation, RoleMap},
+        },
+    };
+
+    fn role_map(pairs: &[(&str, &str)]) -> RoleMap {
+        RoleMap::new(
+            pairs
+                .iter()
+                .map(|(conclusion, premise)| (conclusion.to_string(), premise.to_string()))
+                .collect(),
+        )
+    }
+
+    fn pair(relation: &Relation, x: Argument, z: Argument) -> Predicate {
+        predicate(relation.clone(), vec![sub(x), obj(z)])
+    }
+
+    fn person(name: &str) -> Argument {
+        constant("Person".to_string(), name.to_string())
+    }
+
+    fn student(name: &str) -> Argument {
+        constant("Student".to_string(), name.to_string())
+    }
+
+    fn premises(connection: &mut MockConnection, graph: &InferenceGraph, conclusion: &Proposition) -> Vec<Vec<Proposition>> {
+        extract_backimplications_from_proposition(connection, graph, conclusion)
+            .unwrap()
+            .into_iter()
+            .map(|factor| factor.premise.terms)
+            .collect()
+    }
+
+    #[test]
+    fn test_builtins_prune_groundings() {
+        assert_eq!(BuiltinPredicate::from_relation_name("neq"), Some(BuiltinPredicate::Neq));
+        assert_eq!(
+            BuiltinPredicate::from_relation_name("in_domain:Student"),
+            Some(BuiltinPredicate::InDomain("Student".to_string()))
+        );
+        assert_eq!(BuiltinPredicate::from_relation_name("in_domain:"), None);
+        assert_eq!(BuiltinPredicate::from_relation_name("likes"), None);
+
+        let namespace = "builtin_predicates";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let connection = &mut *connection;
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        for domain in ["Person", "Student"] {
+            graph.register_domain(connection, domain).unwrap();
+        }
+        graph.register_subclass(connection, "Student", "Person").unwrap();
+        assert!(graph.register_subclass(connection, "Person", "Student").is_err());
+        assert!(graph.is_subclass_of(connection, "Student", "Person").unwrap());
+        assert!(!graph.is_subclass_of(connection, "Person", "Student").unwrap());
+        for (domain, name) in [("Person", "alice"), ("Person", "bob"), ("Student", "carol")] {
+            let entity = Entity { domain: domain.to_string(), name: name.to_string() };
+            graph.store_entity(connection, &entity).unwrap();
+        }
+
+        let people = || vec![variable_argument("Person".to_string()), variable_argument("Person".to_string())];
+        let friend = relation("friend".to_string(), people());
+        let knows = relation("knows".to_string(), people());
+        let mentors = relation("mentors".to_string(), people());
+        let teaches = relation("teaches".to_string(), people());
+        for relation in [&friend, &knows, &mentors, &teaches] {
+            graph.register_relation(connection, relation).unwrap();
+        }
+        let x = variable("Person".to_string());
+        let z = variable("Person".to_string());
+        let both = || role_map(&[("sub", "sub"), ("obj", "obj")]);
+
+        // Only friends who are different people know each other.
+        let different = builtin(BuiltinPredicate::Neq, vec![sub(x.clone()), obj(z.clone())]);
+        let rule = implication(
+            conjunction(vec![pair(&friend, x.clone(), z.clone()), different.clone()]),
+            pair(&knows, x.clone(), z.clone()),
+            vec![both(), both()],
+        );
+        graph.store_predicate_implication(connection, &rule).unwrap();
+        // Friends who are students are mentored.
+        let is_student = builtin(BuiltinPredicate::InDomain("Student".to_string()), vec![sub(z.clone())]);
+        let rule = implication(
+            conjunction(vec![pair(&friend, x.clone(), z.clone()), is_student]),
+            pair(&mentors, x.clone(), z.clone()),
+            vec![both(), role_map(&[("obj", "sub")])],
+        );
+        graph.store_predicate_implication(connection, &rule).unwrap();
+        // Students don't teach.
+        let subject_is_student =
+            builtin(BuiltinPredicate::SubclassOf("Student".to_string()), vec![sub(x.clone())]);
+        let rule = implication(
+            conjunction_of_literals(vec![positive(pair(&friend, x.clone(), z.clone())), not(subject_is_student)]),
+            pair(&teaches, x.clone(), z.clone()),
+            vec![both(), role_map(&[("sub", "sub")])],
+        );
+        graph.store_predicate_implication(connection, &rule).unwrap();
+
+        // A premise of builtins alone, or a builtin of the wrong arity, is rejected.
+        let only_builtins = implication(conjunction(vec![different]), pair(&knows, x.clone(), z.clone()), vec![both()]);
+        assert!(graph.store_predicate_implication(connection, &only_builtins).is_err());
+        let bad_arity = implication(
+            conjunction(vec![
+                pair(&friend, x.clone(), z.clone()),
+                builtin(BuiltinPredicate::Eq, vec![sub(x.clone())]),
+            ]),
+            pair(&knows, x.clone(), z.clone()),
+            vec![both(), role_map(&[("sub", "sub")])],
+        );
+        assert!(graph.store_predicate_implication(connection, &bad_arity).is_err());
+
+        let grounded = |relation: &Relation, x: Argument, z: Argument| proposition(relation.clone(), vec![sub(x), obj(z)]);
+        let knows_bob = grounded(&knows, person("alice"), person("bob"));
+        assert_eq!(premises(connection, &graph, &knows_bob), vec![vec![grounded(&friend, person("alice"), person("bob"))]]);
+        assert!(premises(connection, &graph, &grounded(&knows, person("alice"), person("alice"))).is_empty());
+
+        assert_eq!(premises(connection, &graph, &grounded(&mentors, person("alice"), person("carol"))).len(), 1);
+        assert!(premises(connection, &graph, &grounded(&mentors, person("alice"), person("bob"))).is_empty());
+
+        let teacher = premises(connection, &graph, &grounded(&teaches, person("alice"), person("bob")));
+        assert_eq!(teacher.len(), 1);
+        assert!(premises(connection, &graph, &grounded(&teaches, student("carol"), person("bob"))).is_empty());
+
+        // Builtins never become nodes of the proposition graph.
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let proposition_graph = PropositionGraph::new_shared(connection, &model.graph, knows_bob).unwrap();
+        assert!(proposition_graph.all_nodes.iter().all(|node| {
+            !node.is_single() || BuiltinPredicate::from_predicate(&node.extract_single().predicate).is_none()
+        }));
+        assert_eq!(proposition_graph.single_backward.len(), 1);
+    }
+}
@@ -0,0 +1,279 @@
+mod common;
+
+#[cfg(test)]
+mod test_calibration {
+    use super::common::dating_with_held_out_dates;
+    use bayeslog::qbbn::{
+        common::{
+            calibration::{
+                do_calibration, factor_predictions, CalibrationConfig, CalibrationMethod, CalibrationMetrics,
+            },
+            graph::InferenceGraph,
+            model::FactorModel,
+            proposition_db::RedisBeliefTable,
+            redis::MockConnection,
+            resources::ResourceContext,
+            train::{
+                do_training_epochs, evaluate_examples, extract_test_examples, open_training_model, TrainerConfig,
+                TrainingExample, TrainingPlan,
+            },
+        },
+        model::{
+            calibrator::{Calibrator, CalibratorUpdate},
+            config::ExponentialTrainingConfig,
+            exponential::{ExponentialModel, ExponentialModelOptions},
+            ModelWeights,
+        },
+    };
+
+    const NAMESPACE: &str = "dating_simple";
+    const HELD_OUT: usize = 6;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    /// Stored calibrators go through JSON, which may round the last bit.
+    fn same_calibrator(a: &Calibrator, b: &Calibrator) -> bool {
+        (0..=10).all(|i| close(a.apply(i as f64 / 10f64), b.apply(i as f64 / 10f64)))
+    }
+
+    fn sigmoid(x: f64) -> f64 {
+        1f64 / (1f64 + (-x).exp())
+    }
+
+    #[test]
+    fn test_reliability_metrics() {
+        let predictions = [(0.05, 0f64), (0.15, 0f64), (0.85, 0f64), (0.95, 1f64)];
+        let metrics = CalibrationMetrics::from_predictions(&predictions, 10);
+        assert_eq!(metrics.examples, 4);
+        assert_eq!(metrics.bins.len(), 10);
+        let counts: Vec<usize> = metrics.bins.iter().map(|bin| bin.count).collect();
+        assert_eq!(counts, vec![1, 1, 0, 0, 0, 0, 0, 0, 1, 1]);
+        assert!(close(metrics.bins[8].mean_predicted, 0.85));
+        assert!(close(metrics.bins[8].observed, 0f64));
+        assert!(close(metrics.ece, (0.05 + 0.15 + 0.85 + 0.05) / 4f64));
+        assert!(close(metrics.mce, 0.85));
+        assert!(close(metrics.brier, (0.0025 + 0.0225 + 0.7225 + 0.0025) / 4f64));
+        assert!(metrics.render_text().contains("ECE 0.2750"));
+
+        // A prediction of exactly 1 falls into the last bin.
+        let metrics = CalibrationMetrics::from_predictions(&[(1f64, 1f64)], 4);
+        assert_eq!(metrics.bins[3].count, 1);
+        assert!(close(metrics.ece, 0f64));
+    }
+
+    #[test]
+    fn test_platt_undoes_overconfidence() {
+        // The gold values are what the predictions would be with half the confidence.
+        let predictions: Vec<(f64, f64)> = (1..100)
+            .map(|i| {
+                let predicted = i as f64 / 100f64;
+                (predicted, sigmoid(0.5 * (predicted / (1f64 - predicted)).ln()))
+            })
+            .collect();
+        match Calibrator::fit_platt(&predictions) {
+            Calibrator::Platt { slope, intercept } => {
+                assert!((slope - 0.5).abs() < 0.05, "{}", slope);
+                assert!(intercept.abs() < 0.05, "{}", intercept);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_isotonic_is_monotone() {
+        let predictions = [(0.1, 0f64), (0.3, 1f64), (0.5, 0f64), (0.7, 1f64), (0.9, 1f64)];
+        let calibrator = Calibrator::fit_isotonic(&predictions);
+        let Calibrator::Isotonic { thresholds, values } = &calibrator else {
+            panic!("{:?}", calibrator);
+        };
+        // 0.3 and 0.5 pool into one block, as do 0.7 and 0.9.
+        assert_eq!(thresholds.len(), 3);
+        assert!(close(thresholds[1], 0.4));
+        assert_eq!(values, &vec![0f64, 0.5, 1f64]);
+        assert!(close(calibrator.apply(0f64), 0f64));
+        assert!(close(calibrator.apply(0.25), 0.25));
+        assert!(close(calibrator.apply(1f64), 1f64));
+        let mut last = 0f64;
+        for i in 0..=20 {
+            let calibrated = calibrator.apply(i as f64 / 20f64);
+            assert!(calibrated >= last);
+            last = calibrated;
+        }
+    }
+
+    /// A trained dating scenario, with a few labeled `date` questions queued for testing.
+    fn setup() -> ResourceContext {
+        let resources = dating_with_held_out_dates(NAMESPACE, 8, Some(3), HELD_OUT);
+        let config = TrainerConfig {
+            max_epochs: 2,
+            ..TrainerConfig::default()
+        };
+        do_training_epochs(&resources, NAMESPACE.to_string(), &config).unwrap();
+        resources
+    }
+
+    #[test]
+    fn test_fitted_calibrator_is_applied_and_saved() {
+        let resources = setup();
+        let evaluate_only = CalibrationConfig {
+            passes: 3,
+            ..CalibrationConfig::default()
+        };
+        let report = do_calibration(&resources, NAMESPACE.to_string(), &evaluate_only).unwrap();
+        assert_eq!(report.factor.examples, HELD_OUT);
+        assert_eq!(report.marginals.as_ref().unwrap().examples, HELD_OUT);
+        assert!(report.calibrator.is_none());
+        assert!(report.calibrated_factor.is_none());
+
+        let fit = CalibrationConfig {
+            method: Some(CalibrationMethod::Platt),
+            ..evaluate_only
+        };
+        let report = do_calibration(&resources, NAMESPACE.to_string(), &fit).unwrap();
+        let calibrator = report.calibrator.clone().unwrap();
+        // Fitted on the first half of the questions and evaluated on the other.
+        assert_eq!(report.fit_examples, HELD_OUT / 2);
+        assert_eq!(report.factor.examples, HELD_OUT / 2);
+        assert_eq!(report.calibrated_factor.as_ref().unwrap().examples, HELD_OUT / 2);
+        assert_eq!(report.calibrated_marginals.as_ref().unwrap().examples, HELD_OUT / 2);
+        assert!(report.render_text().contains("calibrated factor predictions"));
+
+        // Refitting starts from the uncalibrated predictions again.
+        let refit = do_calibration(&resources, NAMESPACE.to_string(), &fit).unwrap();
+        assert_eq!(refit.factor, report.factor);
+        assert!(same_calibrator(refit.calibrator.as_ref().unwrap(), &calibrator));
+
+        let mut connection = resources.connection.lock().unwrap();
+        let stored = Calibrator::load(&mut connection, NAMESPACE).unwrap().unwrap();
+        assert!(same_calibrator(&stored, &calibrator));
+        let path = std::env::temp_dir().join("bayeslog_test_calibration.json");
+        let path = path.to_str().unwrap();
+        let model = ExponentialModel::new_mutable(NAMESPACE.to_string()).unwrap();
+        model.save_to_file(&mut connection, path).unwrap();
+        let saved = ModelWeights::load_from_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(same_calibrator(&saved.calibrator.unwrap(), &calibrator));
+    }
+
+    #[test]
+    fn test_failed_calibration_keeps_the_stored_calibrator() {
+        let resources = setup();
+        let stored = Calibrator::Platt { slope: 0.5, intercept: 0.1 };
+        Calibrator::store(&mut resources.connection.lock().unwrap(), NAMESPACE, Some(&stored)).unwrap();
+        let everything = CalibrationConfig {
+            method: Some(CalibrationMethod::Isotonic),
+            fit_fraction: 1.0,
+            marginals: false,
+            ..CalibrationConfig::default()
+        };
+        assert!(do_calibration(&resources, NAMESPACE.to_string(), &everything).is_err());
+        let evaluate_only = CalibrationConfig {
+            marginals: false,
+            ..CalibrationConfig::default()
+        };
+        do_calibration(&resources, NAMESPACE.to_string(), &evaluate_only).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        assert_eq!(Calibrator::load(&mut connection, NAMESPACE).unwrap(), Some(stored));
+    }
+
+    #[test]
+    fn test_calibrator_is_kept_on_the_model() {
+        let resources = setup();
+        let mut connection = resources.connection.lock().unwrap();
+        let graph = InferenceGraph::new_mutable(NAMESPACE.to_string()).unwrap();
+        let proposition_db = RedisBeliefTable::new_mutable(NAMESPACE.to_string()).unwrap();
+        let questions = TrainingPlan::new(NAMESPACE.to_string())
+            .unwrap()
+            .get_test_questions(&mut connection)
+            .unwrap();
+        let examples = extract_test_examples(&mut connection, proposition_db.as_ref(), &graph, &questions).unwrap();
+        let predict = |connection: &mut MockConnection, model: &dyn FactorModel| -> Vec<f64> {
+            let predictions = factor_predictions(connection, model, &examples).unwrap();
+            predictions.iter().map(|(predicted, _)| *predicted).collect()
+        };
+        let fresh = || ExponentialModel::new_mutable(NAMESPACE.to_string()).unwrap();
+        let raw = predict(&mut connection, fresh().as_ref());
+        assert!(!raw.is_empty());
+
+        // The stored calibrator is read again only once the weights change. It applies
+        // to conclusions, not to every prediction.
+        let flat = Calibrator::Platt { slope: 0f64, intercept: 0f64 };
+        Calibrator::store(&mut connection, NAMESPACE, Some(&flat)).unwrap();
+        let mut model = fresh();
+        assert!(predict(&mut connection, model.as_ref()).iter().all(|p| close(*p, 0.5)));
+        let TrainingExample::Binary(factor, gold) = &examples[0] else {
+            panic!("expected a boolean example");
+        };
+        assert!(close(model.predict(&mut connection, factor).unwrap().probability, raw[0]));
+        Calibrator::store(&mut connection, NAMESPACE, None).unwrap();
+        assert!(predict(&mut connection, model.as_ref()).iter().all(|p| close(*p, 0.5)));
+        assert_eq!(predict(&mut connection, fresh().as_ref()), raw);
+        model.train(&mut connection, factor, *gold).unwrap();
+        let trained = model.predict(&mut connection, factor).unwrap().probability;
+        assert!(!close(trained, 0.5));
+        assert!(close(model.predict_conclusion(&mut connection, factor).unwrap().probability, trained));
+
+        // A model loaded from a file applies the saved calibrator without storing it.
+        let path = std::env::temp_dir().join("bayeslog_test_calibrator_on_model.json");
+        let path = path.to_str().unwrap();
+        let options = ExponentialModelOptions {
+            calibrator: CalibratorUpdate::Set(flat),
+            ..ExponentialModelOptions::default()
+        };
+        ExponentialModel::new_with_options(NAMESPACE.to_string(), options)
+            .unwrap()
+            .save_to_file(&mut connection, path)
+            .unwrap();
+        let loaded = ExponentialModel::from_file(NAMESPACE.to_string(), path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(predict(&mut connection, loaded.as_ref()).iter().all(|p| close(*p, 0.5)));
+        assert_eq!(Calibrator::load(&mut connection, NAMESPACE).unwrap(), None);
+    }
+
+    #[test]
+    fn test_training_ignores_and_clears_the_stored_calibrator() {
+        let resources = setup();
+        let flat = Calibrator::Platt { slope: 0f64, intercept: 0f64 };
+        {
+            let mut connection = resources.connection.lock().unwrap();
+            let graph = InferenceGraph::new_mutable(NAMESPACE.to_string()).unwrap();
+            let proposition_db = RedisBeliefTable::new_mutable(NAMESPACE.to_string()).unwrap();
+            let questions = TrainingPlan::new(NAMESPACE.to_string())
+                .unwrap()
+                .get_test_questions(&mut connection)
+                .unwrap();
+            let examples =
+                extract_test_examples(&mut connection, proposition_db.as_ref(), &graph, &questions).unwrap();
+
+            // Initializing the weights drops the calibrator fitted to the old ones.
+            Calibrator::store(&mut connection, NAMESPACE, Some(&flat)).unwrap();
+            let training = ExponentialTrainingConfig::default();
+            let model = open_training_model(&mut connection, &graph, NAMESPACE, training).unwrap();
+            assert_eq!(Calibrator::load(&mut connection, NAMESPACE).unwrap(), None);
+
+            // Held-out evaluation during training sees the weights alone.
+            Calibrator::store(&mut connection, NAMESPACE, Some(&flat)).unwrap();
+            let options = ExponentialModelOptions {
+                calibrator: CalibratorUpdate::Clear,
+                ..ExponentialModelOptions::default()
+            };
+            let raw = ExponentialModel::new_with_options(NAMESPACE.to_string(), options).unwrap();
+            let raw = evaluate_examples(&mut connection, raw.as_ref(), &examples).unwrap();
+            let calibrated = ExponentialModel::new_mutable(NAMESPACE.to_string()).unwrap();
+            let calibrated = evaluate_examples(&mut connection, calibrated.as_ref(), &examples).unwrap();
+            assert!(close(calibrated.log_loss, 2f64.ln()));
+            assert_eq!(evaluate_examples(&mut connection, model.as_ref(), &examples).unwrap(), raw);
+        }
+
+        // So does storing the trained weights.
+        let config = TrainerConfig {
+            max_epochs: 1,
+            ..TrainerConfig::default()
+        };
+        do_training_epochs(&resources, NAMESPACE.to_string(), &config).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        assert_eq!(Calibrator::load(&mut connection, NAMESPACE).unwrap(), None);
+    }
+}
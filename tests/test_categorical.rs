@@ -0,0 +1,215 @@
+#[cfg(test)]
+mod test_categorical {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph,
+            interface::BeliefTable,
+            model::{FactorContext, InferenceModel},
+            proposition_db::HashMapBeliefTable,
+            redis::MockConnection,
+            resources::ResourceContext,
+        },
+        inference::{
+            engine::Inferencer,
+            graph::PropositionGraph,
+            mpe::MapConfig,
+            query::{QueryConfig, QueryMethod},
+        },
+        model::{
+            choose::extract_backimplications_from_proposition,
+            creators::{
+                conjunction, constant, implication, obj, predicate, proposition, relation, sub,
+                variable, variable_argument,
+            },
+            exponential::ExponentialModel,
+            objects::{CategoricalRelation, Entity, Proposition, RoleMap},
+        },
+    };
+    use std::collections::HashMap;
+
+    const COLORS: [&str; 3] = ["blue", "brown", "green"];
+
+    fn run(inferencer: &mut Inferencer, connection: &mut MockConnection) {
+        inferencer.initialize_chart(connection).unwrap();
+        for _ in 0..3 {
+            inferencer.do_full_forward_and_backward(connection).unwrap();
+        }
+    }
+
+    /// `eye_color(alice, c)` for each color, plus the graph with `blue_gene(x) -> eye_color(x, blue)`.
+    fn setup(connection: &mut MockConnection, namespace: &str) -> (Box<InferenceGraph>, Vec<Proposition>) {
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        for domain in ["Person", "Color"] {
+            graph.register_domain(connection, &domain.to_string()).unwrap();
+        }
+        let eye_color = relation(
+            "eye_color".to_string(),
+            vec![variable_argument("Person".to_string()), variable_argument("Color".to_string())],
+        );
+        let blue_gene = relation("blue_gene".to_string(), vec![variable_argument("Person".to_string())]);
+        graph.register_relation(connection, &eye_color).unwrap();
+        graph.register_relation(connection, &blue_gene).unwrap();
+        graph
+            .store_entity(connection, &Entity { domain: "Person".to_string(), name: "alice".to_string() })
+            .unwrap();
+        for color in COLORS {
+            graph
+                .store_entity(connection, &Entity { domain: "Color".to_string(), name: color.to_string() })
+                .unwrap();
+        }
+
+        // Only relations that exist, with at least two distinct outcomes, can be categorical.
+        let missing = CategoricalRelation::new("hair".to_string(), "obj".to_string(), vec!["a".to_string(), "b".to_string()]);
+        assert!(graph.register_categorical(connection, &missing).is_err());
+        let single = CategoricalRelation::new("eye_color".to_string(), "obj".to_string(), vec!["blue".to_string()]);
+        assert!(graph.register_categorical(connection, &single).is_err());
+        let categorical = CategoricalRelation::new(
+            "eye_color".to_string(),
+            "obj".to_string(),
+            COLORS.iter().map(|color| color.to_string()).collect(),
+        );
+        graph.register_categorical(connection, &categorical).unwrap();
+
+        let x = variable("Person".to_string());
+        let rule = implication(
+            conjunction(vec![predicate(blue_gene, vec![sub(x.clone())])]),
+            predicate(eye_color.clone(), vec![sub(x), obj(constant("Color".to_string(), "blue".to_string()))]),
+            vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))],
+        );
+        graph.store_predicate_implication(connection, &rule).unwrap();
+
+        let alice = constant("Person".to_string(), "alice".to_string());
+        let outcomes = COLORS
+            .iter()
+            .map(|color| {
+                proposition(
+                    eye_color.clone(),
+                    vec![sub(alice.clone()), obj(constant("Color".to_string(), color.to_string()))],
+                )
+            })
+            .collect();
+        (graph, outcomes)
+    }
+
+    #[test]
+    fn test_outcomes_are_mutually_exclusive() {
+        let namespace = "categorical_inference";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let (graph, outcomes) = setup(&mut connection, namespace);
+        let choice = graph.categorical_choice(&mut connection, &outcomes[1]).unwrap().unwrap();
+        assert_eq!(choice.outcomes, outcomes);
+
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, outcomes[1].clone()).unwrap();
+        // Asking about brown grounds every color, and the rule for blue feeds the variable.
+        assert_eq!(proposition_graph.choice_backward[&choice].len(), 1);
+        let fact_memory = HashMapBeliefTable::new();
+        let mut inferencer =
+            Inferencer::new_mutable(model, proposition_graph, fact_memory.clone()).unwrap();
+
+        run(&mut inferencer, &mut connection);
+        let distribution = inferencer.choice_marginals(&choice).unwrap();
+        assert_eq!(distribution.len(), 3);
+        assert!((distribution.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        let marginals = inferencer.build_marginal_table().unwrap();
+        let total: f64 = outcomes
+            .iter()
+            .map(|outcome| marginals.get_marginal(outcome).unwrap())
+            .sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        // Ruling out blue spreads its mass over the other colors.
+        fact_memory
+            .store_proposition_probability(&mut connection, &outcomes[0], 0.0)
+            .unwrap();
+        run(&mut inferencer, &mut connection);
+        let distribution = inferencer.choice_marginals(&choice).unwrap();
+        assert!(distribution[0].abs() < 1e-9);
+        assert!((distribution[1] + distribution[2] - 1.0).abs() < 1e-9);
+
+        // Observing green rules out brown too.
+        fact_memory
+            .store_proposition_probability(&mut connection, &outcomes[2], 1.0)
+            .unwrap();
+        run(&mut inferencer, &mut connection);
+        let marginals = inferencer.build_marginal_table().unwrap();
+        assert!(marginals.get_marginal(&outcomes[1]).unwrap().abs() < 1e-9);
+        assert!((inferencer.choice_marginals(&choice).unwrap()[2] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_categorical_training() {
+        let namespace = "categorical_training";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let (graph, outcomes) = setup(&mut connection, namespace);
+
+        let mut model = ExponentialModel::new_mutable(namespace.to_string()).unwrap();
+        let factor = extract_backimplications_from_proposition(&mut connection, &graph, &outcomes[0]).unwrap();
+        let context = FactorContext {
+            factor,
+            probabilities: vec![1.0],
+            numeric_values: HashMap::new(),
+        };
+        let before = model.predict_distribution(&mut connection, &context, 3).unwrap();
+        assert!((before[0] - 1.0 / 3.0).abs() < 1e-9);
+        for _ in 0..100 {
+            model.train_distribution(&mut connection, &context, &[1.0, 0.0, 0.0]).unwrap();
+        }
+        let after = model.predict_distribution(&mut connection, &context, 3).unwrap();
+        assert!(after[0] > 0.8, "{:?}", after);
+        assert!((after.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_joint_queries_over_outcomes() {
+        let namespace = "categorical_joint";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let (mut graph, outcomes) = setup(&mut connection, namespace);
+
+        // Registering the relation again replaces its outcomes.
+        let fewer = CategoricalRelation::new(
+            "eye_color".to_string(),
+            "obj".to_string(),
+            vec!["blue".to_string(), "brown".to_string()],
+        );
+        graph.register_categorical(&mut connection, &fewer).unwrap();
+        assert_eq!(graph.get_all_categoricals(&mut connection).unwrap(), vec![fewer]);
+        let choice = graph.categorical_choice(&mut connection, &outcomes[0]).unwrap().unwrap();
+        assert_eq!(choice.outcomes, outcomes[..2].to_vec());
+        let unrelated = proposition(
+            relation("blue_gene".to_string(), vec![variable_argument("Person".to_string())]),
+            vec![sub(constant("Person".to_string(), "alice".to_string()))],
+        );
+        assert!(graph.categorical_choice(&mut connection, &unrelated).unwrap().is_none());
+
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, outcomes[1].clone()).unwrap();
+        let mut inferencer =
+            Inferencer::new_mutable(model, proposition_graph, HashMapBeliefTable::new()).unwrap();
+        run(&mut inferencer, &mut connection);
+        let marginals = inferencer.build_marginal_table().unwrap();
+
+        // Joint queries fall back to clamping, which keeps the outcomes exclusive.
+        let config = QueryConfig::default();
+        let both = inferencer
+            .joint_probability(&mut connection, &[(outcomes[0].clone(), true), (outcomes[1].clone(), true)], &config)
+            .unwrap();
+        assert_eq!(both.method, QueryMethod::Clamping);
+        assert!(both.probability.abs() < 1e-9);
+        let table = inferencer.joint_table(&mut connection, &outcomes[..2], &config).unwrap();
+        for (assignment, probability) in &table.entries {
+            let expected = match assignment.as_slice() {
+                [true, false] => marginals.get_marginal(&outcomes[0]).unwrap(),
+                [false, true] => marginals.get_marginal(&outcomes[1]).unwrap(),
+                _ => 0.0,
+            };
+            assert!((probability - expected).abs() < 1e-9, "{:?}", table.entries);
+        }
+        assert!(inferencer.top_k_explanations(&mut connection, &MapConfig::default()).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+mod common;
+
+#[cfg(test)]
+mod test_em_training {
+    use super::common::{about, implies, people, unary};
+    use bayeslog::qbbn::{
+        common::{
+            em_train::{EmConfig, do_em_training},
+            proposition_db::RedisBeliefTable,
+            resources::ResourceContext,
+            train::TrainingPlan,
+        },
+        model::config::ExponentialTrainingConfig,
+    };
+
+    const ENTITIES: usize = 8;
+    const LATENT: usize = 4;
+
+    /// cause(x) -> hidden(x) -> effect(x), both learned, with `hidden` and `effect` equal
+    /// to `cause`. `hidden` is only observed for the first `ENTITIES - LATENT` entities.
+    fn setup(namespace: &str) -> ResourceContext {
+        let [cause, hidden, effect] = ["cause", "hidden", "effect"].map(|name| unary(name, "Person"));
+        let rules = [implies(&cause, &hidden), implies(&hidden, &effect)];
+        let resources = people(namespace, &[&cause, &hidden, &effect], &rules, ENTITIES);
+        {
+            let mut connection = resources.connection.lock().unwrap();
+            let connection = &mut *connection;
+            let proposition_db = RedisBeliefTable::new_mutable(namespace.to_string()).unwrap();
+            let mut plan = TrainingPlan::new(namespace.to_string()).unwrap();
+            for i in 0..ENTITIES {
+                let value = if i % 2 == 0 { 1f64 } else { 0f64 };
+                proposition_db
+                    .store_proposition_probability(connection, &about(&cause, i), value)
+                    .unwrap();
+                proposition_db
+                    .store_proposition_probability(connection, &about(&effect, i), value)
+                    .unwrap();
+                if i < ENTITIES - LATENT {
+                    proposition_db
+                        .store_proposition_probability(connection, &about(&hidden, i), value)
+                        .unwrap();
+                }
+                plan.add_proposition_to_queue(connection, "training_queue", &about(&hidden, i))
+                    .unwrap();
+                plan.add_proposition_to_queue(connection, "training_queue", &about(&effect, i))
+                    .unwrap();
+            }
+        }
+        resources
+    }
+
+    #[test]
+    fn test_em_learns_with_partly_latent_premise() {
+        let resources = setup("em_latent");
+        let config = EmConfig {
+            max_iterations: 5,
+            tolerance: 1e-6,
+            passes: 4,
+            epochs_per_iteration: 3,
+            model: ExponentialTrainingConfig {
+                learning_rate: 0.5,
+                ..ExponentialTrainingConfig::default()
+            },
+        };
+        let history = do_em_training(&resources, "em_latent".to_string(), &config).unwrap();
+        assert!(history.iterations.len() >= 2);
+        for iteration in &history.iterations {
+            assert_eq!(iteration.latent_questions, LATENT);
+            assert_eq!(iteration.expected_values, LATENT);
+            assert!(iteration.log_likelihood.is_finite() && iteration.log_likelihood <= 0f64);
+        }
+        let first = history.iterations.first().unwrap().log_likelihood;
+        let last = history.iterations.last().unwrap().log_likelihood;
+        assert!(last > first + 0.3, "{} {}", first, last);
+    }
+
+    #[test]
+    fn test_em_stops_when_converged() {
+        let resources = setup("em_converged");
+        let config = EmConfig {
+            max_iterations: 50,
+            tolerance: 10f64,
+            passes: 4,
+            ..EmConfig::default()
+        };
+        let history = do_em_training(&resources, "em_converged".to_string(), &config).unwrap();
+        assert!(history.converged);
+        assert_eq!(history.iterations.len(), 2);
+    }
+}
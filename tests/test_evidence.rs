@@ -0,0 +1,115 @@
+#[cfg(test)]
+mod test_evidence {
+    use bayeslog::qbbn::{
+        common::{
+            model::InferenceModel,
+            proposition_db::HashMapBeliefTable,
+            resources::ResourceContext,
+            train::setup_and_train,
+        },
+        inference::{
+            engine::{Inferencer, MarginalTable},
+            evidence::{EvidenceSet, Observation},
+            graph::PropositionGraph,
+            query::QueryConfig,
+        },
+        model::objects::Proposition,
+        scenarios::dating_simple::SimpleDating,
+    };
+
+    fn find(inferencer: &Inferencer, prefix: &str) -> Proposition {
+        inferencer
+            .bfs_order
+            .iter()
+            .find(|node| node.is_single() && node.debug_string().starts_with(prefix))
+            .unwrap()
+            .extract_single()
+    }
+
+    fn assert_tables_close(left: &MarginalTable, right: &MarginalTable) {
+        for (key, value) in &left.entries {
+            let expected = right.mapping.get(key).unwrap();
+            assert!((value - expected).abs() < 1e-6, "{} {} {}", key, value, expected);
+        }
+    }
+
+    #[test]
+    fn test_evidence_sets_virtual_evidence_and_retraction() {
+        let mut resources = ResourceContext::new_in_memory("dating_simple").unwrap();
+        resources.config.entities_per_domain = 4;
+        setup_and_train(&resources, &SimpleDating {}, "dating_simple").unwrap();
+
+        let mut connection = resources.connection.lock().unwrap();
+        let model = InferenceModel::new_shared("dating_simple".to_string()).unwrap();
+        let target = model.graph.get_target(&mut connection).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, target).unwrap();
+        let mut inferencer =
+            Inferencer::new_mutable(model.clone(), proposition_graph.clone(), HashMapBeliefTable::new())
+                .unwrap();
+        inferencer.initialize_chart(&mut connection).unwrap();
+        for _ in 0..5 {
+            inferencer.do_full_forward_and_backward(&mut connection).unwrap();
+        }
+        let prior = inferencer.build_marginal_table().unwrap();
+
+        let lonely = find(&inferencer, "lonely");
+        let exciting = find(&inferencer, "exciting");
+        let date = find(&inferencer, "date");
+
+        // Several observations, including a virtual reading, applied together.
+        let mut evidence = EvidenceSet::new();
+        evidence.observe(&exciting, true);
+        evidence.observe_likelihood_ratio(&date, 4.0);
+        let report = inferencer.apply_evidence(&mut connection, &evidence).unwrap();
+        assert_eq!(report.applied.len(), 2);
+        assert_eq!(report.applied[1].1, Observation::Virtual(4.0));
+        assert!(report.update.converged);
+        let posterior = inferencer.build_marginal_table().unwrap();
+
+        // Virtual evidence leaves the node unclamped but shifts it towards true.
+        let date_marginal = posterior.get_marginal(&date).unwrap();
+        assert!(date_marginal < 1.0);
+        assert!(date_marginal > prior.get_marginal(&date).unwrap());
+        assert_eq!(posterior.get_marginal(&exciting), Some(1.0));
+
+        // Belief propagation agrees with exact enumeration on this polytree.
+        let exact = inferencer
+            .joint_probability(&mut connection, &[(lonely.clone(), true)], &QueryConfig::default())
+            .unwrap();
+        assert!((exact.probability - posterior.get_marginal(&lonely).unwrap()).abs() < 1e-6);
+
+        // Retracting everything returns to the prior.
+        let report = inferencer
+            .retract_evidence(&mut connection, &[exciting, date])
+            .unwrap();
+        assert_eq!(report.retracted.len(), 2);
+        assert!(inferencer.virtual_evidence.is_empty());
+        let retracted = inferencer.build_marginal_table().unwrap();
+        assert_tables_close(&retracted, &prior);
+    }
+
+    #[test]
+    fn test_invalid_evidence_is_rejected() {
+        let mut resources = ResourceContext::new_in_memory("dating_simple").unwrap();
+        resources.config.entities_per_domain = 2;
+        setup_and_train(&resources, &SimpleDating {}, "dating_simple").unwrap();
+
+        let mut connection = resources.connection.lock().unwrap();
+        let model = InferenceModel::new_shared("dating_simple".to_string()).unwrap();
+        let target = model.graph.get_target(&mut connection).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, target).unwrap();
+        let mut inferencer =
+            Inferencer::new_mutable(model, proposition_graph, HashMapBeliefTable::new()).unwrap();
+        let lonely = find(&inferencer, "lonely");
+
+        let mut evidence = EvidenceSet::new();
+        evidence.observe_likelihood_ratio(&lonely, 0.0);
+        assert!(inferencer.apply_evidence(&mut connection, &evidence).is_err());
+
+        let mut evidence = EvidenceSet::new();
+        evidence.observe_probability(&lonely, 1.5);
+        assert!(inferencer.apply_evidence(&mut connection, &evidence).is_err());
+    }
+}
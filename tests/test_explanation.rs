@@ -0,0 +1,104 @@
+#[cfg(test)]
+mod test_explanation {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph, interface::BeliefTable, model::InferenceModel,
+            proposition_db::HashMapBeliefTable, resources::ResourceContext,
+        },
+        inference::{
+            engine::Inferencer,
+            explanation::{ExplanationConfig, ExplanationTree, PathKind},
+            graph::PropositionGraph,
+        },
+        model::{
+            creators::{
+                conjunction, constant, implication_with_cpd, predicate, proposition, relation, sub,
+                variable, variable_argument,
+            },
+            objects::{CpdType, Entity, Proposition, Relation, RoleMap},
+        },
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_explanation_tree_for_a_marginal() {
+        let namespace = "explanation";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let connection = &mut *connection;
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        let person = "Person".to_string();
+        graph.register_domain(connection, &person).unwrap();
+        graph
+            .store_entity(connection, &Entity { domain: person.clone(), name: "alice".to_string() })
+            .unwrap();
+        let unary = |name: &str| relation(name.to_string(), vec![variable_argument(person.clone())]);
+        let [trusted, connected, honest, lucky, promoted, celebrated] =
+            ["is_trusted", "is_connected", "is_honest", "is_lucky", "gets_promoted", "is_celebrated"].map(unary);
+        for relation in [&trusted, &connected, &honest, &lucky, &promoted, &celebrated] {
+            graph.register_relation(connection, relation).unwrap();
+        }
+        let x = variable(person.clone());
+        let subject = || vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))];
+        let mut noisy = |premise: &Relation, conclusion: &Relation, strength, leak| {
+            let rule = implication_with_cpd(
+                conjunction(vec![predicate(premise.clone(), vec![sub(x.clone())])]),
+                predicate(conclusion.clone(), vec![sub(x.clone())]),
+                subject(),
+                CpdType::NoisyOr { strength, leak },
+            );
+            graph.store_predicate_implication(connection, &rule).unwrap();
+        };
+        noisy(&trusted, &honest, 0.7, 0.1);
+        noisy(&connected, &lucky, 0.5, 0.1);
+        noisy(&honest, &promoted, 0.9, 0.05);
+        noisy(&lucky, &promoted, 0.3, 0.05);
+        noisy(&promoted, &celebrated, 0.8, 0.1);
+
+        let about_alice =
+            |relation: &Relation| proposition(relation.clone(), vec![sub(constant(person.clone(), "alice".to_string()))]);
+        let [honest_alice, promoted_alice, celebrated_alice]: [Proposition; 3] =
+            [&honest, &promoted, &celebrated].map(about_alice);
+        // Alice was not celebrated, which counts against her promotion.
+        let fact_memory = HashMapBeliefTable::new();
+        fact_memory.store_proposition_probability(connection, &celebrated_alice, 0.0).unwrap();
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(connection, &model.graph, celebrated_alice.clone()).unwrap();
+        let mut inferencer = Inferencer::new_mutable(model, proposition_graph, fact_memory).unwrap();
+        inferencer.initialize_chart(connection).unwrap();
+        for _ in 0..5 {
+            inferencer.do_full_forward_and_backward(connection).unwrap();
+        }
+
+        let tree = inferencer.explain(connection, &promoted_alice, &ExplanationConfig::default()).unwrap();
+        let root = &tree.root;
+        let marginals = inferencer.build_marginal_table().unwrap();
+        assert_eq!(Some(root.probability), marginals.get_marginal(&promoted_alice));
+        assert_eq!(root.supporting().count(), 2);
+        assert_eq!(root.opposing().count(), 1);
+        let causes: Vec<_> = root.supporting().collect();
+        assert!(causes.iter().all(|path| path.kind == PathKind::Cause));
+        assert!(causes[0].contribution > causes[1].contribution);
+        assert_eq!(causes[0].group.as_ref().unwrap().terms, vec![honest_alice.clone()]);
+        assert_eq!(causes[0].cpd, Some(CpdType::NoisyOr { strength: 0.9, leak: 0.05 }));
+        let honest_explained = &causes[0].children[0];
+        assert_eq!(Some(honest_explained.probability), marginals.get_marginal(&honest_alice));
+        assert_eq!(honest_explained.paths.len(), 2);
+        let effect = root.opposing().next().unwrap();
+        assert_eq!(effect.kind, PathKind::Effect);
+        assert_eq!(effect.children[0].observed, Some(0.0));
+        assert_eq!(tree.evidence, vec![(celebrated_alice.clone(), 0.0)]);
+
+        let text = tree.render_text();
+        assert!(text.starts_with(&format!("alice gets promoted: {:.3}", root.probability)));
+        assert!(text.contains("because alice is honest"));
+        assert!(text.contains("evidence:\n  alice is celebrated: 0.000"));
+        let round_trip: ExplanationTree = serde_json::from_str(&serde_json::to_string(&tree).unwrap()).unwrap();
+        assert_eq!(round_trip.render_text(), text);
+
+        // Only the first level is broken down at depth one.
+        let shallow = inferencer.explain(connection, &promoted_alice, &ExplanationConfig { depth: 1 }).unwrap();
+        assert!(shallow.root.paths.iter().flat_map(|path| &path.children).all(|child| child.paths.is_empty()));
+    }
+}
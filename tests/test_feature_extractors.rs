@@ -0,0 +1,113 @@
+mod common;
+
+#[cfg(test)]
+mod test_feature_extractors {
+    use super::common::{context, rule};
+    use bayeslog::qbbn::{
+        common::{
+            model::FactorContext,
+            redis::MockConnection,
+        },
+        model::{
+            exponential::{features_from_factor, ExponentialModel, ExponentialModelOptions},
+            features::{FeatureExtractor, FeatureExtractors},
+            objects::ImplicationFactor,
+            weights::ExponentialWeights,
+        },
+    };
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::sync::Arc;
+
+    /// Interacts the number of premises with their mean probability.
+    struct PremiseCount;
+
+    impl FeatureExtractor for PremiseCount {
+        fn feature_names(&self, _implication: &ImplicationFactor, class_label: usize) -> Vec<String> {
+            vec![format!("premise_count_{}", class_label)]
+        }
+
+        fn extract(
+            &self,
+            factor: &FactorContext,
+            class_label: usize,
+            features: &mut HashMap<String, f64>,
+        ) -> Result<(), Box<dyn Error>> {
+            let count = factor.probabilities.len() as f64;
+            let mean = factor.probabilities.iter().sum::<f64>() / count.max(1f64);
+            features.insert(format!("premise_count_{}", class_label), count * mean);
+            Ok(())
+        }
+    }
+
+    /// A constant bias, added for one relation only.
+    struct Bias;
+
+    impl FeatureExtractor for Bias {
+        fn extract(
+            &self,
+            _factor: &FactorContext,
+            class_label: usize,
+            features: &mut HashMap<String, f64>,
+        ) -> Result<(), Box<dyn Error>> {
+            features.insert(format!("bias_{}", class_label), 1f64);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_added_extractors_add_features() {
+        let mut extractors = FeatureExtractors::default();
+        extractors.add(Arc::new(PremiseCount));
+        extractors.add_for_relation("alarm", Arc::new(Bias));
+
+        let alarm = context(&rule("smoke", "alarm"), 0.5);
+        let features = extractors.features(None, &alarm).unwrap();
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[1]["premise_count_1"], 0.5);
+        assert_eq!(features[0]["bias_0"], 1f64);
+        // The built-in features are still there, and other relations get no bias.
+        assert!(features[1].keys().any(|name| name.starts_with("+>+")));
+        let fire = extractors.features(None, &context(&rule("smoke", "fire"), 0.5)).unwrap();
+        assert!(fire[1].contains_key("premise_count_1"));
+        assert!(!fire[1].contains_key("bias_1"));
+        // Other extractor sets and the plain built-in features are unaffected.
+        assert!(!features_from_factor(&alarm).unwrap()[1].contains_key("premise_count_1"));
+        assert!(!FeatureExtractors::default().features(None, &alarm).unwrap()[1]
+            .contains_key("premise_count_1"));
+
+        let names = extractors.feature_names(None, &rule("smoke", "alarm"), 1);
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[2], "premise_count_1");
+    }
+
+    #[test]
+    fn test_weights_are_initialized_and_used() {
+        let namespace = "feature_extractors_model";
+        let mut extractors = FeatureExtractors::default();
+        extractors.add(Arc::new(PremiseCount));
+        let mut connection = MockConnection::new_in_memory().unwrap();
+        let smoke_alarm = rule("smoke", "alarm");
+
+        let options = ExponentialModelOptions {
+            extractors,
+            ..ExponentialModelOptions::default()
+        };
+        let mut model = ExponentialModel::new_with_options(namespace.to_string(), options).unwrap();
+        model.initialize_connection(&mut connection, &smoke_alarm).unwrap();
+        let mut weights = ExponentialWeights::new(namespace.to_string()).unwrap();
+        let names = vec!["premise_count_0".to_string(), "premise_count_1".to_string()];
+        let initialized = weights.read_weight_vector(&mut connection, &names).unwrap();
+        assert!(initialized.values().all(|weight| *weight != 0f64), "{:?}", initialized);
+        let exported = weights.to_model_weights(&mut connection).unwrap();
+        assert!(exported.weights.contains_key("premise_count_1"));
+
+        // Raising the extractor's weight for the true class raises the prediction.
+        let alarm = context(&smoke_alarm, 1f64);
+        let before = model.predict(&mut connection, &alarm).unwrap().probability;
+        let raised = HashMap::from([("premise_count_1".to_string(), initialized["premise_count_1"] + 2f64)]);
+        weights.save_weight_vector(&mut connection, &raised).unwrap();
+        let after = model.predict(&mut connection, &alarm).unwrap().probability;
+        assert!(after > before, "{} {}", before, after);
+    }
+}
@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod test_incremental_inference {
+    use bayeslog::qbbn::{
+        common::{
+            interface::BeliefTable,
+            model::InferenceModel,
+            proposition_db::HashMapBeliefTable,
+            redis::MockConnection,
+            resources::ResourceContext,
+            train::setup_and_train,
+        },
+        inference::{engine::Inferencer, graph::PropositionGraph},
+        model::weights::ExponentialWeights,
+        scenarios::dating_simple::SimpleDating,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_incremental_update_matches_full_passes() {
+        let mut resources = ResourceContext::new_in_memory("dating_simple").unwrap();
+        resources.config.entities_per_domain = 4;
+        setup_and_train(&resources, &SimpleDating {}, "dating_simple").unwrap();
+
+        let mut connection = resources.connection.lock().unwrap();
+        let model = InferenceModel::new_shared("dating_simple".to_string()).unwrap();
+        let target = model.graph.get_target(&mut connection).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, target).unwrap();
+        let fact_memory = HashMapBeliefTable::new();
+
+        // Warm up a chart before any evidence arrives.
+        let mut inferencer =
+            Inferencer::new_mutable(model.clone(), proposition_graph.clone(), fact_memory.clone())
+                .unwrap();
+        inferencer.initialize_chart(&mut connection).unwrap();
+        for _ in 0..5 {
+            inferencer.do_full_forward_and_backward(&mut connection).unwrap();
+        }
+        let total_nodes = inferencer.bfs_order.len();
+
+        // Observe one proposition and only propagate its effects.
+        let lonely = inferencer
+            .bfs_order
+            .iter()
+            .find(|node| node.is_single() && node.debug_string().starts_with("lonely"))
+            .unwrap()
+            .clone();
+        fact_memory
+            .store_proposition_probability(&mut connection, &lonely.extract_single(), 1.0)
+            .unwrap();
+        let stats = inferencer
+            .do_incremental_update(&mut connection, &[lonely.clone()])
+            .unwrap();
+        assert!(stats.converged);
+        assert!(stats.nodes_visited > 0);
+        assert!(stats.nodes_visited < total_nodes, "Update should not touch every node");
+        let incremental = inferencer.build_marginal_table().unwrap();
+
+        // Recompute from scratch with the same evidence.
+        let mut fresh = Inferencer::new_mutable(model, proposition_graph, fact_memory).unwrap();
+        fresh.initialize_chart(&mut connection).unwrap();
+        for _ in 0..5 {
+            fresh.do_full_forward_and_backward(&mut connection).unwrap();
+        }
+        let full = fresh.build_marginal_table().unwrap();
+
+        assert_eq!(incremental.entries.len(), full.entries.len());
+        for (key, value) in &incremental.entries {
+            let expected = full.mapping.get(key).unwrap();
+            assert!(
+                (value - expected).abs() < 1e-6,
+                "{} incremental={} full={}",
+                key,
+                value,
+                expected
+            );
+        }
+        assert_eq!(
+            incremental.get_marginal(&lonely.extract_single()),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn test_weight_writes_invalidate_cached_factors() {
+        let mut resources = ResourceContext::new_in_memory("dating_simple").unwrap();
+        resources.config.entities_per_domain = 4;
+        setup_and_train(&resources, &SimpleDating {}, "dating_simple").unwrap();
+
+        let mut connection = resources.connection.lock().unwrap();
+        let model = InferenceModel::new_shared("dating_simple".to_string()).unwrap();
+        let target = model.graph.get_target(&mut connection).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, target).unwrap();
+        let fact_memory = HashMapBeliefTable::new();
+        let run = |inferencer: &mut Inferencer, connection: &mut _| {
+            inferencer.initialize_chart(connection).unwrap();
+            for _ in 0..5 {
+                inferencer.do_full_forward_and_backward(connection).unwrap();
+            }
+            inferencer.build_marginal_table().unwrap()
+        };
+
+        // Fill the cache, then move every weight the graph reads.
+        let mut warm =
+            Inferencer::new_mutable(model.clone(), proposition_graph.clone(), fact_memory.clone())
+                .unwrap();
+        let before = run(&mut warm, &mut connection);
+        let mut weights = ExponentialWeights::new("dating_simple".to_string()).unwrap();
+        let features = warm.model_features(&mut connection).unwrap();
+        let current = weights.read_weight_vector(&mut connection, &features).unwrap();
+        let moved: HashMap<String, f64> =
+            current.into_iter().map(|(feature, weight)| (feature, -2.0 * weight)).collect();
+        weights.save_weight_vector(&mut connection, &moved).unwrap();
+
+        // The warm inferencer rescored its factors instead of reusing stale scores.
+        let after = run(&mut warm, &mut connection);
+        let mut fresh = Inferencer::new_mutable(model, proposition_graph, fact_memory).unwrap();
+        let expected = run(&mut fresh, &mut connection);
+        assert!(before
+            .entries
+            .iter()
+            .any(|(key, value)| (value - expected.mapping[key]).abs() > 1e-6));
+        for (key, value) in &after.entries {
+            assert!((value - expected.mapping[key]).abs() < 1e-9, "{} {} {}", key, value, expected.mapping[key]);
+        }
+    }
+
+    #[test]
+    fn test_weight_versions_are_per_database_and_namespace() {
+        let mut connection = MockConnection::new_in_memory().unwrap();
+        let mut elsewhere = MockConnection::new_in_memory().unwrap();
+        let mut mine = ExponentialWeights::new("weight_version_mine".to_string()).unwrap();
+        let mut other = ExponentialWeights::new("weight_version_other".to_string()).unwrap();
+        let weights = HashMap::from([("feature".to_string(), 1f64)]);
+        let before = mine.version(&connection);
+        other.save_weight_vector(&mut connection, &weights).unwrap();
+        mine.save_weight_vector(&mut elsewhere, &weights).unwrap();
+        assert_eq!(mine.version(&connection), before);
+        mine.save_weight_vector(&mut connection, &weights).unwrap();
+        assert!(mine.version(&connection) > before);
+        // Every model of a namespace in one database shares its counter.
+        let same = ExponentialWeights::new("weight_version_mine".to_string()).unwrap();
+        assert_eq!(same.version(&connection), mine.version(&connection));
+    }
+}
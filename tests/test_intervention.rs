@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod test_intervention {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph, model::InferenceModel, proposition_db::HashMapBeliefTable,
+            resources::ResourceContext,
+        },
+        inference::{
+            engine::{Inferencer, MarginalTable},
+            graph::PropositionGraph,
+            query::QueryConfig,
+            table::PropositionNode,
+        },
+        model::{
+            creators::{
+                conjunction, constant, implication_with_cpd, obj, predicate, proposition, relation,
+                sub, variable, variable_argument,
+            },
+            objects::{CpdType, Entity, Predicate, Proposition, RoleMap},
+        },
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_intervention_differs_from_observation() {
+        let namespace = "intervention";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let connection = &mut *connection;
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        for domain in ["Man", "Woman"] {
+            graph.register_domain(connection, domain).unwrap();
+        }
+        let jack = Entity { domain: "Man".to_string(), name: "jack".to_string() };
+        let jill = Entity { domain: "Woman".to_string(), name: "jill".to_string() };
+        graph.store_entity(connection, &jack).unwrap();
+        graph.store_entity(connection, &jill).unwrap();
+        let man = || variable_argument("Man".to_string());
+        let [exists, charming, lonely] = ["exists_man", "charming", "lonely"].map(|name| relation(name.to_string(), vec![man()]));
+        let date = relation("date".to_string(), vec![man(), variable_argument("Woman".to_string())]);
+        for relation in [&exists, &charming, &lonely, &date] {
+            graph.register_relation(connection, relation).unwrap();
+        }
+        let x = variable("Man".to_string());
+        let y = variable("Woman".to_string());
+        let about = |relation| predicate(relation, vec![sub(x.clone())]);
+        let subject = || RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]));
+        let mut store = |premise: Vec<Predicate>, conclusion: Predicate, cpd| {
+            let roles = premise.iter().map(|_| subject()).collect();
+            let rule = implication_with_cpd(conjunction(premise), conclusion, roles, cpd);
+            graph.store_predicate_implication(connection, &rule).unwrap();
+        };
+        // Being charming makes jack both lonely and likely to date jill; loneliness
+        // makes a date a little more likely on its own.
+        let dating = predicate(date.clone(), vec![sub(x.clone()), obj(y.clone())]);
+        store(vec![about(exists.clone())], about(charming.clone()), CpdType::NoisyOr { strength: 0.5, leak: 0.1 });
+        store(vec![about(charming.clone()), about(exists.clone())], about(lonely.clone()), CpdType::And);
+        store(vec![about(charming.clone())], dating.clone(), CpdType::NoisyOr { strength: 0.8, leak: 0.1 });
+        store(vec![about(lonely.clone())], dating, CpdType::NoisyOr { strength: 0.3, leak: 0.05 });
+
+        let jack_arg = || constant("Man".to_string(), "jack".to_string());
+        let of_jack = |relation| proposition(relation, vec![sub(jack_arg())]);
+        let charming_jack: Proposition = of_jack(charming);
+        let lonely_jack: Proposition = of_jack(lonely);
+        let they_date = proposition(date, vec![sub(jack_arg()), obj(constant("Woman".to_string(), "jill".to_string()))]);
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let proposition_graph = PropositionGraph::new_shared(connection, &model.graph, they_date.clone()).unwrap();
+        let inferencer = Inferencer::new_mutable(model, proposition_graph.clone(), HashMapBeliefTable::new()).unwrap();
+        let config = QueryConfig::default();
+        let marginal = |table: &MarginalTable, proposition| {
+            table.get_marginal(proposition).unwrap()
+        };
+
+        let prior = inferencer.clamped_marginal_table(connection, &[], config.clamping_passes).unwrap();
+        let observed = inferencer
+            .clamped_marginal_table(connection, &[(lonely_jack.clone(), true)], config.clamping_passes)
+            .unwrap();
+        let forced = inferencer
+            .intervention_marginals(connection, &[(lonely_jack.clone(), true)], &[], &config)
+            .unwrap();
+        let prevented = inferencer
+            .intervention_marginals(connection, &[(lonely_jack.clone(), false)], &[], &config)
+            .unwrap();
+        // Seeing jack lonely means he is charming; making him lonely does not.
+        assert!((marginal(&observed, &charming_jack) - 1.0).abs() < 1e-9);
+        assert!((marginal(&forced, &charming_jack) - marginal(&prior, &charming_jack)).abs() < 1e-9);
+        assert_eq!(marginal(&forced, &lonely_jack), 1.0);
+        assert_eq!(marginal(&prevented, &lonely_jack), 0.0);
+        assert!(marginal(&observed, &they_date) > marginal(&forced, &they_date));
+        assert!(marginal(&forced, &they_date) > marginal(&prevented, &they_date));
+
+        // Evidence downstream of the intervention still reaches the causes.
+        let dated = inferencer
+            .intervention_marginals(connection, &[(lonely_jack.clone(), false)], &[(they_date.clone(), true)], &config)
+            .unwrap();
+        assert!(marginal(&dated, &charming_jack) > marginal(&prior, &charming_jack));
+
+        // Virtual evidence is weighed in, except on the intervened proposition.
+        let mut sensing = Inferencer::new_mutable(
+            inferencer.model.clone(),
+            proposition_graph.clone(),
+            HashMapBeliefTable::new(),
+        )
+        .unwrap();
+        sensing.virtual_evidence.insert(PropositionNode::from_single(&charming_jack), 4.0);
+        sensing.virtual_evidence.insert(PropositionNode::from_single(&lonely_jack), 0.25);
+        let sensed = sensing
+            .intervention_marginals(connection, &[(lonely_jack.clone(), true)], &[], &config)
+            .unwrap();
+        assert!(marginal(&sensed, &charming_jack) > marginal(&forced, &charming_jack));
+        assert_eq!(marginal(&sensed, &lonely_jack), 1.0);
+        sensing.virtual_evidence.remove(&PropositionNode::from_single(&charming_jack));
+        let sensed = sensing
+            .intervention_marginals(connection, &[(lonely_jack.clone(), true)], &[], &config)
+            .unwrap();
+        assert!((marginal(&sensed, &they_date) - marginal(&forced, &they_date)).abs() < 1e-9);
+
+        // The stored graph keeps its factors.
+        assert!(!proposition_graph.get_single_backward(&lonely_jack).is_empty());
+        let surgery = proposition_graph.with_interventions(&[(lonely_jack.clone(), true)]).unwrap();
+        assert!(surgery.get_single_backward(&lonely_jack).is_empty());
+        assert_eq!(surgery.fixed_value(&lonely_jack), Some(1.0));
+        let unknown = of_jack(relation("bored".to_string(), vec![man()]));
+        assert!(inferencer.intervention_marginals(connection, &[(unknown, true)], &[], &config).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod test_joint_queries {
+    use bayeslog::qbbn::{
+        common::{
+            interface::BeliefTable,
+            model::InferenceModel,
+            proposition_db::HashMapBeliefTable,
+            resources::ResourceContext,
+            train::setup_and_train,
+        },
+        inference::{
+            engine::Inferencer,
+            graph::PropositionGraph,
+            joint::{log_sum_exp, LogSumExp},
+            query::{QueryConfig, QueryMethod, MAX_JOINT_TABLE_PROPOSITIONS},
+            table::PropositionNode,
+        },
+        model::objects::Proposition,
+        scenarios::dating_simple::SimpleDating,
+    };
+
+    fn find(inferencer: &Inferencer, prefix: &str) -> Proposition {
+        inferencer
+            .bfs_order
+            .iter()
+            .find(|node| node.is_single() && node.debug_string().starts_with(prefix))
+            .unwrap()
+            .extract_single()
+    }
+
+    #[test]
+    fn test_joint_and_conditional_queries() {
+        let mut resources = ResourceContext::new_in_memory("dating_simple").unwrap();
+        resources.config.entities_per_domain = 4;
+        setup_and_train(&resources, &SimpleDating {}, "dating_simple").unwrap();
+
+        let mut connection = resources.connection.lock().unwrap();
+        let model = InferenceModel::new_shared("dating_simple".to_string()).unwrap();
+        let target = model.graph.get_target(&mut connection).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, target).unwrap();
+        let fact_memory = HashMapBeliefTable::new();
+        let mut inferencer =
+            Inferencer::new_mutable(model, proposition_graph, fact_memory.clone()).unwrap();
+
+        let lonely = find(&inferencer, "lonely");
+        let exciting = find(&inferencer, "exciting");
+        let date = find(&inferencer, "date");
+        fact_memory
+            .store_proposition_probability(&mut connection, &date, 1.0)
+            .unwrap();
+
+        let config = QueryConfig::default();
+        let table = inferencer
+            .joint_table(&mut connection, &[lonely.clone(), exciting.clone()], &config)
+            .unwrap();
+        assert_eq!(table.method, QueryMethod::Exact);
+        assert_eq!(table.entries.len(), 4);
+        let total: f64 = table.entries.iter().map(|(_, probability)| probability).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        let both = inferencer
+            .joint_probability(&mut connection, &[(lonely.clone(), true), (exciting.clone(), true)], &config)
+            .unwrap();
+        assert!((both.probability - table.probability_of(&[true, true]).unwrap()).abs() < 1e-9);
+
+        let conditional = inferencer
+            .conditional_probability(&mut connection, &[(lonely.clone(), true)], &[(exciting.clone(), true)], &config)
+            .unwrap();
+        assert!((conditional.probability - both.probability / table.marginal(1)).abs() < 1e-9);
+
+        // This graph is a polytree, so clamping agrees with enumeration.
+        let clamping_config = QueryConfig {
+            max_exact_variables: 0,
+            ..QueryConfig::default()
+        };
+        let clamped = inferencer
+            .joint_table(&mut connection, &[lonely.clone(), exciting.clone()], &clamping_config)
+            .unwrap();
+        assert_eq!(clamped.method, QueryMethod::Clamping);
+        for ((assignment, exact), (_, approximate)) in table.entries.iter().zip(clamped.entries.iter()) {
+            assert!((exact - approximate).abs() < 1e-6, "{:?}", assignment);
+        }
+
+        // Both methods weigh in virtual evidence.
+        inferencer
+            .virtual_evidence
+            .insert(PropositionNode::from_single(&exciting), 3.0);
+        let sensed = inferencer
+            .joint_table(&mut connection, &[lonely.clone(), exciting.clone()], &config)
+            .unwrap();
+        assert_eq!(sensed.method, QueryMethod::Exact);
+        assert!(sensed.marginal(1) > table.marginal(1));
+        let clamped = inferencer
+            .joint_table(&mut connection, &[lonely.clone(), exciting], &clamping_config)
+            .unwrap();
+        for ((assignment, exact), (_, approximate)) in sensed.entries.iter().zip(clamped.entries.iter()) {
+            assert!((exact - approximate).abs() < 1e-6, "{:?}", assignment);
+        }
+        inferencer.virtual_evidence.clear();
+
+        // Sizes past the enumeration limits are errors rather than overflowing shifts.
+        let too_many = vec![lonely.clone(); MAX_JOINT_TABLE_PROPOSITIONS + 1];
+        assert!(inferencer.joint_table(&mut connection, &too_many, &config).is_err());
+        let unbounded = QueryConfig {
+            max_exact_variables: 64,
+            ..QueryConfig::default()
+        };
+        assert!(inferencer
+            .joint_probability(&mut connection, &[(lonely, true)], &unbounded)
+            .is_err());
+    }
+
+    #[test]
+    fn test_running_log_sum_exp() {
+        let values = [-3.0, 700.0, f64::NEG_INFINITY, 699.5, -1e9];
+        let mut running = LogSumExp::default();
+        assert_eq!(running.value(), f64::NEG_INFINITY);
+        for value in values {
+            running.add(value);
+        }
+        assert!((running.value() - log_sum_exp(&values)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,121 @@
+#[cfg(test)]
+mod test_lazy_grounding {
+    use bayeslog::qbbn::{
+        common::{
+            interface::BeliefTable,
+            model::InferenceModel,
+            proposition_db::HashMapBeliefTable,
+            resources::ResourceContext,
+            train::setup_and_train,
+        },
+        inference::{
+            graph::PropositionGraph,
+            grounding::{GroundingConfig, LazyGrounder},
+        },
+        model::{
+            creators::{constant, obj, proposition, relation, sub, variable_argument},
+            objects::{Domain, Proposition},
+        },
+        scenarios::dating_simple::SimpleDating,
+    };
+
+    fn date(man: &str, woman: &str) -> Proposition {
+        let date_relation = relation(
+            "date".to_string(),
+            vec![
+                variable_argument(Domain::MAN.to_string()),
+                variable_argument(Domain::WOMAN.to_string()),
+            ],
+        );
+        proposition(
+            date_relation,
+            vec![
+                sub(constant(Domain::MAN.to_string(), man.to_string())),
+                obj(constant(Domain::WOMAN.to_string(), woman.to_string())),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_lazy_grounding_for_several_queries() {
+        let mut resources = ResourceContext::new_in_memory("dating_simple").unwrap();
+        resources.config.entities_per_domain = 3;
+        setup_and_train(&resources, &SimpleDating {}, "dating_simple").unwrap();
+
+        let mut connection = resources.connection.lock().unwrap();
+        let model = InferenceModel::new_shared("dating_simple".to_string()).unwrap();
+
+        // Without a depth bound, grounding the registered target matches eager grounding.
+        let target = model.graph.get_target(&mut connection).unwrap();
+        let eager = PropositionGraph::new_shared(&mut connection, &model.graph, target.clone()).unwrap();
+        let mut grounder = LazyGrounder::new(GroundingConfig::default());
+        let lazy = grounder
+            .ground(&mut connection, &model.graph, &[target.clone()])
+            .unwrap();
+        assert_eq!(lazy.all_nodes, eager.all_nodes);
+        assert_eq!(lazy.roots, eager.roots);
+
+        // Several queries, none of them registered as the target.
+        let first = date("train_Man1", "train_Woman1");
+        let second = date("train_Man2", "train_Woman2");
+        let fact_memory = HashMapBeliefTable::new();
+        let marginals = grounder
+            .query_marginals(
+                &mut connection,
+                model.clone(),
+                fact_memory.clone(),
+                &[first.clone(), second.clone()],
+                5,
+            )
+            .unwrap();
+        assert!(marginals.get_marginal(&first).is_some());
+        assert!(marginals.get_marginal(&second).is_some());
+        assert!(marginals.get_marginal(&target).is_none());
+
+        // The same query set is served from the cache.
+        let lookups = grounder.stats.lookups;
+        grounder
+            .ground(&mut connection, &model.graph, &[second.clone(), first.clone()])
+            .unwrap();
+        assert_eq!(grounder.stats.graph_cache_hits, 1);
+        assert_eq!(grounder.stats.lookups, lookups);
+
+        // Changing the depth bound or the frontier prior grounds the queries again.
+        grounder.config.max_depth = Some(1);
+        let bounded_first = grounder
+            .ground(&mut connection, &model.graph, &[first.clone(), second.clone()])
+            .unwrap();
+        assert_eq!(grounder.stats.graph_cache_hits, 1);
+        assert!(!bounded_first.frontier.is_empty());
+        assert!(bounded_first.frontier.values().all(|prior| *prior == 0.5));
+        grounder.config.frontier_prior = 0.2;
+        let reprior = grounder
+            .ground(&mut connection, &model.graph, &[first.clone(), second.clone()])
+            .unwrap();
+        assert_eq!(grounder.stats.graph_cache_hits, 1);
+        assert!(reprior.frontier.values().all(|prior| *prior == 0.2));
+
+        // A depth bound leaves a frontier that still accepts evidence.
+        let mut shallow = LazyGrounder::new(GroundingConfig {
+            max_depth: Some(1),
+            frontier_prior: 0.5,
+        });
+        let bounded = shallow
+            .ground(&mut connection, &model.graph, &[first.clone()])
+            .unwrap();
+        assert!(shallow.stats.truncated > 0);
+        assert!(bounded.all_nodes.len() < eager.all_nodes.len());
+        let frontier: Proposition = bounded.frontier.keys().next().unwrap().clone();
+        let prior = shallow
+            .query_marginals(&mut connection, model.clone(), fact_memory.clone(), &[first.clone()], 5)
+            .unwrap();
+        assert!((prior.get_marginal(&frontier).unwrap() - 0.5).abs() < 1e-9);
+        fact_memory
+            .store_proposition_probability(&mut connection, &frontier, 1.0)
+            .unwrap();
+        let posterior = shallow
+            .query_marginals(&mut connection, model, fact_memory, &[first], 5)
+            .unwrap();
+        assert_eq!(posterior.get_marginal(&frontier), Some(1.0));
+    }
+}
@@ -0,0 +1,88 @@
+#[cfg(test)]
+mod test_map_queries {
+    use bayeslog::qbbn::{
+        common::{
+            interface::BeliefTable,
+            model::InferenceModel,
+            proposition_db::HashMapBeliefTable,
+            resources::ResourceContext,
+            train::setup_and_train,
+        },
+        inference::{
+            engine::Inferencer,
+            graph::PropositionGraph,
+            mpe::{MapConfig, MapMethod},
+        },
+        scenarios::dating_simple::SimpleDating,
+    };
+
+    #[test]
+    fn test_top_k_explanations_exact_and_max_product() {
+        let mut resources = ResourceContext::new_in_memory("dating_simple").unwrap();
+        resources.config.entities_per_domain = 4;
+        setup_and_train(&resources, &SimpleDating {}, "dating_simple").unwrap();
+
+        let mut connection = resources.connection.lock().unwrap();
+        let model = InferenceModel::new_shared("dating_simple".to_string()).unwrap();
+        let target = model.graph.get_target(&mut connection).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, target).unwrap();
+        let fact_memory = HashMapBeliefTable::new();
+        let inferencer =
+            Inferencer::new_mutable(model, proposition_graph, fact_memory.clone()).unwrap();
+
+        // Explain an observed date.
+        let date = inferencer
+            .bfs_order
+            .iter()
+            .find(|node| node.is_single() && node.debug_string().starts_with("date"))
+            .unwrap()
+            .extract_single();
+        fact_memory
+            .store_proposition_probability(&mut connection, &date, 1.0)
+            .unwrap();
+
+        let config = MapConfig {
+            top_k: 4,
+            ..MapConfig::default()
+        };
+        let exact = inferencer.top_k_explanations(&mut connection, &config).unwrap();
+        assert_eq!(exact.method, MapMethod::Exact);
+        assert_eq!(exact.assignments.len(), 4);
+        for pair in exact.assignments.windows(2) {
+            assert!(pair[0].log_score >= pair[1].log_score);
+        }
+        for ranked in &exact.assignments {
+            assert_eq!(ranked.value_of(&date), Some(true));
+            let probability = ranked.probability.unwrap();
+            assert!(probability > 0.0 && probability <= 1.0);
+        }
+
+        // Forcing message passing should recover the same optimum on this graph.
+        let approximate_config = MapConfig {
+            top_k: 4,
+            max_exact_variables: 0,
+            ..MapConfig::default()
+        };
+        let approximate = inferencer
+            .top_k_explanations(&mut connection, &approximate_config)
+            .unwrap();
+        assert_eq!(approximate.method, MapMethod::MaxProduct);
+        assert!(approximate.converged);
+        assert!(
+            (approximate.assignments[0].log_score - exact.assignments[0].log_score).abs() < 1e-9
+        );
+        assert_eq!(approximate.assignments[0].assignment, exact.assignments[0].assignment);
+
+        let best = inferencer.most_probable_explanation(&mut connection).unwrap();
+        assert_eq!(best.assignment, exact.assignments[0].assignment);
+
+        // Settings that would enumerate past 2^30 assignments are rejected up front.
+        let unbounded = MapConfig {
+            max_exact_variables: 64,
+            ..MapConfig::default()
+        };
+        assert!(unbounded.validate().is_err());
+        assert!(inferencer.top_k_explanations(&mut connection, &unbounded).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod test_negation {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph,
+            interface::BeliefTable,
+            model::InferenceModel,
+            proposition_db::HashMapBeliefTable,
+            resources::ResourceContext,
+        },
+        inference::{engine::Inferencer, graph::PropositionGraph},
+        model::{
+            choose::extract_existence_factor_for_proposition,
+            creators::{
+                conjunction_of_literals, constant, implication_with_cpd, not, not_provable,
+                predicate, proposition, relation, sub, variable, variable_argument,
+            },
+            objects::{CpdType, Entity, ImplicationFactor, Negation, Proposition, RoleMap},
+        },
+    };
+    use std::collections::HashMap;
+
+    fn marginal_of(
+        inferencer: &mut Inferencer,
+        connection: &mut bayeslog::qbbn::common::redis::MockConnection,
+        proposition: &Proposition,
+    ) -> f64 {
+        inferencer.initialize_chart(connection).unwrap();
+        for _ in 0..3 {
+            inferencer.do_full_forward_and_backward(connection).unwrap();
+        }
+        inferencer
+            .build_marginal_table()
+            .unwrap()
+            .get_marginal(proposition)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_negated_premises() {
+        let namespace = "negation";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+
+        let lawn_domain = "Lawn".to_string();
+        graph.register_domain(&mut connection, &lawn_domain).unwrap();
+        let unary = |name: &str| relation(name.to_string(), vec![variable_argument(lawn_domain.clone())]);
+        let rain = unary("rain");
+        let dry = unary("dry");
+        let watered = unary("watered");
+        let thirsty = unary("thirsty");
+        for relation in [&rain, &dry, &watered, &thirsty] {
+            graph.register_relation(&mut connection, relation).unwrap();
+        }
+        let entity = Entity {
+            domain: lawn_domain.clone(),
+            name: "lawn0".to_string(),
+        };
+        graph.store_entity(&mut connection, &entity).unwrap();
+        let lawn = constant(lawn_domain.clone(), entity.name.clone());
+
+        let rain_lawn = proposition(rain.clone(), vec![sub(lawn.clone())]);
+        let mut existence = extract_existence_factor_for_proposition(&rain_lawn).unwrap();
+        existence.cpd = CpdType::Table(vec![0.0, 0.3]);
+        graph.store_predicate_implication(&mut connection, &existence).unwrap();
+
+        // dry(x) <- ¬rain(x), and thirsty(x) <- ~watered(x) under a closed world.
+        let x = variable(lawn_domain.clone());
+        let same_subject = || vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))];
+        let dry_rule = implication_with_cpd(
+            conjunction_of_literals(vec![not(predicate(rain.clone(), vec![sub(x.clone())]))]),
+            predicate(dry.clone(), vec![sub(x.clone())]),
+            same_subject(),
+            CpdType::And,
+        );
+        let thirsty_rule = implication_with_cpd(
+            conjunction_of_literals(vec![not_provable(predicate(watered.clone(), vec![sub(x.clone())]))]),
+            predicate(thirsty.clone(), vec![sub(x.clone())]),
+            same_subject(),
+            CpdType::And,
+        );
+        graph.store_predicate_implication(&mut connection, &dry_rule).unwrap();
+        graph.store_predicate_implication(&mut connection, &thirsty_rule).unwrap();
+
+        // Negations survive storage and are part of the rule's identity.
+        let stored = graph.get_all_implications(&mut connection).unwrap();
+        let stored_dry = stored
+            .iter()
+            .find(|implication| implication.conclusion.relation.relation_name == "dry")
+            .unwrap();
+        assert_eq!(stored_dry.premise.negation(0), Some(Negation::Probabilistic));
+        assert!(stored_dry.premise.hash_string().starts_with('¬'));
+        let record = serde_json::to_string(&existence).unwrap();
+        assert!(!record.contains("negations"));
+        assert!(serde_json::from_str::<ImplicationFactor>(&record).unwrap().premise.negations.is_empty());
+
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let fact_memory = HashMapBeliefTable::new();
+
+        let dry_lawn = proposition(dry, vec![sub(lawn.clone())]);
+        let dry_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, dry_lawn.clone()).unwrap();
+        let mut inferencer =
+            Inferencer::new_mutable(model.clone(), dry_graph, fact_memory.clone()).unwrap();
+        assert!((marginal_of(&mut inferencer, &mut connection, &dry_lawn) - 0.7).abs() < 1e-9);
+        fact_memory
+            .store_proposition_probability(&mut connection, &rain_lawn, 1.0)
+            .unwrap();
+        assert!(marginal_of(&mut inferencer, &mut connection, &dry_lawn).abs() < 1e-9);
+
+        // Nothing says the lawn was watered, so it is thirsty until the fact is stored.
+        let thirsty_lawn = proposition(thirsty, vec![sub(lawn.clone())]);
+        let thirsty_graph =
+            PropositionGraph::new_shared(&mut connection, &model.graph, thirsty_lawn.clone()).unwrap();
+        let mut inferencer =
+            Inferencer::new_mutable(model, thirsty_graph, fact_memory.clone()).unwrap();
+        assert!((marginal_of(&mut inferencer, &mut connection, &thirsty_lawn) - 1.0).abs() < 1e-9);
+        let watered_lawn = proposition(watered, vec![sub(lawn.clone())]);
+        fact_memory
+            .store_proposition_probability(&mut connection, &watered_lawn, 1.0)
+            .unwrap();
+        assert!(marginal_of(&mut inferencer, &mut connection, &thirsty_lawn).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod test_numeric {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph,
+            model::{FactorContext, InferenceModel},
+            proposition_db::HashMapBeliefTable,
+            resources::ResourceContext,
+        },
+        inference::{engine::Inferencer, graph::PropositionGraph},
+        model::{
+            choose::extract_backimplications_from_proposition,
+            creators::{
+                comparison, conjunction, constant, implication, implication_with_cpd, predicate,
+                proposition, relation, sub, variable, variable_argument,
+            },
+            exponential::features_from_factor,
+            objects::{ComparisonOp, CpdType, Entity, NumericComparison, RoleMap},
+            weights::numeric_feature,
+        },
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_comparison_relation_names() {
+        let over_thirty = NumericComparison::new("age".to_string(), ComparisonOp::Gt, 30.0);
+        assert_eq!(over_thirty.relation_name(), "age>30");
+        assert_eq!(NumericComparison::from_relation_name("age>30"), Some(over_thirty.clone()));
+        let cheap = NumericComparison::from_relation_name("price<=-2.5").unwrap();
+        assert_eq!(cheap.op, ComparisonOp::Le);
+        assert_eq!(cheap.threshold, -2.5);
+        assert!(NumericComparison::from_relation_name("likes").is_none());
+        assert!(NumericComparison::from_relation_name("age>old").is_none());
+
+        assert!(over_thirty.holds(Some(45.0)));
+        assert!(!over_thirty.holds(Some(30.0)));
+        assert!(!over_thirty.holds(None));
+        assert!((over_thirty.margin(45.0) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_comparisons_ground_deterministically() {
+        let namespace = "numeric_comparisons";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        let person = "Person".to_string();
+        graph.register_domain(&mut connection, &person).unwrap();
+        let adult = relation("adult".to_string(), vec![variable_argument(person.clone())]);
+        let discount = relation("discount".to_string(), vec![variable_argument(person.clone())]);
+        graph.register_relation(&mut connection, &adult).unwrap();
+        graph.register_relation(&mut connection, &discount).unwrap();
+
+        // Carol has no age, so no comparison holds of her.
+        let ages = [("alice", Some(45.0)), ("bob", Some(12.0)), ("carol", None)];
+        for (name, age) in ages {
+            let entity = Entity { domain: person.clone(), name: name.to_string() };
+            graph.store_entity(&mut connection, &entity).unwrap();
+            if let Some(age) = age {
+                graph.store_numeric_attribute(&mut connection, &entity, "age", age).unwrap();
+            }
+        }
+        let alice = Entity { domain: person.clone(), name: "alice".to_string() };
+        assert_eq!(graph.get_numeric_attribute(&mut connection, &alice, "age").unwrap(), Some(45.0));
+        assert!(graph.store_numeric_attribute(&mut connection, &alice, "age", f64::NAN).is_err());
+
+        let x = variable(person.clone());
+        let same_subject = || vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))];
+        let over_thirty = comparison("age", ComparisonOp::Gt, 30.0, sub(x.clone()));
+        graph
+            .store_predicate_implication(
+                &mut connection,
+                &implication_with_cpd(
+                    conjunction(vec![over_thirty.clone()]),
+                    predicate(adult.clone(), vec![sub(x.clone())]),
+                    same_subject(),
+                    CpdType::Or,
+                ),
+            )
+            .unwrap();
+        graph
+            .store_predicate_implication(
+                &mut connection,
+                &implication(
+                    conjunction(vec![over_thirty]),
+                    predicate(discount.clone(), vec![sub(x.clone())]),
+                    same_subject(),
+                ),
+            )
+            .unwrap();
+
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        for (name, age) in ages {
+            let someone = constant(person.clone(), name.to_string());
+            let target = proposition(adult.clone(), vec![sub(someone.clone())]);
+            let proposition_graph =
+                PropositionGraph::new_shared(&mut connection, &model.graph, target.clone()).unwrap();
+            let mut inferencer =
+                Inferencer::new_mutable(model.clone(), proposition_graph, HashMapBeliefTable::new()).unwrap();
+            inferencer.initialize_chart(&mut connection).unwrap();
+            for _ in 0..3 {
+                inferencer.do_full_forward_and_backward(&mut connection).unwrap();
+            }
+            let marginals = inferencer.build_marginal_table().unwrap();
+            let expected = if age.is_some_and(|age| age > 30.0) { 1.0 } else { 0.0 };
+            let test = proposition(
+                relation("age>30".to_string(), vec![variable_argument(person.clone())]),
+                vec![sub(someone)],
+            );
+            assert!((marginals.get_marginal(&test).unwrap() - expected).abs() < 1e-9, "{}", name);
+            assert!((marginals.get_marginal(&target).unwrap() - expected).abs() < 1e-9, "{}", name);
+        }
+
+        // The learned rule sees how far past the threshold the age is.
+        let alice_discount = proposition(discount, vec![sub(constant(person.clone(), "alice".to_string()))]);
+        let factors = extract_backimplications_from_proposition(&mut connection, &graph, &alice_discount).unwrap();
+        let test = factors[0].premise.terms[0].clone();
+        let context = FactorContext {
+            factor: factors,
+            probabilities: vec![1.0],
+            numeric_values: HashMap::from([(test, 45.0)]),
+        };
+        let feature = context.factor[0].inference.unique_key();
+        let features = features_from_factor(&context).unwrap();
+        assert_eq!(features[1][&numeric_feature(&feature, 0, "linear", 1)], 0.5);
+        assert_eq!(features[1][&numeric_feature(&feature, 0, "bin1", 1)], 1.0);
+        assert!(features[0].contains_key(&numeric_feature(&feature, 0, "linear", 0)));
+    }
+}
@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod test_optimizers {
+    use bayeslog::qbbn::{
+        common::{
+            interface::ScenarioMaker,
+            resources::ResourceContext,
+            train::{do_training_epochs, TrainerConfig},
+        },
+        model::{
+            config::{ExponentialTrainingConfig, LearningRateSchedule, Optimizer},
+            optimizer::OptimizerState,
+        },
+        scenarios::dating_simple::SimpleDating,
+    };
+    use std::collections::HashMap;
+
+    fn features(values: &[(&str, f64)]) -> HashMap<String, f64> {
+        values.iter().map(|(name, value)| (name.to_string(), *value)).collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_learning_rate_schedules() {
+        let mut config = ExponentialTrainingConfig {
+            learning_rate: 0.1,
+            ..ExponentialTrainingConfig::default()
+        };
+        assert!(close(config.learning_rate_at(100), 0.1));
+        config.schedule = LearningRateSchedule::Step { every: 10, factor: 0.5 };
+        assert!(close(config.learning_rate_at(9), 0.1));
+        assert!(close(config.learning_rate_at(10), 0.05));
+        assert!(close(config.learning_rate_at(25), 0.025));
+        config.schedule = LearningRateSchedule::InverseTime { decay: 1.0 };
+        assert!(close(config.learning_rate_at(0), 0.1));
+        assert!(close(config.learning_rate_at(4), 0.02));
+    }
+
+    #[test]
+    fn test_default_is_plain_sgd() {
+        let config = ExponentialTrainingConfig::default();
+        let mut state = OptimizerState::default();
+        let weights = features(&[("a", 0.5), ("b", -0.2)]);
+        let gold = features(&[("a", 1.0), ("b", 0.0)]);
+        let expected = features(&[("a", 0.4), ("b", 0.3)]);
+        let updated = state.update(&config, &weights, &gold, &expected, false);
+        assert!(close(updated["a"], 0.5 + 0.05 * 0.6));
+        assert!(close(updated["b"], -0.2 - 0.05 * 0.3));
+    }
+
+    #[test]
+    fn test_momentum_accumulates() {
+        let config = ExponentialTrainingConfig {
+            learning_rate: 0.1,
+            optimizer: Optimizer::Sgd { momentum: 0.9 },
+            ..ExponentialTrainingConfig::default()
+        };
+        let mut state = OptimizerState::default();
+        let gold = features(&[("a", 1.0)]);
+        let expected = features(&[("a", 0.0)]);
+        let first = state.update(&config, &features(&[("a", 0.0)]), &gold, &expected, false);
+        state.advance();
+        let second = state.update(&config, &first, &gold, &expected, false);
+        assert!(close(first["a"], 0.1));
+        assert!(close(second["a"] - first["a"], 0.1 * 1.9));
+        assert_eq!(state.step(), 1);
+    }
+
+    #[test]
+    fn test_regularization_shrinks_weights() {
+        let weights = features(&[("a", 2.0), ("b", -2.0)]);
+        let neutral = features(&[("a", 0.5), ("b", 0.5)]);
+        let l2 = ExponentialTrainingConfig {
+            l2: 0.1,
+            ..ExponentialTrainingConfig::default()
+        };
+        let updated = OptimizerState::default().update(&l2, &weights, &neutral, &neutral, false);
+        assert!(close(updated["a"], 2.0 - 0.05 * 0.2));
+        assert!(close(updated["b"], -2.0 + 0.05 * 0.2));
+        let l1 = ExponentialTrainingConfig {
+            l1: 0.1,
+            ..ExponentialTrainingConfig::default()
+        };
+        let updated = OptimizerState::default().update(&l1, &weights, &neutral, &neutral, false);
+        assert!(close(updated["a"], 2.0 - 0.05 * 0.1));
+        assert!(close(updated["b"], -2.0 + 0.05 * 0.1));
+    }
+
+    #[test]
+    fn test_gradient_clipping() {
+        let config = ExponentialTrainingConfig {
+            learning_rate: 1.0,
+            gradient_clip: Some(1.0),
+            ..ExponentialTrainingConfig::default()
+        };
+        let weights = features(&[("a", 0.0), ("b", 0.0)]);
+        let gold = features(&[("a", 3.0), ("b", 4.0)]);
+        let expected = features(&[("a", 0.0), ("b", 0.0)]);
+        let updated = OptimizerState::default().update(&config, &weights, &gold, &expected, false);
+        assert!(close(updated["a"], 0.6));
+        assert!(close(updated["b"], 0.8));
+    }
+
+    #[test]
+    fn test_adaptive_first_step_has_learning_rate_size() {
+        let weights = features(&[("a", 0.0), ("b", 0.0)]);
+        let gold = features(&[("a", 0.9), ("b", 0.0)]);
+        let expected = features(&[("a", 0.1), ("b", 0.01)]);
+        for optimizer in [
+            Optimizer::AdaGrad { epsilon: 1e-12 },
+            Optimizer::Adam { beta1: 0.9, beta2: 0.999, epsilon: 1e-12 },
+        ] {
+            let config = ExponentialTrainingConfig {
+                learning_rate: 0.01,
+                optimizer,
+                ..ExponentialTrainingConfig::default()
+            };
+            let updated = OptimizerState::default().update(&config, &weights, &gold, &expected, false);
+            assert!((updated["a"] - 0.01).abs() < 1e-6, "{:?} {:?}", optimizer, updated);
+            assert!((updated["b"] + 0.01).abs() < 1e-6, "{:?} {:?}", optimizer, updated);
+        }
+    }
+
+    #[test]
+    fn test_adam_bias_correction_is_per_feature() {
+        let config = ExponentialTrainingConfig {
+            learning_rate: 0.01,
+            optimizer: Optimizer::Adam { beta1: 0.9, beta2: 0.999, epsilon: 1e-12 },
+            ..ExponentialTrainingConfig::default()
+        };
+        let mut state = OptimizerState::default();
+        let mut weights = features(&[("a", 0.0)]);
+        for _ in 0..20 {
+            weights = state.update(&config, &weights, &features(&[("a", 1.0)]), &features(&[]), false);
+            state.advance();
+        }
+        // A feature first seen late takes a full first step, as it would in a fresh run.
+        weights.insert("b".to_string(), 0.0);
+        let gold = features(&[("a", 1.0), ("b", 0.5)]);
+        let updated = state.update(&config, &weights, &gold, &features(&[]), false);
+        assert!((updated["b"] - 0.01).abs() < 1e-6, "{:?}", updated);
+        assert_eq!(state.step(), 20);
+    }
+
+    #[test]
+    fn test_epoch_training_with_adam_and_l2() {
+        let mut resources = ResourceContext::new_in_memory("dating_simple").unwrap();
+        resources.config.entities_per_domain = 6;
+        SimpleDating {}.setup_scenario(&resources).unwrap();
+        let config = TrainerConfig {
+            max_epochs: 4,
+            early_stopping_patience: 4,
+            model: ExponentialTrainingConfig {
+                learning_rate: 0.05,
+                schedule: LearningRateSchedule::InverseTime { decay: 0.01 },
+                optimizer: Optimizer::Adam { beta1: 0.9, beta2: 0.999, epsilon: 1e-8 },
+                l2: 0.01,
+                gradient_clip: Some(1.0),
+                ..ExponentialTrainingConfig::default()
+            },
+            ..TrainerConfig::default()
+        };
+        let history = do_training_epochs(&resources, "dating_simple".to_string(), &config).unwrap();
+        assert_eq!(history.epochs.len(), 4);
+        let first = history.epochs.first().unwrap().training_loss;
+        let last = history.epochs.last().unwrap().training_loss;
+        assert!(last.is_finite());
+        assert!(last < first, "{} {}", first, last);
+    }
+}
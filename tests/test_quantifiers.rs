@@ -0,0 +1,249 @@
+#[cfg(test)]
+mod test_quantifiers {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph,
+            interface::BeliefTable,
+            model::InferenceModel,
+            proposition_db::HashMapBeliefTable,
+            resources::ResourceContext,
+            train::term_probability_for_training,
+        },
+        inference::{
+            engine::Inferencer,
+            graph::PropositionGraph,
+            mpe::{MapConfig, MapMethod},
+            query::{QueryConfig, QueryMethod},
+            table::PropositionNode,
+        },
+        model::{
+            choose::extract_backimplications_from_proposition,
+            creators::{
+                conjunction, constant, implication_with_cpd, obj, predicate, proposition,
+                quantified, relation, sub, variable, variable_argument,
+            },
+            objects::{CpdType, Entity, Proposition, Quantifier, QuantifierKind, RoleMap},
+        },
+    };
+    use std::collections::HashMap;
+
+    const PEOPLE: [&str; 3] = ["alice", "bob", "carol"];
+
+    #[test]
+    fn test_quantified_premises_aggregate_instances() {
+        let namespace = "quantifiers";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let connection = &mut *connection;
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        let person = "Person".to_string();
+        graph.register_domain(connection, &person).unwrap();
+        graph.register_domain(connection, "Ghost").unwrap();
+        for name in PEOPLE {
+            graph
+                .store_entity(connection, &Entity { domain: person.clone(), name: name.to_string() })
+                .unwrap();
+        }
+        let likes = relation(
+            "likes".to_string(),
+            vec![variable_argument(person.clone()), variable_argument(person.clone())],
+        );
+        let popular = relation("popular".to_string(), vec![variable_argument(person.clone())]);
+        let friendly = relation("friendly".to_string(), vec![variable_argument(person.clone())]);
+        let haunted = relation("haunted".to_string(), vec![variable_argument(person.clone())]);
+        for relation in [&likes, &popular, &friendly, &haunted] {
+            graph.register_relation(connection, relation).unwrap();
+        }
+        let alice = constant(person.clone(), "alice".to_string());
+        let likes_alice = |name: &str| {
+            proposition(likes.clone(), vec![sub(alice.clone()), obj(constant(person.clone(), name.to_string()))])
+        };
+        // Give `likes` a parent, so its instances can take evidence.
+        graph.ensure_existence_backlinks_for_proposition(connection, &likes_alice("bob")).unwrap();
+
+        // Only the subject is mapped, so the object is quantified.
+        let x = variable(person.clone());
+        let y = variable(person.clone());
+        let subject = || vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))];
+        let exists = Quantifier::exists(0.9, 0.05);
+        let for_all = Quantifier::for_all(0.95, 0.2);
+        let some_liked = quantified(exists, predicate(likes.clone(), vec![sub(x.clone()), obj(y.clone())]));
+        let all_liked = quantified(for_all, predicate(likes.clone(), vec![sub(x.clone()), obj(y.clone())]));
+        for (premise, conclusion) in [(&some_liked, &popular), (&all_liked, &friendly)] {
+            let rule = implication_with_cpd(
+                conjunction(vec![premise.clone()]),
+                predicate(conclusion.clone(), vec![sub(x.clone())]),
+                subject(),
+                CpdType::Or,
+            );
+            graph.store_predicate_implication(connection, &rule).unwrap();
+        }
+        assert_eq!(
+            Quantifier::from_relation(&some_liked.relation),
+            Some((exists, likes.clone()))
+        );
+        assert_eq!(Quantifier::from_relation(&for_all.aggregate_relation(&likes)).unwrap().0.kind, QuantifierKind::ForAll);
+        assert!(Quantifier::from_relation(&likes).is_none());
+
+        // The aggregate is a node of its own, over every person alice might like.
+        let popular_alice = proposition(popular.clone(), vec![sub(alice.clone())]);
+        let factors = extract_backimplications_from_proposition(connection, &graph, &popular_alice).unwrap();
+        let aggregate: Proposition = factors[0].premise.terms[0].clone();
+        let instances = extract_backimplications_from_proposition(connection, &graph, &aggregate).unwrap();
+        assert_eq!(instances.len(), 1);
+        let mut names: Vec<Proposition> = instances[0].premise.terms.clone();
+        names.sort_by_key(|instance| instance.hash_string());
+        assert_eq!(names, PEOPLE.iter().map(|name| likes_alice(name)).collect::<Vec<_>>());
+
+        let fact_memory = HashMapBeliefTable::new();
+        for name in PEOPLE {
+            let value = if name == "bob" { 1.0 } else { 0.0 };
+            fact_memory.store_proposition_probability(connection, &likes_alice(name), value).unwrap();
+        }
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let friendly_alice = proposition(friendly.clone(), vec![sub(alice.clone())]);
+        let marginal = |connection: &mut _, target: &Proposition| {
+            let proposition_graph =
+                PropositionGraph::new_shared(connection, &model.graph, target.clone()).unwrap();
+            assert!(proposition_graph.all_nodes.iter().any(|node| {
+                *node != PropositionNode::from_single(target)
+                    && node.is_single()
+                    && Quantifier::from_relation(&node.extract_single().predicate.relation).is_some()
+            }));
+            let mut inferencer =
+                Inferencer::new_mutable(model.clone(), proposition_graph, fact_memory.clone()).unwrap();
+            inferencer.initialize_chart(connection).unwrap();
+            for _ in 0..3 {
+                inferencer.do_full_forward_and_backward(connection).unwrap();
+            }
+            inferencer.build_marginal_table().unwrap().get_marginal(target).unwrap()
+        };
+        // One of three liked: noisy-OR fires once, noisy-AND misses twice.
+        let some = 1.0 - 0.95 * 0.1;
+        assert!((marginal(connection, &popular_alice) - some).abs() < 1e-9);
+        assert!((marginal(connection, &friendly_alice) - 0.95 * 0.2 * 0.2).abs() < 1e-9);
+        let trained = term_probability_for_training(connection, fact_memory.as_ref(), &graph, &aggregate).unwrap();
+        assert!((trained.unwrap() - some).abs() < 1e-9);
+
+        // Enumeration scores the aggregate's CPD in closed form, and agrees.
+        let proposition_graph =
+            PropositionGraph::new_shared(connection, &model.graph, popular_alice.clone()).unwrap();
+        let inferencer = Inferencer::new_mutable(model.clone(), proposition_graph, fact_memory.clone()).unwrap();
+        let exact = inferencer
+            .joint_probability(connection, &[(popular_alice.clone(), true)], &QueryConfig::default())
+            .unwrap();
+        assert_eq!(exact.method, QueryMethod::Exact);
+        assert!((exact.probability - some).abs() < 1e-9, "{}", exact.probability);
+
+        // Quantifying over an empty domain is an error, not a vacuous truth.
+        let ghost = variable("Ghost".to_string());
+        let rule = implication_with_cpd(
+            conjunction(vec![quantified(exists, predicate(likes.clone(), vec![sub(x.clone()), obj(ghost)]))]),
+            predicate(haunted.clone(), vec![sub(x.clone())]),
+            subject(),
+            CpdType::Or,
+        );
+        graph.store_predicate_implication(connection, &rule).unwrap();
+        let haunted_alice = proposition(haunted, vec![sub(alice.clone())]);
+        let factors = extract_backimplications_from_proposition(connection, &graph, &haunted_alice).unwrap();
+        assert!(extract_backimplications_from_proposition(connection, &graph, &factors[0].premise.terms[0]).is_err());
+    }
+
+    #[test]
+    fn test_quantifiers_over_a_large_domain() {
+        let namespace = "quantifiers_large_domain";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let connection = &mut *connection;
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        let person = "Person".to_string();
+        graph.register_domain(connection, &person).unwrap();
+        let people: Vec<String> = (0..64).map(|index| format!("person{}", index)).collect();
+        for name in &people {
+            graph
+                .store_entity(connection, &Entity { domain: person.clone(), name: name.clone() })
+                .unwrap();
+        }
+        let likes = relation(
+            "likes".to_string(),
+            vec![variable_argument(person.clone()), variable_argument(person.clone())],
+        );
+        let popular = relation("popular".to_string(), vec![variable_argument(person.clone())]);
+        let friendly = relation("friendly".to_string(), vec![variable_argument(person.clone())]);
+        let celebrity = relation("celebrity".to_string(), vec![variable_argument(person.clone())]);
+        for relation in [&likes, &popular, &friendly, &celebrity] {
+            graph.register_relation(connection, relation).unwrap();
+        }
+        let first = constant(person.clone(), people[0].clone());
+        let likes_first = |name: &str| {
+            proposition(likes.clone(), vec![sub(first.clone()), obj(constant(person.clone(), name.to_string()))])
+        };
+        graph.ensure_existence_backlinks_for_proposition(connection, &likes_first(&people[1])).unwrap();
+
+        // Both quantifiers range over the same 64 instances, far too many to enumerate, and
+        // the target depends on both aggregates, so both are in one graph.
+        let x = variable(person.clone());
+        let y = variable(person.clone());
+        let subject = || vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))];
+        let exists = Quantifier::exists(0.9, 0.05);
+        let for_all = Quantifier::for_all(0.95, 0.2);
+        for (quantifier, conclusion) in [(exists, &popular), (for_all, &friendly)] {
+            let premise = quantified(quantifier, predicate(likes.clone(), vec![sub(x.clone()), obj(y.clone())]));
+            let rule = implication_with_cpd(
+                conjunction(vec![premise]),
+                predicate(conclusion.clone(), vec![sub(x.clone())]),
+                subject(),
+                CpdType::Or,
+            );
+            graph.store_predicate_implication(connection, &rule).unwrap();
+        }
+        let both = implication_with_cpd(
+            conjunction(vec![
+                predicate(popular.clone(), vec![sub(x.clone())]),
+                predicate(friendly.clone(), vec![sub(x.clone())]),
+            ]),
+            predicate(celebrity.clone(), vec![sub(x.clone())]),
+            vec![subject()[0].clone(), subject()[0].clone()],
+            CpdType::And,
+        );
+        graph.store_predicate_implication(connection, &both).unwrap();
+
+        let fact_memory = HashMapBeliefTable::new();
+        let mut probabilities = vec![];
+        for (index, name) in people.iter().enumerate() {
+            let probability = (index % 7) as f64 / 10.0;
+            fact_memory.store_proposition_probability(connection, &likes_first(name), probability).unwrap();
+            probabilities.push(probability);
+        }
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let target = proposition(celebrity, vec![sub(first.clone())]);
+        let proposition_graph = PropositionGraph::new_shared(connection, &model.graph, target).unwrap();
+        let instance_groups = proposition_graph
+            .all_nodes
+            .iter()
+            .filter(|node| node.is_group() && node.extract_group().terms.len() == people.len())
+            .count();
+        assert_eq!(instance_groups, 2);
+        let mut inferencer = Inferencer::new_mutable(model, proposition_graph, fact_memory).unwrap();
+        // The joint factor graph keeps each aggregate's CPD in closed form instead of
+        // tabulating its 2^64 premise assignments.
+        let joint = inferencer.build_joint_factor_graph(connection).unwrap();
+        assert!(joint.free_variables().len() > people.len());
+        let explanation = inferencer.top_k_explanations(connection, &MapConfig::default()).unwrap();
+        assert_eq!(explanation.method, MapMethod::MaxProduct);
+        assert!(explanation.assignments[0].log_score.is_finite());
+        inferencer.initialize_chart(connection).unwrap();
+        for _ in 0..3 {
+            inferencer.do_full_forward_and_backward(connection).unwrap();
+        }
+        let marginals = inferencer.build_marginal_table().unwrap();
+
+        // Each aggregate follows its own quantifier's CPD over the independent instances.
+        let some = 1.0 - 0.95 * probabilities.iter().map(|p| 1.0 - 0.9 * p).product::<f64>();
+        let all = 0.95 * probabilities.iter().map(|p| p + (1.0 - p) * 0.2).product::<f64>();
+        let popular_first = proposition(popular, vec![sub(first.clone())]);
+        let friendly_first = proposition(friendly, vec![sub(first.clone())]);
+        assert!((marginals.get_marginal(&popular_first).unwrap() - some).abs() < 1e-9);
+        assert!((marginals.get_marginal(&friendly_first).unwrap() - all).abs() < 1e-12);
+    }
+}
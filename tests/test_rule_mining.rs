@@ -0,0 +1,224 @@
+mod common;
+
+#[cfg(test)]
+mod test_rule_mining {
+    use super::common::{about, implies, people, unary};
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph,
+            proposition_db::RedisBeliefTable,
+            resources::ResourceContext,
+            rule_mining::{candidate_rules, do_rule_mining, RuleMiningConfig},
+            train::TrainingPlan,
+        },
+        model::{
+            choose::extract_backimplications_from_proposition,
+            creators::{constant, obj, predicate, proposition, relation, sub, variable, variable_argument},
+            objects::{Entity, ImplicationFactor, Proposition, Relation},
+        },
+    };
+
+    const NAMESPACE: &str = "rule_mining";
+    const ENTITIES: usize = 18;
+
+    fn binary(name: &str) -> Relation {
+        let person = || variable_argument("Person".to_string());
+        relation(name.to_string(), vec![person(), person()])
+    }
+
+    /// `target` equals `signal`, and `noise` is unrelated to both. A third of the
+    /// entities are held out.
+    fn setup() -> ResourceContext {
+        let [signal, noise, target] = ["signal", "noise", "target"].map(|name| unary(name, "Person"));
+        let resources = people(NAMESPACE, &[&signal, &noise, &target], &[], ENTITIES);
+        {
+            let mut connection = resources.connection.lock().unwrap();
+            let connection = &mut *connection;
+            let proposition_db = RedisBeliefTable::new_mutable(NAMESPACE.to_string()).unwrap();
+            let mut plan = TrainingPlan::new(NAMESPACE.to_string()).unwrap();
+            for i in 0..ENTITIES {
+                let value = if i % 2 == 0 { 1f64 } else { 0f64 };
+                let unrelated = if i % 3 == 0 { 1f64 } else { 0f64 };
+                proposition_db
+                    .store_proposition_probability(connection, &about(&signal, i), value)
+                    .unwrap();
+                proposition_db
+                    .store_proposition_probability(connection, &about(&noise, i), unrelated)
+                    .unwrap();
+                proposition_db
+                    .store_proposition_probability(connection, &about(&target, i), value)
+                    .unwrap();
+                plan.maybe_add_to_training(connection, i % 3 != 2, &about(&target, i))
+                    .unwrap();
+                plan.maybe_add_to_test(connection, i % 3 == 2, &about(&target, i))
+                    .unwrap();
+            }
+        }
+        resources
+    }
+
+    fn config(insert: bool) -> RuleMiningConfig {
+        let mut config = RuleMiningConfig {
+            max_premise_terms: 1,
+            conclusions: Some(vec!["target".to_string()]),
+            insert,
+            ..RuleMiningConfig::default()
+        };
+        config.trainer.model.learning_rate = 0.5;
+        config
+    }
+
+    #[test]
+    fn test_candidate_rules() {
+        let [signal, noise, target] = ["signal", "noise", "target"].map(|name| unary(name, "Person"));
+        let relations = vec![signal.clone(), noise.clone(), target.clone()];
+        let only_target = RuleMiningConfig {
+            conclusions: Some(vec!["target".to_string()]),
+            ..RuleMiningConfig::default()
+        };
+        // signal, noise, and both together.
+        assert_eq!(candidate_rules(&relations, &[], &only_target).len(), 3);
+        let existing = vec![implies(&signal, &target), implies(&target, &noise)];
+        let candidates = candidate_rules(&relations, &existing, &only_target);
+        // signal is known, and noise would close a cycle through target.
+        assert!(candidates.is_empty(), "{:?}", candidates);
+
+        // A binary premise binds to a binary conclusion both ways round.
+        let relations = vec![binary("friend"), binary("likes")];
+        let only_likes = RuleMiningConfig {
+            conclusions: Some(vec!["likes".to_string()]),
+            max_premise_terms: 1,
+            ..RuleMiningConfig::default()
+        };
+        let candidates = candidate_rules(&relations, &[], &only_likes);
+        assert_eq!(candidates.len(), 2);
+        assert_ne!(candidates[0].unique_key(), candidates[1].unique_key());
+    }
+
+    /// The role maps of `rule`'s terms, as sorted pairs of a variable and a premise role.
+    fn role_maps(rule: &ImplicationFactor) -> Vec<Vec<(String, String)>> {
+        let mut result: Vec<_> = rule.role_maps.role_maps.iter().map(|role_map| role_map.role_map.clone()).collect();
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn test_join_variables_link_premise_terms() {
+        let parent = binary("parent");
+        let grandparent = binary("grandparent");
+        let relations = vec![parent.clone(), grandparent.clone()];
+        let only_grandparent = |max_join_variables| RuleMiningConfig {
+            conclusions: Some(vec!["grandparent".to_string()]),
+            max_join_variables,
+            ..RuleMiningConfig::default()
+        };
+        let pair = |variable: &str, role: &str| (variable.to_string(), role.to_string());
+        let chain = vec![
+            vec![pair("join0", "obj"), pair("sub", "sub")],
+            vec![pair("join0", "sub"), pair("obj", "obj")],
+        ];
+        let candidates = candidate_rules(&relations, &[], &only_grandparent(1));
+        let rule = candidates
+            .iter()
+            .find(|candidate| role_maps(candidate) == chain)
+            .expect("parent(x, y) & parent(y, z) -> grandparent(x, z) is a candidate")
+            .clone();
+        // Every join variable links two terms, and no rule comes up twice.
+        for candidate in &candidates {
+            let joined = role_maps(candidate)
+                .iter()
+                .filter(|role_map| role_map.iter().any(|(variable, _)| variable == "join0"))
+                .count();
+            assert!(joined == 0 || joined == 2, "{:?}", candidate);
+        }
+        let mut keys: Vec<String> = candidates.iter().map(|candidate| candidate.unique_key()).collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), candidates.len());
+        let without_joins = candidate_rules(&relations, &[], &only_grandparent(0));
+        assert!(without_joins.len() < candidates.len());
+        assert!(without_joins.iter().all(|candidate| role_maps(candidate) != chain));
+
+        // The rule is grounded once for every person in the middle.
+        let namespace = "rule_mining_joins";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let connection = &mut *connection;
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        let person = "Person".to_string();
+        graph.register_domain(connection, &person).unwrap();
+        let people = ["alice", "bob", "carol"];
+        for name in people {
+            graph
+                .store_entity(connection, &Entity { domain: person.clone(), name: name.to_string() })
+                .unwrap();
+        }
+        for relation in &relations {
+            graph.register_relation(connection, relation).unwrap();
+        }
+        graph.store_predicate_implication(connection, &rule).unwrap();
+        let named = |name: &str| constant(person.clone(), name.to_string());
+        let alice_carol = proposition(grandparent.clone(), vec![sub(named("alice")), obj(named("carol"))]);
+        let factors = extract_backimplications_from_proposition(connection, &graph, &alice_carol).unwrap();
+        let sorted = |mut terms: Vec<Proposition>| {
+            terms.sort_by_key(|term| term.hash_string());
+            terms
+        };
+        let mut grounded: Vec<Vec<Proposition>> =
+            factors.iter().map(|factor| sorted(factor.premise.terms.clone())).collect();
+        grounded.sort_by_key(|terms| format!("{:?}", terms));
+        let mut expected: Vec<Vec<Proposition>> = people
+            .iter()
+            .map(|name| {
+                sorted(vec![
+                    proposition(parent.clone(), vec![sub(named("alice")), obj(named(name))]),
+                    proposition(parent.clone(), vec![sub(named(name)), obj(named("carol"))]),
+                ])
+            })
+            .collect();
+        expected.sort_by_key(|terms| format!("{:?}", terms));
+        assert_eq!(grounded, expected);
+    }
+
+    #[test]
+    fn test_dry_run_reports_best_rule_and_leaves_graph() {
+        let resources = setup();
+        let target = predicate(unary("target", "Person"), vec![sub(variable("Person".to_string()))]);
+        let stored = |resources: &ResourceContext| {
+            let mut connection = resources.connection.lock().unwrap();
+            let graph = InferenceGraph::new_mutable(NAMESPACE.to_string()).unwrap();
+            (
+                graph.get_all_implications(&mut connection).unwrap().len(),
+                graph.predicate_backward_links(&mut connection, &target).unwrap().len(),
+            )
+        };
+        let before = stored(&resources);
+        let report = do_rule_mining(&resources, NAMESPACE.to_string(), &config(false)).unwrap();
+        assert_eq!(report.candidates, 2);
+        assert_eq!(report.unscorable, 0);
+        let best = report.rules.first().unwrap();
+        assert_eq!(best.rule.premise.terms[0].relation.relation_name, "signal");
+        assert!(best.improvement() > 0.1, "{}", report.render_text());
+        assert!(best.held_out_examples > 0);
+        assert!(report.inserted.is_empty());
+        assert_eq!(stored(&resources), before);
+    }
+
+    #[test]
+    fn test_insert_stores_reported_rules() {
+        let resources = setup();
+        let report = do_rule_mining(&resources, NAMESPACE.to_string(), &config(true)).unwrap();
+        assert!(!report.inserted.is_empty());
+        let mut connection = resources.connection.lock().unwrap();
+        let graph = InferenceGraph::new_mutable(NAMESPACE.to_string()).unwrap();
+        let stored: Vec<String> = graph
+            .get_all_implications(&mut connection)
+            .unwrap()
+            .iter()
+            .map(|implication| implication.unique_key())
+            .collect();
+        for rule in &report.inserted {
+            assert!(stored.contains(&rule.unique_key()));
+        }
+    }
+}
@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod test_seeded_training {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph,
+            interface::ScenarioMaker,
+            model::InferenceModel,
+            proposition_db::HashMapBeliefTable,
+            resources::ResourceContext,
+            train::{do_training_epochs, TrainerConfig},
+        },
+        inference::{engine::Inferencer, graph::PropositionGraph},
+        model::weights::{negative_feature, positive_feature, ExponentialWeights, CLASS_LABELS},
+        scenarios::dating_simple::SimpleDating,
+    };
+    use std::collections::HashMap;
+
+    const NAMESPACE: &str = "dating_simple";
+
+    /// Sets up and trains a fresh scenario with `seed`, returning the training loss
+    /// of each epoch and the stored weights.
+    fn train(seed: u64) -> (Vec<f64>, HashMap<String, f64>) {
+        let mut resources = ResourceContext::new_in_memory(NAMESPACE).unwrap();
+        resources.config.entities_per_domain = 6;
+        resources.config.seed = Some(seed);
+        SimpleDating {}.setup_scenario(&resources).unwrap();
+        let config = TrainerConfig {
+            max_epochs: 3,
+            shuffle: true,
+            ..TrainerConfig::default()
+        };
+        let history = do_training_epochs(&resources, NAMESPACE.to_string(), &config).unwrap();
+        let losses = history.epochs.iter().map(|report| report.training_loss).collect();
+        let mut connection = resources.connection.lock().unwrap();
+        let graph = InferenceGraph::new_mutable(NAMESPACE.to_string()).unwrap();
+        let features: Vec<String> = graph
+            .get_all_implications(&mut connection)
+            .unwrap()
+            .iter()
+            .flat_map(|implication| {
+                let key = implication.unique_key();
+                CLASS_LABELS.into_iter().flat_map(move |class_label| {
+                    [positive_feature(&key, class_label), negative_feature(&key, class_label)]
+                })
+            })
+            .collect();
+        let weights = ExponentialWeights::new(NAMESPACE.to_string())
+            .unwrap()
+            .read_weight_vector(&mut connection, &features)
+            .unwrap();
+        (losses, weights)
+    }
+
+    /// Sets up and trains a fresh scenario with `seed`, then runs inference for its
+    /// target, returning the marginals in BFS order.
+    fn marginals(seed: u64) -> Vec<(String, f64)> {
+        let mut resources = ResourceContext::new_in_memory(NAMESPACE).unwrap();
+        resources.config.entities_per_domain = 6;
+        resources.config.seed = Some(seed);
+        SimpleDating {}.setup_scenario(&resources).unwrap();
+        let config = TrainerConfig {
+            max_epochs: 1,
+            ..TrainerConfig::default()
+        };
+        do_training_epochs(&resources, NAMESPACE.to_string(), &config).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let model = InferenceModel::new_shared(NAMESPACE.to_string()).unwrap();
+        let target = model.graph.get_target(&mut connection).unwrap();
+        let graph = PropositionGraph::new_shared(&mut connection, &model.graph, target).unwrap();
+        let mut inferencer = Inferencer::new_mutable(model, graph, HashMapBeliefTable::new()).unwrap();
+        inferencer.initialize_chart(&mut connection).unwrap();
+        inferencer.do_full_forward_and_backward(&mut connection).unwrap();
+        inferencer.build_marginal_table().unwrap().entries
+    }
+
+    #[test]
+    fn test_same_seed_is_bit_identical() {
+        let (first_losses, first_weights) = train(7);
+        let (second_losses, second_weights) = train(7);
+        assert!(!first_weights.is_empty());
+        assert_eq!(first_losses, second_losses);
+        assert_eq!(first_weights, second_weights);
+    }
+
+    #[test]
+    fn test_different_seed_differs() {
+        let (first_losses, first_weights) = train(7);
+        let (second_losses, second_weights) = train(8);
+        assert!(first_losses != second_losses || first_weights != second_weights);
+    }
+
+    #[test]
+    fn test_same_seed_gives_identical_marginals() {
+        let first = marginals(7);
+        let second = marginals(7);
+        assert!(first.len() > 1);
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,155 @@
+#[cfg(test)]
+mod test_sensitivity {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph,
+            interface::BeliefTable,
+            model::{FactorContext, InferenceModel},
+            proposition_db::HashMapBeliefTable,
+            resources::ResourceContext,
+        },
+        inference::{
+            engine::Inferencer,
+            graph::PropositionGraph,
+            sensitivity::{SensitivityConfig, SensitivityEntry, SensitivitySource},
+        },
+        model::{
+            choose::extract_backimplications_from_proposition,
+            creators::{
+                conjunction, constant, implication, implication_with_cpd, predicate, proposition,
+                relation, sub, variable, variable_argument,
+            },
+            objects::{CpdType, Entity, Proposition, Relation, RoleMap},
+            exponential::ExponentialModel,
+            weights::ExponentialWeights,
+        },
+    };
+    use std::{collections::HashMap, sync::Arc};
+
+    fn assert_ranked(entries: &[SensitivityEntry]) {
+        for pair in entries.windows(2) {
+            assert!(pair[0].derivative.abs() >= pair[1].derivative.abs());
+        }
+    }
+
+    #[test]
+    fn test_sensitivity_to_evidence_and_weights() {
+        let namespace = "sensitivity";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let connection = &mut *connection;
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        let person = "Person".to_string();
+        graph.register_domain(connection, &person).unwrap();
+        graph
+            .store_entity(connection, &Entity { domain: person.clone(), name: "alice".to_string() })
+            .unwrap();
+        let unary = |name: &str| relation(name.to_string(), vec![variable_argument(person.clone())]);
+        let [cause, middle, happy, skilled, rich] =
+            ["cause", "middle", "happy", "skilled", "rich"].map(unary);
+        for relation in [&cause, &middle, &happy, &skilled, &rich] {
+            graph.register_relation(connection, relation).unwrap();
+        }
+        let x = variable(person.clone());
+        let subject = || vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))];
+        let rule = |premise, conclusion, cpd| {
+            implication_with_cpd(
+                conjunction(vec![predicate(premise, vec![sub(x.clone())])]),
+                predicate(conclusion, vec![sub(x.clone())]),
+                subject(),
+                cpd,
+            )
+        };
+        // cause -> middle -> happy, all built-in; skilled -> rich is learned.
+        graph.store_predicate_implication(connection, &rule(cause.clone(), middle.clone(), CpdType::Or)).unwrap();
+        let noisy = CpdType::NoisyOr { strength: 0.8, leak: 0.1 };
+        graph.store_predicate_implication(connection, &rule(middle.clone(), happy.clone(), noisy)).unwrap();
+        let learned = implication(
+            conjunction(vec![predicate(skilled.clone(), vec![sub(x.clone())])]),
+            predicate(rich.clone(), vec![sub(x.clone())]),
+            subject(),
+        );
+        graph.store_predicate_implication(connection, &learned).unwrap();
+
+        let alice = constant(person.clone(), "alice".to_string());
+        let about_alice = |relation: &Relation| proposition(relation.clone(), vec![sub(alice.clone())]);
+        let middle_alice: Proposition = about_alice(&middle);
+        let fact_memory = HashMapBeliefTable::new();
+        fact_memory.store_proposition_probability(connection, &middle_alice, 0.5).unwrap();
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let inferencer = |connection: &mut _, target: &Proposition| {
+            let proposition_graph =
+                PropositionGraph::new_shared(connection, &model.graph, target.clone()).unwrap();
+            Inferencer::new_mutable(model.clone(), proposition_graph, fact_memory.clone()).unwrap()
+        };
+        let config = SensitivityConfig::default();
+
+        // P(happy) = 1 - (1 - 0.8 * P(middle)) * (1 - 0.1), so the derivative is 0.72.
+        let happy_alice = about_alice(&happy);
+        let mut happy_inferencer = inferencer(connection, &happy_alice);
+        let report = happy_inferencer.sensitivity(connection, &happy_alice, &config).unwrap();
+        assert!((report.marginal - 0.46).abs() < 1e-9);
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].source, SensitivitySource::Evidence(middle_alice.clone()));
+        assert!((report.entries[0].derivative - 0.72).abs() < 1e-9);
+        assert_eq!(fact_memory.get_proposition_probability(connection, &middle_alice).unwrap(), Some(0.5));
+
+        // Every weight the learned factor reads is reported, and none is written.
+        let rich_alice = about_alice(&rich);
+        let mut rich_inferencer = inferencer(connection, &rich_alice);
+        let features = rich_inferencer.model_features(connection).unwrap();
+        assert!(!features.is_empty());
+        let weights = ExponentialWeights::new(namespace.to_string()).unwrap();
+        let before = weights.read_weight_vector(connection, &features).unwrap();
+        let version = model.model.weight_version(connection);
+        let report = rich_inferencer.sensitivity(connection, &rich_alice, &config).unwrap();
+        assert_eq!(report.entries.len(), features.len());
+        assert_ranked(&report.entries);
+        assert!(report.entries[0].derivative.abs() > 0.0);
+        assert_eq!(weights.read_weight_vector(connection, &features).unwrap(), before);
+        assert_eq!(model.model.weight_version(connection), version);
+        let after = rich_inferencer.build_marginal_table().unwrap().get_marginal(&rich_alice).unwrap();
+        assert!((after - report.marginal).abs() < 1e-9);
+        assert!(report.render_sensitivity_table().contains(&features[0]));
+
+        // A run that fails leaves the evidence and weights as they were.
+        assert!(happy_inferencer.evidence_sensitivity(connection, &rich_alice, &config).is_err());
+        assert_eq!(fact_memory.get_proposition_probability(connection, &middle_alice).unwrap(), Some(0.5));
+        assert!(rich_inferencer.weight_sensitivity(connection, &happy_alice, &config).is_err());
+        assert_eq!(weights.read_weight_vector(connection, &features).unwrap(), before);
+
+        // Weights are reported as the model predicts with them, unconsolidated deltas included.
+        let mut online = ExponentialModel::new_mutable(namespace.to_string()).unwrap();
+        let context = FactorContext {
+            factor: extract_backimplications_from_proposition(connection, &graph, &rich_alice).unwrap(),
+            probabilities: vec![1.0],
+            numeric_values: HashMap::new(),
+        };
+        for _ in 0..5 {
+            online.train(connection, &context, 1.0).unwrap();
+        }
+        let online = InferenceModel {
+            graph: model.graph.clone(),
+            model: Arc::from(online),
+        };
+        let effective: Vec<f64> = features
+            .iter()
+            .map(|feature| online.model.read_weight(connection, feature).unwrap())
+            .collect();
+        let proposition_graph = PropositionGraph::new_shared(connection, &online.graph, rich_alice.clone()).unwrap();
+        let mut online_inferencer =
+            Inferencer::new_mutable(Arc::new(online), proposition_graph, fact_memory.clone()).unwrap();
+        let entries = online_inferencer.weight_sensitivity(connection, &rich_alice, &config).unwrap();
+        assert_eq!(weights.read_weight_vector(connection, &features).unwrap(), before);
+        let mut moved = 0;
+        for entry in &entries {
+            let SensitivitySource::Weight(feature) = &entry.source else { unreachable!() };
+            let index = features.iter().position(|candidate| *candidate == *feature).unwrap();
+            assert_eq!(entry.value, effective[index]);
+            if (entry.value - before[feature.as_str()]).abs() > 1e-6 {
+                moved += 1;
+            }
+        }
+        assert!(moved > 0);
+    }
+}
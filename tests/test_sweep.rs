@@ -0,0 +1,153 @@
+#[cfg(test)]
+mod test_sweep {
+    use bayeslog::qbbn::common::sweep::{
+        best_trial, run_sweep, ParameterSpace, SearchStrategy, SweepSpec, TrialConfig,
+    };
+    use std::collections::BTreeMap;
+    use std::io::Write;
+
+    fn spec(search: SearchStrategy, parameters: &[(&str, ParameterSpace)]) -> SweepSpec {
+        SweepSpec {
+            scenario: "dating_simple".to_string(),
+            search,
+            seed: Some(5),
+            parameters: parameters
+                .iter()
+                .map(|(name, space)| (name.to_string(), space.clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_grid_covers_every_combination() {
+        let grid = spec(
+            SearchStrategy::Grid,
+            &[
+                ("learning_rate", ParameterSpace::Values(vec![0.01, 0.1])),
+                ("l2", ParameterSpace::Values(vec![0.0, 0.1, 1.0])),
+            ],
+        );
+        let trials = grid.trials().unwrap();
+        assert_eq!(trials.len(), 6);
+        assert_eq!(trials[0], BTreeMap::from([("l2".to_string(), 0.0), ("learning_rate".to_string(), 0.01)]));
+        assert_eq!(trials[5], BTreeMap::from([("l2".to_string(), 1.0), ("learning_rate".to_string(), 0.1)]));
+
+        let ranged = spec(
+            SearchStrategy::Grid,
+            &[("l2", ParameterSpace::Range { min: 0.0, max: 1.0, log: false })],
+        );
+        assert!(ranged.trials().is_err());
+    }
+
+    #[test]
+    fn test_random_search_is_seeded_and_in_range() {
+        let random = spec(
+            SearchStrategy::Random { trials: 20 },
+            &[
+                ("learning_rate", ParameterSpace::Range { min: 1e-3, max: 1e-1, log: true }),
+                ("max_epochs", ParameterSpace::Values(vec![2.0, 4.0])),
+            ],
+        );
+        let trials = random.trials().unwrap();
+        assert_eq!(trials.len(), 20);
+        assert_eq!(trials, random.trials().unwrap());
+        for trial in &trials {
+            assert!((1e-3..=1e-1).contains(&trial["learning_rate"]));
+            assert!(trial["max_epochs"] == 2.0 || trial["max_epochs"] == 4.0);
+        }
+    }
+
+    #[test]
+    fn test_spaces_without_values_are_rejected() {
+        let invalid = [
+            ParameterSpace::Values(vec![]),
+            ParameterSpace::Range { min: 0.5, max: 0.1, log: false },
+            ParameterSpace::Range { min: 0.0, max: 1.0, log: true },
+            ParameterSpace::Range { min: -1.0, max: 1.0, log: true },
+            ParameterSpace::Range { min: 0.0, max: f64::INFINITY, log: false },
+        ];
+        for space in invalid {
+            for search in [SearchStrategy::Grid, SearchStrategy::Random { trials: 3 }] {
+                assert!(spec(search, &[("l2", space.clone())]).trials().is_err(), "{:?}", space);
+            }
+        }
+
+        let mut json = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        write!(
+            json,
+            "{{\"scenario\": \"one_var\", \"search\": {{\"random\": {{\"trials\": 2}}}}, \"parameters\": {{\"l2\": {{\"min\": 1.0, \"max\": 0.0}}}}}}"
+        )
+        .unwrap();
+        assert!(SweepSpec::from_file(json.path().to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_parameters_and_spec_files() {
+        let parameters = BTreeMap::from([
+            ("entities_per_domain".to_string(), 7.0),
+            ("l1".to_string(), 0.5),
+            ("max_delta_size".to_string(), 100.0),
+        ]);
+        let config = TrialConfig::from_parameters(&parameters).unwrap();
+        assert_eq!(config.entities_per_domain, 7);
+        assert_eq!(config.trainer.model.l1, 0.5);
+        assert_eq!(config.weights.max_delta_size, 100);
+        let unknown = BTreeMap::from([("learning_speed".to_string(), 1.0)]);
+        assert!(TrialConfig::from_parameters(&unknown).is_err());
+
+        let mut toml = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        write!(
+            toml,
+            "scenario = \"one_var\"\nsearch = {{ random = {{ trials = 3 }} }}\n\n[parameters]\nl2 = [0.0, 0.1]\nlearning_rate = {{ min = 0.01, max = 0.1 }}\n"
+        )
+        .unwrap();
+        let parsed = SweepSpec::from_file(toml.path().to_str().unwrap()).unwrap();
+        assert_eq!(parsed.search, SearchStrategy::Random { trials: 3 });
+        assert_eq!(parsed.parameters["l2"], ParameterSpace::Values(vec![0.0, 0.1]));
+
+        let mut json = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        write!(
+            json,
+            "{{\"scenario\": \"one_var\", \"search\": \"grid\", \"seed\": 1, \"parameters\": {{\"l2\": [0.0]}}}}"
+        )
+        .unwrap();
+        let parsed = SweepSpec::from_file(json.path().to_str().unwrap()).unwrap();
+        assert_eq!(parsed.search, SearchStrategy::Grid);
+        assert_eq!(parsed.seed, Some(1));
+    }
+
+    #[test]
+    fn test_sweep_runs_isolated_trials() {
+        let grid = spec(
+            SearchStrategy::Grid,
+            &[
+                ("entities_per_domain", ParameterSpace::Values(vec![4.0])),
+                ("max_epochs", ParameterSpace::Values(vec![2.0])),
+                ("learning_rate", ParameterSpace::Values(vec![0.001, 0.1])),
+                ("passes", ParameterSpace::Values(vec![2.0])),
+            ],
+        );
+        let mut recorded = 0;
+        let results = run_sweep(&grid, |_| {
+            recorded += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(recorded, 2);
+        for result in &results {
+            assert!(result.error.is_none(), "{:?}", result.error);
+            assert_eq!(result.epochs, 2);
+            assert!(result.score.unwrap().is_finite());
+        }
+        // Each trial starts from the same seeded scenario and weights, so only the
+        // learning rate tells them apart.
+        assert_ne!(results[0].score, results[1].score);
+        let best = best_trial(&results).unwrap();
+        assert!(results.iter().all(|result| best.score <= result.score));
+
+        let failing = spec(SearchStrategy::Grid, &[("momentum_decay", ParameterSpace::Values(vec![0.5]))]);
+        let results = run_sweep(&failing, |_| Ok(())).unwrap();
+        assert!(results[0].error.is_some());
+        assert!(best_trial(&results).is_none());
+    }
+}
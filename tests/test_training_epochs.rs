@@ -0,0 +1,132 @@
+mod common;
+
+#[cfg(test)]
+mod test_training_epochs {
+    use super::common::dating_with_held_out_dates;
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph,
+            proposition_db::RedisBeliefTable,
+            resources::ResourceContext,
+            train::{
+                do_training_epochs, evaluate_examples, extract_test_examples, open_training_model,
+                train_epochs_with, TrainerConfig, TrainingPlan,
+            },
+        },
+        model::{config::ExponentialTrainingConfig, exponential::ExponentialModel},
+    };
+
+    const HELD_OUT: usize = 3;
+
+    fn setup() -> ResourceContext {
+        dating_with_held_out_dates("dating_simple", 8, None, HELD_OUT)
+    }
+
+    #[test]
+    fn test_keeps_weights_of_best_epoch() {
+        let resources = setup();
+        let config = TrainerConfig {
+            max_epochs: 6,
+            early_stopping_patience: 6,
+            min_delta: 0f64,
+            shuffle: true,
+            ..TrainerConfig::default()
+        };
+        let history = do_training_epochs(&resources, "dating_simple".to_string(), &config).unwrap();
+        assert_eq!(history.epochs.len(), 6);
+        assert!(!history.stopped_early);
+        for report in &history.epochs {
+            let held_out = report.held_out.unwrap();
+            assert_eq!(held_out.examples, HELD_OUT);
+            assert!(report.training_loss.is_finite() && report.training_loss > 0f64);
+            assert!((0f64..=1f64).contains(&held_out.brier));
+            assert!((0f64..=1f64).contains(&held_out.accuracy));
+        }
+        let best = history.best().unwrap();
+        for report in &history.epochs {
+            assert!(best.monitored_loss() <= report.monitored_loss());
+        }
+
+        // A fresh model reads the stored weights, which are the best epoch's.
+        let mut connection = resources.connection.lock().unwrap();
+        let graph = InferenceGraph::new_mutable("dating_simple".to_string()).unwrap();
+        let proposition_db = RedisBeliefTable::new_mutable("dating_simple".to_string()).unwrap();
+        let plan = TrainingPlan::new("dating_simple".to_string()).unwrap();
+        let test_questions = plan.get_test_questions(&mut connection).unwrap();
+        let test =
+            extract_test_examples(&mut connection, proposition_db.as_ref(), &graph, &test_questions)
+                .unwrap();
+        let model = ExponentialModel::new_mutable("dating_simple".to_string()).unwrap();
+        let metrics = evaluate_examples(&mut connection, model.as_ref(), &test).unwrap();
+        let expected = best.held_out.unwrap();
+        assert!((metrics.log_loss - expected.log_loss).abs() < 1e-9);
+        assert!((metrics.brier - expected.brier).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_stops_early_on_plateau() {
+        let resources = setup();
+        let config = TrainerConfig {
+            max_epochs: 20,
+            early_stopping_patience: 2,
+            min_delta: 10f64,
+            shuffle: false,
+            ..TrainerConfig::default()
+        };
+        let history = do_training_epochs(&resources, "dating_simple".to_string(), &config).unwrap();
+        assert!(history.stopped_early);
+        assert_eq!(history.epochs.len(), 3);
+        assert_eq!(history.best_epoch, 0);
+    }
+
+    #[test]
+    fn test_trains_in_batches() {
+        let resources = setup();
+        let config = TrainerConfig {
+            max_epochs: 3,
+            early_stopping_patience: 3,
+            batch_size: 4,
+            ..TrainerConfig::default()
+        };
+        let history = do_training_epochs(&resources, "dating_simple".to_string(), &config).unwrap();
+        assert_eq!(history.epochs.len(), 3);
+        for report in &history.epochs {
+            assert!(report.training_loss.is_finite() && report.training_loss > 0f64);
+            assert_eq!(report.held_out.unwrap().examples, HELD_OUT);
+        }
+    }
+
+    #[test]
+    fn test_diverged_epochs_are_never_best() {
+        let resources = setup();
+        let mut connection = resources.connection.lock().unwrap();
+        let graph = InferenceGraph::new_mutable("dating_simple".to_string()).unwrap();
+        let training = ExponentialTrainingConfig::default();
+        let mut model = open_training_model(&mut connection, &graph, "dating_simple", training).unwrap();
+        let config = TrainerConfig {
+            max_epochs: 6,
+            early_stopping_patience: 2,
+            min_delta: 0f64,
+            ..TrainerConfig::default()
+        };
+
+        // The epochs report these losses, without training.
+        let mut losses = [1.0, f64::NAN, 0.5, f64::NAN, f64::INFINITY, 0.1].into_iter();
+        let history = train_epochs_with(&mut connection, model.as_mut(), &[], &config, |_, _, _| {
+            Ok(losses.next().unwrap())
+        })
+        .unwrap();
+        assert!(history.stopped_early);
+        assert_eq!(history.epochs.len(), 5);
+        assert_eq!(history.best_epoch, 2);
+        assert_eq!(history.best().unwrap().training_loss, 0.5);
+
+        let mut losses = [f64::NAN, f64::NAN].into_iter();
+        let history = train_epochs_with(&mut connection, model.as_mut(), &[], &config, |_, _, _| {
+            Ok(losses.next().unwrap())
+        })
+        .unwrap();
+        assert!(history.stopped_early);
+        assert!(history.best().is_none());
+    }
+}
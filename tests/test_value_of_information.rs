@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod test_value_of_information {
+    use bayeslog::qbbn::{
+        common::{
+            graph::InferenceGraph, interface::BeliefTable, model::InferenceModel,
+            proposition_db::HashMapBeliefTable, resources::ResourceContext,
+        },
+        inference::{
+            engine::Inferencer,
+            graph::PropositionGraph,
+            query::QueryConfig,
+            value_of_information::{binary_entropy, QuestionConfig, QuestionFocus},
+        },
+        model::{
+            creators::{
+                conjunction, constant, implication_with_cpd, predicate, proposition, relation, sub,
+                variable, variable_argument,
+            },
+            objects::{CpdType, Entity, Proposition, Relation, RoleMap},
+        },
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_questions_ranked_by_value_of_information() {
+        assert_eq!(binary_entropy(0.5), 1.0);
+        assert_eq!(binary_entropy(1.0), 0.0);
+
+        let namespace = "value_of_information";
+        let resources = ResourceContext::new_in_memory(namespace).unwrap();
+        let mut connection = resources.connection.lock().unwrap();
+        let connection = &mut *connection;
+        let mut graph = InferenceGraph::new_mutable(namespace.to_string()).unwrap();
+        let person = "Person".to_string();
+        graph.register_domain(connection, &person).unwrap();
+        let alice = Entity { domain: person.clone(), name: "alice".to_string() };
+        graph.store_entity(connection, &alice).unwrap();
+        let unary = |name: &str| relation(name.to_string(), vec![variable_argument(person.clone())]);
+        let [trusted, connected, honest, lucky, promoted] =
+            ["is_trusted", "is_connected", "is_honest", "is_lucky", "gets_promoted"].map(unary);
+        for relation in [&trusted, &connected, &honest, &lucky, &promoted] {
+            graph.register_relation(connection, relation).unwrap();
+        }
+        let x = variable(person.clone());
+        let subject = || vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))];
+        let mut noisy = |premise: &Relation, conclusion: &Relation, strength, leak| {
+            let rule = implication_with_cpd(
+                conjunction(vec![predicate(premise.clone(), vec![sub(x.clone())])]),
+                predicate(conclusion.clone(), vec![sub(x.clone())]),
+                subject(),
+                CpdType::NoisyOr { strength, leak },
+            );
+            graph.store_predicate_implication(connection, &rule).unwrap();
+        };
+        noisy(&trusted, &honest, 0.7, 0.1);
+        noisy(&connected, &lucky, 0.5, 0.1);
+        noisy(&honest, &promoted, 0.9, 0.05);
+        noisy(&lucky, &promoted, 0.3, 0.05);
+
+        let about_alice =
+            |relation: &Relation| proposition(relation.clone(), vec![sub(constant(person.clone(), "alice".to_string()))]);
+        let [honest_alice, lucky_alice, promoted_alice]: [Proposition; 3] = [&honest, &lucky, &promoted].map(about_alice);
+        assert_eq!(honest_alice.natural_language(), "alice is honest");
+        let fact_memory = HashMapBeliefTable::new();
+        let model = InferenceModel::new_shared(namespace.to_string()).unwrap();
+        let proposition_graph =
+            PropositionGraph::new_shared(connection, &model.graph, promoted_alice.clone()).unwrap();
+        let inferencer = Inferencer::new_mutable(model, proposition_graph, fact_memory.clone()).unwrap();
+
+        // The stronger cause is the better question, and its value is the expected drop
+        // in the target's entropy.
+        let focus = QuestionFocus::Target(promoted_alice.clone());
+        let config = QuestionConfig { render_text: true, ..QuestionConfig::default() };
+        let questions = inferencer.value_of_information(connection, &focus, &config).unwrap();
+        let asked: Vec<&Proposition> = questions.iter().map(|question| &question.proposition).collect();
+        assert_eq!(asked, vec![&honest_alice, &lucky_alice]);
+        assert!(questions[1].value > 0.0);
+        assert_eq!(questions[0].text.as_deref(), Some("Is it true that alice is honest?"));
+        let query = QueryConfig::default();
+        let mut given = |value| {
+            inferencer
+                .conditional_probability(connection, &[(promoted_alice.clone(), true)], &[(honest_alice.clone(), value)], &query)
+                .unwrap()
+                .probability
+        };
+        let (if_true, if_false) = (given(true), given(false));
+        let p = questions[0].probability;
+        let prior = inferencer.joint_probability(connection, &[(promoted_alice.clone(), true)], &query).unwrap().probability;
+        let expected = binary_entropy(prior) - p * binary_entropy(if_true) - (1.0 - p) * binary_entropy(if_false);
+        assert!((questions[0].value - expected).abs() < 1e-6);
+
+        // About an entity, a question also settles itself. Observed propositions are
+        // not asked about.
+        fact_memory.store_proposition_probability(connection, &lucky_alice, 1.0).unwrap();
+        let about = QuestionFocus::Entity(alice);
+        assert_eq!(inferencer.focus_propositions(&about).len(), 5);
+        let questions = inferencer.value_of_information(connection, &about, &QuestionConfig::default()).unwrap();
+        let asked: Vec<&Proposition> = questions.iter().map(|question| &question.proposition).collect();
+        assert_eq!(asked, vec![&honest_alice, &promoted_alice]);
+        assert!(questions[0].text.is_none());
+        assert!(questions[1].value >= binary_entropy(questions[1].probability) - 1e-9);
+    }
+}
@@ -0,0 +1,134 @@
+mod common;
+
+#[cfg(test)]
+mod test_weight_tying {
+    use super::common::{context, rule};
+    use bayeslog::qbbn::{
+        common::redis::MockConnection,
+        model::{
+            creators::{implication, obj, predicate, relation, sub, variable, variable_argument},
+            exponential::ExponentialModel,
+            features::FeatureExtractors,
+            objects::{ImplicationFactor, PredicateGroup, RoleMap},
+            tying::{template_key, TyingGroup, TyingScope, TyingSelector, WeightTying},
+            ModelWeights,
+        },
+    };
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn symmetric(name: &str) -> ImplicationFactor {
+        let man = || variable("Man".to_string());
+        let friends = relation(
+            name.to_string(),
+            vec![variable_argument("Man".to_string()), variable_argument("Man".to_string())],
+        );
+        implication(
+            PredicateGroup::new(vec![predicate(friends.clone(), vec![sub(man()), obj(man())])]),
+            predicate(friends, vec![sub(man()), obj(man())]),
+            vec![RoleMap::new(HashMap::from([
+                ("sub".to_string(), "obj".to_string()),
+                ("obj".to_string(), "sub".to_string()),
+            ]))],
+        )
+    }
+
+    fn template_tying() -> WeightTying {
+        WeightTying::new(vec![TyingGroup {
+            name: "shape".to_string(),
+            selector: TyingSelector::ConclusionRelations(vec!["alarm".to_string(), "delay".to_string()]),
+            scope: TyingScope::Template,
+        }])
+    }
+
+    #[test]
+    fn test_weight_keys() {
+        let (smoke, fog, other) = (rule("smoke", "alarm"), rule("fog", "delay"), rule("rain", "wet"));
+        assert_eq!(template_key(&smoke), template_key(&fog));
+        assert_ne!(template_key(&smoke), template_key(&rule("alarm", "alarm")));
+
+        let tying = template_tying();
+        assert_eq!(tying.weight_key(&smoke), tying.weight_key(&fog));
+        assert_eq!(tying.weight_key(&other), other.unique_key());
+        assert!(!tying.is_tied(&other));
+
+        let per_relation = WeightTying::new(vec![TyingGroup {
+            name: "conclusion".to_string(),
+            selector: TyingSelector::All,
+            scope: TyingScope::ConclusionRelation,
+        }]);
+        assert_eq!(per_relation.weight_key(&smoke), per_relation.weight_key(&rule("fire", "alarm")));
+        assert_ne!(per_relation.weight_key(&smoke), per_relation.weight_key(&fog));
+
+        // Symmetric rules over any relation share one set of weights.
+        let symmetric_tying: WeightTying =
+            serde_json::from_str(r#"{"groups": [{"name": "symmetric", "selector": "symmetric"}]}"#).unwrap();
+        assert_eq!(symmetric_tying.groups[0].scope, TyingScope::Group);
+        let (friends, siblings) = (symmetric("friends"), symmetric("siblings"));
+        assert!(symmetric_tying.is_tied(&friends));
+        assert!(!symmetric_tying.is_tied(&smoke));
+        assert_eq!(symmetric_tying.weight_key(&friends), symmetric_tying.weight_key(&siblings));
+        assert_eq!(symmetric_tying.weight_key(&friends), "tied[symmetric]");
+    }
+
+    #[test]
+    fn test_tied_rules_learn_together() {
+        let namespace = "weight_tying_training";
+        let mut connection = MockConnection::new_in_memory().unwrap();
+        WeightTying::store(&mut connection, namespace, Some(&template_tying())).unwrap();
+        let (smoke, fog, other) = (rule("smoke", "alarm"), rule("fog", "delay"), rule("rain", "wet"));
+        let extractors = FeatureExtractors::default();
+        let tying = Arc::new(template_tying());
+        let smoke_features = extractors.features(Some(&tying), &context(&smoke, 1f64)).unwrap();
+        let fog_features = extractors.features(Some(&tying), &context(&fog, 1f64)).unwrap();
+        let mut smoke_names: Vec<_> = smoke_features[1].keys().collect();
+        let mut fog_names: Vec<_> = fog_features[1].keys().collect();
+        smoke_names.sort();
+        fog_names.sort();
+        assert_eq!(smoke_names, fog_names);
+
+        let mut model = ExponentialModel::new_mutable(namespace.to_string()).unwrap();
+        for implication in [&smoke, &fog, &other] {
+            model.initialize_connection(&mut connection, implication).unwrap();
+        }
+        let fog_before = model.predict(&mut connection, &context(&fog, 1f64)).unwrap().probability;
+        let other_before = model.predict(&mut connection, &context(&other, 1f64)).unwrap().probability;
+        for _ in 0..50 {
+            model.train(&mut connection, &context(&smoke, 1f64), 1f64).unwrap();
+        }
+        // Only the rule tied to the trained one learned from its data.
+        let fog_after = model.predict(&mut connection, &context(&fog, 1f64)).unwrap().probability;
+        let other_after = model.predict(&mut connection, &context(&other, 1f64)).unwrap().probability;
+        assert!(fog_after > fog_before + 0.1, "{} {}", fog_before, fog_after);
+        assert!((other_after - other_before).abs() < 1e-9, "{} {}", other_before, other_after);
+
+        // The tying is stored with the graph, so other databases are untied.
+        let mut other_connection = MockConnection::new_in_memory().unwrap();
+        assert_eq!(WeightTying::load(&mut other_connection, namespace).unwrap(), None);
+    }
+
+    #[test]
+    fn test_tying_is_saved_with_the_weights() {
+        let namespace = "weight_tying_saved";
+        let mut connection = MockConnection::new_in_memory().unwrap();
+        WeightTying::store(&mut connection, namespace, Some(&template_tying())).unwrap();
+        let mut model = ExponentialModel::new_mutable(namespace.to_string()).unwrap();
+        model.initialize_connection(&mut connection, &rule("smoke", "alarm")).unwrap();
+
+        let path = std::env::temp_dir().join("bayeslog_test_weight_tying.json");
+        let path = path.to_str().unwrap();
+        model.save_to_file(&mut connection, path).unwrap();
+        let saved = ModelWeights::load_from_file(path).unwrap();
+        assert_eq!(saved.weight_tying, Some(template_tying()));
+        assert!(!saved.weights.is_empty());
+        assert!(saved.weights.keys().all(|feature| feature.contains("tied[shape]")), "{:?}", saved.weights);
+
+        // A model loaded from the file keeps its tying, whatever the database stores.
+        WeightTying::store(&mut connection, namespace, None).unwrap();
+        let loaded = ExponentialModel::from_file(namespace.to_string(), path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let features = loaded.factor_features(&mut connection, &context(&rule("fog", "delay"), 1f64)).unwrap();
+        assert!(features[1].keys().all(|feature| feature.contains("tied[shape]")), "{:?}", features);
+        assert_eq!(WeightTying::load(&mut connection, namespace).unwrap(), None);
+    }
+}